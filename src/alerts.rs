@@ -0,0 +1,234 @@
+use crate::broker::order::Fill;
+use crate::live::LiveMetrics;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+pub mod notifier;
+
+pub use notifier::Notifier;
+
+// Sends `notify` whenever the condition is met during a paper session
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlertRule {
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    pub notify: Notifier,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "when", rename_all = "lowercase")]
+pub enum AlertCondition {
+    // Every executed order
+    Fill,
+    // Drawdown from the equity peak above `threshold` percent, once until a new peak
+    Drawdown { threshold: f64 },
+    // The strategy trapped or failed during a call
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Alert {
+    pub session: String,
+    pub time: Option<NaiveDateTime>,
+    #[serde(flatten)]
+    pub event: AlertEvent,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum AlertEvent {
+    Fill {
+        fill: Fill,
+    },
+    Drawdown {
+        // In percent of the peak
+        drawdown: f64,
+        equity: f64,
+        peak_equity: f64,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl Alert {
+    // Human readable summary for chat and email notifiers
+    pub fn message(&self) -> String {
+        let time = self
+            .time
+            .map(|time| format!(" at {}", time))
+            .unwrap_or_default();
+        let event = match &self.event {
+            AlertEvent::Fill { fill } => format!(
+                "{:?} {} {} @ {:.4} (fees {:.2})",
+                fill.direction, fill.size, fill.asset, fill.price, fill.fees
+            ),
+            AlertEvent::Drawdown {
+                drawdown,
+                equity,
+                peak_equity,
+            } => format!(
+                "drawdown of {:.2}% (equity {:.2}, peak {:.2})",
+                drawdown, equity, peak_equity
+            ),
+            AlertEvent::Error { message } => format!("strategy error: {}", message),
+        };
+        format!("[kronos {}]{} {}", self.session, time, event)
+    }
+}
+
+pub fn validate(rules: &[AlertRule]) -> Result<(), &'static str> {
+    for rule in rules {
+        if let AlertCondition::Drawdown { threshold } = rule.condition {
+            if !(threshold > 0.0 && threshold < 100.0) {
+                return Err("The drawdown threshold must be a percentage between 0 and 100");
+            }
+        }
+        rule.notify.validate()?;
+    }
+    Ok(())
+}
+
+// Evaluates the rules after each tick of a session
+pub struct AlertMonitor {
+    rules: Vec<AlertRule>,
+    fills_seen: usize,
+    peak_equity: f64,
+    // Drawdown rules already triggered since the last peak
+    triggered: Vec<bool>,
+}
+
+impl AlertMonitor {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let triggered = vec![false; rules.len()];
+        AlertMonitor {
+            rules,
+            fills_seen: 0,
+            peak_equity: 0.0,
+            triggered,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Alerts raised since the last check, with where to send them
+    pub fn check(
+        &mut self,
+        metrics: &LiveMetrics,
+        fills: &[Fill],
+        error: Option<String>,
+    ) -> Vec<(Notifier, Alert)> {
+        let alert = |event: AlertEvent| Alert {
+            session: metrics.session.clone(),
+            time: metrics.time,
+            event,
+        };
+
+        let new_fills = fills.get(self.fills_seen..).unwrap_or_default();
+        self.fills_seen = fills.len();
+
+        if metrics.equity > self.peak_equity {
+            self.peak_equity = metrics.equity;
+            self.triggered.fill(false);
+        }
+        let drawdown = match self.peak_equity > 0.0 {
+            true => (self.peak_equity - metrics.equity) / self.peak_equity * 100.0,
+            false => 0.0,
+        };
+
+        let mut alerts = vec![];
+        for (rule, triggered) in self.rules.iter().zip(self.triggered.iter_mut()) {
+            match rule.condition {
+                AlertCondition::Fill => alerts.extend(new_fills.iter().map(|fill| {
+                    let fill = fill.clone();
+                    (rule.notify.clone(), alert(AlertEvent::Fill { fill }))
+                })),
+                AlertCondition::Drawdown { threshold } if drawdown > threshold && !*triggered => {
+                    *triggered = true;
+                    alerts.push((
+                        rule.notify.clone(),
+                        alert(AlertEvent::Drawdown {
+                            drawdown,
+                            equity: metrics.equity,
+                            peak_equity: self.peak_equity,
+                        }),
+                    ));
+                }
+                AlertCondition::Error => {
+                    if let Some(message) = &error {
+                        alerts.push((
+                            rule.notify.clone(),
+                            alert(AlertEvent::Error {
+                                message: message.clone(),
+                            }),
+                        ));
+                    }
+                }
+                AlertCondition::Drawdown { .. } => {}
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::order::OrderDirection;
+
+    fn metrics(equity: f64) -> LiveMetrics {
+        LiveMetrics {
+            session: "test".to_string(),
+            time: None,
+            running: true,
+            cash: equity,
+            equity,
+            day_start_equity: equity,
+            today_pnl: 0.0,
+            total_pnl: 0.0,
+            positions: vec![],
+            open_orders: vec![],
+            active_algo_orders: 0,
+        }
+    }
+
+    #[test]
+    fn drawdown_alerts_once_per_peak() {
+        let notify = Notifier::Webhook {
+            url: "http://localhost/alerts".to_string(),
+        };
+        let mut monitor = AlertMonitor::new(vec![
+            AlertRule {
+                condition: AlertCondition::Drawdown { threshold: 10.0 },
+                notify: notify.clone(),
+            },
+            AlertRule {
+                condition: AlertCondition::Fill,
+                notify,
+            },
+        ]);
+        let fill = Fill {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            time: NaiveDateTime::default(),
+            price: 100.0,
+            size: 1.0,
+            fees: 0.0,
+            slippage: 0.0,
+        };
+
+        let alerts = [1000.0, 950.0, 880.0, 850.0, 1100.0, 980.0]
+            .iter()
+            .enumerate()
+            .map(|(i, equity)| {
+                let fills = vec![fill.clone(); i / 2];
+                monitor.check(&metrics(*equity), &fills, None).len()
+            })
+            .collect::<Vec<_>>();
+
+        // Drawdowns of 12% and 10.9% from the two peaks, and a fill every other tick
+        assert_eq!(alerts, vec![0, 0, 2, 0, 1, 1]);
+    }
+}
@@ -0,0 +1,206 @@
+use super::{drawdown, metrics::GlobalMetrics};
+use crate::broker::{
+    fee::FeeType,
+    order::{Order, OrderDirection, OrderType, SizeSpec, TimeInForce},
+    Broker,
+};
+use crate::data::OHLCVData;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+// Buy and hold of the traded asset over the same range and with the same costs as the strategy
+#[derive(Serialize, Debug, Clone)]
+pub struct Benchmark {
+    // None when the buy never filled, e.g. the session stayed closed
+    pub entry_time: Option<NaiveDateTime>,
+    pub quantity: f64,
+    pub final_value: f64,
+    pub net_profit: f64,
+    pub roi: f64,
+    pub fees: f64,
+    pub slippage: f64,
+    pub sharpe_ratio: f64,
+    pub max_drawdown: f64,
+    pub max_drawdown_duration_days: i64,
+    // Benchmark equity at each timestamp of the strategy equity curve, to overlay both directly
+    pub curve: Vec<CurvePoint>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CurvePoint {
+    pub timestamp: NaiveDateTime,
+    pub strategy: f64,
+    pub benchmark: f64,
+}
+
+// Buys with the whole capital on the first bar of the range and sells at the close of the last
+// one. Trades through `broker` so fees, slippage, carry and sessions apply like for the strategy
+pub fn buy_and_hold(
+    mut broker: Broker,
+    asset: &str,
+    data: &[OHLCVData],
+    (start, end): (NaiveDateTime, NaiveDateTime),
+    strategy_curve: &[(NaiveDateTime, f64)],
+    risk_free_rate: f64,
+) -> Option<Benchmark> {
+    let bars: Vec<&OHLCVData> = data
+        .iter()
+        .filter(|bar| bar.timestamp >= start && bar.timestamp <= end)
+        .collect();
+    let first = bars.first()?;
+    let initial_capital = broker.cash;
+    if initial_capital <= 0.0 || first.open <= 0.0 {
+        return None;
+    }
+
+    // Sized for the worst slippage so the buy and its fees fit in the cash
+    let price = first.open * (1.0 + broker.slippage_range.1.max(0.0));
+    let notional = price * broker.contract.multiplier * broker.contract.rate(price);
+    let quantity = match &broker.fee_type {
+        Some(FeeType::Flat(fee)) => (initial_capital - fee) / notional,
+        Some(FeeType::Percentage(percentage)) => initial_capital / (notional * (1.0 + percentage)),
+        // Covers the rate and the minimum together, the fee being at most their sum
+        Some(FeeType::PerUnit { rate, minimum, .. }) => {
+            (initial_capital - minimum) / (notional + rate)
+        }
+        None => initial_capital / notional,
+    };
+    if !(quantity.is_finite() && quantity > 0.0) {
+        return None;
+    }
+    broker.place_order(Order {
+        asset: asset.to_string(),
+        direction: OrderDirection::Buy,
+        size: SizeSpec::Quantity(quantity),
+        order_type: OrderType::Market,
+        time_in_force: TimeInForce::Gtc,
+        placed_at: None,
+    });
+
+    for (i, bar) in bars.iter().enumerate() {
+        broker.settle(&bar.timestamp, bar);
+        broker.handle_unfulfilled_orders(&bar.timestamp, bar);
+        if i + 1 == bars.len() {
+            broker.close_all_positions();
+            let at_close = OHLCVData {
+                open: bar.close,
+                ..(*bar).clone()
+            };
+            broker.handle_unfulfilled_orders(&bar.timestamp, &at_close);
+        }
+        let equity = broker.cash + broker.portfolio_value(bar);
+        broker
+            .trade_tracker
+            .record_equity_snapshot(bar.timestamp, equity);
+    }
+
+    let tracker = &broker.trade_tracker;
+    let equity_curve = tracker.get_equity_curve();
+    let final_value = equity_curve
+        .last()
+        .map(|(_, value)| *value)
+        .unwrap_or(initial_capital);
+    let net_profit = final_value - initial_capital;
+    let (max_drawdown, max_drawdown_duration_days) =
+        drawdown::max_drawdown(&drawdown::drawdowns(equity_curve));
+    let entry = broker.fills.first();
+
+    Some(Benchmark {
+        entry_time: entry.map(|fill| fill.time),
+        quantity: entry.map_or(0.0, |fill| fill.size),
+        final_value,
+        net_profit,
+        roi: net_profit / initial_capital * 100.0,
+        fees: tracker.total_fees,
+        slippage: tracker.total_slippage,
+        sharpe_ratio: GlobalMetrics::calculate_sharpe_ratio(equity_curve, risk_free_rate),
+        max_drawdown,
+        max_drawdown_duration_days,
+        curve: align(strategy_curve, equity_curve, initial_capital),
+    })
+}
+
+// Samples `benchmark` at the timestamps of `strategy` with its last value at or before each of
+// them. Both accounts start from the same capital, which is held before the first benchmark point,
+// so the curves share their base and the entry costs of the benchmark stay visible
+fn align(
+    strategy: &[(NaiveDateTime, f64)],
+    benchmark: &[(NaiveDateTime, f64)],
+    initial_capital: f64,
+) -> Vec<CurvePoint> {
+    let mut benchmark = benchmark.iter().peekable();
+    let mut value = initial_capital;
+    strategy
+        .iter()
+        .map(|&(timestamp, strategy)| {
+            while let Some((_, next)) = benchmark.next_if(|(time, _)| *time <= timestamp) {
+                value = *next;
+            }
+            CurvePoint {
+                timestamp,
+                strategy,
+                benchmark: value,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn pays_the_fees_of_the_strategy() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |days: i64, open: f64, close: f64| OHLCVData {
+            timestamp: start + Duration::days(days),
+            open,
+            high: open.max(close),
+            low: open.min(close),
+            close,
+            volume: 1000,
+        };
+        let data = vec![
+            bar(0, 100.0, 90.0),
+            bar(1, 90.0, 120.0),
+            bar(2, 120.0, 110.0),
+        ];
+
+        let mut broker = Broker::new();
+        broker.set_cash(1010.0);
+        broker.set_fees(FeeType::Flat(10.0));
+        let benchmark = buy_and_hold(
+            broker,
+            "AAPL",
+            &data,
+            (start, start + Duration::days(2)),
+            &[],
+            0.0,
+        )
+        .unwrap();
+
+        // 10 units bought at the first open, sold at the last close, a fee on each side
+        assert_eq!(benchmark.quantity, 10.0);
+        assert_eq!(benchmark.fees, 20.0);
+        assert_eq!(benchmark.final_value, 1090.0);
+        assert_eq!(benchmark.max_drawdown, ((1090.0 - 1200.0) / 1200.0) * 100.0);
+    }
+
+    #[test]
+    fn curve_follows_the_strategy_timestamps() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let at = |days: i64, value: f64| (start + Duration::days(days), value);
+        // The strategy ticks on the weekend the benchmark skips
+        let strategy = [at(0, 1000.0), at(1, 1010.0), at(2, 1020.0), at(3, 1005.0)];
+        let benchmark = [at(1, 990.0), at(3, 1100.0)];
+
+        let curve: Vec<f64> = align(&strategy, &benchmark, 1000.0)
+            .iter()
+            .map(|point| point.benchmark)
+            .collect();
+        assert_eq!(curve, vec![1000.0, 990.0, 990.0, 1100.0]);
+    }
+}
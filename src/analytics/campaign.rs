@@ -0,0 +1,181 @@
+use crate::broker::contract::Contract;
+use crate::broker::order::{Fill, OrderDirection};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Fills of one asset from a flat position back to flat, however many times it was scaled
+#[derive(Serialize, Debug, Clone)]
+pub struct Campaign {
+    pub asset: String,
+    pub entry_time: NaiveDateTime,
+    // None while the position is still open at the end of the run
+    pub exit_time: Option<NaiveDateTime>,
+    pub entries: u32,
+    pub exits: u32,
+    pub max_quantity: f64,
+    pub bought_quantity: f64,
+    pub average_entry_price: f64,
+    pub average_exit_price: Option<f64>,
+    pub fees: f64,
+    // Net of fees, only for closed campaigns
+    pub profit_loss: Option<f64>,
+    #[serde(skip)]
+    entry_value: f64,
+    #[serde(skip)]
+    sold_quantity: f64,
+    #[serde(skip)]
+    sold_notional: f64,
+    #[serde(skip)]
+    cash_flow: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CampaignReport {
+    pub campaigns: Vec<Campaign>,
+    pub closed: u32,
+    // Closed campaigns with a positive P&L
+    pub win_rate: Option<f64>,
+    pub average_entries: Option<f64>,
+    pub average_exits: Option<f64>,
+    // Share of the campaigns scaled in at least once
+    pub pyramided_share: Option<f64>,
+}
+
+impl Campaign {
+    fn open(fill: &Fill) -> Self {
+        Campaign {
+            asset: fill.asset.clone(),
+            entry_time: fill.time,
+            exit_time: None,
+            entries: 0,
+            exits: 0,
+            max_quantity: 0.0,
+            bought_quantity: 0.0,
+            average_entry_price: 0.0,
+            average_exit_price: None,
+            fees: 0.0,
+            profit_loss: None,
+            entry_value: 0.0,
+            sold_quantity: 0.0,
+            sold_notional: 0.0,
+            cash_flow: 0.0,
+        }
+    }
+}
+
+// Positions below `dust_threshold` count as flat
+pub fn campaigns(fills: &[Fill], contract: &Contract, dust_threshold: f64) -> CampaignReport {
+    let mut open: HashMap<String, (Campaign, f64)> = HashMap::new();
+    let mut campaigns = vec![];
+
+    for fill in fills {
+        let (campaign, quantity) = open
+            .entry(fill.asset.clone())
+            .or_insert_with(|| (Campaign::open(fill), 0.0));
+        let value = fill.price * fill.size * contract.multiplier * contract.rate(fill.price);
+        campaign.fees += fill.fees;
+        campaign.cash_flow -= fill.fees;
+
+        match fill.direction {
+            OrderDirection::Buy => {
+                *quantity += fill.size;
+                campaign.entries += 1;
+                campaign.bought_quantity += fill.size;
+                campaign.entry_value += fill.price * fill.size;
+                campaign.average_entry_price = campaign.entry_value / campaign.bought_quantity;
+                campaign.max_quantity = campaign.max_quantity.max(*quantity);
+                campaign.cash_flow -= value;
+            }
+            OrderDirection::Sell => {
+                *quantity -= fill.size;
+                campaign.exits += 1;
+                campaign.sold_quantity += fill.size;
+                campaign.sold_notional += fill.price * fill.size;
+                campaign.average_exit_price = Some(campaign.sold_notional / campaign.sold_quantity);
+                campaign.cash_flow += value;
+            }
+        }
+
+        if *quantity <= dust_threshold {
+            let (mut campaign, _) = open.remove(&fill.asset).expect("Campaign opened above");
+            campaign.exit_time = Some(fill.time);
+            campaign.profit_loss = Some(campaign.cash_flow);
+            campaigns.push(campaign);
+        }
+    }
+
+    let mut still_open: Vec<Campaign> = open.into_values().map(|(campaign, _)| campaign).collect();
+    still_open.sort_by_key(|campaign| campaign.entry_time);
+    campaigns.extend(still_open);
+
+    let closed: Vec<&Campaign> = campaigns.iter().filter(|c| c.exit_time.is_some()).collect();
+    let share = |count: usize, total: usize| (total > 0).then(|| count as f64 / total as f64);
+    let average = |values: Vec<u32>| {
+        let n = values.len();
+        (n > 0).then(|| values.iter().sum::<u32>() as f64 / n as f64)
+    };
+
+    CampaignReport {
+        closed: closed.len() as u32,
+        win_rate: share(
+            closed
+                .iter()
+                .filter(|c| c.profit_loss.is_some_and(|pl| pl > 0.0))
+                .count(),
+            closed.len(),
+        ),
+        average_entries: average(campaigns.iter().map(|c| c.entries).collect()),
+        average_exits: average(closed.iter().map(|c| c.exits).collect()),
+        pyramided_share: share(
+            campaigns.iter().filter(|c| c.entries > 1).count(),
+            campaigns.len(),
+        ),
+        campaigns,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_scaled_fills_from_flat_to_flat() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let fill = |day: i64, direction, price: f64, size: f64| Fill {
+            asset: "AAPL".to_string(),
+            direction,
+            time: start + chrono::Duration::days(day),
+            price,
+            size,
+            fees: 1.0,
+            slippage: 0.0,
+        };
+        let fills = vec![
+            // Scaled in twice and out twice
+            fill(0, OrderDirection::Buy, 100.0, 2.0),
+            fill(1, OrderDirection::Buy, 110.0, 2.0),
+            fill(2, OrderDirection::Sell, 120.0, 1.0),
+            fill(3, OrderDirection::Sell, 115.0, 3.0),
+            // Still open
+            fill(4, OrderDirection::Buy, 100.0, 1.0),
+        ];
+
+        let report = campaigns(&fills, &Contract::default(), 1e-9);
+        assert_eq!(report.campaigns.len(), 2);
+        assert_eq!(report.closed, 1);
+
+        let first = &report.campaigns[0];
+        assert_eq!((first.entries, first.exits), (2, 2));
+        assert_eq!(first.max_quantity, 4.0);
+        assert_eq!(first.average_entry_price, 105.0);
+        assert_eq!(first.average_exit_price, Some(116.25));
+        // 465 sold - 420 bought - 4 fees
+        assert_eq!(first.profit_loss, Some(41.0));
+
+        assert!(report.campaigns[1].exit_time.is_none());
+        assert_eq!(report.win_rate, Some(1.0));
+        assert_eq!(report.pyramided_share, Some(0.5));
+    }
+}
@@ -0,0 +1,71 @@
+use serde::Serialize;
+
+// Spread of one metric over several runs
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    // Sample standard deviation, 0 for a single value
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub first_quartile: f64,
+    pub third_quartile: f64,
+    pub percentile_5: f64,
+    pub percentile_95: f64,
+}
+
+// Non finite values (e.g. the Sharpe ratio of a flat run) are left out
+pub fn distribution(values: &[f64]) -> Option<Distribution> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(f64::total_cmp);
+
+    let n = sorted.len();
+    let mean = sorted.iter().sum::<f64>() / n as f64;
+    let variance = match n {
+        1 => 0.0,
+        _ => sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64,
+    };
+
+    Some(Distribution {
+        count: n,
+        mean,
+        median: quantile(&sorted, 0.5),
+        std_dev: variance.sqrt(),
+        min: sorted[0],
+        max: sorted[n - 1],
+        first_quartile: quantile(&sorted, 0.25),
+        third_quartile: quantile(&sorted, 0.75),
+        percentile_5: quantile(&sorted, 0.05),
+        percentile_95: quantile(&sorted, 0.95),
+    })
+}
+
+// Linear interpolation between the closest ranks of sorted values
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distribution_of_metric() {
+        let stats = distribution(&[3.0, 1.0, f64::NAN, 4.0, 2.0]).unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.mean, 2.5);
+        assert_eq!(stats.median, 2.5);
+        assert_eq!(stats.first_quartile, 1.75);
+        assert_eq!(stats.third_quartile, 3.25);
+        assert!((stats.percentile_95 - 3.85).abs() < 1e-9);
+        assert!((stats.std_dev - 1.2909944).abs() < 1e-6);
+        assert_eq!(distribution(&[f64::NAN]), None);
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+// Fall of the equity below its previous high, until it gets back to it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Drawdown {
+    pub peak: NaiveDateTime,
+    pub peak_value: f64,
+    pub trough: NaiveDateTime,
+    pub trough_value: f64,
+    // Percent of the peak, negative
+    pub depth: f64,
+    // First time the equity is back at the peak, None when it never recovered
+    pub recovery: Option<NaiveDateTime>,
+    pub recovered: bool,
+    // From the peak to the recovery, or to the end of the curve when not recovered
+    pub duration_days: i64,
+    pub decline_days: i64,
+    // From the trough to the recovery
+    pub time_to_recovery_days: Option<i64>,
+}
+
+// Every drawdown of the equity curve, in order
+pub fn drawdowns(equity_curve: &[(NaiveDateTime, f64)]) -> Vec<Drawdown> {
+    let Some(&(start, value)) = equity_curve.first() else {
+        return vec![];
+    };
+    let end = equity_curve.last().map_or(start, |(time, _)| *time);
+
+    let mut drawdowns = vec![];
+    let mut peak = (start, value);
+    let mut current: Option<Drawdown> = None;
+    for &(time, value) in equity_curve {
+        if value >= peak.1 {
+            if let Some(mut drawdown) = current.take() {
+                drawdown.recovery = Some(time);
+                drawdown.recovered = true;
+                drawdown.duration_days = (time - drawdown.peak).num_days();
+                drawdown.time_to_recovery_days = Some((time - drawdown.trough).num_days());
+                drawdowns.push(drawdown);
+            }
+            peak = (time, value);
+            continue;
+        }
+
+        let drawdown = current.get_or_insert(Drawdown {
+            peak: peak.0,
+            peak_value: peak.1,
+            trough: time,
+            trough_value: value,
+            depth: 0.0,
+            recovery: None,
+            recovered: false,
+            duration_days: 0,
+            decline_days: 0,
+            time_to_recovery_days: None,
+        });
+        if value <= drawdown.trough_value {
+            drawdown.trough = time;
+            drawdown.trough_value = value;
+            drawdown.depth = (value - peak.1) / peak.1 * 100.0;
+            drawdown.decline_days = (time - peak.0).num_days();
+        }
+    }
+
+    if let Some(mut drawdown) = current {
+        drawdown.duration_days = (end - drawdown.peak).num_days();
+        drawdowns.push(drawdown);
+    }
+    drawdowns
+}
+
+// Deepest drawdown and longest time under water
+pub fn max_drawdown(drawdowns: &[Drawdown]) -> (f64, i64) {
+    drawdowns
+        .iter()
+        .fold((0.0, 0), |(depth, duration), drawdown| {
+            (
+                drawdown.depth.min(depth),
+                drawdown.duration_days.max(duration),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn measures_drawdowns_from_peak_to_recovery() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let day = |days: i64| start + Duration::days(days);
+        let curve: Vec<(NaiveDateTime, f64)> = [100.0, 90.0, 80.0, 95.0, 100.0, 120.0, 110.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| (day(i as i64), value))
+            .collect();
+
+        let drawdowns = drawdowns(&curve);
+        assert_eq!(drawdowns.len(), 2);
+
+        let first = &drawdowns[0];
+        assert_eq!(
+            (first.peak, first.trough, first.recovery),
+            (day(0), day(2), Some(day(4)))
+        );
+        assert_eq!(first.depth, -20.0);
+        assert_eq!(
+            (first.duration_days, first.time_to_recovery_days),
+            (4, Some(2))
+        );
+
+        // Still under water at the end of the curve
+        let last = &drawdowns[1];
+        assert_eq!(
+            (last.peak, last.recovery, last.recovered),
+            (day(5), None, false)
+        );
+        assert_eq!(last.duration_days, 1);
+
+        assert_eq!(max_drawdown(&drawdowns), (-20.0, 4));
+    }
+}
@@ -0,0 +1,343 @@
+use super::cross_section::{self, Distribution};
+use crate::broker::algo::AlgoOrderReport;
+use crate::broker::order::{Order, OrderDirection, OrderType};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+// Execution quality of the fills against their arrival price (the open of the first bar the
+// order could trade on), independently of the signals that placed them
+pub struct ExecutionTracker {
+    market: FillStats,
+    limit: FillStats,
+    stop: FillStats,
+    algo: FillStats,
+    limit_orders: RestingOrderStats,
+    stop_orders: RestingOrderStats,
+    rejected_orders: u32,
+    peak_reserved_cash: f64,
+    queue_misses: u32,
+    capped_fills: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct FillStats {
+    pub fills: u32,
+    pub quantity: f64,
+    // Cost of the fills against the arrival price in the account currency, positive is adverse
+    pub arrival_slippage: f64,
+    // Weighted by the arrival notional
+    pub arrival_slippage_bps: Option<f64>,
+    #[serde(skip)]
+    arrival_notional: f64,
+}
+
+// Limit or stop orders waiting in the book for their price
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RestingOrderStats {
+    pub placed: u32,
+    pub filled: u32,
+    // Past their time in force, or IOC and FOK orders that could not fill
+    pub expired: u32,
+    // Cancelled by the strategy or by a liquidation
+    pub cancelled: u32,
+    // Still resting at the end of the run
+    pub open: u32,
+    pub fill_rate: Option<f64>,
+    // From the first bar the order could trade on
+    pub average_time_to_fill_seconds: Option<f64>,
+    // Seconds from the placement of the order to its fill
+    pub time_to_fill: Option<Distribution>,
+    #[serde(skip)]
+    time_to_fill_seconds: i64,
+    #[serde(skip)]
+    ages: Vec<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExecutionReport {
+    pub market: FillStats,
+    pub limit: FillStats,
+    pub stop: FillStats,
+    // Children of the execution algos
+    pub algo: FillStats,
+    pub limit_orders: RestingOrderStats,
+    pub stop_orders: RestingOrderStats,
+    // Buys refused when placed for exceeding the buying power, with `broker.check_buying_power`
+    pub rejected_orders: u32,
+    // Highest cash set aside by the resting buys at once, with `broker.reserve_cash`
+    pub peak_reserved_cash: f64,
+    // Bars touching a limit order without reaching it in the queue, with `broker.queue`
+    pub queue_misses: u32,
+    // Orders larger than the liquidity of the bar, cut down by `broker.volume_share` or
+    // `broker.max_participation`
+    pub capped_fills: u32,
+    // Sum over the algo parents, the basis points are of their total arrival value
+    pub implementation_shortfall: f64,
+    pub implementation_shortfall_bps: Option<f64>,
+}
+
+// Costs are positive, the sign flips for sells which want higher prices
+pub fn side(direction: &OrderDirection) -> f64 {
+    match direction {
+        OrderDirection::Buy => 1.0,
+        OrderDirection::Sell => -1.0,
+    }
+}
+
+// Implementation shortfall of a parent order against its arrival price: the cost of the fills,
+// the unfilled part marked at `last_price` (opportunity cost) and the fees
+#[allow(clippy::too_many_arguments)]
+pub fn implementation_shortfall(
+    direction: &OrderDirection,
+    arrival_price: f64,
+    filled: f64,
+    filled_notional: f64,
+    unfilled: f64,
+    last_price: f64,
+    fees: f64,
+    point_value: f64,
+) -> f64 {
+    let execution = (filled_notional - arrival_price * filled) * point_value;
+    let opportunity = (last_price - arrival_price) * unfilled * point_value;
+    side(direction) * (execution + opportunity) + fees
+}
+
+impl FillStats {
+    fn record(&mut self, cost: f64, quantity: f64, arrival_notional: f64) {
+        self.fills += 1;
+        self.quantity += quantity;
+        self.arrival_slippage += cost;
+        self.arrival_notional += arrival_notional;
+        self.arrival_slippage_bps = (self.arrival_notional > 0.0)
+            .then(|| self.arrival_slippage / self.arrival_notional * 10_000.0);
+    }
+}
+
+impl RestingOrderStats {
+    fn report(&self, open: u32) -> RestingOrderStats {
+        RestingOrderStats {
+            open,
+            fill_rate: (self.placed > 0).then(|| self.filled as f64 / self.placed as f64),
+            average_time_to_fill_seconds: (self.filled > 0)
+                .then(|| self.time_to_fill_seconds as f64 / self.filled as f64),
+            time_to_fill: cross_section::distribution(&self.ages),
+            ..self.clone()
+        }
+    }
+}
+
+impl ExecutionTracker {
+    pub fn new() -> Self {
+        ExecutionTracker {
+            market: FillStats::default(),
+            limit: FillStats::default(),
+            stop: FillStats::default(),
+            algo: FillStats::default(),
+            limit_orders: RestingOrderStats::default(),
+            stop_orders: RestingOrderStats::default(),
+            rejected_orders: 0,
+            peak_reserved_cash: 0.0,
+            queue_misses: 0,
+            capped_fills: 0,
+        }
+    }
+
+    fn resting(&mut self, order_type: &OrderType) -> Option<&mut RestingOrderStats> {
+        match order_type {
+            OrderType::Market => None,
+            OrderType::Limit(_) => Some(&mut self.limit_orders),
+            OrderType::Stop(_) => Some(&mut self.stop_orders),
+        }
+    }
+
+    pub fn record_placed(&mut self, order_type: &OrderType) {
+        if let Some(stats) = self.resting(order_type) {
+            stats.placed += 1;
+        }
+    }
+
+    pub fn record_rejected(&mut self) {
+        self.rejected_orders += 1;
+    }
+
+    pub fn record_capped(&mut self) {
+        self.capped_fills += 1;
+    }
+
+    pub fn record_queue_miss(&mut self) {
+        self.queue_misses += 1;
+    }
+
+    pub fn record_reserved(&mut self, reserved: f64) {
+        self.peak_reserved_cash = self.peak_reserved_cash.max(reserved);
+    }
+
+    pub fn record_expired(&mut self, order_type: &OrderType) {
+        if let Some(stats) = self.resting(order_type) {
+            stats.expired += 1;
+        }
+    }
+
+    pub fn record_cancelled(&mut self, order_type: &OrderType) {
+        if let Some(stats) = self.resting(order_type) {
+            stats.cancelled += 1;
+        }
+    }
+
+    pub fn record_fill(
+        &mut self,
+        order: &Order,
+        arrival: (NaiveDateTime, f64),
+        time: NaiveDateTime,
+        price: f64,
+        quantity: f64,
+        point_value: f64,
+    ) {
+        let (arrival_time, _) = arrival;
+        if let Some(stats) = self.resting(&order.order_type) {
+            stats.filled += 1;
+            stats.time_to_fill_seconds += (time - arrival_time).num_seconds();
+            if let Some(placed_at) = order.placed_at {
+                stats.ages.push((time - placed_at).num_seconds() as f64);
+            }
+        }
+        self.record_partial_fill(order, arrival, price, quantity, point_value);
+    }
+
+    // Fill of part of an order, the order counts as filled with its last fill
+    pub fn record_partial_fill(
+        &mut self,
+        order: &Order,
+        arrival: (NaiveDateTime, f64),
+        price: f64,
+        quantity: f64,
+        point_value: f64,
+    ) {
+        let (_, arrival_price) = arrival;
+        let cost = side(&order.direction) * (price - arrival_price) * quantity * point_value;
+        let arrival_notional = arrival_price * quantity * point_value;
+        let stats = match order.order_type {
+            OrderType::Market => &mut self.market,
+            OrderType::Stop(_) => &mut self.stop,
+            OrderType::Limit(_) => &mut self.limit,
+        };
+        stats.record(cost, quantity, arrival_notional);
+    }
+
+    pub fn record_algo_fill(
+        &mut self,
+        direction: &OrderDirection,
+        arrival_price: f64,
+        price: f64,
+        quantity: f64,
+        point_value: f64,
+    ) {
+        let cost = side(direction) * (price - arrival_price) * quantity * point_value;
+        self.algo
+            .record(cost, quantity, arrival_price * quantity * point_value);
+    }
+
+    // `open_orders` is the number of limit and stop orders still resting
+    pub fn report(
+        &self,
+        open_orders: (u32, u32),
+        algo_orders: &[AlgoOrderReport],
+    ) -> ExecutionReport {
+        let traded = algo_orders
+            .iter()
+            .filter_map(|p| p.shortfall.zip(p.arrival_value));
+        // Folded from 0 as an empty float sum is -0
+        let implementation_shortfall = traded
+            .clone()
+            .fold(0.0, |total, (shortfall, _)| total + shortfall);
+        let arrival_value = traded.fold(0.0, |total, (_, value)| total + value);
+
+        ExecutionReport {
+            market: self.market.clone(),
+            limit: self.limit.clone(),
+            stop: self.stop.clone(),
+            algo: self.algo.clone(),
+            limit_orders: self.limit_orders.report(open_orders.0),
+            stop_orders: self.stop_orders.report(open_orders.1),
+            rejected_orders: self.rejected_orders,
+            peak_reserved_cash: self.peak_reserved_cash,
+            queue_misses: self.queue_misses,
+            capped_fills: self.capped_fills,
+            implementation_shortfall,
+            implementation_shortfall_bps: (arrival_value > 0.0)
+                .then(|| implementation_shortfall / arrival_value * 10_000.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::order::{SizeSpec, TimeInForce};
+
+    fn date(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").expect("Invalid date")
+    }
+
+    #[test]
+    fn limit_fill_rate_and_arrival_slippage() {
+        let mut tracker = ExecutionTracker::new();
+        let order = |direction, order_type, placed_at: &str| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(1.0),
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: Some(date(placed_at)),
+        };
+        let limit = order(
+            OrderDirection::Buy,
+            OrderType::Limit(99.0),
+            "2023-12-31 00:00:00",
+        );
+        for _ in 0..3 {
+            tracker.record_placed(&limit.order_type);
+        }
+        tracker.record_expired(&limit.order_type);
+
+        // Bought 2 at 99 a day after arriving at 100, a 1% improvement
+        tracker.record_fill(
+            &limit,
+            (date("2024-01-01 00:00:00"), 100.0),
+            date("2024-01-02 00:00:00"),
+            99.0,
+            2.0,
+            1.0,
+        );
+        // Sold 1 at 101 arriving at 102, 1 adverse point
+        tracker.record_fill(
+            &order(
+                OrderDirection::Sell,
+                OrderType::Market,
+                "2024-01-01 00:00:00",
+            ),
+            (date("2024-01-01 00:00:00"), 102.0),
+            date("2024-01-01 00:00:00"),
+            101.0,
+            1.0,
+            1.0,
+        );
+
+        let report = tracker.report((1, 0), &[]);
+        assert_eq!(report.limit.arrival_slippage, -2.0);
+        assert_eq!(report.limit.arrival_slippage_bps, Some(-100.0));
+        assert_eq!(report.market.arrival_slippage, 1.0);
+        assert_eq!(report.limit_orders.filled, 1);
+        assert_eq!(report.limit_orders.open, 1);
+        assert_eq!(report.limit_orders.fill_rate, Some(1.0 / 3.0));
+        assert_eq!(
+            report.limit_orders.average_time_to_fill_seconds,
+            Some(86400.0)
+        );
+        // Placed the day before its arrival, the market order has no queue
+        let time_to_fill = report.limit_orders.time_to_fill.unwrap();
+        assert_eq!((time_to_fill.count, time_to_fill.median), (1, 172800.0));
+        assert!(report.stop_orders.time_to_fill.is_none());
+        assert_eq!(report.implementation_shortfall_bps, None);
+    }
+}
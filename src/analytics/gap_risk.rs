@@ -0,0 +1,157 @@
+use super::trade::Trade;
+use crate::broker::contract::Contract;
+use crate::data::OHLCVData;
+use chrono::{Datelike, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+// P&L of the closed trades split between the moves from a close to the next open across a night
+// or a weekend and the moves while the market trades
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GapRisk {
+    // Before costs, in the account currency
+    pub overnight_pnl: f64,
+    pub weekend_pnl: f64,
+    pub intraday_pnl: f64,
+    // Gaps held through, counted once per trade
+    pub overnight_gaps: usize,
+    pub weekend_gaps: usize,
+    // Percent of the price moves held through that happened in the gaps, in absolute value
+    pub gap_exposure_percent: f64,
+}
+
+fn spans_weekend(from: NaiveDate, to: NaiveDate) -> bool {
+    from.iter_days()
+        .skip(1)
+        .take_while(|day| *day < to)
+        .any(|day| matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+}
+
+// Trades of `symbol` measured on `data` sorted by time, days are those of `timezone`. None when no
+// trade was held over a bar
+pub fn gap_risk(
+    trades: &[Trade],
+    data: &[OHLCVData],
+    symbol: Option<&str>,
+    contract: &Contract,
+    timezone: Tz,
+) -> Option<GapRisk> {
+    let date = |bar: &OHLCVData| {
+        bar.timestamp
+            .and_utc()
+            .with_timezone(&timezone)
+            .date_naive()
+    };
+    let mut risk = GapRisk::default();
+    let (mut moves, mut gap_moves) = (0.0, 0.0);
+    let mut measured = false;
+
+    for trade in trades {
+        let (Some(exit_time), Some(exit_price)) = (trade.exit_time, trade.exit_price) else {
+            continue;
+        };
+        if symbol.is_some_and(|symbol| symbol != trade.asset) {
+            continue;
+        }
+        let first = data.partition_point(|bar| bar.timestamp <= trade.entry_time);
+        let last = data.partition_point(|bar| bar.timestamp <= exit_time);
+        let (Some(first), Some(last)) = (first.checked_sub(1), last.checked_sub(1)) else {
+            continue;
+        };
+        if last <= first {
+            continue;
+        }
+        measured = true;
+
+        let units = trade.quantity * contract.multiplier * contract.rate(exit_price);
+        let mut intraday = data[first].close - trade.entry_price;
+        moves += intraday.abs();
+        for j in first + 1..=last {
+            let (previous, bar) = (&data[j - 1], &data[j]);
+            let gap = bar.open - previous.close;
+            let body = match j == last {
+                true => exit_price - bar.open,
+                false => bar.close - bar.open,
+            };
+            intraday += body;
+            moves += gap.abs() + body.abs();
+
+            let (from, to) = (date(previous), date(bar));
+            if from == to {
+                intraday += gap;
+            } else if spans_weekend(from, to) {
+                risk.weekend_pnl += gap * units;
+                risk.weekend_gaps += 1;
+                gap_moves += gap.abs();
+            } else {
+                risk.overnight_pnl += gap * units;
+                risk.overnight_gaps += 1;
+                gap_moves += gap.abs();
+            }
+        }
+        risk.intraday_pnl += intraday * units;
+    }
+
+    if moves > 0.0 {
+        risk.gap_exposure_percent = gap_moves / moves * 100.0;
+    }
+    measured.then_some(risk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::trade::TradeDirection;
+    use chrono::{Duration, NaiveDateTime};
+
+    #[test]
+    fn splits_the_pnl_between_the_gaps_and_the_sessions() {
+        // Thursday
+        let start = NaiveDateTime::parse_from_str("2024-01-04 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        // Thursday, Friday and Monday, each opening 2 above the previous close and closing 1 above
+        // its open
+        let bars: Vec<_> = [0, 1, 4]
+            .iter()
+            .enumerate()
+            .map(|(i, day)| {
+                let open = 100.0 + 3.0 * i as f64;
+                OHLCVData {
+                    timestamp: start + Duration::days(*day),
+                    open,
+                    high: open + 1.0,
+                    low: open,
+                    close: open + 1.0,
+                    volume: 1000,
+                }
+            })
+            .collect();
+        let mut trade = Trade::new(
+            1,
+            "AAPL".to_string(),
+            start,
+            100.0,
+            2.0,
+            0.0,
+            0.0,
+            None,
+            TradeDirection::Long,
+        );
+        trade.close(start + Duration::days(4), 106.5, 0.0, 0.0, None, 1.0);
+
+        let risk = gap_risk(
+            &[trade],
+            &bars,
+            Some("AAPL"),
+            &Contract::default(),
+            chrono_tz::UTC,
+        )
+        .unwrap();
+        assert_eq!((risk.overnight_gaps, risk.weekend_gaps), (1, 1));
+        assert_eq!(risk.overnight_pnl, 4.0);
+        assert_eq!(risk.weekend_pnl, 4.0);
+        // 1 on Thursday and Friday, 0.5 on Monday
+        assert_eq!(risk.intraday_pnl, 5.0);
+        assert!((risk.gap_exposure_percent - 4.0 / 6.5 * 100.0).abs() < 1e-9);
+    }
+}
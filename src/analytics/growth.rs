@@ -0,0 +1,99 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+
+pub const SECONDS_PER_YEAR: f64 = 365.25 * 86400.0;
+
+// Compound annual growth rate in percent of a growth factor over `years`
+pub fn cagr(growth: f64, years: f64) -> Option<f64> {
+    (years > 0.0 && growth > 0.0).then(|| (growth.powf(1.0 / years) - 1.0) * 100.0)
+}
+
+pub fn years_between(start: &NaiveDateTime, end: &NaiveDateTime) -> f64 {
+    (*end - *start).num_seconds() as f64 / SECONDS_PER_YEAR
+}
+
+// CAGR of the equity over trailing windows, one point per day once the curve spans the window
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RollingCagr {
+    pub one_year: Vec<CagrPoint>,
+    pub three_year: Vec<CagrPoint>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CagrPoint {
+    pub timestamp: NaiveDateTime,
+    pub cagr: f64,
+}
+
+impl RollingCagr {
+    pub fn new(equity_curve: &[(NaiveDateTime, f64)]) -> Self {
+        RollingCagr {
+            one_year: rolling_cagr(equity_curve, 1),
+            three_year: rolling_cagr(equity_curve, 3),
+        }
+    }
+}
+
+// From the last snapshot at least `years` before each day close, over the actual time between both
+fn rolling_cagr(equity_curve: &[(NaiveDateTime, f64)], years: i64) -> Vec<CagrPoint> {
+    let window = Duration::seconds((years as f64 * SECONDS_PER_YEAR) as i64);
+    let mut points = vec![];
+    // Snapshots up to `eligible` are at least a window old
+    let mut eligible = 0;
+    for (i, &(time, equity)) in equity_curve.iter().enumerate() {
+        while equity_curve
+            .get(eligible)
+            .is_some_and(|(start, _)| *start <= time - window)
+        {
+            eligible += 1;
+        }
+        let last_of_day = equity_curve
+            .get(i + 1)
+            .is_none_or(|(next, _)| next.date() != time.date());
+        let Some(&(start_time, start_equity)) =
+            eligible.checked_sub(1).map(|start| &equity_curve[start])
+        else {
+            continue;
+        };
+        if !last_of_day || start_equity <= 0.0 {
+            continue;
+        }
+        if let Some(cagr) = cagr(equity / start_equity, years_between(&start_time, &time)) {
+            points.push(CagrPoint {
+                timestamp: time,
+                cagr,
+            });
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolls_the_cagr_over_the_trailing_year() {
+        let start = NaiveDateTime::parse_from_str("2020-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        // Twice a day, growing 10% over each year
+        let curve: Vec<_> = (0..2 * 800)
+            .map(|i| {
+                let time = start + Duration::hours(12 * i);
+                (time, 100.0 * 1.1f64.powf(years_between(&start, &time)))
+            })
+            .collect();
+
+        let rolling = RollingCagr::new(&curve);
+        assert!(rolling.three_year.is_empty());
+        let first = &rolling.one_year[0];
+        assert!(first.timestamp >= start + Duration::days(365));
+        assert!(first.timestamp < start + Duration::days(367));
+        assert_eq!(rolling.one_year.len(), 800 - 365);
+        assert!(rolling
+            .one_year
+            .iter()
+            .all(|point| (point.cagr - 10.0).abs() < 1e-6));
+        assert!((cagr(1.21, 2.0).unwrap() - 10.0).abs() < 1e-9);
+    }
+}
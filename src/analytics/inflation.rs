@@ -0,0 +1,102 @@
+use crate::broker::sweep::AnnualRate;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Price level the real returns are measured against
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Inflation {
+    // Annual inflation, constant or dated like the cash sweep rate
+    Rate(AnnualRate),
+    // Levels of a price index such as the CPI, each one holding until the next
+    Cpi(Vec<IndexLevel>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexLevel {
+    pub date: NaiveDateTime,
+    pub value: f64,
+}
+
+impl Inflation {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            Inflation::Rate(rate) => rate.validate(),
+            Inflation::Cpi(levels) => {
+                if levels.is_empty()
+                    || !levels
+                        .iter()
+                        .all(|level| level.value.is_finite() && level.value > 0.0)
+                {
+                    return Err("The price index needs positive levels");
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn prepare(mut self) -> Self {
+        match &mut self {
+            Inflation::Rate(rate) => rate.sort(),
+            Inflation::Cpi(levels) => levels.sort_by_key(|level| level.date),
+        }
+        self
+    }
+
+    // Growth of the price level from `start` to `end`, 1.05 after 5% of inflation
+    pub fn price_level(&self, start: &NaiveDateTime, end: &NaiveDateTime) -> f64 {
+        match self {
+            Inflation::Rate(rate) => rate.growth(start, end),
+            Inflation::Cpi(levels) => {
+                let level = |time: &NaiveDateTime| {
+                    let i = levels.partition_point(|level| &level.date <= time);
+                    levels[i.saturating_sub(1)].value
+                };
+                level(end) / level(start)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::sweep::RateChange;
+    use chrono::Duration;
+
+    #[test]
+    fn measures_the_price_level_over_the_period() {
+        let start = NaiveDateTime::parse_from_str("2020-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let years = |years: i64| start + Duration::days(365 * years);
+
+        let constant = Inflation::Rate(AnnualRate::Constant(0.1));
+        assert!((constant.price_level(&start, &years(2)) - 1.21).abs() < 1e-9);
+
+        let dated = Inflation::Rate(AnnualRate::Series(vec![
+            RateChange {
+                from: years(1),
+                rate: 0.2,
+            },
+            RateChange {
+                from: start,
+                rate: 0.1,
+            },
+        ]))
+        .prepare();
+        assert!((dated.price_level(&start, &years(2)) - 1.1 * 1.2).abs() < 1e-9);
+
+        let cpi = Inflation::Cpi(vec![
+            IndexLevel {
+                date: start,
+                value: 250.0,
+            },
+            IndexLevel {
+                date: years(1),
+                value: 260.0,
+            },
+        ]);
+        assert!(cpi.validate().is_ok());
+        assert!((cpi.price_level(&start, &(years(1) + Duration::days(10))) - 1.04).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,139 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurrencyPosition {
+    Prefix,
+    Suffix,
+}
+
+// How numbers and dates are written in the exported reports, ISO dates and plain numbers by default
+#[derive(Debug, Clone)]
+pub struct ReportLocale {
+    pub decimal: char,
+    pub thousands: Option<char>,
+    pub date_format: String,
+    pub currency: Option<String>,
+    pub currency_position: CurrencyPosition,
+}
+
+impl Default for ReportLocale {
+    fn default() -> Self {
+        ReportLocale {
+            decimal: '.',
+            thousands: None,
+            date_format: "%Y-%m-%d".to_string(),
+            currency: None,
+            currency_position: CurrencyPosition::Prefix,
+        }
+    }
+}
+
+impl ReportLocale {
+    pub fn preset(tag: &str) -> Option<Self> {
+        use CurrencyPosition::*;
+        let (decimal, thousands, date_format, currency, currency_position) = match tag {
+            "en-US" => ('.', ',', "%m/%d/%Y", "$", Prefix),
+            "en-GB" => ('.', ',', "%d/%m/%Y", "£", Prefix),
+            "de-DE" => (',', '.', "%d.%m.%Y", "€", Suffix),
+            "fr-FR" => (',', '\u{202F}', "%d/%m/%Y", "€", Suffix),
+            "es-ES" | "it-IT" => (',', '.', "%d/%m/%Y", "€", Suffix),
+            "de-CH" => ('.', '\'', "%d.%m.%Y", "CHF", Prefix),
+            _ => return None,
+        };
+        Some(ReportLocale {
+            decimal,
+            thousands: Some(thousands),
+            date_format: date_format.to_string(),
+            currency: Some(currency.to_string()),
+            currency_position,
+        })
+    }
+
+    // strftime pattern (`%d.%m.%Y`), refused when chrono can't format it
+    pub fn set_date_format(&mut self, date_format: &str) -> Result<(), &'static str> {
+        if StrftimeItems::new(date_format).any(|item| matches!(item, Item::Error)) {
+            return Err("Invalid date format");
+        }
+        self.date_format = date_format.to_string();
+        Ok(())
+    }
+
+    // Decimal commas are used with semicolon separated values
+    pub fn separator(&self) -> char {
+        match self.decimal {
+            ',' => ';',
+            _ => ',',
+        }
+    }
+
+    pub fn date(&self, date: NaiveDateTime) -> String {
+        date.format(&self.date_format).to_string()
+    }
+
+    // Quantities keep all their digits and aren't grouped
+    pub fn number(&self, value: f64) -> String {
+        value.to_string().replace('.', &self.decimal.to_string())
+    }
+
+    // Two decimals, grouped thousands and the currency symbol
+    pub fn amount(&self, value: f64) -> String {
+        let formatted = format!("{:.2}", value.abs());
+        let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, "00"));
+
+        let mut grouped = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if let Some(thousands) = self.thousands {
+                if i > 0 && (integer.len() - i) % 3 == 0 {
+                    grouped.push(thousands);
+                }
+            }
+            grouped.push(digit);
+        }
+        let number = format!("{}{}{}", grouped, self.decimal, fraction);
+        let sign = if formatted.bytes().any(|b| b != b'0' && b != b'.') && value < 0.0 {
+            "-"
+        } else {
+            ""
+        };
+
+        match &self.currency {
+            None => format!("{}{}", sign, number),
+            Some(currency) => match self.currency_position {
+                // Currency codes are spaced from the amount, symbols aren't
+                CurrencyPosition::Prefix if currency.chars().count() > 1 => {
+                    format!("{}{} {}", sign, currency, number)
+                }
+                CurrencyPosition::Prefix => format!("{}{}{}", sign, currency, number),
+                CurrencyPosition::Suffix => format!("{}{} {}", sign, number, currency),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_like_the_locale() {
+        let date = NaiveDateTime::parse_from_str("2024-03-07 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+
+        let german = ReportLocale::preset("de-DE").unwrap();
+        assert_eq!(german.amount(-1234567.891), "-1.234.567,89 €");
+        assert_eq!(german.number(2.5), "2,5");
+        assert_eq!(german.date(date), "07.03.2024");
+        assert_eq!(german.separator(), ';');
+
+        let american = ReportLocale::preset("en-US").unwrap();
+        assert_eq!(american.amount(1099.0), "$1,099.00");
+        assert_eq!(american.date(date), "03/07/2024");
+
+        let swiss = ReportLocale::preset("de-CH").unwrap();
+        assert_eq!(swiss.amount(-0.001), "CHF 0.00");
+
+        assert_eq!(ReportLocale::default().amount(-98.0), "-98.00");
+        assert!(ReportLocale::default().set_date_format("%Q").is_err());
+    }
+}
@@ -1,7 +1,15 @@
+use super::drawdown;
+use super::growth::{self, cagr};
+use super::inflation::Inflation;
+use super::plugin::{MetricInput, MetricRegistry};
 use super::trade::Trade;
-use crate::broker::fee::FeeType;
-use chrono::{Duration, NaiveDateTime};
-use serde::Serialize;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Bumped when metrics are added or computed differently, results of an older version are
+// recomputed with `POST /runs/{id}/recompute`
+pub const METRICS_VERSION: u32 = 5;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct GlobalMetrics {
@@ -11,11 +19,20 @@ pub struct GlobalMetrics {
     pub gross_profit: f64,
     pub total_fees: f64,
     pub total_slippage: f64,
+    pub total_price_improvement: f64,
+    pub total_carry: f64,
+    pub total_cash_yield: f64,
     pub net_profit: f64,
     pub net_profit_percentage: f64,
     pub num_orders_placed: i32,
     pub num_orders_executed: i32,
+    pub num_liquidations: i32,
     pub roi: f64,
+    // Compound annual growth rate over the calendar time of the equity curve, added in version 4
+    pub cagr: f64,
+    // Deflated by `parameters.inflation`, null without it
+    pub real_roi: Option<f64>,
+    pub real_cagr: Option<f64>,
     pub sharpe_ratio: f64,
     pub max_drawdown: f64,
     pub max_drawdown_duration_days: i64,
@@ -29,30 +46,149 @@ pub struct GlobalMetrics {
     pub winning_trades: usize,
     pub losing_trades: usize,
     pub avg_trade_duration_hours: f64,
+    // Mean entry and exit efficiencies of the trades, the timing within the range held apart
+    // from the direction, added in version 5
+    pub avg_entry_efficiency: f64,
+    pub avg_exit_efficiency: f64,
+    pub buy_hold_roi: f64,
+    pub buy_hold_final_value: f64,
+    pub buy_hold_net_profit: f64,
+    // Values of the metric plugins
+    #[serde(flatten)]
+    pub custom: BTreeMap<String, f64>,
+}
+
+// Metrics keyed by name with their unit, for clients rendering any metric without knowing the
+// fields of `GlobalMetrics`. Non finite values (e.g. an infinite profit factor) are left out
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsMap {
+    pub values: BTreeMap<String, f64>,
+    pub units: BTreeMap<String, &'static str>,
+}
+
+impl MetricsMap {
+    // `plugin_unit` gives the unit of the plugin values
+    pub fn new(
+        metrics: &GlobalMetrics,
+        plugin_unit: impl Fn(&str) -> Option<&'static str>,
+    ) -> Self {
+        let mut values = BTreeMap::new();
+        let mut units = BTreeMap::new();
+        if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(metrics) {
+            for (name, value) in fields {
+                let Some(value) = value.as_f64().filter(|value| value.is_finite()) else {
+                    continue;
+                };
+                let unit = GlobalMetrics::unit(&name)
+                    .or_else(|| plugin_unit(&name))
+                    .unwrap_or("number");
+                units.insert(name.clone(), unit);
+                values.insert(name, value);
+            }
+        }
+        MetricsMap { values, units }
+    }
+}
+
+// Broker totals the metrics are computed from along with the trades and the equity curve, kept in
+// the result so the metrics can be computed again without the run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsInputs {
+    pub initial_capital: f64,
+    pub risk_free_rate: f64,
+    pub cash: f64,
+    pub portfolio_value: f64,
+    pub num_orders_placed: i32,
+    pub num_orders_executed: i32,
+    pub num_liquidations: i32,
+    pub total_fees: f64,
+    pub total_slippage: f64,
+    pub total_price_improvement: f64,
+    pub total_carry: f64,
+    // Added in version 2
+    #[serde(default)]
+    pub total_cash_yield: f64,
+    // Of the buy and hold benchmark, 0 without one
     pub buy_hold_roi: f64,
     pub buy_hold_final_value: f64,
     pub buy_hold_net_profit: f64,
+    // Added in version 3
+    #[serde(default)]
+    pub inflation: Option<Inflation>,
+}
+
+// Metrics with the values of the plugins and their map
+pub fn compute(
+    trades: &[Trade],
+    equity_curve: &[(NaiveDateTime, f64)],
+    inputs: &MetricsInputs,
+    plugins: &MetricRegistry,
+) -> (GlobalMetrics, MetricsMap) {
+    let mut metrics = GlobalMetrics::calculate(trades, equity_curve, inputs);
+    metrics.custom = plugins.compute(&MetricInput {
+        trades,
+        equity_curve,
+        risk_free_rate: inputs.risk_free_rate,
+    });
+    let map = MetricsMap::new(&metrics, |name| plugins.unit(name));
+    (metrics, map)
 }
 
 impl GlobalMetrics {
+    // Unit of a typed metric, `currency` being the account currency
+    pub fn unit(name: &str) -> Option<&'static str> {
+        let unit = match name {
+            "cash"
+            | "portfolio_value"
+            | "total_equity"
+            | "gross_profit"
+            | "total_fees"
+            | "total_slippage"
+            | "total_price_improvement"
+            | "total_carry"
+            | "total_cash_yield"
+            | "net_profit"
+            | "avg_win"
+            | "avg_loss"
+            | "largest_win"
+            | "largest_loss"
+            | "buy_hold_final_value"
+            | "buy_hold_net_profit" => "currency",
+            "net_profit_percentage"
+            | "roi"
+            | "cagr"
+            | "real_roi"
+            | "real_cagr"
+            | "avg_entry_efficiency"
+            | "avg_exit_efficiency"
+            | "max_drawdown"
+            | "win_rate"
+            | "buy_hold_roi" => "percent",
+            "num_orders_placed"
+            | "num_orders_executed"
+            | "num_liquidations"
+            | "total_trades"
+            | "winning_trades"
+            | "losing_trades" => "count",
+            "sharpe_ratio" | "profit_factor" => "ratio",
+            "max_drawdown_duration_days" => "days",
+            "avg_trade_duration_hours" => "hours",
+            _ => return None,
+        };
+        Some(unit)
+    }
+
     pub fn calculate(
         trades: &[Trade],
         equity_curve: &[(NaiveDateTime, f64)],
-        initial_capital: f64,
-        risk_free_rate: f64,
-        cash: f64,
-        portfolio_value: f64,
-        num_orders_placed: i32,
-        num_orders_executed: i32,
-        total_fees: f64,
-        total_slippage: f64,
-        first_price: Option<f64>,
-        last_price: Option<f64>,
-        fee_type: &Option<FeeType>,
+        inputs: &MetricsInputs,
     ) -> Self {
         if trades.is_empty() {
             return Self::default();
         }
+        let initial_capital = inputs.initial_capital;
+        let (cash, portfolio_value) = (inputs.cash, inputs.portfolio_value);
+        let (total_fees, total_slippage) = (inputs.total_fees, inputs.total_slippage);
 
         let total_trades = trades.len();
         let winning_trades: Vec<_> = trades
@@ -117,9 +253,29 @@ impl GlobalMetrics {
             .unwrap_or(initial_capital);
         let roi = ((final_value - initial_capital) / initial_capital) * 100.0;
 
-        let sharpe_ratio = Self::calculate_sharpe_ratio(equity_curve, risk_free_rate);
+        // Actual calendar time from the first to the last equity snapshot
+        let span = equity_curve.first().map(|(start, _)| {
+            let end = equity_curve.last().map_or(*start, |(end, _)| *end);
+            (*start, end)
+        });
+        let years = span.map_or(0.0, |(start, end)| growth::years_between(&start, &end));
+        let growth = if initial_capital > 0.0 {
+            final_value / initial_capital
+        } else {
+            0.0
+        };
+        let (real_roi, real_cagr) = match (&inputs.inflation, span) {
+            (Some(inflation), Some((start, end))) if initial_capital > 0.0 => {
+                let real_growth = growth / inflation.price_level(&start, &end);
+                (Some((real_growth - 1.0) * 100.0), cagr(real_growth, years))
+            }
+            _ => (None, None),
+        };
 
-        let (max_drawdown, max_drawdown_duration_days) = Self::calculate_max_drawdown(equity_curve);
+        let sharpe_ratio = Self::calculate_sharpe_ratio(equity_curve, inputs.risk_free_rate);
+
+        let (max_drawdown, max_drawdown_duration_days) =
+            drawdown::max_drawdown(&drawdown::drawdowns(equity_curve));
 
         let avg_trade_duration_hours = if !trades.is_empty() {
             let total_duration: i64 = trades
@@ -137,6 +293,13 @@ impl GlobalMetrics {
             0.0
         };
 
+        let mean = |values: Vec<f64>| match values.len() {
+            0 => 0.0,
+            n => values.iter().sum::<f64>() / n as f64,
+        };
+        let avg_entry_efficiency = mean(trades.iter().filter_map(|t| t.entry_efficiency).collect());
+        let avg_exit_efficiency = mean(trades.iter().filter_map(|t| t.exit_efficiency).collect());
+
         let total_equity = cash + portfolio_value;
         let gross_profit = total_equity - initial_capital;
         let net_profit = gross_profit - total_fees - total_slippage;
@@ -146,13 +309,6 @@ impl GlobalMetrics {
             0.0
         };
 
-        let (buy_hold_roi, buy_hold_final_value, buy_hold_net_profit) =
-            if let (Some(first), Some(last)) = (first_price, last_price) {
-                Self::calculate_buy_and_hold(initial_capital, first, last, fee_type)
-            } else {
-                (0.0, 0.0, 0.0)
-            };
-
         GlobalMetrics {
             cash: f64::trunc(cash * 100.0) / 100.0,
             portfolio_value: f64::trunc(portfolio_value * 100.0) / 100.0,
@@ -160,11 +316,18 @@ impl GlobalMetrics {
             gross_profit: f64::trunc(gross_profit * 100.0) / 100.0,
             total_fees: f64::trunc(total_fees * 100.0) / 100.0,
             total_slippage: f64::trunc(total_slippage * 100.0) / 100.0,
+            total_price_improvement: f64::trunc(inputs.total_price_improvement * 100.0) / 100.0,
+            total_carry: f64::trunc(inputs.total_carry * 100.0) / 100.0,
+            total_cash_yield: f64::trunc(inputs.total_cash_yield * 100.0) / 100.0,
             net_profit: f64::trunc(net_profit * 100.0) / 100.0,
             net_profit_percentage: f64::trunc(net_profit_percentage * 100.0) / 100.0,
-            num_orders_placed,
-            num_orders_executed,
+            num_orders_placed: inputs.num_orders_placed,
+            num_orders_executed: inputs.num_orders_executed,
+            num_liquidations: inputs.num_liquidations,
             roi,
+            cagr: cagr(growth, years).unwrap_or(0.0),
+            real_roi,
+            real_cagr,
             sharpe_ratio,
             max_drawdown,
             max_drawdown_duration_days,
@@ -178,13 +341,19 @@ impl GlobalMetrics {
             winning_trades: winning_trades.len(),
             losing_trades: losing_trades.len(),
             avg_trade_duration_hours,
-            buy_hold_roi: f64::trunc(buy_hold_roi * 100.0) / 100.0,
-            buy_hold_final_value: f64::trunc(buy_hold_final_value * 100.0) / 100.0,
-            buy_hold_net_profit: f64::trunc(buy_hold_net_profit * 100.0) / 100.0,
+            avg_entry_efficiency,
+            avg_exit_efficiency,
+            buy_hold_roi: f64::trunc(inputs.buy_hold_roi * 100.0) / 100.0,
+            buy_hold_final_value: f64::trunc(inputs.buy_hold_final_value * 100.0) / 100.0,
+            buy_hold_net_profit: f64::trunc(inputs.buy_hold_net_profit * 100.0) / 100.0,
+            custom: BTreeMap::new(),
         }
     }
 
-    fn calculate_sharpe_ratio(equity_curve: &[(NaiveDateTime, f64)], risk_free_rate: f64) -> f64 {
+    pub(super) fn calculate_sharpe_ratio(
+        equity_curve: &[(NaiveDateTime, f64)],
+        risk_free_rate: f64,
+    ) -> f64 {
         if equity_curve.len() < 2 {
             return 0.0;
         }
@@ -219,79 +388,6 @@ impl GlobalMetrics {
 
         sharpe * (252.0_f64).sqrt()
     }
-
-    fn calculate_max_drawdown(equity_curve: &[(NaiveDateTime, f64)]) -> (f64, i64) {
-        if equity_curve.is_empty() {
-            return (0.0, 0);
-        }
-
-        let mut max_value = equity_curve[0].1;
-        let mut max_drawdown = 0.0;
-        let mut max_drawdown_duration = Duration::zero();
-        let mut drawdown_start: Option<NaiveDateTime> = None;
-
-        for &(time, value) in equity_curve.iter() {
-            if value > max_value {
-                max_value = value;
-                drawdown_start = None;
-            } else {
-                let drawdown = ((value - max_value) / max_value) * 100.0;
-                if drawdown < max_drawdown {
-                    max_drawdown = drawdown;
-                }
-
-                if drawdown_start.is_none() {
-                    drawdown_start = Some(time);
-                }
-
-                if let Some(start) = drawdown_start {
-                    let duration = time - start;
-                    if duration > max_drawdown_duration {
-                        max_drawdown_duration = duration;
-                    }
-                }
-            }
-        }
-
-        (max_drawdown, max_drawdown_duration.num_days())
-    }
-
-    fn calculate_buy_and_hold(
-        initial_capital: f64,
-        first_price: f64,
-        last_price: f64,
-        fee_type: &Option<FeeType>,
-    ) -> (f64, f64, f64) {
-        if first_price <= 0.0 || last_price <= 0.0 || initial_capital <= 0.0 {
-            return (0.0, 0.0, 0.0);
-        }
-
-        let buy_fee = match fee_type {
-            Some(FeeType::Flat(fee)) => *fee,
-            Some(FeeType::Percentage(percentage)) => initial_capital * percentage,
-            None => 0.0,
-        };
-
-        let capital_after_buy_fee = initial_capital - buy_fee;
-        if capital_after_buy_fee <= 0.0 {
-            return (0.0, 0.0, 0.0);
-        }
-
-        let shares = capital_after_buy_fee / first_price;
-        let value_before_sell = shares * last_price;
-
-        let sell_fee = match fee_type {
-            Some(FeeType::Flat(fee)) => *fee,
-            Some(FeeType::Percentage(percentage)) => value_before_sell * percentage,
-            None => 0.0,
-        };
-
-        let final_value = value_before_sell - sell_fee;
-        let net_profit = final_value - initial_capital;
-        let roi = (net_profit / initial_capital) * 100.0;
-
-        (roi, final_value, net_profit)
-    }
 }
 
 impl Default for GlobalMetrics {
@@ -303,11 +399,18 @@ impl Default for GlobalMetrics {
             gross_profit: 0.0,
             total_fees: 0.0,
             total_slippage: 0.0,
+            total_price_improvement: 0.0,
+            total_carry: 0.0,
+            total_cash_yield: 0.0,
             net_profit: 0.0,
             net_profit_percentage: 0.0,
             num_orders_placed: 0,
             num_orders_executed: 0,
+            num_liquidations: 0,
             roi: 0.0,
+            cagr: 0.0,
+            real_roi: None,
+            real_cagr: None,
             sharpe_ratio: 0.0,
             max_drawdown: 0.0,
             max_drawdown_duration_days: 0,
@@ -321,9 +424,37 @@ impl Default for GlobalMetrics {
             winning_trades: 0,
             losing_trades: 0,
             avg_trade_duration_hours: 0.0,
+            avg_entry_efficiency: 0.0,
+            avg_exit_efficiency: 0.0,
             buy_hold_roi: 0.0,
             buy_hold_final_value: 0.0,
             buy_hold_net_profit: 0.0,
+            custom: BTreeMap::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_carries_units_and_skips_non_finite_values() {
+        let mut metrics = GlobalMetrics {
+            roi: 12.5,
+            profit_factor: f64::INFINITY,
+            ..GlobalMetrics::default()
+        };
+        metrics.custom.insert("kelly_fraction".to_string(), 0.2);
+        metrics.custom.insert("turnover".to_string(), 3.0);
+
+        let map = MetricsMap::new(&metrics, |name| {
+            (name == "kelly_fraction").then_some("ratio")
+        });
+        assert_eq!(map.values["roi"], 12.5);
+        assert_eq!(map.units["roi"], "percent");
+        assert!(!map.values.contains_key("profit_factor"));
+        assert_eq!(map.units["kelly_fraction"], "ratio");
+        assert_eq!(map.units["turnover"], "number");
+    }
+}
@@ -1,3 +1,20 @@
+pub mod benchmark;
+pub mod campaign;
+pub mod cross_section;
+pub mod drawdown;
+pub mod execution;
+pub mod gap_risk;
+pub mod growth;
+pub mod inflation;
+pub mod locale;
 pub mod metrics;
+pub mod plugin;
+pub mod reconciliation;
+pub mod regression;
+pub mod screen;
+pub mod seasonality;
+pub mod significance;
+pub mod slice;
+pub mod tax;
 pub mod tracker;
 pub mod trade;
@@ -0,0 +1,173 @@
+use super::metrics::GlobalMetrics;
+use super::trade::Trade;
+use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+// What a metric plugin is computed from
+pub struct MetricInput<'a> {
+    pub trades: &'a [Trade],
+    pub equity_curve: &'a [(NaiveDateTime, f64)],
+    // Annual rate
+    pub risk_free_rate: f64,
+}
+
+// Computes named values merged into the run metrics, for metrics `GlobalMetrics` doesn't have
+pub trait MetricPlugin: Send + Sync {
+    fn compute(&self, input: &MetricInput) -> Vec<(String, f64)>;
+
+    // Unit of a value of the plugin reported with the metrics map (`currency`, `percent`, ...)
+    fn unit(&self, _name: &str) -> Option<&'static str> {
+        None
+    }
+}
+
+// Plugins computed at the end of every run, the built-in ones are registered by default
+pub struct MetricRegistry {
+    plugins: Vec<Box<dyn MetricPlugin>>,
+}
+
+impl Default for MetricRegistry {
+    fn default() -> Self {
+        let mut registry = MetricRegistry { plugins: vec![] };
+        registry.register(Box::new(Expectancy));
+        registry.register(Box::new(SortinoRatio));
+        registry
+    }
+}
+
+impl MetricRegistry {
+    pub fn register(&mut self, plugin: Box<dyn MetricPlugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn unit(&self, name: &str) -> Option<&'static str> {
+        self.plugins.iter().find_map(|plugin| plugin.unit(name))
+    }
+
+    // Values named like a `GlobalMetrics` field or a value of a previous plugin are dropped
+    pub fn compute(&self, input: &MetricInput) -> BTreeMap<String, f64> {
+        let reserved = match serde_json::to_value(GlobalMetrics::default()) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => serde_json::Map::new(),
+        };
+
+        let mut values = BTreeMap::new();
+        for (name, value) in self.plugins.iter().flat_map(|plugin| plugin.compute(input)) {
+            if reserved.contains_key(&name) || values.contains_key(&name) {
+                eprintln!("Ignoring the duplicated metric {}", name);
+                continue;
+            }
+            values.insert(name, value);
+        }
+        values
+    }
+}
+
+// Average profit per trade, and in multiples of the initial risk over the trades with a stop
+struct Expectancy;
+
+impl MetricPlugin for Expectancy {
+    fn compute(&self, input: &MetricInput) -> Vec<(String, f64)> {
+        let mut values = vec![];
+        let profits: Vec<f64> = input
+            .trades
+            .iter()
+            .filter_map(|trade| trade.profit_loss)
+            .collect();
+        if !profits.is_empty() {
+            values.push((
+                "expectancy".to_string(),
+                profits.iter().sum::<f64>() / profits.len() as f64,
+            ));
+        }
+
+        let r_multiples: Vec<f64> = input
+            .trades
+            .iter()
+            .filter_map(|trade| trade.r_multiple)
+            .collect();
+        if !r_multiples.is_empty() {
+            values.push((
+                "expectancy_r".to_string(),
+                r_multiples.iter().sum::<f64>() / r_multiples.len() as f64,
+            ));
+        }
+        values
+    }
+
+    fn unit(&self, name: &str) -> Option<&'static str> {
+        match name {
+            "expectancy" => Some("currency"),
+            "expectancy_r" => Some("r_multiple"),
+            _ => None,
+        }
+    }
+}
+
+// Like the Sharpe ratio with the deviation of the returns below the risk free rate only
+struct SortinoRatio;
+
+impl MetricPlugin for SortinoRatio {
+    fn compute(&self, input: &MetricInput) -> Vec<(String, f64)> {
+        let returns: Vec<f64> = input
+            .equity_curve
+            .windows(2)
+            .filter(|w| w[0].1 != 0.0)
+            .map(|w| (w[1].1 - w[0].1) / w[0].1)
+            .collect();
+        if returns.is_empty() {
+            return vec![];
+        }
+
+        let daily_risk_free = input.risk_free_rate / 252.0;
+        let mean_return = returns.iter().sum::<f64>() / returns.len() as f64;
+        let downside = returns
+            .iter()
+            .map(|r| (r - daily_risk_free).min(0.0).powi(2))
+            .sum::<f64>()
+            / returns.len() as f64;
+        let sortino = match downside > 0.0 {
+            true => (mean_return - daily_risk_free) / downside.sqrt() * 252.0_f64.sqrt(),
+            false => 0.0,
+        };
+        vec![("sortino_ratio".to_string(), sortino)]
+    }
+
+    fn unit(&self, _name: &str) -> Option<&'static str> {
+        Some("ratio")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Constant(&'static str);
+
+    impl MetricPlugin for Constant {
+        fn compute(&self, _input: &MetricInput) -> Vec<(String, f64)> {
+            vec![(self.0.to_string(), 1.0)]
+        }
+    }
+
+    #[test]
+    fn registered_plugins_extend_the_metrics() {
+        let mut registry = MetricRegistry::default();
+        registry.register(Box::new(Constant("kelly_fraction")));
+        // Can't shadow a typed metric
+        registry.register(Box::new(Constant("roi")));
+
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let curve = [(start, 100.0), (start, 110.0), (start, 108.0)];
+        let values = registry.compute(&MetricInput {
+            trades: &[],
+            equity_curve: &curve,
+            risk_free_rate: 0.0,
+        });
+
+        assert_eq!(values.get("kelly_fraction"), Some(&1.0));
+        assert!(!values.contains_key("roi"));
+        assert!(values["sortino_ratio"] > 0.0);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::broker::order::{Fill, OrderDirection};
+use crate::broker::Broker;
+use serde::Serialize;
+
+// Relative difference under which two totals are considered equal
+const TOLERANCE: f64 = 1e-6;
+
+// Broker ledger against the trades of the tracker at the end of a run, a discrepancy means
+// the accounting of the run can't be trusted
+#[derive(Serialize, Debug, Clone)]
+pub struct Reconciliation {
+    pub consistent: bool,
+    pub checks: Vec<ReconciliationCheck>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReconciliationCheck {
+    pub name: &'static str,
+    pub broker: f64,
+    pub tracker: f64,
+    pub difference: f64,
+    pub consistent: bool,
+}
+
+impl ReconciliationCheck {
+    fn new(name: &'static str, broker: f64, tracker: f64) -> Self {
+        let difference = broker - tracker;
+        let scale = broker.abs().max(tracker.abs()).max(1.0);
+        ReconciliationCheck {
+            name,
+            broker,
+            tracker,
+            difference,
+            consistent: difference.abs() <= TOLERANCE * scale,
+        }
+    }
+}
+
+// Open trades are valued at `last_price` for the point value of the contract. The hedges of the
+// exposure band and the beta overlay trade outside of the tracker, their fills are added to the cash
+pub fn reconcile(broker: &Broker, last_price: f64) -> Reconciliation {
+    let tracker = &broker.trade_tracker;
+    let closed = tracker.get_closed_trades();
+    let open: Vec<_> = tracker.get_open_trades().collect();
+    let point_value = broker.contract.multiplier * broker.contract.rate(last_price);
+
+    // Cash paid for the open trades, only their fees for margin contracts
+    let open_cost: f64 = open
+        .iter()
+        .map(|trade| match broker.contract.margin {
+            true => trade.entry_fees,
+            false => trade.entry_price * trade.quantity * point_value + trade.entry_fees,
+        })
+        .sum();
+    let realized: f64 = closed.iter().filter_map(|trade| trade.profit_loss).sum();
+    let trade_fees: f64 = closed
+        .iter()
+        .map(|trade| trade.entry_fees + trade.exit_fees)
+        .chain(open.iter().map(|trade| trade.entry_fees))
+        .sum();
+
+    let fill_total = |value: fn(&Fill) -> f64| -> f64 { broker.fills.iter().map(value).sum() };
+    let quantity = |direction: OrderDirection| -> f64 {
+        broker
+            .fills
+            .iter()
+            .filter(|fill| fill.direction == direction)
+            .map(|fill| fill.size)
+            .sum()
+    };
+    let closed_quantity: f64 = closed.iter().map(|trade| trade.quantity).sum();
+    let open_quantity: f64 = open.iter().map(|trade| trade.quantity).sum();
+
+    let checks = vec![
+        ReconciliationCheck::new(
+            "cash_delta",
+            broker.cash - tracker.initial_capital,
+            realized + tracker.total_carry + tracker.total_cash_yield - open_cost
+                + broker.hedge_cash_flow(),
+        ),
+        ReconciliationCheck::new("fees", fill_total(|fill| fill.fees), trade_fees),
+        ReconciliationCheck::new(
+            "slippage",
+            fill_total(|fill| fill.slippage * fill.size),
+            tracker.total_slippage,
+        ),
+        ReconciliationCheck::new(
+            "executed_orders",
+            (broker.analytics.total_exec_orders + broker.analytics.total_liquidations) as f64,
+            broker.fills.len() as f64,
+        ),
+        ReconciliationCheck::new(
+            "bought_quantity",
+            quantity(OrderDirection::Buy),
+            closed_quantity + open_quantity,
+        ),
+        ReconciliationCheck::new(
+            "sold_quantity",
+            quantity(OrderDirection::Sell),
+            closed_quantity,
+        ),
+    ];
+
+    Reconciliation {
+        consistent: checks.iter().all(|check| check.consistent),
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::fee::FeeType;
+    use crate::broker::order::{Order, OrderType, SizeSpec, TimeInForce};
+    use crate::data::OHLCVData;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn ledger_matches_trades() {
+        let mut broker = Broker::new();
+        broker.set_cash(10_000.0);
+        broker.set_fees(FeeType::Flat(1.0));
+        broker.set_seed(7);
+        broker.set_slippage(0.0, 0.01);
+
+        let bar = |day: u32, price: f64| OHLCVData {
+            timestamp: NaiveDateTime::parse_from_str(
+                &format!("2024-01-0{} 00:00:00", day),
+                "%Y-%m-%d %H:%M:%S",
+            )
+            .expect("Invalid date"),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0,
+        };
+        let order = |direction, size| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        for (day, direction, size, price) in [
+            (1, OrderDirection::Buy, 3.0, 100.0),
+            (2, OrderDirection::Buy, 2.0, 110.0),
+            (3, OrderDirection::Sell, 4.0, 105.0),
+        ] {
+            broker.place_order(order(direction, size));
+            let bar = bar(day, price);
+            broker.handle_unfulfilled_orders(&bar.timestamp, &bar);
+        }
+
+        let reconciliation = reconcile(&broker, 105.0);
+        assert!(reconciliation.consistent, "{:?}", reconciliation.checks);
+
+        // Cash moved without going through a trade
+        broker.cash += 5.0;
+        let reconciliation = reconcile(&broker, 105.0);
+        assert!(!reconciliation.consistent);
+        assert!(!reconciliation.checks[0].consistent);
+    }
+}
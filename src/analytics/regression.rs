@@ -0,0 +1,149 @@
+use crate::broker::hooks::OrderEvent;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Bumped when the recorded events change shape, older fixtures have to be recorded again
+pub const FIXTURE_VERSION: u32 = 1;
+
+// Relative difference under which two numbers of the events are considered equal
+const TOLERANCE: f64 = 1e-9;
+
+// Differences listed in a verification, the count covers all of them
+const MAX_DIFFERENCES: usize = 50;
+
+// Order sequence of a strategy on given data, kept by its author and verified after changes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fixture {
+    pub version: u32,
+    pub strategy_hash: String,
+    pub data_hash: Option<String>,
+    // Slippage draws of the recording, replayed by the verification
+    pub seed: u64,
+    pub events: Vec<OrderEvent>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Verification {
+    // Same events as the fixture, in the same order
+    pub matches: bool,
+    // The module or the data differ from the recording, expected after a refactor
+    pub strategy_changed: bool,
+    pub data_changed: bool,
+    pub expected_events: usize,
+    pub actual_events: usize,
+    pub different_events: usize,
+    pub differences: Vec<EventDifference>,
+}
+
+// Event at `index` of the sequences, None past the end of one of them
+#[derive(Serialize, Debug, Clone)]
+pub struct EventDifference {
+    pub index: usize,
+    pub expected: Option<OrderEvent>,
+    pub actual: Option<OrderEvent>,
+}
+
+// Equal JSON values, numbers within the tolerance
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0),
+            _ => a == b,
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| same(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+impl Fixture {
+    // Events compared one by one, an inserted or missing order shows up as every event after it
+    pub fn verify(
+        &self,
+        strategy_hash: &str,
+        data_hash: Option<&str>,
+        events: Vec<OrderEvent>,
+    ) -> Verification {
+        let value = |event: Option<&OrderEvent>| {
+            event.map(|event| serde_json::to_value(event).unwrap_or_default())
+        };
+        let mut different_events = 0;
+        let mut differences = vec![];
+        for index in 0..self.events.len().max(events.len()) {
+            let (expected, actual) = (self.events.get(index), events.get(index));
+            let equal = match (value(expected), value(actual)) {
+                (Some(a), Some(b)) => same(&a, &b),
+                _ => false,
+            };
+            if equal {
+                continue;
+            }
+            different_events += 1;
+            if differences.len() < MAX_DIFFERENCES {
+                differences.push(EventDifference {
+                    index,
+                    expected: expected.cloned(),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+
+        Verification {
+            matches: different_events == 0,
+            strategy_changed: self.strategy_hash != strategy_hash,
+            data_changed: self.data_hash.as_deref() != data_hash,
+            expected_events: self.events.len(),
+            actual_events: events.len(),
+            different_events,
+            differences,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::order::{Fill, OrderDirection};
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn diffs_the_events_against_the_fixture() {
+        let time = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let fill = |price: f64| {
+            OrderEvent::Filled(Fill {
+                asset: "AAPL".to_string(),
+                direction: OrderDirection::Buy,
+                time,
+                price,
+                size: 1.0,
+                fees: 0.0,
+                slippage: 0.0,
+            })
+        };
+        let fixture = Fixture {
+            version: FIXTURE_VERSION,
+            strategy_hash: "a".to_string(),
+            data_hash: None,
+            seed: 1,
+            events: vec![fill(100.0), fill(101.0)],
+        };
+
+        let verification = fixture.verify("b", None, vec![fill(100.0 + 1e-12), fill(101.0)]);
+        assert!(verification.matches);
+        assert!(verification.strategy_changed);
+        assert!(!verification.data_changed);
+
+        let verification = fixture.verify("a", None, vec![fill(100.0), fill(102.0), fill(1.0)]);
+        assert!(!verification.matches);
+        assert_eq!(verification.different_events, 2);
+        assert_eq!(verification.differences[0].index, 1);
+        assert!(verification.differences[1].expected.is_none());
+    }
+}
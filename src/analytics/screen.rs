@@ -0,0 +1,153 @@
+use crate::data::OHLCVData;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Filters on the bars of each symbol over the simulated range, then the `top` ones by `rank_by`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ScreenCriteria {
+    pub min_bars: Option<usize>,
+    pub min_average_volume: Option<f64>,
+    // Close to close over the range, 0.05 for 5%
+    pub min_return: Option<f64>,
+    // Standard deviation of the bar returns
+    pub max_volatility: Option<f64>,
+    pub rank_by: ScreenRank,
+    pub top: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenRank {
+    // Highest first
+    #[default]
+    Return,
+    AverageVolume,
+    // Lowest first
+    Volatility,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ScreenStats {
+    pub symbol: String,
+    pub bars: usize,
+    pub average_volume: f64,
+    pub total_return: f64,
+    pub volatility: f64,
+    pub selected: bool,
+}
+
+impl ScreenStats {
+    pub fn new(symbol: String, data: &[OHLCVData], range: (NaiveDateTime, NaiveDateTime)) -> Self {
+        let bars: Vec<_> = data
+            .iter()
+            .filter(|bar| bar.timestamp >= range.0 && bar.timestamp <= range.1)
+            .collect();
+        let returns: Vec<f64> = bars
+            .windows(2)
+            .filter(|w| w[0].close != 0.0)
+            .map(|w| w[1].close / w[0].close - 1.0)
+            .collect();
+        let mean = |values: &[f64]| match values.len() {
+            0 => 0.0,
+            n => values.iter().sum::<f64>() / n as f64,
+        };
+        let average_return = mean(&returns);
+        let variance = mean(
+            &returns
+                .iter()
+                .map(|r| (r - average_return).powi(2))
+                .collect::<Vec<_>>(),
+        );
+        let volumes: Vec<f64> = bars.iter().map(|bar| bar.volume as f64).collect();
+
+        ScreenStats {
+            symbol,
+            bars: bars.len(),
+            average_volume: mean(&volumes),
+            total_return: match (bars.first(), bars.last()) {
+                (Some(first), Some(last)) if first.close != 0.0 => last.close / first.close - 1.0,
+                _ => 0.0,
+            },
+            volatility: variance.sqrt(),
+            selected: false,
+        }
+    }
+
+    fn passes(&self, criteria: &ScreenCriteria) -> bool {
+        criteria.min_bars.is_none_or(|min| self.bars >= min)
+            && criteria
+                .min_average_volume
+                .is_none_or(|min| self.average_volume >= min)
+            && criteria
+                .min_return
+                .is_none_or(|min| self.total_return >= min)
+            && criteria
+                .max_volatility
+                .is_none_or(|max| self.volatility <= max)
+    }
+}
+
+// Marks the selected candidates, ties keep the order of the candidates
+pub fn screen(criteria: &ScreenCriteria, candidates: &mut [ScreenStats]) {
+    let mut passing: Vec<usize> = (0..candidates.len())
+        .filter(|&i| candidates[i].passes(criteria))
+        .collect();
+    let key = |stats: &ScreenStats| match criteria.rank_by {
+        ScreenRank::Return => -stats.total_return,
+        ScreenRank::AverageVolume => -stats.average_volume,
+        ScreenRank::Volatility => stats.volatility,
+    };
+    passing.sort_by(|&a, &b| key(&candidates[a]).total_cmp(&key(&candidates[b])));
+    for i in passing.into_iter().take(criteria.top.unwrap_or(usize::MAX)) {
+        candidates[i].selected = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn selects_the_top_passing_symbols() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let series = |closes: &[f64], volume: u64| -> Vec<OHLCVData> {
+            closes
+                .iter()
+                .enumerate()
+                .map(|(day, close)| OHLCVData {
+                    timestamp: start + Duration::days(day as i64),
+                    open: *close,
+                    high: *close,
+                    low: *close,
+                    close: *close,
+                    volume,
+                })
+                .collect()
+        };
+        let range = (start, start + Duration::days(10));
+        let mut candidates = vec![
+            ScreenStats::new("A".to_string(), &series(&[10.0, 11.0, 12.0], 100), range),
+            ScreenStats::new("B".to_string(), &series(&[10.0, 10.5, 11.0], 5000), range),
+            ScreenStats::new("C".to_string(), &series(&[10.0, 13.0, 15.0], 5000), range),
+            ScreenStats::new("D".to_string(), &series(&[10.0, 9.0, 8.0], 5000), range),
+        ];
+        assert!((candidates[0].total_return - 0.2).abs() < 1e-12);
+
+        let criteria = ScreenCriteria {
+            min_average_volume: Some(1000.0),
+            min_return: Some(0.0),
+            top: Some(1),
+            ..ScreenCriteria::default()
+        };
+        screen(&criteria, &mut candidates);
+        let selected: Vec<_> = candidates
+            .iter()
+            .filter(|stats| stats.selected)
+            .map(|stats| stats.symbol.as_str())
+            .collect();
+        assert_eq!(selected, ["C"]);
+    }
+}
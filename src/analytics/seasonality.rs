@@ -0,0 +1,312 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+// Recurring day the returns are lined up around, on the days with data
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SeasonalEvent {
+    // Last day of the month
+    MonthEnd,
+    // Last day of March, June, September and December
+    QuarterEnd,
+    // Third Friday of the month, or the day before it when it has no data
+    OptionsExpiry,
+    // First day on or after this day of the month
+    DayOfMonth(u32),
+}
+
+// Tables of `parameters.seasonality`
+#[derive(Deserialize, Debug, Clone)]
+pub struct SeasonalitySettings {
+    #[serde(default = "default_events")]
+    pub events: Vec<SeasonalEvent>,
+    // Days before and after the events, 3 by default
+    pub window: Option<usize>,
+}
+
+fn default_events() -> Vec<SeasonalEvent> {
+    vec![SeasonalEvent::MonthEnd, SeasonalEvent::OptionsExpiry]
+}
+
+impl SeasonalitySettings {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.window.is_some_and(|window| window > 31) {
+            return Err("The seasonality window is at most 31 days");
+        }
+        let day_of_month = |event: &SeasonalEvent| match event {
+            SeasonalEvent::DayOfMonth(day) => (1..=31).contains(day),
+            _ => true,
+        };
+        if !self.events.iter().all(day_of_month) {
+            return Err("The day of the month is between 1 and 31");
+        }
+        Ok(())
+    }
+}
+
+// Mean return of the periods sharing a key: a month, a day of the month or a day from an event
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SeasonalRow {
+    pub key: i64,
+    pub observations: usize,
+    // In percent
+    pub mean_return: f64,
+    pub positive_percent: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EventTable {
+    pub event: SeasonalEvent,
+    // Keyed by the offset in days with data, 0 being the return of the event day itself
+    pub rows: Vec<SeasonalRow>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SeasonalTables {
+    // Returns from the last close of the previous month, keyed 1 to 12
+    pub by_month: Vec<SeasonalRow>,
+    // Daily returns keyed by the day of the month
+    pub by_day_of_month: Vec<SeasonalRow>,
+    pub events: Vec<EventTable>,
+}
+
+// Seasonality of the traded symbol and of the equity of the strategy
+#[derive(Serialize, Debug, Clone)]
+pub struct Seasonality {
+    pub symbol: SeasonalTables,
+    pub strategy: SeasonalTables,
+}
+
+impl Seasonality {
+    // Both series are taken at the last value of each day of `timezone`
+    pub fn new(
+        prices: &[(NaiveDateTime, f64)],
+        equity_curve: &[(NaiveDateTime, f64)],
+        settings: &SeasonalitySettings,
+        timezone: Tz,
+    ) -> Self {
+        Seasonality {
+            symbol: tables(&daily(prices, timezone), settings),
+            strategy: tables(&daily(equity_curve, timezone), settings),
+        }
+    }
+}
+
+// Last value of each day, sorted by time
+fn daily(series: &[(NaiveDateTime, f64)], timezone: Tz) -> Vec<(NaiveDate, f64)> {
+    let mut days: Vec<(NaiveDate, f64)> = vec![];
+    for &(time, value) in series {
+        let date = time.and_utc().with_timezone(&timezone).date_naive();
+        match days.last_mut() {
+            Some(last) if last.0 == date => last.1 = value,
+            _ => days.push((date, value)),
+        }
+    }
+    days
+}
+
+fn tables(days: &[(NaiveDate, f64)], settings: &SeasonalitySettings) -> SeasonalTables {
+    // Return of each day from the previous one, None for the first day
+    let returns: Vec<Option<f64>> = (0..days.len())
+        .map(|i| {
+            let previous = days[i.checked_sub(1)?].1;
+            (previous != 0.0).then(|| days[i].1 / previous - 1.0)
+        })
+        .collect();
+
+    let mut by_month: Vec<Vec<f64>> = vec![vec![]; 12];
+    for (i, day) in days.iter().enumerate() {
+        if !ends_month(days, i) {
+            continue;
+        }
+        // From the close of the previous month, the first month of the data is left out
+        let previous = days[..i]
+            .iter()
+            .rev()
+            .find(|other| month(other.0) != month(day.0));
+        if let Some(previous) = previous.filter(|previous| previous.1 != 0.0) {
+            by_month[day.0.month0() as usize].push(day.1 / previous.1 - 1.0);
+        }
+    }
+
+    let mut by_day: Vec<Vec<f64>> = vec![vec![]; 31];
+    for (day, value) in days.iter().zip(&returns) {
+        if let Some(value) = value {
+            by_day[day.0.day0() as usize].push(*value);
+        }
+    }
+
+    let window = settings.window.unwrap_or(3) as i64;
+    let events = settings
+        .events
+        .iter()
+        .map(|&event| {
+            let mut offsets: Vec<Vec<f64>> = vec![vec![]; (2 * window + 1) as usize];
+            for index in event_days(days, event) {
+                for offset in -window..=window {
+                    let returned = usize::try_from(index as i64 + offset)
+                        .ok()
+                        .and_then(|i| returns.get(i).copied().flatten());
+                    if let Some(value) = returned {
+                        offsets[(offset + window) as usize].push(value);
+                    }
+                }
+            }
+            EventTable {
+                event,
+                rows: rows(offsets, -window),
+            }
+        })
+        .collect();
+
+    SeasonalTables {
+        by_month: rows(by_month, 1),
+        by_day_of_month: rows(by_day, 1),
+        events,
+    }
+}
+
+fn month(date: NaiveDate) -> (i32, u32) {
+    (date.year(), date.month())
+}
+
+// Last day with data of its month, known once the data goes on in the next month or covers its
+// last calendar day
+fn ends_month(days: &[(NaiveDate, f64)], i: usize) -> bool {
+    let date = days[i].0;
+    match days.get(i + 1) {
+        Some(next) => month(next.0) != month(date),
+        None => date
+            .succ_opt()
+            .is_some_and(|next| month(next) != month(date)),
+    }
+}
+
+// Indices of the event days, one per month at most
+fn event_days(days: &[(NaiveDate, f64)], event: SeasonalEvent) -> Vec<usize> {
+    let mut indices = vec![];
+    let mut start = 0;
+    while start < days.len() {
+        let (year, month_number) = month(days[start].0);
+        let end = start
+            + days[start..]
+                .iter()
+                .take_while(|day| month(day.0) == (year, month_number))
+                .count();
+        // The day of the event is only known when the data covers the days around it
+        let covers = |date: NaiveDate| {
+            (start > 0 || days[start].0 <= date) && days.last().is_some_and(|last| last.0 >= date)
+        };
+        let index = match event {
+            SeasonalEvent::MonthEnd => Some(end - 1).filter(|&i| ends_month(days, i)),
+            SeasonalEvent::QuarterEnd => Some(end - 1)
+                .filter(|&i| ends_month(days, i))
+                .filter(|_| month_number % 3 == 0),
+            SeasonalEvent::OptionsExpiry => {
+                let expiry =
+                    NaiveDate::from_weekday_of_month_opt(year, month_number, Weekday::Fri, 3)
+                        .filter(|expiry| covers(*expiry));
+                expiry.and_then(|expiry| {
+                    let before = days[start..end].partition_point(|day| day.0 <= expiry);
+                    before.checked_sub(1).map(|i| start + i)
+                })
+            }
+            SeasonalEvent::DayOfMonth(day) => {
+                let date = NaiveDate::from_ymd_opt(year, month_number, 1)
+                    .and_then(|first| first.with_day(day))
+                    .filter(|date| covers(*date));
+                date.and_then(|date| {
+                    let after = days[start..end].partition_point(|other| other.0 < date);
+                    (start + after < end).then_some(start + after)
+                })
+            }
+        };
+        indices.extend(index);
+        start = end;
+    }
+    indices
+}
+
+fn rows(groups: Vec<Vec<f64>>, first_key: i64) -> Vec<SeasonalRow> {
+    groups
+        .into_iter()
+        .enumerate()
+        .map(|(i, returns)| {
+            let observations = returns.len();
+            let (mean_return, positive_percent) = match observations {
+                0 => (0.0, 0.0),
+                n => (
+                    returns.iter().sum::<f64>() / n as f64 * 100.0,
+                    returns.iter().filter(|value| **value > 0.0).count() as f64 / n as f64 * 100.0,
+                ),
+            };
+            SeasonalRow {
+                key: first_key + i as i64,
+                observations,
+                mean_return,
+                positive_percent,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(from: &str, values: &[f64]) -> Vec<(NaiveDateTime, f64)> {
+        let start = NaiveDateTime::parse_from_str(from, "%Y-%m-%d %H:%M:%S").expect("Invalid date");
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (start + chrono::Duration::days(i as i64), *value))
+            .collect()
+    }
+
+    #[test]
+    fn lines_the_returns_up_by_month_day_and_event() {
+        // Daily from January 30th to March 1st 2024, up 1% on every day but the month ends
+        let mut values = vec![100.0];
+        for i in 1..32 {
+            let previous = values[i - 1];
+            let month_end = i == 1 || i == 30;
+            values.push(previous * if month_end { 0.98 } else { 1.01 });
+        }
+        let prices = series("2024-01-30 16:00:00", &values);
+        let settings = SeasonalitySettings {
+            events: vec![
+                SeasonalEvent::MonthEnd,
+                SeasonalEvent::OptionsExpiry,
+                SeasonalEvent::DayOfMonth(10),
+            ],
+            window: Some(1),
+        };
+        let tables = Seasonality::new(&prices, &prices, &settings, chrono_tz::UTC).symbol;
+
+        // February is the only complete month, from the close of January 31st to February 29th
+        let february = &tables.by_month[1];
+        assert_eq!(february.observations, 1);
+        let expected = (1.01f64.powi(28) * 0.98 - 1.0) * 100.0;
+        assert!((february.mean_return - expected).abs() < 1e-9);
+        assert_eq!(tables.by_month[0].observations, 0);
+
+        // The 31st only falls in January, the 1st both in February and March
+        assert!((tables.by_day_of_month[30].mean_return + 2.0).abs() < 1e-9);
+        assert_eq!(tables.by_day_of_month[0].observations, 2);
+
+        // January 31st and February 29th, the day after the last one is March 1st
+        let month_end = &tables.events[0].rows;
+        assert_eq!(
+            month_end.iter().map(|row| row.key).collect::<Vec<_>>(),
+            [-1, 0, 1]
+        );
+        assert_eq!(month_end[1].observations, 2);
+        assert!((month_end[1].mean_return + 2.0).abs() < 1e-9);
+        assert_eq!(month_end[1].positive_percent, 0.0);
+        assert_eq!(month_end[2].positive_percent, 100.0);
+        // Only the expiry on Friday February 16th, January's is before the data and March's after it
+        assert_eq!(tables.events[1].rows[1].observations, 1);
+        assert_eq!(tables.events[2].rows[1].observations, 1);
+    }
+}
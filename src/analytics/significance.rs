@@ -0,0 +1,149 @@
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PairedTest {
+    pub observations: usize,
+    pub mean_difference: f64,
+    pub t_statistic: f64,
+    // Two-sided, from the Student's t distribution
+    pub p_value: f64,
+}
+
+// Paired t-test on the differences of two series observed at the same times
+pub fn paired_t_test(a: &[f64], b: &[f64]) -> Option<PairedTest> {
+    let differences: Vec<f64> = a.iter().zip(b).map(|(a, b)| a - b).collect();
+    let n = differences.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mean = differences.iter().sum::<f64>() / n as f64;
+    let variance = differences.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    let degrees_of_freedom = (n - 1) as f64;
+
+    let (t_statistic, p_value) = if variance == 0.0 {
+        // Identical series can't be told apart, any constant offset is certain
+        match mean == 0.0 {
+            true => (0.0, 1.0),
+            false => (mean.signum() * f64::INFINITY, 0.0),
+        }
+    } else {
+        let t = mean / (variance / n as f64).sqrt();
+        let x = degrees_of_freedom / (degrees_of_freedom + t * t);
+        (t, incomplete_beta(x, degrees_of_freedom / 2.0, 0.5))
+    };
+
+    Some(PairedTest {
+        observations: n,
+        mean_difference: mean,
+        t_statistic,
+        p_value,
+    })
+}
+
+// Simple returns between consecutive values
+pub fn returns(values: &[f64]) -> Vec<f64> {
+    values
+        .windows(2)
+        .map(|w| if w[0] != 0.0 { w[1] / w[0] - 1.0 } else { 0.0 })
+        .collect()
+}
+
+// Regularized incomplete beta function I_x(a, b), continued fraction from Numerical Recipes
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=200 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let even = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + even * d;
+        d = if d.abs() < TINY { 1.0 / TINY } else { 1.0 / d };
+        c = 1.0 + even / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        h *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + odd * d;
+        d = if d.abs() < TINY { 1.0 / TINY } else { 1.0 / d };
+        c = 1.0 + odd / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+// Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate() {
+        series += coefficient / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paired_t_test_matches_reference() {
+        // Differences [1, 2, 3, 4, 5, 4]: t = 5.2697, df = 5, two-sided p = 0.00327
+        let a = [2.0, 4.0, 6.0, 8.0, 10.0, 9.0];
+        let b = [1.0, 2.0, 3.0, 4.0, 5.0, 5.0];
+        let test = paired_t_test(&a, &b).expect("Not enough observations");
+
+        assert_eq!(test.observations, 6);
+        assert!((test.t_statistic - 5.2697).abs() < 1e-3);
+        assert!((test.p_value - 0.00327).abs() < 1e-4);
+
+        let same = paired_t_test(&a, &a).expect("Not enough observations");
+        assert_eq!(same.p_value, 1.0);
+    }
+}
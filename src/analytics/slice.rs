@@ -0,0 +1,156 @@
+use super::inflation::Inflation;
+use super::metrics::{self, GlobalMetrics, MetricsInputs};
+use super::plugin::MetricRegistry;
+use super::trade::Trade;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Named part of the range reported on its own, e.g. the in-sample and out-of-sample periods
+#[derive(Deserialize, Debug, Clone)]
+pub struct Slice {
+    pub name: String,
+    pub start: NaiveDateTime,
+    // Included like the end of the range
+    pub end: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SliceReport {
+    pub name: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    // Equity at the start of the slice, what its returns are measured against
+    pub start_equity: f64,
+    pub metrics: GlobalMetrics,
+}
+
+pub fn validate(slices: &[Slice]) -> Result<(), &'static str> {
+    for (i, slice) in slices.iter().enumerate() {
+        if slice.name.is_empty() {
+            return Err("A slice needs a name");
+        }
+        if slice.start > slice.end {
+            return Err("A slice can't end before it starts");
+        }
+        if slices[..i].iter().any(|other| other.name == slice.name) {
+            return Err("The names of the slices must be unique");
+        }
+    }
+    Ok(())
+}
+
+impl Slice {
+    // Metrics of the trades closed and the equity within the slice. The equity at its end stands
+    // for the cash, the fees and slippage are those of its trades and the other broker totals of
+    // the run (orders, carry, cash yield, buy and hold) are left at zero
+    pub fn report(
+        &self,
+        trades: &[Trade],
+        equity_curve: &[(NaiveDateTime, f64)],
+        risk_free_rate: f64,
+        inflation: Option<&Inflation>,
+        plugins: &MetricRegistry,
+    ) -> SliceReport {
+        let first = equity_curve.partition_point(|(time, _)| *time < self.start);
+        let last = equity_curve.partition_point(|(time, _)| *time <= self.end);
+        let curve = &equity_curve[first..last.max(first)];
+        // From the close before the slice when there is one
+        let start_equity = first
+            .checked_sub(1)
+            .and_then(|before| equity_curve.get(before))
+            .or(curve.first())
+            .map_or(0.0, |(_, equity)| *equity);
+        let end_equity = curve.last().map_or(start_equity, |(_, equity)| *equity);
+
+        let trades: Vec<Trade> = trades
+            .iter()
+            .filter(|trade| {
+                trade
+                    .exit_time
+                    .is_some_and(|exit| exit >= self.start && exit <= self.end)
+            })
+            .cloned()
+            .collect();
+        let inputs = MetricsInputs {
+            initial_capital: start_equity,
+            risk_free_rate,
+            cash: end_equity,
+            total_fees: trades.iter().map(|t| t.entry_fees + t.exit_fees).sum(),
+            total_slippage: trades
+                .iter()
+                .map(|t| t.entry_slippage + t.exit_slippage)
+                .sum(),
+            inflation: inflation.cloned(),
+            ..MetricsInputs::default()
+        };
+        let (metrics, _) = metrics::compute(&trades, curve, &inputs, plugins);
+
+        SliceReport {
+            name: self.name.clone(),
+            start: self.start,
+            end: self.end,
+            start_equity,
+            metrics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::trade::TradeDirection;
+    use chrono::Duration;
+
+    #[test]
+    fn measures_each_slice_from_its_own_start() {
+        let start = NaiveDateTime::parse_from_str("2020-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let day = |days: i64| start + Duration::days(days);
+        // Up 10% over the first 10 days, down to 99 over the next 10
+        let curve: Vec<_> = (0..=20)
+            .map(|i| (day(i), if i <= 10 { 100.0 + i as f64 } else { 99.0 }))
+            .collect();
+        let trade = |exit: i64, profit_loss: f64| Trade {
+            exit_time: Some(day(exit)),
+            profit_loss: Some(profit_loss),
+            ..Trade::new(
+                1,
+                "AAPL".to_string(),
+                day(0),
+                100.0,
+                1.0,
+                0.0,
+                0.0,
+                None,
+                TradeDirection::Long,
+            )
+        };
+        let trades = [trade(5, 10.0), trade(15, -11.0)];
+        let slices = [
+            Slice {
+                name: "IS".to_string(),
+                start: day(0),
+                end: day(10),
+            },
+            Slice {
+                name: "OOS".to_string(),
+                start: day(11),
+                end: day(20),
+            },
+        ];
+        assert!(validate(&slices).is_ok());
+
+        let registry = MetricRegistry::default();
+        let reports: Vec<_> = slices
+            .iter()
+            .map(|slice| slice.report(&trades, &curve, 0.0, None, &registry))
+            .collect();
+        assert_eq!(reports[0].start_equity, 100.0);
+        assert!((reports[0].metrics.roi - 10.0).abs() < 1e-9);
+        assert_eq!(reports[0].metrics.winning_trades, 1);
+        // The out-of-sample slice starts from the close before it
+        assert_eq!(reports[1].start_equity, 110.0);
+        assert!((reports[1].metrics.roi + 10.0).abs() < 1e-9);
+        assert_eq!(reports[1].metrics.losing_trades, 1);
+    }
+}
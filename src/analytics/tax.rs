@@ -0,0 +1,141 @@
+use crate::analytics::{locale::ReportLocale, trade::Trade};
+use chrono::{Months, NaiveDateTime};
+
+// Realized gain of a closed lot, fees are part of the cost basis and the proceeds
+pub struct RealizedGain {
+    pub asset: String,
+    pub quantity: f64,
+    pub acquired: NaiveDateTime,
+    pub disposed: NaiveDateTime,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub gain: f64,
+    pub long_term: bool,
+}
+
+// Open trades aren't realized and are left out, a lot is long term when held for more than `long_term_months`
+pub fn realized_gains(trades: &[Trade], long_term_months: u32) -> Vec<RealizedGain> {
+    let mut gains: Vec<RealizedGain> = trades
+        .iter()
+        .filter_map(|trade| {
+            let (disposed, exit_price) = (trade.exit_time?, trade.exit_price?);
+            let proceeds = exit_price * trade.quantity - trade.exit_fees;
+            let cost_basis = trade.entry_price * trade.quantity + trade.entry_fees;
+            let long_term = trade
+                .entry_time
+                .checked_add_months(Months::new(long_term_months))
+                .is_some_and(|threshold| disposed > threshold);
+
+            Some(RealizedGain {
+                asset: trade.asset.clone(),
+                quantity: trade.quantity,
+                acquired: trade.entry_time,
+                disposed,
+                proceeds,
+                cost_basis,
+                gain: proceeds - cost_basis,
+                long_term,
+            })
+        })
+        .collect();
+
+    gains.sort_by_key(|gain| (gain.disposed, gain.acquired));
+    gains
+}
+
+pub fn to_csv(gains: &[RealizedGain], locale: &ReportLocale) -> String {
+    let separator = locale.separator();
+    let row = |fields: &[String]| {
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|field| escape(field, separator))
+            .collect();
+        fields.join(&separator.to_string()) + "\n"
+    };
+
+    let header = [
+        "description",
+        "asset",
+        "quantity",
+        "date_acquired",
+        "date_disposed",
+        "proceeds",
+        "cost_basis",
+        "gain",
+        "term",
+    ];
+    let mut csv = row(&header.map(str::to_string));
+
+    for gain in gains {
+        csv.push_str(&row(&[
+            format!("{} {}", locale.number(gain.quantity), gain.asset),
+            gain.asset.clone(),
+            locale.number(gain.quantity),
+            locale.date(gain.acquired),
+            locale.date(gain.disposed),
+            locale.amount(gain.proceeds),
+            locale.amount(gain.cost_basis),
+            locale.amount(gain.gain),
+            if gain.long_term { "long" } else { "short" }.to_string(),
+        ]));
+    }
+
+    csv
+}
+
+fn escape(field: &str, separator: char) -> String {
+    if field.contains([separator, '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::trade::TradeDirection;
+
+    fn date(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").expect("Invalid date")
+    }
+
+    fn closed_trade(entry: &str, exit: &str) -> Trade {
+        let mut trade = Trade::new(
+            1,
+            "AAPL".to_string(),
+            date(entry),
+            100.0,
+            10.0,
+            1.0,
+            0.0,
+            None,
+            TradeDirection::Long,
+        );
+        trade.close(date(exit), 110.0, 1.0, 0.0, None, 1.0);
+        trade
+    }
+
+    #[test]
+    fn long_term_after_holding_period() {
+        let trades = [
+            closed_trade("2023-01-10 00:00:00", "2024-01-10 00:00:00"),
+            closed_trade("2023-01-10 00:00:00", "2024-01-11 00:00:00"),
+        ];
+        let gains = realized_gains(&trades, 12);
+
+        assert!(!gains[0].long_term);
+        assert!(gains[1].long_term);
+        assert_eq!(gains[0].proceeds, 1099.0);
+        assert_eq!(gains[0].cost_basis, 1001.0);
+        assert_eq!(gains[0].gain, 98.0);
+        assert_eq!(
+            to_csv(&gains[..1], &ReportLocale::default()).lines().nth(1),
+            Some("10 AAPL,AAPL,10,2023-01-10,2024-01-10,1099.00,1001.00,98.00,short")
+        );
+        assert_eq!(
+            to_csv(&gains[..1], &ReportLocale::preset("fr-FR").unwrap()).lines().nth(1),
+            Some("10 AAPL;AAPL;10;10/01/2023;10/01/2024;1\u{202F}099,00 €;1\u{202F}001,00 €;98,00 €;short")
+        );
+    }
+}
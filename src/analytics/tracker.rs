@@ -1,4 +1,6 @@
-use super::trade::{Trade, TradeDirection};
+use super::trade::{ForcedExit, Trade, TradeDirection};
+use crate::broker::order::PartialFill;
+use crate::broker::position::DEFAULT_DUST_THRESHOLD;
 use chrono::NaiveDateTime;
 use std::collections::HashMap;
 
@@ -10,6 +12,16 @@ pub struct TradeTracker {
     pub initial_capital: f64,
     pub total_fees: f64,
     pub total_slippage: f64,
+    pub total_price_improvement: f64,
+    // Swaps and funding received (positive) or paid on open positions
+    pub total_carry: f64,
+    // Yield of the swept cash
+    pub total_cash_yield: f64,
+    // Open trades left with less than this quantity are closed with the sell
+    dust_threshold: f64,
+    // Orders filled over several bars by `broker.volume_share`, each fill opening or closing
+    // trades as usual
+    pub partial_fills: Vec<PartialFill>,
 }
 
 impl TradeTracker {
@@ -22,6 +34,11 @@ impl TradeTracker {
             initial_capital: 0.0,
             total_fees: 0.0,
             total_slippage: 0.0,
+            total_price_improvement: 0.0,
+            total_carry: 0.0,
+            total_cash_yield: 0.0,
+            dust_threshold: DEFAULT_DUST_THRESHOLD,
+            partial_fills: Vec::new(),
         }
     }
 
@@ -29,6 +46,13 @@ impl TradeTracker {
         self.initial_capital = capital;
     }
 
+    pub fn set_dust_threshold(&mut self, threshold: f64) {
+        self.dust_threshold = threshold;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    // `slippage` is the per unit cost of the slippage (negative when it favored the fill) and
+    // `point_value` converts it to the account currency for the trade
     pub fn record_buy(
         &mut self,
         asset: &str,
@@ -37,9 +61,12 @@ impl TradeTracker {
         quantity: f64,
         fees: f64,
         slippage: f64,
+        price_improvement: Option<f64>,
+        point_value: f64,
     ) {
         self.total_fees += fees;
-        self.total_slippage += slippage * quantity;
+        self.total_slippage += slippage.abs() * quantity;
+        self.total_price_improvement += price_improvement.unwrap_or(0.0) * quantity;
 
         let trade = Trade::new(
             self.next_trade_id,
@@ -48,7 +75,8 @@ impl TradeTracker {
             price,
             quantity,
             fees,
-            slippage,
+            slippage * quantity * point_value,
+            price_improvement,
             TradeDirection::Long,
         );
 
@@ -60,6 +88,7 @@ impl TradeTracker {
             .push(trade);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn record_sell(
         &mut self,
         asset: &str,
@@ -68,9 +97,12 @@ impl TradeTracker {
         quantity: f64,
         fees: f64,
         slippage: f64,
+        price_improvement: Option<f64>,
+        point_value: f64,
     ) {
         self.total_fees += fees;
-        self.total_slippage += slippage * quantity;
+        self.total_slippage += slippage.abs() * quantity;
+        self.total_price_improvement += price_improvement.unwrap_or(0.0) * quantity;
 
         let open_positions = match self.open_trades.get_mut(asset) {
             Some(positions) => positions,
@@ -82,36 +114,46 @@ impl TradeTracker {
         let mut trades_to_close = Vec::new();
 
         for (idx, trade) in open_positions.iter_mut().enumerate() {
-            if remaining_quantity <= 0.0 {
+            if remaining_quantity <= self.dust_threshold {
                 break;
             }
 
             let quantity_to_close = remaining_quantity.min(trade.quantity);
             let fee_proportion = quantity_to_close / quantity;
 
-            if quantity_to_close >= trade.quantity {
+            let exit_slippage = slippage * quantity_to_close * point_value;
+
+            if quantity_to_close >= trade.quantity - self.dust_threshold {
                 trade.close(
                     time,
                     price,
                     total_fees * fee_proportion,
-                    slippage * fee_proportion,
+                    exit_slippage,
+                    price_improvement,
+                    point_value,
                 );
                 trades_to_close.push(idx);
             } else {
                 let mut closed_trade = trade.clone();
                 closed_trade.quantity = quantity_to_close;
-                let closed_entry_fees = trade.entry_fees * (quantity_to_close / trade.quantity);
+                let closed_share = quantity_to_close / trade.quantity;
+                let closed_entry_fees = trade.entry_fees * closed_share;
+                let closed_entry_slippage = trade.entry_slippage * closed_share;
                 closed_trade.entry_fees = closed_entry_fees;
+                closed_trade.entry_slippage = closed_entry_slippage;
                 closed_trade.close(
                     time,
                     price,
                     total_fees * fee_proportion,
-                    slippage * fee_proportion,
+                    exit_slippage,
+                    price_improvement,
+                    point_value,
                 );
                 self.closed_trades.push(closed_trade);
 
                 trade.quantity -= quantity_to_close;
                 trade.entry_fees -= closed_entry_fees;
+                trade.entry_slippage -= closed_entry_slippage;
             }
 
             remaining_quantity -= quantity_to_close;
@@ -127,14 +169,48 @@ impl TradeTracker {
         }
     }
 
+    pub fn record_partial_fill(&mut self, fill: PartialFill) {
+        self.partial_fills.push(fill);
+    }
+
+    // Marks the trades of `asset` closed at `time` as closed by the broker
+    pub fn mark_forced_exits(&mut self, asset: &str, time: &NaiveDateTime, reason: ForcedExit) {
+        for trade in self.closed_trades.iter_mut().rev() {
+            if trade.exit_time.is_some_and(|exit| exit < *time) {
+                break;
+            }
+            if trade.asset == asset && trade.exit_time == Some(*time) {
+                trade.forced_exit = Some(reason);
+            }
+        }
+    }
+
+    // Protective stop of the open trades of `asset` that don't have one yet
+    pub fn attach_stop(&mut self, asset: &str, stop_price: f64) {
+        for trade in self.open_trades.get_mut(asset).into_iter().flatten() {
+            trade.attach_stop(stop_price);
+        }
+    }
+
     pub fn record_equity_snapshot(&mut self, time: NaiveDateTime, total_value: f64) {
         self.equity_curve.push((time, total_value));
     }
 
+    // Replaces the value of the last snapshot, after a change at its time
+    pub fn revise_last_snapshot(&mut self, total_value: f64) {
+        if let Some((_, value)) = self.equity_curve.last_mut() {
+            *value = total_value;
+        }
+    }
+
     pub fn get_closed_trades(&self) -> &[Trade] {
         &self.closed_trades
     }
 
+    pub fn get_open_trades(&self) -> impl Iterator<Item = &Trade> {
+        self.open_trades.values().flatten()
+    }
+
     pub fn get_equity_curve(&self) -> &[(NaiveDateTime, f64)] {
         &self.equity_curve
     }
@@ -1,13 +1,14 @@
+use crate::data::OHLCVData;
 use chrono::NaiveDateTime;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TradeDirection {
     Long,
 }
 
 // Complete order (buy + sell)
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
     pub id: u64,
     pub asset: String,
@@ -15,14 +16,57 @@ pub struct Trade {
     pub entry_price: f64,
     pub quantity: f64,
     pub entry_fees: f64,
+    // Cost of the slippage of the fills in the account currency
     pub entry_slippage: f64,
+    // Per unit, against the limit/stop price, None for market orders
+    pub entry_price_improvement: Option<f64>,
     pub exit_time: Option<NaiveDateTime>,
     pub exit_price: Option<f64>,
     pub exit_fees: f64,
     pub exit_slippage: f64,
+    pub exit_price_improvement: Option<f64>,
+    // Net of the fees and slippage
     pub profit_loss: Option<f64>,
+    // Before any cost, what the trade made at the quoted prices
+    #[serde(default)]
+    pub gross_profit_loss: Option<f64>,
+    #[serde(default)]
+    pub total_costs: Option<f64>,
     pub return_pct: Option<f64>,
     pub direction: TradeDirection,
+    // Price of the first protective stop attached while the trade was open, and its distance
+    // below the entry per unit
+    #[serde(default)]
+    pub initial_stop: Option<f64>,
+    #[serde(default)]
+    pub initial_stop_distance: Option<f64>,
+    // Profit or loss in multiples of the initial risk, when a stop was attached below the entry
+    #[serde(default)]
+    pub r_multiple: Option<f64>,
+    // Bars closed after the entry up to the exit
+    #[serde(default)]
+    pub bars_held: Option<usize>,
+    // Percent of the range traded over the holding window left above the entry and below the
+    // exit, 100 for an entry at the low and an exit at the high
+    #[serde(default)]
+    pub entry_efficiency: Option<f64>,
+    #[serde(default)]
+    pub exit_efficiency: Option<f64>,
+    // Set when the broker closed the trade instead of the strategy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forced_exit: Option<ForcedExit>,
+}
+
+// Why the broker sold a position on its own
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForcedExit {
+    // Margin call of a leveraged position
+    Liquidation,
+    // Daily flattening of `broker.flatten`
+    Flatten,
+    // Sale of the open positions with `parameters.liquidate_at_end`
+    EndOfRun,
 }
 
 impl Trade {
@@ -35,6 +79,7 @@ impl Trade {
         quantity: f64,
         entry_fees: f64,
         entry_slippage: f64,
+        entry_price_improvement: Option<f64>,
         direction: TradeDirection,
     ) -> Self {
         Trade {
@@ -45,13 +90,31 @@ impl Trade {
             quantity,
             entry_fees,
             entry_slippage,
+            entry_price_improvement,
             exit_time: None,
             exit_price: None,
             exit_fees: 0.0,
             exit_slippage: 0.0,
+            exit_price_improvement: None,
             profit_loss: None,
+            gross_profit_loss: None,
+            total_costs: None,
             return_pct: None,
             direction,
+            initial_stop: None,
+            initial_stop_distance: None,
+            r_multiple: None,
+            bars_held: None,
+            entry_efficiency: None,
+            exit_efficiency: None,
+            forced_exit: None,
+        }
+    }
+
+    pub fn attach_stop(&mut self, stop_price: f64) {
+        if self.initial_stop.is_none() {
+            self.initial_stop = Some(stop_price);
+            self.initial_stop_distance = Some(self.entry_price - stop_price);
         }
     }
 
@@ -61,14 +124,18 @@ impl Trade {
         exit_price: f64,
         exit_fees: f64,
         exit_slippage: f64,
+        exit_price_improvement: Option<f64>,
+        // Account currency value of a one point move per unit, 1 for spot assets in the account currency
+        point_value: f64,
     ) {
         self.exit_time = Some(exit_time);
         self.exit_price = Some(exit_price);
         self.exit_fees = exit_fees;
         self.exit_slippage = exit_slippage;
+        self.exit_price_improvement = exit_price_improvement;
 
-        let entry_cost = self.entry_price * self.quantity + self.entry_fees;
-        let exit_value = exit_price * self.quantity - exit_fees;
+        let entry_cost = self.entry_price * self.quantity * point_value + self.entry_fees;
+        let exit_value = exit_price * self.quantity * point_value - exit_fees;
 
         match self.direction {
             TradeDirection::Long => {
@@ -78,6 +145,106 @@ impl Trade {
 
         if let Some(pl) = self.profit_loss {
             self.return_pct = Some((pl / entry_cost) * 100.0);
+
+            let costs = self.entry_fees + self.exit_fees + self.entry_slippage + self.exit_slippage;
+            self.total_costs = Some(costs);
+            self.gross_profit_loss = Some(pl + costs);
+
+            let risk = self
+                .initial_stop_distance
+                .map(|distance| distance * self.quantity * point_value)
+                .filter(|risk| *risk > 0.0);
+            self.r_multiple = risk.map(|risk| pl / risk);
         }
     }
 }
+
+// Fills `bars_held` of the closed trades, `data` being sorted by time
+pub fn count_bars_held(trades: &mut [Trade], data: &[OHLCVData]) {
+    for trade in trades {
+        let Some(exit_time) = trade.exit_time else {
+            continue;
+        };
+        let after_entry = data.partition_point(|bar| bar.timestamp <= trade.entry_time);
+        let until_exit = data.partition_point(|bar| bar.timestamp <= exit_time);
+        trade.bars_held = Some(until_exit.saturating_sub(after_entry));
+    }
+}
+
+// Fills the entry and exit efficiencies of the closed trades of `symbol` from the bars from the
+// entry to the exit, `data` being sorted by time. Trades of other assets are left out
+pub fn measure_efficiency(trades: &mut [Trade], data: &[OHLCVData], symbol: Option<&str>) {
+    for trade in trades {
+        let (Some(exit_time), Some(exit_price)) = (trade.exit_time, trade.exit_price) else {
+            continue;
+        };
+        if symbol.is_some_and(|symbol| symbol != trade.asset) {
+            continue;
+        }
+        // From the bar the entry was filled on
+        let first = data
+            .partition_point(|bar| bar.timestamp <= trade.entry_time)
+            .saturating_sub(1);
+        let last = data.partition_point(|bar| bar.timestamp <= exit_time);
+        let Some(window) = data.get(first..last).filter(|window| !window.is_empty()) else {
+            continue;
+        };
+        let high = window.iter().map(|bar| bar.high).fold(f64::MIN, f64::max);
+        let low = window.iter().map(|bar| bar.low).fold(f64::MAX, f64::min);
+        let range = high - low;
+        if range <= 0.0 {
+            continue;
+        }
+        match trade.direction {
+            TradeDirection::Long => {
+                trade.entry_efficiency = Some((high - trade.entry_price) / range * 100.0);
+                trade.exit_efficiency = Some((exit_price - low) / range * 100.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn measures_the_entry_and_exit_within_the_range_held() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bars: Vec<_> = [(100.0, 95.0), (110.0, 90.0), (105.0, 100.0), (200.0, 50.0)]
+            .iter()
+            .enumerate()
+            .map(|(day, &(high, low))| OHLCVData {
+                timestamp: start + Duration::days(day as i64),
+                open: low,
+                high,
+                low,
+                close: high,
+                volume: 1000,
+            })
+            .collect();
+        let mut trade = Trade::new(
+            1,
+            "AAPL".to_string(),
+            start,
+            95.0,
+            1.0,
+            0.0,
+            0.0,
+            None,
+            TradeDirection::Long,
+        );
+        trade.close(start + Duration::days(2), 105.0, 0.0, 0.0, None, 1.0);
+        let mut other = trade.clone();
+        other.asset = "MSFT".to_string();
+        let mut trades = [trade, other];
+
+        measure_efficiency(&mut trades, &bars, Some("AAPL"));
+        // Held over a range from 90 to 110, the last bar is after the exit
+        assert_eq!(trades[0].entry_efficiency, Some(75.0));
+        assert_eq!(trades[0].exit_efficiency, Some(75.0));
+        assert_eq!(trades[1].entry_efficiency, None);
+    }
+}
@@ -0,0 +1,82 @@
+use super::position::Position;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Capital of one strategy of a multi-strategy run. It trades through the broker like the others,
+// its cash and positions are its share of the consolidated account
+pub struct SubAccount {
+    pub name: String,
+    pub cash: f64,
+    pub portfolio: HashMap<String, Position>,
+    initial_cash: f64,
+    fees: f64,
+    executed_orders: u32,
+    peak_equity: f64,
+    // In percent of the peak
+    max_drawdown: f64,
+}
+
+// Analytics of a sub-account, the metrics of the run are those of the consolidated account
+#[derive(Serialize, Debug, Clone)]
+pub struct SubAccountReport {
+    pub name: String,
+    pub initial_cash: f64,
+    pub cash: f64,
+    // Positions valued at the last close
+    pub equity: f64,
+    pub net_profit: f64,
+    // In percent
+    pub roi: f64,
+    pub fees: f64,
+    pub executed_orders: u32,
+    pub max_drawdown: f64,
+}
+
+impl SubAccount {
+    pub fn new(name: String, cash: f64) -> Self {
+        SubAccount {
+            name,
+            cash,
+            portfolio: HashMap::new(),
+            initial_cash: cash,
+            fees: 0.0,
+            executed_orders: 0,
+            peak_equity: cash,
+            max_drawdown: 0.0,
+        }
+    }
+
+    // Value of the positions of a cash settled asset at `price`
+    pub fn equity(&self, price: f64, point_value: f64) -> f64 {
+        let quantity: f64 = self.portfolio.values().map(|p| p.quantity).sum();
+        self.cash + quantity * price * point_value
+    }
+
+    pub fn record_fill(&mut self, fees: f64) {
+        self.fees += fees;
+        self.executed_orders += 1;
+    }
+
+    pub fn record_equity(&mut self, equity: f64) {
+        self.peak_equity = self.peak_equity.max(equity);
+        if self.peak_equity > 0.0 {
+            let drawdown = (self.peak_equity - equity) / self.peak_equity * 100.0;
+            self.max_drawdown = self.max_drawdown.max(drawdown);
+        }
+    }
+
+    pub fn report(&self, equity: f64) -> SubAccountReport {
+        let net_profit = equity - self.initial_cash;
+        SubAccountReport {
+            name: self.name.clone(),
+            initial_cash: self.initial_cash,
+            cash: self.cash,
+            equity,
+            net_profit,
+            roi: net_profit / self.initial_cash * 100.0,
+            fees: self.fees,
+            executed_orders: self.executed_orders,
+            max_drawdown: self.max_drawdown,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::analytics::execution::implementation_shortfall;
+use crate::broker::order::{Order, OrderDirection};
+use crate::data::OHLCVData;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Slices a parent order into child orders, at most one child per bar. Children keep the parent
+// order type so a limit parent only fills its slices when the limit is reached
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExecutionAlgo {
+    // Equal slices spread over `duration_seconds`, late slices catch up on the next bar
+    Twap { duration_seconds: i64, slices: u32 },
+    // A share of each bar volume until the parent is filled
+    Vwap { participation: f64 },
+    // Only `visible` units are shown to the market at a time
+    Iceberg { visible: f64 },
+}
+
+impl ExecutionAlgo {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match *self {
+            ExecutionAlgo::Twap {
+                duration_seconds,
+                slices,
+            } if duration_seconds <= 0 || slices == 0 => Err("Invalid TWAP schedule"),
+            ExecutionAlgo::Vwap { participation }
+                if !(participation > 0.0 && participation <= 1.0) =>
+            {
+                Err("The VWAP participation must be in (0, 1]")
+            }
+            ExecutionAlgo::Iceberg { visible } if !(visible.is_finite() && visible > 0.0) => {
+                Err("Invalid iceberg visible size")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParentStatus {
+    Active,
+    Filled,
+    Expired,
+    Cancelled,
+}
+
+pub struct ParentOrder {
+    pub id: u64,
+    pub order: Order,
+    // Quantity of the order, resolved on the first bar for cash and equity sizes
+    pub size: f64,
+    pub algo: ExecutionAlgo,
+    pub status: ParentStatus,
+    pub filled: f64,
+    pub children: u32,
+    // Quote currency value of the fills and account currency fees
+    pub notional: f64,
+    pub fees: f64,
+    // Open of the first bar the parent traded on, the decision price of the shortfall
+    pub arrival: Option<(NaiveDateTime, f64)>,
+    pub last_bar: Option<NaiveDateTime>,
+    pub last_price: f64,
+}
+
+impl ParentOrder {
+    pub fn new(id: u64, order: Order, algo: ExecutionAlgo) -> Self {
+        ParentOrder {
+            id,
+            size: order.size.fixed().unwrap_or(0.0),
+            order,
+            algo,
+            status: ParentStatus::Active,
+            filled: 0.0,
+            children: 0,
+            notional: 0.0,
+            fees: 0.0,
+            arrival: None,
+            last_bar: None,
+            last_price: 0.0,
+        }
+    }
+
+    pub fn remaining(&self) -> f64 {
+        (self.size - self.filled).max(0.0)
+    }
+
+    // Size of the next child on this bar
+    pub fn child_size(&self, current_time: &NaiveDateTime, bar: &OHLCVData) -> f64 {
+        let remaining = self.remaining();
+        let size = match self.algo {
+            ExecutionAlgo::Twap {
+                duration_seconds,
+                slices,
+            } => {
+                let start = self.arrival.map(|(time, _)| time).unwrap_or(*current_time);
+                let elapsed = (*current_time - start).num_seconds().max(0);
+                let due = (elapsed * slices as i64 / duration_seconds + 1).min(slices as i64);
+                self.size * due as f64 / slices as f64 - self.filled
+            }
+            ExecutionAlgo::Vwap { participation } => participation * bar.volume as f64,
+            ExecutionAlgo::Iceberg { visible } => visible,
+        };
+        size.clamp(0.0, remaining)
+    }
+
+    pub fn record_fill(&mut self, size: f64, price: f64, fees: f64) {
+        self.filled += size;
+        self.notional += size * price;
+        self.fees += fees;
+        self.children += 1;
+        // Float sums of the slices can fall a hair short of the parent size
+        if self.remaining() <= self.size * 1e-9 {
+            self.status = ParentStatus::Filled;
+        }
+    }
+
+    pub fn report(&self, point_value: f64) -> AlgoOrderReport {
+        let arrival_price = self.arrival.map(|(_, price)| price);
+        let average_price = (self.filled > 0.0).then(|| self.notional / self.filled);
+        let arrival_value = arrival_price.map(|arrival| arrival * self.size * point_value);
+
+        let shortfall = arrival_price.map(|arrival| {
+            implementation_shortfall(
+                &self.order.direction,
+                arrival,
+                self.filled,
+                self.notional,
+                self.remaining(),
+                self.last_price,
+                self.fees,
+                point_value,
+            )
+        });
+        let shortfall_bps = shortfall
+            .zip(arrival_value)
+            .and_then(|(shortfall, value)| (value > 0.0).then(|| shortfall / value * 10_000.0));
+
+        AlgoOrderReport {
+            id: self.id,
+            asset: self.order.asset.clone(),
+            direction: self.order.direction.clone(),
+            algo: self.algo,
+            status: self.status,
+            size: self.size,
+            filled: self.filled,
+            children: self.children,
+            arrival_time: self.arrival.map(|(time, _)| time),
+            arrival_price,
+            arrival_value,
+            average_price,
+            fees: self.fees,
+            shortfall,
+            shortfall_bps,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AlgoOrderReport {
+    pub id: u64,
+    pub asset: String,
+    pub direction: OrderDirection,
+    pub algo: ExecutionAlgo,
+    pub status: ParentStatus,
+    pub size: f64,
+    pub filled: f64,
+    pub children: u32,
+    pub arrival_time: Option<NaiveDateTime>,
+    pub arrival_price: Option<f64>,
+    // Parent size at the arrival price in the account currency
+    pub arrival_value: Option<f64>,
+    pub average_price: Option<f64>,
+    pub fees: f64,
+    // Implementation shortfall in the account currency, see `analytics::execution`
+    pub shortfall: Option<f64>,
+    pub shortfall_bps: Option<f64>,
+}
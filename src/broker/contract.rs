@@ -0,0 +1,131 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+
+// Currency of the feed prices (the quote currency) to the account currency
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conversion {
+    // The account is in the quote currency
+    Quote,
+    // The account is in the base currency, amounts are divided by the current price
+    Base,
+    // Fixed quote to account rate
+    Rate(f64),
+}
+
+// Overnight charge on open positions at a daily rollover time
+#[derive(Debug, Clone)]
+pub struct Rollover {
+    pub time: NaiveTime,
+    pub timezone: Tz,
+    // Per unit of a long position in the quote currency, positive is a credit
+    pub long_rate: f64,
+    // Charged three times to cover the weekend
+    pub triple_day: Weekday,
+}
+
+// Fractions of the notional, the account equity must cover `initial` to open and `maintenance`
+// to keep the position, which is liquidated otherwise
+#[derive(Debug, Clone, Copy)]
+pub struct MarginRequirement {
+    pub initial: f64,
+    pub maintenance: f64,
+}
+
+// Periodic payments between longs and shorts, longs pay positive rates on their notional
+#[derive(Debug, Clone)]
+pub enum Funding {
+    // Same rate every interval, counted from midnight UTC
+    Constant { rate: f64, interval: Duration },
+    // Rates paid at their timestamp
+    Schedule(Vec<(NaiveDateTime, f64)>),
+}
+
+// How the traded asset is sized, settled and charged, the default is a cash settled spot asset
+#[derive(Debug, Clone)]
+pub struct Contract {
+    // Units per order size (e.g. 100 000 for a standard FX lot)
+    pub multiplier: f64,
+    // Only the P&L is settled in cash and positions are worth their unrealized P&L
+    pub margin: bool,
+    pub conversion: Conversion,
+    pub rollover: Option<Rollover>,
+    // Margin trading without requirement is unlimited
+    pub margin_requirement: Option<MarginRequirement>,
+    pub funding: Option<Funding>,
+}
+
+impl Default for Contract {
+    fn default() -> Self {
+        Contract {
+            multiplier: 1.0,
+            margin: false,
+            conversion: Conversion::Quote,
+            rollover: None,
+            margin_requirement: None,
+            funding: None,
+        }
+    }
+}
+
+impl Contract {
+    // Quote to account rate at `price`
+    pub fn rate(&self, price: f64) -> f64 {
+        match self.conversion {
+            Conversion::Quote => 1.0,
+            Conversion::Base => 1.0 / price,
+            Conversion::Rate(rate) => rate,
+        }
+    }
+
+    // Funding rates paid in (from, to]
+    pub fn funding_rates(&self, from: &NaiveDateTime, to: &NaiveDateTime) -> Vec<f64> {
+        match &self.funding {
+            None => vec![],
+            Some(Funding::Constant { rate, interval }) => {
+                let interval = interval.num_seconds();
+                if interval <= 0 {
+                    return vec![];
+                }
+                let (from, to) = (from.and_utc().timestamp(), to.and_utc().timestamp());
+                let payments = to.div_euclid(interval) - from.div_euclid(interval);
+                vec![*rate; payments.max(0) as usize]
+            }
+            Some(Funding::Schedule(schedule)) => schedule
+                .iter()
+                .filter(|(time, _)| from < time && time <= to)
+                .map(|(_, rate)| *rate)
+                .collect(),
+        }
+    }
+
+    // Number of charged nights for the rollovers in (from, to], triple days count for 3
+    pub fn rollover_nights(&self, from: &NaiveDateTime, to: &NaiveDateTime) -> u32 {
+        let Some(rollover) = &self.rollover else {
+            return 0;
+        };
+
+        let local_from = rollover.timezone.from_utc_datetime(from).date_naive();
+        let local_to = rollover.timezone.from_utc_datetime(to).date_naive();
+
+        let mut nights = 0;
+        let mut date = local_from;
+        while date <= local_to {
+            let instant = rollover
+                .timezone
+                .from_local_datetime(&date.and_time(rollover.time))
+                .earliest()
+                .map(|time| time.naive_utc());
+
+            let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+            if instant.is_some_and(|instant| from < &instant && &instant <= to) && !weekend {
+                nights += if date.weekday() == rollover.triple_day {
+                    3
+                } else {
+                    1
+                };
+            }
+            date += Duration::days(1);
+        }
+        nights
+    }
+}
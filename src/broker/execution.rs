@@ -1,17 +1,38 @@
+use crate::analytics::execution::{ExecutionReport, ExecutionTracker};
 use crate::analytics::tracker::TradeTracker;
+use crate::analytics::trade::ForcedExit;
 use crate::broker::{
-    fee::FeeType,
-    order::{Order, OrderDirection, OrderType},
-    position::Position,
+    account::{SubAccount, SubAccountReport},
+    algo::{AlgoOrderReport, ExecutionAlgo, ParentOrder, ParentStatus},
+    contract::Contract,
+    exposure::{ExposureMonitor, ExposureReport},
+    fee::{FeeCurrency, FeeTotal, FeeType},
+    flatten::DailyFlatten,
+    hedge::HedgeBook,
+    hooks::OrderHook,
+    limits::PositionLimits,
+    margin::{MarginAccount, MarginReport},
+    order::{
+        CashShortfall, DownsizedOrder, Fill, FillModel, Order, OrderDirection, OrderType,
+        PartialFill, SizeSpec, TimeInForce,
+    },
+    overlay::{BetaHedge, BetaHedgeReport},
+    position::{DustClosure, Position, DEFAULT_DUST_THRESHOLD},
+    queue::{QueueModel, QueuePlace},
+    sweep::CashSweep,
 };
+use crate::calendar::Calendar;
 use crate::data::OHLCVData;
 use chrono::NaiveDateTime;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::collections::HashMap;
 
 pub struct BrokerMetrics {
     pub total_placed_orders: i32,
     pub total_exec_orders: i32,
+    pub total_liquidations: i32,
+    // Orders dropped because the strategy was warming up
+    pub warmup_orders: i32,
 }
 
 impl BrokerMetrics {
@@ -19,34 +40,172 @@ impl BrokerMetrics {
         BrokerMetrics {
             total_placed_orders: 0,
             total_exec_orders: 0,
+            total_liquidations: 0,
+            warmup_orders: 0,
         }
     }
 }
 
+// Order resting in the book, with the state the broker keeps for it until it is removed
+pub struct RestingOrder {
+    pub order: Order,
+    // From 1 in the order they were placed
+    pub id: u64,
+    // First bar it could trade on (time, open)
+    arrival: Option<(NaiveDateTime, f64)>,
+    // Sub-account it was placed from
+    account: Option<usize>,
+    // Cash set aside until it fills, expires or is cancelled
+    reservation: f64,
+    // Place in the queue at its price, set on the first touch
+    queue: Option<QueuePlace>,
+    // Quantity it took on the bar of `volume_bar`, against the maximum participation
+    taken: Option<(NaiveDateTime, f64)>,
+}
+
 pub struct Broker {
     pub cash: f64,
     pub fee_type: Option<FeeType>,
+    pub fee_currency: FeeCurrency,
+    // Fees taken out of the quantities bought, by asset
+    fees_in_kind: Vec<FeeTotal>,
     pub slippage_range: (f64, f64),
     pub portfolio: HashMap<String, Position>,
-    pub orders: Vec<Order>,
+    pub orders: Vec<RestingOrder>,
+    // Resting buys set their estimated cost aside when placed, until they fill, expire or are
+    // cancelled
+    reserve_cash: bool,
+    // Reserved by the other orders while one is filled
+    held_cash: f64,
+    // Price of the market orders on their bar, the open by default
+    fill_model: FillModel,
+    // Share of the volume of a bar the orders can trade, the rest of an order rests for the
+    // next bars
+    volume_share: Option<f64>,
+    // Bar the orders were last matched on and its volume
+    volume_bar: Option<NaiveDateTime>,
+    bar_volume: f64,
+    // Volume still tradable on the bar of `volume_bar`
+    volume_left: f64,
+    // Percent of the volume of a bar a single order can take, over all the ticks on it
+    max_participation: Option<f64>,
+    // Limit orders only touched by a bar fill from their place in the queue
+    queue_model: Option<QueueModel>,
+    queue_rng: Option<StdRng>,
+    pub algo_orders: Vec<ParentOrder>,
+    pub fills: Vec<Fill>,
+    // Sells leaving less than this quantity close the whole position
+    pub dust_threshold: f64,
+    // What a buy the cash doesn't cover becomes, rejected by default
+    cash_shortfall: CashShortfall,
+    pub downsized_orders: Vec<DownsizedOrder>,
+    // Buys beyond the buying power are refused as soon as they are placed
+    check_buying_power: bool,
+    // Whether the last order placed was refused, for the strategy to check right away
+    pub last_order_rejected: bool,
+    pub last_order_id: Option<u64>,
+    next_order_id: u64,
+    pub dust_closures: Vec<DustClosure>,
+    // Order quantities are rounded down to whole lots, except the sells closing a position
+    lot_size: Option<f64>,
     slippage_values: Vec<f64>,
     slippage_index: usize,
+    seed: Option<u64>,
     pub analytics: BrokerMetrics,
     pub trade_tracker: TradeTracker,
+    pub execution_tracker: ExecutionTracker,
+    pub calendar: Calendar,
+    pub contract: Contract,
+    pub limits: PositionLimits,
+    // Loan against the positions of a cash settled asset
+    margin_account: Option<MarginAccount>,
+    margin_report: MarginReport,
+    // Capital of each strategy of a multi-strategy run, the rest of the cash isn't allocated
+    accounts: Vec<SubAccount>,
+    // Sub-account the strategy being ticked trades from, its cash and positions are swapped in
+    active_account: Option<usize>,
+    // Net exposure band and the hedge keeping the book in it
+    exposure: Option<ExposureMonitor>,
+    // Short hedge of the beta of the book to a benchmark
+    beta_hedge: Option<BetaHedge>,
+    // Yield of the uninvested cash
+    cash_sweep: Option<CashSweep>,
+    // Positions sold every day at a set time
+    flatten: Option<DailyFlatten>,
+    hooks: Vec<Box<dyn OrderHook>>,
+    // Orders of the strategy held back instead of placed, for an ensemble to vote on
+    captured_orders: Option<Vec<Order>>,
+    last_settlement: Option<NaiveDateTime>,
+    // Time of the last bar the orders were matched on, stamped on the orders placed after it
+    clock: Option<NaiveDateTime>,
+    // Close of that bar, the positions are marked at it between the ticks
+    mark_price: Option<f64>,
+    // Orders placed before it are dropped, the strategy only warms up its indicators
+    trading_start: Option<NaiveDateTime>,
+    // Only asset the orders are matched and the positions marked against, when the run has a
+    // symbol. The others have no bars to fill on
+    traded_asset: Option<String>,
 }
 
+const WARMUP_REJECTION: &str = "Orders are dropped during the warm-up";
+const BUYING_POWER_REJECTION: &str = "Not enough buying power";
+const ASSET_REJECTION: &str = "Only the symbol of the run can be traded";
+
 impl Broker {
     pub fn new() -> Self {
         Broker {
             cash: 0.0,
             fee_type: None,
+            fee_currency: FeeCurrency::Account,
+            fees_in_kind: vec![],
             slippage_range: (0.0, 0.0),
             portfolio: HashMap::new(),
             orders: vec![],
+            reserve_cash: false,
+            held_cash: 0.0,
+            fill_model: FillModel::default(),
+            volume_share: None,
+            volume_left: 0.0,
+            volume_bar: None,
+            max_participation: None,
+            bar_volume: 0.0,
+            queue_model: None,
+            queue_rng: None,
+            algo_orders: vec![],
+            fills: vec![],
+            dust_threshold: DEFAULT_DUST_THRESHOLD,
+            cash_shortfall: CashShortfall::default(),
+            downsized_orders: vec![],
+            check_buying_power: false,
+            last_order_rejected: false,
+            last_order_id: None,
+            next_order_id: 1,
+            dust_closures: vec![],
+            lot_size: None,
             slippage_values: vec![],
             slippage_index: 0,
+            seed: None,
             analytics: BrokerMetrics::new(),
             trade_tracker: TradeTracker::new(),
+            execution_tracker: ExecutionTracker::new(),
+            calendar: Calendar::new(),
+            contract: Contract::default(),
+            limits: PositionLimits::default(),
+            margin_account: None,
+            margin_report: MarginReport::default(),
+            accounts: vec![],
+            active_account: None,
+            exposure: None,
+            beta_hedge: None,
+            cash_sweep: None,
+            flatten: None,
+            hooks: vec![],
+            captured_orders: None,
+            last_settlement: None,
+            clock: None,
+            mark_price: None,
+            trading_start: None,
+            traded_asset: None,
         }
     }
 
@@ -59,11 +218,39 @@ impl Broker {
         self.fee_type = Some(fee_type);
     }
 
+    pub fn set_fee_currency(&mut self, currency: FeeCurrency) {
+        self.fee_currency = currency;
+    }
+
+    // Fees of the tracked trades by currency, the account one first
+    pub fn fee_totals(&self) -> Vec<FeeTotal> {
+        let in_kind: f64 = self.fees_in_kind.iter().map(|total| total.value).sum();
+        let account = self.trade_tracker.total_fees - in_kind;
+        let mut totals = vec![];
+        if account != 0.0 {
+            totals.push(FeeTotal {
+                currency: "account".to_string(),
+                amount: account,
+                value: account,
+            });
+        }
+        totals.extend(self.fees_in_kind.iter().cloned());
+        totals
+    }
+
+    // Makes the slippage draws reproducible, must be set before the slippage
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
     pub fn set_slippage(&mut self, min_slippage: f64, max_slippage: f64) {
         self.slippage_range = (min_slippage, max_slippage);
 
         self.slippage_values = Vec::with_capacity(10000);
-        let mut rng = rand::rng();
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
         for _ in 0..10000 {
             self.slippage_values
                 .push(rng.random_range(min_slippage..=max_slippage));
@@ -71,328 +258,2038 @@ impl Broker {
         self.slippage_index = 0;
     }
 
-    pub fn place_order(&mut self, order: Order) {
-        self.analytics.total_placed_orders += 1;
-        self.orders.push(order);
+    pub fn set_dust_threshold(&mut self, threshold: f64) {
+        self.dust_threshold = threshold;
+        self.trade_tracker.set_dust_threshold(threshold);
     }
 
-    #[inline]
-    fn calculate_fees(&mut self, amount: f64) -> f64 {
-        match &self.fee_type {
-            Some(FeeType::Flat(fee)) => *fee,
-            Some(FeeType::Percentage(percentage)) => amount * *percentage,
-            _ => 0.0,
-        }
+    pub fn set_cash_shortfall(&mut self, policy: CashShortfall) {
+        self.cash_shortfall = policy;
     }
 
-    #[inline]
-    fn try_execute_and_remove(
-        &mut self,
-        i: &mut usize,
-        order: &Order,
-        price: f64,
-        current_time: &NaiveDateTime,
-    ) {
-        match self.execute_order(order.clone(), price, current_time) {
-            Ok(_) => {
-                self.analytics.total_exec_orders += 1;
-                self.orders.swap_remove(*i);
-            }
-            Err(e) => {
-                eprintln!("Failed to execute order: {}", e);
-                *i += 1;
-            }
-        }
+    pub fn set_check_buying_power(&mut self, enabled: bool) {
+        self.check_buying_power = enabled;
     }
 
-    #[inline]
-    pub fn handle_unfulfilled_orders(
-        &mut self,
-        current_time: &NaiveDateTime,
-        current_price: &OHLCVData,
-    ) {
-        let mut i = 0;
-        while i < self.orders.len() {
-            let order = self.orders[i].clone();
-
-            if let Some(valid_until) = order.valid_until {
-                if current_time > &valid_until {
-                    self.orders.swap_remove(i);
-                    continue;
-                }
-            }
+    pub fn set_reserve_cash(&mut self, enabled: bool) {
+        self.reserve_cash = enabled;
+    }
 
-            match order.order_type {
-                OrderType::Market => {
-                    self.try_execute_and_remove(&mut i, &order, current_price.open, current_time);
-                }
-                OrderType::Limit(price) => {
-                    if (order.direction == OrderDirection::Buy && current_price.open <= price)
-                        || (order.direction == OrderDirection::Sell && current_price.open >= price)
-                    {
-                        self.try_execute_and_remove(
-                            &mut i,
-                            &order,
-                            current_price.open,
-                            current_time,
-                        );
-                    } else {
-                        i += 1;
-                    }
-                }
-                OrderType::Stop(price) => {
-                    if (order.direction == OrderDirection::Buy && current_price.open >= price)
-                        || (order.direction == OrderDirection::Sell && current_price.open <= price)
-                    {
-                        self.try_execute_and_remove(
-                            &mut i,
-                            &order,
-                            current_price.open,
-                            current_time,
-                        );
-                    } else {
-                        i += 1;
-                    }
-                }
-            }
-        }
+    pub fn set_fill_model(&mut self, model: FillModel) {
+        self.fill_model = model;
     }
 
-    #[inline]
-    fn apply_slippage(&mut self, market_price: f64) -> f64 {
-        if self.slippage_values.is_empty() {
-            return market_price;
-        }
+    pub fn set_volume_share(&mut self, share: f64) {
+        self.volume_share = Some(share);
+    }
 
-        let slippage_percentage =
-            self.slippage_values[self.slippage_index % self.slippage_values.len()];
-        self.slippage_index += 1;
-        market_price * (1.0 + slippage_percentage)
+    pub fn set_max_participation(&mut self, percent: f64) {
+        self.max_participation = Some(percent);
     }
 
-    fn execute_order(
-        &mut self,
-        order: Order,
-        market_price: f64,
-        current_time: &NaiveDateTime,
-    ) -> Result<(), String> {
-        let execution_price = self.apply_slippage(market_price);
-        let slippage_diff = execution_price - market_price;
+    // Draws from the seed like the slippage, must be set after it
+    pub fn set_queue_model(&mut self, model: QueueModel) {
+        self.queue_model = Some(model);
+        self.queue_rng = Some(match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        });
+    }
 
-        match order.direction {
-            OrderDirection::Buy => {
-                let total_cost = order.size * execution_price;
-                let fees = self.calculate_fees(total_cost);
-                let total_spent = total_cost + fees;
+    pub fn set_lot_size(&mut self, lot_size: f64) {
+        self.lot_size = Some(lot_size);
+    }
 
-                if self.cash >= total_spent {
-                    self.cash -= total_spent;
+    pub fn set_calendar(&mut self, calendar: Calendar) {
+        self.calendar = calendar;
+    }
 
-                    let position = self
-                        .portfolio
-                        .entry(order.asset.clone())
-                        .or_insert_with(|| Position::new(0.0, execution_price));
+    pub fn set_contract(&mut self, contract: Contract) {
+        self.contract = contract;
+    }
 
-                    position.update(order.size, execution_price);
+    pub fn set_limits(&mut self, limits: PositionLimits) {
+        self.limits = limits;
+    }
 
-                    self.trade_tracker.record_buy(
-                        &order.asset,
-                        *current_time,
-                        execution_price,
-                        order.size,
-                        fees,
-                        slippage_diff.abs(),
-                    );
+    pub fn set_margin_account(&mut self, account: MarginAccount) {
+        self.margin_report.leverage = account.leverage;
+        self.margin_account = Some(account.prepare());
+    }
 
-                    Ok(())
-                } else {
-                    Err("Not enough cash".to_string())
-                }
-            }
-            OrderDirection::Sell => {
-                let total_raw_value = order.size * execution_price;
-                let fees = self.calculate_fees(total_raw_value);
-                let total_value = total_raw_value - fees;
+    // Allocates `cash` of the broker to a new sub-account, set the cash first
+    pub fn add_account(&mut self, name: String, cash: f64) -> Result<usize, &'static str> {
+        if !(cash.is_finite() && cash > 0.0) {
+            return Err("The cash of a sub-account must be positive");
+        }
+        if self.accounts.iter().any(|account| account.name == name) {
+            return Err("The sub-accounts need distinct names");
+        }
+        let allocated: f64 = self.accounts.iter().map(|account| account.cash).sum();
+        if allocated + cash > self.cash {
+            return Err("The sub-accounts hold more than the cash of the broker");
+        }
+        self.accounts.push(SubAccount::new(name, cash));
+        Ok(self.accounts.len() - 1)
+    }
 
-                let Some(position) = self.portfolio.get_mut(&order.asset) else {
-                    return Err("Position not found in portfolio".to_string());
-                };
+    // The strategy ticked until `leave_account` sees the cash and positions of the sub-account
+    // and trades from it
+    pub fn enter_account(&mut self, index: usize) {
+        self.leave_account();
+        self.swap_account(index);
+        self.active_account = Some(index);
+    }
 
-                if position.quantity < order.size {
-                    return Err("Not enough quantity to sell".to_string());
-                }
+    pub fn leave_account(&mut self) {
+        if let Some(index) = self.active_account.take() {
+            self.swap_account(index);
+        }
+    }
 
-                position.remove(order.size)?;
-                self.cash += total_value;
+    fn swap_account(&mut self, index: usize) {
+        let account = &mut self.accounts[index];
+        std::mem::swap(&mut self.cash, &mut account.cash);
+        std::mem::swap(&mut self.portfolio, &mut account.portfolio);
+    }
 
-                self.trade_tracker.record_sell(
-                    &order.asset,
-                    *current_time,
-                    execution_price,
-                    order.size,
-                    fees,
-                    slippage_diff.abs(),
-                );
+    // Fills an order of a sub-account against its own cash and positions, then books the fill
+    // on the consolidated account
+    fn execute_for(
+        &mut self,
+        account: Option<usize>,
+        order: Order,
+        price: f64,
+        current_time: &NaiveDateTime,
+    ) -> Result<Fill, String> {
+        let Some(index) = account else {
+            return self.execute_order(order, price, current_time);
+        };
+        self.swap_account(index);
+        let cash = self.cash;
+        let result = self.execute_order(order, price, current_time);
+        let cash_flow = self.cash - cash;
+        self.swap_account(index);
 
-                if position.quantity == 0.0 {
-                    self.portfolio.remove(&order.asset);
+        let fill = result?;
+        self.cash += cash_flow;
+        self.accounts[index].record_fill(fill.fees);
+        match fill.direction {
+            OrderDirection::Buy => self
+                .portfolio
+                .entry(fill.asset.clone())
+                .or_insert_with(|| Position::new(0.0, fill.price))
+                .update(fill.size, fill.price),
+            OrderDirection::Sell => {
+                if let Some(position) = self.portfolio.get_mut(&fill.asset) {
+                    position.quantity -= fill.size;
+                    // What the sub-accounts sold may not add up to the position exactly
+                    if position.quantity <= self.dust_threshold {
+                        self.portfolio.remove(&fill.asset);
+                    }
                 }
-                Ok(())
             }
         }
+        Ok(fill)
     }
 
-    // Return the total value of all the positions at the current market price
-    pub fn portfolio_value(&self, data: &OHLCVData) -> f64 {
-        let mut total_value = 0.0;
+    // A position closed by the broker is closed in the sub-accounts, each getting its share of
+    // the proceeds
+    fn close_in_accounts(&mut self, fill: &Fill) {
+        let point_value = self.contract.multiplier * self.contract.rate(fill.price);
+        for account in self.accounts.iter_mut() {
+            let Some(position) = account.portfolio.remove(&fill.asset) else {
+                continue;
+            };
+            let fees = fill.fees * position.quantity / fill.size;
+            account.cash += position.quantity * fill.price * point_value - fees;
+            account.record_fill(fees);
+        }
+    }
 
-        for position in self.portfolio.values() {
-            let current_price = data.close;
-            total_value += position.quantity * current_price;
-            //println!(
-            //    "Asset: {}, Quantity: {}, Price: {}",
-            //    asset, position.quantity, current_price
-            //);
+    // Records the equity of every sub-account with the equity curve
+    pub fn record_accounts(&mut self, current_price: &OHLCVData) {
+        let point_value = self.contract.multiplier * self.contract.rate(current_price.close);
+        for account in self.accounts.iter_mut() {
+            let equity = account.equity(current_price.close, point_value);
+            account.record_equity(equity);
         }
+    }
 
-        total_value
+    pub fn account_reports(&self, last_price: f64) -> Vec<SubAccountReport> {
+        let point_value = self.contract.multiplier * self.contract.rate(last_price);
+        self.accounts
+            .iter()
+            .map(|account| account.report(account.equity(last_price, point_value)))
+            .collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::NaiveDateTime;
+    pub fn set_exposure(&mut self, exposure: ExposureMonitor) {
+        self.exposure = Some(exposure);
+    }
 
-    fn create_dummy_price(open: f64, high: f64, low: f64, close: f64) -> OHLCVData {
-        OHLCVData {
-            timestamp: NaiveDateTime::parse_from_str("1999-11-01 00:00:00", "%Y-%m-%d %H:%M:%S")
-                .expect("Invalid date"),
-            open,
-            high,
-            low,
-            close,
-            volume: 1000,
-        }
+    pub fn set_beta_hedge(&mut self, beta_hedge: BetaHedge) {
+        self.beta_hedge = Some(beta_hedge);
     }
 
-    fn create_dummy_date(date: &str) -> NaiveDateTime {
-        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").expect("Invalid date")
+    pub fn set_cash_sweep(&mut self, sweep: CashSweep) {
+        self.cash_sweep = Some(sweep.prepare());
     }
 
-    #[test]
-    fn is_order_placed() {
-        let mut broker = Broker::new();
-        let order = Order {
-            asset: "AAPL".to_string(),
-            direction: OrderDirection::Buy,
-            size: 1.0,
-            order_type: OrderType::Market,
-            valid_until: None,
-        };
-        broker.place_order(order);
+    pub fn set_flatten(&mut self, flatten: DailyFlatten) {
+        self.flatten = Some(flatten);
+    }
 
-        assert_eq!(broker.analytics.total_placed_orders, 1);
-        assert_eq!(broker.analytics.total_exec_orders, 0);
-        assert_eq!(broker.orders.len(), 1);
-        assert_eq!(broker.orders[0].asset, "AAPL");
-        assert_eq!(broker.orders[0].direction, OrderDirection::Buy);
-        assert_eq!(broker.orders[0].size, 1.0);
-        assert_eq!(broker.orders[0].order_type, OrderType::Market);
+    pub fn add_hook(&mut self, hook: Box<dyn OrderHook>) {
+        self.hooks.push(hook);
     }
 
-    #[test]
-    fn is_buy_market_order_executed() {
+    pub fn set_trading_start(&mut self, start: NaiveDateTime) {
+        self.trading_start = Some(start);
+    }
+
+    pub fn set_traded_asset(&mut self, asset: String) {
+        self.traded_asset = Some(asset);
+    }
+
+    fn trades(&self, asset: &str) -> bool {
+        self.traded_asset
+            .as_ref()
+            .is_none_or(|traded| traded == asset)
+    }
+
+    fn warming_up(&self) -> bool {
+        matches!((self.clock, self.trading_start), (Some(clock), Some(start)) if clock < start)
+    }
+
+    // Fresh broker with the same costs, contract and sessions starting from the initial capital,
+    // without the position limits. Slippage draws are replayed from the first one
+    pub fn benchmark(&self) -> Broker {
         let mut broker = Broker::new();
-        let order = Order {
-            asset: "AAPL".to_string(),
-            direction: OrderDirection::Buy,
-            size: 1.0,
-            order_type: OrderType::Market,
-            valid_until: None,
+        broker.set_cash(self.trade_tracker.initial_capital);
+        broker.fee_type = self.fee_type.clone();
+        broker.fee_currency = self.fee_currency;
+        broker.slippage_range = self.slippage_range;
+        broker.slippage_values = self.slippage_values.clone();
+        broker.seed = self.seed;
+        broker.set_dust_threshold(self.dust_threshold);
+        broker.lot_size = self.lot_size;
+        broker.fill_model = self.fill_model;
+        broker.set_calendar(self.calendar.clone());
+        broker.set_contract(self.contract.clone());
+        broker.cash_sweep = self.cash_sweep.clone();
+        broker.traded_asset = self.traded_asset.clone();
+        broker
+    }
+
+    // Periodic charges on the open positions (FX swaps, funding), the yield of the swept cash and
+    // margin calls, called on every tick
+    pub fn settle(&mut self, current_time: &NaiveDateTime, current_price: &OHLCVData) {
+        let Some(previous) = self.last_settlement.replace(*current_time) else {
+            return;
         };
-        broker.set_cash(1000.0);
-        broker.set_fees(FeeType::Flat(1.0));
-        broker.place_order(order);
 
-        // Simulate next tick
-        let dummy_price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
-        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+        let quantity: f64 = self.portfolio.values().map(|p| p.quantity).sum();
+        let point_value = self.contract.multiplier * self.contract.rate(current_price.close);
 
-        // Check the cash in our balance after the execution (order price + fees)
-        assert_eq!(broker.cash, 899.0);
-        assert_eq!(broker.portfolio_value(&dummy_price), 99.0);
+        let mut carry = 0.0;
+        if let Some(rollover) = &self.contract.rollover {
+            let nights = self.contract.rollover_nights(&previous, current_time);
+            carry += rollover.long_rate * quantity * nights as f64 * point_value;
+        }
+        for rate in self.contract.funding_rates(&previous, current_time) {
+            carry -= rate * quantity * current_price.close * point_value;
+        }
+        self.cash += carry;
+        self.trade_tracker.total_carry += carry;
 
-        // Check if the asset is in the portfolio
-        let position = broker.portfolio.get("AAPL").unwrap();
-        assert_eq!(position.quantity, 1.0);
-        assert_eq!(position.average_price, 100.0);
+        if let (Some(sweep), false) = (&self.cash_sweep, self.warming_up()) {
+            let cash_yield = sweep.accrue(self.cash, &previous, current_time);
+            self.cash += cash_yield;
+            self.trade_tracker.total_cash_yield += cash_yield;
+        }
+
+        if let Some(account) = &self.margin_account {
+            let interest = account.interest(self.cash, &previous, current_time);
+            self.cash -= interest;
+            self.trade_tracker.total_carry -= interest;
+            self.margin_report.interest_paid += interest;
+        }
+
+        self.check_liquidation(current_time, current_price);
     }
 
-    #[test]
-    fn not_enough_cash() {
-        let mut broker = Broker::new();
-        let order = Order {
-            asset: "AAPL".to_string(),
-            direction: OrderDirection::Buy,
-            size: 1.0,
-            order_type: OrderType::Market,
-            valid_until: None,
+    // Cash the broker lends, 0 without a margin account
+    pub fn loan(&self) -> f64 {
+        match self.margin_account {
+            Some(_) => (-self.cash).max(0.0),
+            None => 0.0,
+        }
+    }
+
+    // Cash positions can be bought with at the last matched close, borrowed included, less the
+    // cash reserved by the resting buys
+    pub fn buying_power(&self) -> f64 {
+        let long = self.mark_price.map_or(0.0, |price| self.long_value(price));
+        let buying_power = match &self.margin_account {
+            Some(account) => account.buying_power(self.cash + long, long),
+            None => self.cash,
         };
-        broker.set_fees(FeeType::Flat(1.0));
-        broker.place_order(order);
+        (buying_power - self.reserved_cash(self.active_account)).max(0.0)
+    }
 
-        // Simulate next tick
-        let dummy_price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
-        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+    // Cash set aside by the resting buys of a sub-account, or of the main account
+    pub fn reserved_cash(&self, account: Option<usize>) -> f64 {
+        self.orders
+            .iter()
+            .filter(|resting| resting.account == account)
+            .map(|resting| resting.reservation)
+            .sum()
+    }
 
-        // Check the cash in our balance after the execution (order price + fees)
-        assert_eq!(broker.cash, 0.0);
-        assert_eq!(broker.portfolio_value(&dummy_price), 0.0);
+    // Cash a buy can spend, the reservations of the other orders being held back
+    fn free_cash(&self) -> f64 {
+        self.cash - self.held_cash
+    }
 
-        // Check if there is no assets in the portolio
-        assert!(!broker.portfolio.contains_key("AAPL"));
+    // Value and fees of a buy at its limit or stop price, or at the last close for a market
+    // order. None for margin contracts and before the first bar
+    fn order_cost(&self, order: &Order) -> Option<f64> {
+        if self.contract.margin {
+            return None;
+        }
+        let price = match order.order_type {
+            OrderType::Limit(price) | OrderType::Stop(price) => price,
+            OrderType::Market => self.mark_price?,
+        };
+        let point_value = self.contract.multiplier * self.contract.rate(price);
+        let equity = self.cash + self.value_at(price);
+        let quantity = order.size.quantity(price, equity, point_value);
+        let notional = quantity * price * point_value;
+        let fees = self.calculate_fees(notional, quantity);
+        match self.fee_currency {
+            FeeCurrency::Asset => Some(notional),
+            FeeCurrency::Account => Some(notional + fees),
+        }
     }
 
-    #[test]
-    fn add_to_existing_position() {
-        let mut broker = Broker::new();
-        let order = Order {
-            asset: "AAPL".to_string(),
-            direction: OrderDirection::Buy,
-            size: 1.0,
-            order_type: OrderType::Market,
-            valid_until: None,
+    // Whether the buying power pays for a new order, sells always do. Downsized buys only need
+    // some buying power left
+    fn covers(&self, order: &Order) -> bool {
+        if order.direction == OrderDirection::Sell {
+            return true;
+        }
+        let Some(cost) = self.order_cost(order) else {
+            return true;
         };
-        broker.set_cash(1000.0);
-        broker.set_fees(FeeType::Flat(1.0));
-        broker
-            .portfolio
-            .insert("AAPL".to_string(), Position::new(1.0, 100.0));
+        let buying_power = self.buying_power();
+        match self.cash_shortfall {
+            CashShortfall::Reject => cost <= buying_power,
+            CashShortfall::Downsize => buying_power > 0.0,
+        }
+    }
 
-        broker.place_order(order);
+    // Records the loan and the leverage of the tick
+    pub fn record_margin(&mut self, current_price: &OHLCVData) {
+        if self.margin_account.is_none() {
+            return;
+        }
+        let long = self.long_value(current_price.close);
+        let equity = self.cash + long;
+        let report = &mut self.margin_report;
+        report.max_loan = report.max_loan.max(-self.cash);
+        if equity > 0.0 {
+            report.max_leverage = report.max_leverage.max(long / equity);
+        }
+    }
 
-        // Simulate next tick
-        let dummy_price = create_dummy_price(110.0, 111.0, 98.0, 99.0);
-        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+    pub fn margin_report(&self) -> Option<MarginReport> {
+        self.margin_account.as_ref().map(|_| MarginReport {
+            loan: self.loan(),
+            ..self.margin_report.clone()
+        })
+    }
 
-        // Check if the assets are in the portfolio
-        let position = broker.portfolio.get("AAPL").unwrap();
-        assert_eq!(position.quantity, 2.0);
+    // Price at which the equity only covers the maintenance margin of the position
+    pub fn liquidation_price(&self, asset: &str) -> Option<f64> {
+        if let (Some(account), false) = (&self.margin_account, self.contract.margin) {
+            // Loan on the cash: cash + p * q * pv = maintenance * p * q * pv
+            let position = self.portfolio.get(asset)?;
+            let exposure = position.quantity
+                * self.contract.multiplier
+                * self.contract.rate(position.average_price);
+            let price = -self.cash / (exposure * (1.0 - account.maintenance_margin));
+            return (price > 0.0).then_some(price);
+        }
+        let requirement = self.contract.margin_requirement?;
+        let position = self.portfolio.get(asset)?;
+        // Cross margin on the quote currency: cash + (p - avg) * q * pv = maintenance * p * q * pv
+        let exposure = position.quantity
+            * self.contract.multiplier
+            * self.contract.rate(position.average_price);
+        let price = (position.average_price * exposure - self.cash)
+            / (exposure * (1.0 - requirement.maintenance));
+        (price > 0.0).then_some(price)
+    }
 
-        // Calculate the new average price: (100 * 1 + 110 * 1) / 2 = 105
-        assert_eq!(position.average_price, 105.0);
+    // Profit or loss of the position at the last matched close, None when the asset isn't held
+    pub fn unrealized_pnl(&self, asset: &str) -> Option<f64> {
+        let position = self.portfolio.get(asset)?;
+        let price = self.mark_price?;
+        let point_value = self.contract.multiplier * self.contract.rate(price);
+        Some((price - position.average_price) * position.quantity * point_value)
     }
 
-    #[test]
-    fn is_sell_market_order_executed() {
+    // Cash and positions at the last matched close, the cash alone before the first bar
+    pub fn total_equity(&self) -> f64 {
+        self.cash + self.mark_price.map_or(0.0, |price| self.value_at(price))
+    }
+
+    fn check_liquidation(&mut self, current_time: &NaiveDateTime, current_price: &OHLCVData) {
+        let assets: Vec<String> = self.portfolio.keys().cloned().collect();
+        for asset in assets {
+            let Some(price) = self.liquidation_price(&asset) else {
+                continue;
+            };
+            if current_price.low > price {
+                continue;
+            }
+
+            // At the liquidation price, or at the open when it gapped through
+            let price = current_price.open.min(price);
+            match self.force_exit(&asset, price, current_time, ForcedExit::Liquidation) {
+                Ok(_) => {
+                    self.analytics.total_liquidations += 1;
+                    let mut i = 0;
+                    while i < self.orders.len() {
+                        if self.orders[i].order.asset != asset {
+                            i += 1;
+                            continue;
+                        }
+                        let order = self.remove_order(i);
+                        self.execution_tracker.record_cancelled(&order.order_type);
+                    }
+                    for parent in self.algo_orders.iter_mut() {
+                        if parent.order.asset == asset && parent.status == ParentStatus::Active {
+                            parent.status = ParentStatus::Cancelled;
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Failed to liquidate {}: {}", asset, e),
+            }
+        }
+    }
+
+    // Id of the order in the book, None when it was refused or held back by an ensemble
+    pub fn place_order(&mut self, mut order: Order) -> Option<u64> {
+        if let Some(captured) = self.captured_orders.as_mut() {
+            captured.push(order);
+            return None;
+        }
+        self.last_order_rejected = true;
+        self.last_order_id = None;
+        if self.warming_up() {
+            self.analytics.warmup_orders += 1;
+            self.hooks
+                .iter_mut()
+                .for_each(|hook| hook.on_reject(&order, WARMUP_REJECTION));
+            return None;
+        }
+        if !self.trades(&order.asset) {
+            self.execution_tracker.record_rejected();
+            self.hooks
+                .iter_mut()
+                .for_each(|hook| hook.on_reject(&order, ASSET_REJECTION));
+            return None;
+        }
+        if self.check_buying_power && !self.covers(&order) {
+            self.execution_tracker.record_rejected();
+            self.hooks
+                .iter_mut()
+                .for_each(|hook| hook.on_reject(&order, BUYING_POWER_REJECTION));
+            return None;
+        }
+        order.placed_at = order.placed_at.or(self.clock);
+        if !self.hooks.is_empty() {
+            let mut hooks = std::mem::take(&mut self.hooks);
+            let accepted = hooks
+                .iter_mut()
+                .try_for_each(|hook| hook.on_order_placed(&order));
+            if let Err(reason) = &accepted {
+                hooks
+                    .iter_mut()
+                    .for_each(|hook| hook.on_reject(&order, reason));
+            }
+            self.hooks = hooks;
+            if accepted.is_err() {
+                return None;
+            }
+        }
+
+        if let (OrderDirection::Sell, OrderType::Stop(price)) =
+            (&order.direction, &order.order_type)
+        {
+            self.trade_tracker.attach_stop(&order.asset, *price);
+        }
+        self.analytics.total_placed_orders += 1;
+        self.execution_tracker.record_placed(&order.order_type);
+        let reservation = self.reservation(&order);
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        self.orders.push(RestingOrder {
+            order,
+            id,
+            arrival: None,
+            account: self.active_account,
+            reservation,
+            queue: None,
+            taken: None,
+        });
+        self.last_order_rejected = false;
+        self.last_order_id = Some(id);
+        if reservation > 0.0 {
+            let reserved: f64 = self.orders.iter().map(|resting| resting.reservation).sum();
+            self.execution_tracker.record_reserved(reserved);
+        }
+        Some(id)
+    }
+
+    // Cash a resting buy sets aside with `reserve_cash`
+    fn reservation(&self, order: &Order) -> f64 {
+        match (&order.direction, &order.order_type) {
+            (OrderDirection::Buy, OrderType::Limit(_) | OrderType::Stop(_))
+                if self.reserve_cash =>
+            {
+                self.order_cost(order).unwrap_or(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    // Open orders with their id
+    pub fn open_orders(&self) -> impl Iterator<Item = (u64, &Order)> {
+        self.orders
+            .iter()
+            .map(|resting| (resting.id, &resting.order))
+    }
+
+    // Index of an open order of the account trading, the other sub-accounts can't reach it
+    fn order_index(&self, id: u64) -> Result<usize, String> {
+        self.orders
+            .iter()
+            .position(|resting| resting.id == id && resting.account == self.active_account)
+            .ok_or_else(|| "Order not found".to_string())
+    }
+
+    // Removes an open order from the book, its reserved cash with it, or stops an active algo
+    // parent. Parents share the ids of the orders
+    pub fn cancel_order(&mut self, id: u64) -> Result<(), String> {
+        let parent = self.algo_orders.iter_mut().find(|parent| {
+            parent.id == id
+                && parent.status == ParentStatus::Active
+                && self.active_account.is_none()
+        });
+        if let Some(parent) = parent {
+            parent.status = ParentStatus::Cancelled;
+            return Ok(());
+        }
+        let order = self.remove_order(self.order_index(id)?);
+        self.execution_tracker.record_cancelled(&order.order_type);
+        Ok(())
+    }
+
+    // Changes the quantity and the limit or stop price of an open order. A new price or a larger
+    // quantity loses the place in the queue
+    pub fn amend_order(
+        &mut self,
+        id: u64,
+        size: Option<f64>,
+        price: Option<f64>,
+    ) -> Result<(), String> {
+        let i = self.order_index(id)?;
+        let mut amended = self.orders[i].order.clone();
+        if let Some(size) = size {
+            if !(size.is_finite() && size > 0.0) {
+                return Err("Invalid order size".to_string());
+            }
+            let current = self.order_quantity(&amended, self.mark_price.unwrap_or(0.0));
+            if size > current {
+                self.orders[i].queue = None;
+            }
+            amended.size = SizeSpec::Quantity(size);
+        }
+        if let Some(price) = price {
+            if !(price.is_finite() && price > 0.0) {
+                return Err("Invalid order price".to_string());
+            }
+            amended.order_type = match amended.order_type {
+                OrderType::Market => return Err("Market orders have no price".to_string()),
+                OrderType::Limit(_) => OrderType::Limit(price),
+                OrderType::Stop(_) => OrderType::Stop(price),
+            };
+            self.orders[i].queue = None;
+        }
+
+        // The amended order is checked without the cash it reserved so far
+        let reservation = std::mem::take(&mut self.orders[i].reservation);
+        if self.check_buying_power && !self.covers(&amended) {
+            self.orders[i].reservation = reservation;
+            return Err(BUYING_POWER_REJECTION.to_string());
+        }
+        self.orders[i].reservation = self.reservation(&amended);
+        self.orders[i].order = amended;
+        Ok(())
+    }
+
+    // Holds the orders placed from now on back until they are taken
+    pub fn capture_orders(&mut self) {
+        self.captured_orders = Some(vec![]);
+    }
+
+    // Orders held back since `capture_orders`, the next ones are placed again
+    pub fn take_captured_orders(&mut self) -> Vec<Order> {
+        self.captured_orders.take().unwrap_or_default()
+    }
+
+    // Market sell of the whole position, false when the asset isn't held
+    pub fn close_position(&mut self, asset: &str) -> bool {
+        let Some(position) = self.portfolio.get(asset) else {
+            return false;
+        };
+        let order = Order {
+            asset: asset.to_string(),
+            direction: OrderDirection::Sell,
+            size: SizeSpec::Quantity(position.quantity),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        self.place_order(order);
+        true
+    }
+
+    // Returns the number of positions being closed
+    pub fn close_all_positions(&mut self) -> usize {
+        let mut assets: Vec<String> = self.portfolio.keys().cloned().collect();
+        assets.sort();
+        assets
+            .iter()
+            .filter(|asset| self.close_position(asset))
+            .count()
+    }
+
+    // Sells every position at the close of the last bar once the run is over, with the fees and
+    // slippage of a market order
+    pub fn liquidate(&mut self, last_bar: &OHLCVData) {
+        let mut assets: Vec<String> = self.portfolio.keys().cloned().collect();
+        assets.sort();
+        for asset in assets {
+            // Counted like the orders of the strategy
+            self.analytics.total_placed_orders += 1;
+            let (price, time) = (last_bar.close, &last_bar.timestamp);
+            match self.force_exit(&asset, price, time, ForcedExit::EndOfRun) {
+                Ok(_) => self.analytics.total_exec_orders += 1,
+                Err(e) => eprintln!("Failed to liquidate {} at the end of the run: {}", asset, e),
+            }
+        }
+        let equity = self.cash + self.portfolio_value(last_bar);
+        self.trade_tracker.revise_last_snapshot(equity);
+    }
+
+    // Sells every position at the open of the first bar past the flattening time of each day
+    pub fn manage_flatten(&mut self, current_time: &NaiveDateTime, current_price: &OHLCVData) {
+        if self.warming_up() || self.portfolio.is_empty() {
+            return;
+        }
+        if !self
+            .flatten
+            .as_mut()
+            .is_some_and(|flatten| flatten.due(current_time))
+        {
+            return;
+        }
+        let mut assets: Vec<String> = self.portfolio.keys().cloned().collect();
+        assets.sort();
+        for asset in assets {
+            self.analytics.total_placed_orders += 1;
+            match self.force_exit(
+                &asset,
+                current_price.open,
+                current_time,
+                ForcedExit::Flatten,
+            ) {
+                Ok(_) => self.analytics.total_exec_orders += 1,
+                Err(e) => eprintln!("Failed to flatten {}: {}", asset, e),
+            }
+        }
+    }
+
+    // Market sell of the whole position decided by the broker, its trades are marked with `reason`
+    fn force_exit(
+        &mut self,
+        asset: &str,
+        price: f64,
+        time: &NaiveDateTime,
+        reason: ForcedExit,
+    ) -> Result<Fill, String> {
+        let order = Order {
+            asset: asset.to_string(),
+            direction: OrderDirection::Sell,
+            size: SizeSpec::Quantity(self.portfolio[asset].quantity),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let fill = self.execute_order(order, price, time)?;
+        self.trade_tracker.mark_forced_exits(asset, time, reason);
+        self.close_in_accounts(&fill);
+        Ok(fill)
+    }
+
+    fn remove_order(&mut self, i: usize) -> Order {
+        self.orders.swap_remove(i).order
+    }
+
+    // Parent order sliced into children by the broker, returns the parent id
+    pub fn place_algo_order(&mut self, order: Order, algo: ExecutionAlgo) -> Result<u64, String> {
+        if self.captured_orders.is_some() {
+            return Err("Algo orders can't be voted on by an ensemble".to_string());
+        }
+        if self.active_account.is_some() {
+            return Err("Algo orders can't be placed from a sub-account".to_string());
+        }
+        if self.warming_up() {
+            self.analytics.warmup_orders += 1;
+            return Err(WARMUP_REJECTION.to_string());
+        }
+        algo.validate()?;
+        if !self.trades(&order.asset) {
+            return Err(ASSET_REJECTION.to_string());
+        }
+        if !matches!(order.time_in_force, TimeInForce::Gtc | TimeInForce::Gtd(_)) {
+            return Err("Algo orders are good till cancelled or till a date".to_string());
+        }
+        if !order.size.is_valid() {
+            return Err("Invalid order size".to_string());
+        }
+
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        self.algo_orders.push(ParentOrder::new(id, order, algo));
+        Ok(id)
+    }
+
+    // Sends the children of the active parents, once per bar
+    fn handle_algo_orders(&mut self, current_time: &NaiveDateTime, current_price: &OHLCVData) {
+        for i in 0..self.algo_orders.len() {
+            let parent = &mut self.algo_orders[i];
+            if parent.status != ParentStatus::Active
+                || parent.last_bar == Some(current_price.timestamp)
+            {
+                continue;
+            }
+            if matches!(parent.order.time_in_force, TimeInForce::Gtd(until) if current_time > &until)
+            {
+                parent.status = ParentStatus::Expired;
+                continue;
+            }
+            if !self.calendar.is_open(&parent.order.asset, current_time) {
+                continue;
+            }
+
+            parent.last_bar = Some(current_price.timestamp);
+            parent.last_price = current_price.close;
+            if parent.arrival.is_none() {
+                parent.arrival = Some((*current_time, current_price.open));
+                // Cash and equity sizes are fixed for the whole parent at its arrival
+                if parent.order.size.fixed().is_none() {
+                    let price = current_price.open;
+                    let point_value = self.contract.multiplier * self.contract.rate(price);
+                    let equity = self.cash + self.value_at(price);
+                    let parent = &mut self.algo_orders[i];
+                    parent.size = parent.order.size.quantity(price, equity, point_value);
+                }
+            }
+
+            let parent = &self.algo_orders[i];
+            let size = parent.child_size(current_time, current_price);
+            let child = Order {
+                size: SizeSpec::Quantity(size),
+                ..parent.order.clone()
+            };
+            let Some(fill_price) = child
+                .fill_price(current_price, self.fill_model)
+                .filter(|_| size > 0.0)
+            else {
+                continue;
+            };
+
+            self.analytics.total_placed_orders += 1;
+            match self.execute_order(child, fill_price, current_time) {
+                Ok(fill) => {
+                    self.analytics.total_exec_orders += 1;
+                    let parent = &mut self.algo_orders[i];
+                    parent.record_fill(fill.size, fill.price, fill.fees);
+
+                    let point_value = self.contract.multiplier * self.contract.rate(fill.price);
+                    let arrival_price = parent.arrival.map_or(fill_price, |(_, price)| price);
+                    self.execution_tracker.record_algo_fill(
+                        &parent.order.direction,
+                        arrival_price,
+                        fill.price,
+                        fill.size,
+                        point_value,
+                    );
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to execute child order, cancelling its parent: {}",
+                        e
+                    );
+                    self.algo_orders[i].status = ParentStatus::Cancelled;
+                }
+            }
+        }
+    }
+
+    pub fn algo_order_reports(&self) -> Vec<AlgoOrderReport> {
+        self.algo_orders
+            .iter()
+            .map(|parent| {
+                let point_value = self.contract.multiplier * self.contract.rate(parent.last_price);
+                parent.report(point_value)
+            })
+            .collect()
+    }
+
+    #[inline]
+    pub fn calculate_fees(&self, amount: f64, quantity: f64) -> f64 {
+        match &self.fee_type {
+            Some(FeeType::Flat(fee)) => *fee,
+            Some(FeeType::Percentage(percentage)) => amount * *percentage,
+            Some(FeeType::PerUnit {
+                rate,
+                minimum,
+                maximum_percent,
+            }) => (quantity * rate)
+                .max(*minimum)
+                .min(amount * maximum_percent / 100.0),
+            _ => 0.0,
+        }
+    }
+
+    // Value, fees, fee quantity taken in kind and cash spent of a buy of `size`
+    fn buy_costs(
+        &mut self,
+        size: f64,
+        price: f64,
+        point_value: f64,
+        in_kind: bool,
+    ) -> (f64, f64, f64, f64) {
+        let total_cost = size * price * point_value;
+        let fees = self.calculate_fees(total_cost, size);
+        let fee_quantity = match in_kind {
+            true => fees / (price * point_value),
+            false => 0.0,
+        };
+        // Margin contracts only pay the fees upfront
+        let total_spent = match (self.contract.margin, in_kind) {
+            (true, _) => fees,
+            (false, true) => total_cost,
+            (false, false) => total_cost + fees,
+        };
+        (total_cost, fees, fee_quantity, total_spent)
+    }
+
+    // Largest quantity below `size` the cash pays for along with its fees, in whole lots. None
+    // when not even a lot is affordable
+    fn affordable_size(
+        &mut self,
+        size: f64,
+        price: f64,
+        point_value: f64,
+        in_kind: bool,
+    ) -> Option<f64> {
+        // The fees never shrink as the quantity grows
+        let (mut low, mut high) = (0.0, size);
+        for _ in 0..64 {
+            let middle = (low + high) / 2.0;
+            match self.buy_costs(middle, price, point_value, in_kind).3 <= self.free_cash() {
+                true => low = middle,
+                false => high = middle,
+            }
+        }
+        let mut affordable = low;
+        if let Some(lot_size) = self.lot_size {
+            affordable = (low / lot_size + 1e-9).floor() * lot_size;
+            if self.buy_costs(affordable, price, point_value, in_kind).3 > self.free_cash() {
+                affordable -= lot_size;
+            }
+        }
+        (affordable > self.dust_threshold).then_some(affordable)
+    }
+
+    fn record_fee_in_kind(&mut self, asset: &str, quantity: f64, value: f64) {
+        match self
+            .fees_in_kind
+            .iter_mut()
+            .find(|total| total.currency == asset)
+        {
+            Some(total) => {
+                total.amount += quantity;
+                total.value += value;
+            }
+            None => self.fees_in_kind.push(FeeTotal {
+                currency: asset.to_string(),
+                amount: quantity,
+                value,
+            }),
+        }
+    }
+
+    #[inline]
+    fn try_execute_and_remove(
+        &mut self,
+        i: &mut usize,
+        order: &Order,
+        price: f64,
+        current_time: &NaiveDateTime,
+        arrival: (NaiveDateTime, f64),
+    ) {
+        // Beyond the liquidity of the bar only part of the order fills, a FOK order is killed
+        let quantity = self.order_quantity(order, price);
+        let limit = self.fill_limit(*i).filter(|limit| quantity > *limit);
+        let capped = limit.is_some();
+        let mut executed = order.clone();
+        if let Some(limit) = limit {
+            let partial = match self.lot_size {
+                Some(lot_size) => (limit / lot_size + 1e-9).floor() * lot_size,
+                None => limit,
+            };
+            // Orders waiting for the next bar once the volume is used up aren't counted again
+            if partial > self.dust_threshold {
+                self.execution_tracker.record_capped();
+            }
+            if order.time_in_force == TimeInForce::Fok || partial <= self.dust_threshold {
+                self.rest_or_cancel(i, order);
+                return;
+            }
+            executed.size = SizeSpec::Quantity(partial);
+        }
+
+        let RestingOrder {
+            account,
+            reservation,
+            ..
+        } = self.orders[*i];
+        self.held_cash = self.reserved_cash(account) - reservation;
+        let result = self.execute_for(account, executed.clone(), price, current_time);
+        self.held_cash = 0.0;
+        match result {
+            Ok(fill) => {
+                self.analytics.total_exec_orders += 1;
+                self.volume_left -= fill.size;
+                let point_value = self.contract.multiplier * self.contract.rate(fill.price);
+                if !capped {
+                    self.execution_tracker.record_fill(
+                        order,
+                        arrival,
+                        *current_time,
+                        fill.price,
+                        fill.size,
+                        point_value,
+                    );
+                    self.remove_order(*i);
+                    return;
+                }
+
+                self.execution_tracker.record_partial_fill(
+                    order,
+                    arrival,
+                    fill.price,
+                    fill.size,
+                    point_value,
+                );
+                let filled = executed.size.fixed().unwrap_or(fill.size);
+                let remaining = quantity - filled;
+                self.trade_tracker.record_partial_fill(PartialFill {
+                    asset: order.asset.clone(),
+                    direction: order.direction.clone(),
+                    time: *current_time,
+                    price: fill.price,
+                    filled: fill.size,
+                    remaining,
+                });
+                self.orders[*i].reservation *= remaining / quantity;
+                let taken = self.taken_on_bar(*i) + fill.size;
+                self.orders[*i].taken = self.volume_bar.map(|bar| (bar, taken));
+                self.orders[*i].order.size = SizeSpec::Quantity(remaining);
+                self.rest_or_cancel(i, order);
+            }
+            Err(e) => {
+                eprintln!("Failed to execute order: {}", e);
+                self.rest_or_cancel(i, order);
+            }
+        }
+    }
+
+    // Quantity the order `i` can take on the current bar, the least of the volume left to the
+    // orders and of what its participation leaves. None without a liquidity limit
+    fn fill_limit(&self, i: usize) -> Option<f64> {
+        let shared = self.volume_share.map(|_| self.volume_left);
+        let single = self
+            .max_participation
+            .map(|percent| (percent / 100.0 * self.bar_volume - self.taken_on_bar(i)).max(0.0));
+        match (shared, single) {
+            (Some(shared), Some(single)) => Some(shared.min(single)),
+            (shared, single) => shared.or(single),
+        }
+    }
+
+    // Quantity the order `i` already took on the current bar
+    fn taken_on_bar(&self, i: usize) -> f64 {
+        match self.orders[i].taken {
+            Some((bar, taken)) if Some(bar) == self.volume_bar => taken,
+            _ => 0.0,
+        }
+    }
+
+    // Quantity of an order filled at `price`, notional and equity sizes converted as they would
+    // be when it executes
+    fn order_quantity(&self, order: &Order, price: f64) -> f64 {
+        let point_value = self.contract.multiplier * self.contract.rate(price);
+        let equity = self.cash + self.value_at(price);
+        order.size.quantity(price, equity, point_value)
+    }
+
+    // Whether a limit order the bar only touched gets its fill from its place in the queue, drawn
+    // once per bar. The orders the price went through always fill
+    fn clears_queue(&mut self, i: usize, order: &Order, price: f64, bar: &OHLCVData) -> bool {
+        let Some(model) = self.queue_model.clone() else {
+            return true;
+        };
+        let touched = match (&order.order_type, &order.direction) {
+            (OrderType::Limit(limit), OrderDirection::Buy) => model.touches(bar.low, *limit),
+            (OrderType::Limit(limit), OrderDirection::Sell) => model.touches(bar.high, *limit),
+            _ => false,
+        };
+        if !touched {
+            return true;
+        }
+
+        let ahead = match self.orders[i].queue {
+            Some(place) if place.bar == bar.timestamp => return place.filled,
+            Some(place) => place.ahead,
+            None => model.ahead,
+        };
+        let volume = bar.volume as f64;
+        let probability = model.fill_probability(ahead, self.order_quantity(order, price), volume);
+        let filled = self
+            .queue_rng
+            .as_mut()
+            .is_some_and(|rng| rng.random::<f64>() < probability);
+        self.orders[i].queue = Some(QueuePlace {
+            ahead: model.advance(ahead, volume),
+            bar: bar.timestamp,
+            filled,
+        });
+        if !filled {
+            self.execution_tracker.record_queue_miss();
+        }
+        filled
+    }
+
+    // Moves on to the next order, IOC and FOK orders are cancelled instead of resting
+    fn rest_or_cancel(&mut self, i: &mut usize, order: &Order) {
+        match order.time_in_force.is_immediate() {
+            true => {
+                self.remove_order(*i);
+                self.execution_tracker.record_expired(&order.order_type);
+            }
+            false => *i += 1,
+        }
+    }
+
+    #[inline]
+    pub fn handle_unfulfilled_orders(
+        &mut self,
+        current_time: &NaiveDateTime,
+        current_price: &OHLCVData,
+    ) {
+        self.clock = Some(*current_time);
+        self.mark_price = Some(current_price.close);
+        // The ticks on the same bar share its volume
+        if self.volume_bar != Some(current_price.timestamp) {
+            self.volume_bar = Some(current_price.timestamp);
+            self.bar_volume = current_price.volume as f64;
+            self.volume_left = self.volume_share.unwrap_or(0.0) * self.bar_volume;
+        }
+        self.handle_algo_orders(current_time, current_price);
+
+        let mut i = 0;
+        while i < self.orders.len() {
+            let order = self.orders[i].order.clone();
+
+            // Day orders expire at the close of the session they could first trade in, GTC ones
+            // rest across the sessions
+            let expired = match order.time_in_force {
+                TimeInForce::Gtd(until) => current_time > &until,
+                TimeInForce::Day => self.orders[i].arrival.is_some_and(|(arrival, _)| {
+                    current_time >= &self.calendar.day_order_expiry(&order.asset, &arrival)
+                }),
+                _ => false,
+            };
+            if expired {
+                self.remove_order(i);
+                self.execution_tracker.record_expired(&order.order_type);
+                continue;
+            }
+
+            // Orders rest until the asset session opens
+            if !self.calendar.is_open(&order.asset, current_time) {
+                i += 1;
+                continue;
+            }
+
+            let arrival = *self.orders[i]
+                .arrival
+                .get_or_insert((*current_time, current_price.open));
+            let fill_price = order
+                .fill_price(current_price, self.fill_model)
+                .filter(|&price| self.clears_queue(i, &order, price, current_price));
+            match fill_price {
+                Some(fill_price) => {
+                    self.try_execute_and_remove(&mut i, &order, fill_price, current_time, arrival)
+                }
+                None => self.rest_or_cancel(&mut i, &order),
+            }
+        }
+    }
+
+    // Whether the next ticks on the same bar could trade: algo parents slice on every tick, and
+    // resting orders fill on the bar in force once it reaches their price and their session opens.
+    // Flattening positions depends on the time of the tick
+    pub fn can_trade_before(
+        &self,
+        current_time: &NaiveDateTime,
+        current_price: &OHLCVData,
+    ) -> bool {
+        self.algo_orders
+            .iter()
+            .any(|parent| parent.status == ParentStatus::Active)
+            || self
+                .orders
+                .iter()
+                .map(|resting| &resting.order)
+                .any(|order| {
+                    !self.calendar.is_open(&order.asset, current_time)
+                        || order.fill_price(current_price, self.fill_model).is_some()
+                })
+            || (self.flatten.is_some() && !self.portfolio.is_empty())
+    }
+
+    pub fn execution_report(&self, algo_orders: &[AlgoOrderReport]) -> ExecutionReport {
+        let open = |resting: fn(&OrderType) -> bool| {
+            self.orders
+                .iter()
+                .filter(|order| resting(&order.order.order_type))
+                .count() as u32
+        };
+        let open_orders = (
+            open(|order_type| matches!(order_type, OrderType::Limit(_))),
+            open(|order_type| matches!(order_type, OrderType::Stop(_))),
+        );
+        self.execution_tracker.report(open_orders, algo_orders)
+    }
+
+    #[inline]
+    pub fn apply_slippage(&mut self, market_price: f64, direction: &OrderDirection) -> f64 {
+        if self.slippage_values.is_empty() {
+            return market_price;
+        }
+
+        let slippage_percentage =
+            self.slippage_values[self.slippage_index % self.slippage_values.len()];
+        self.slippage_index += 1;
+        // Against the trader, buys fill higher and sells lower
+        match direction {
+            OrderDirection::Buy => market_price * (1.0 + slippage_percentage),
+            OrderDirection::Sell => market_price * (1.0 - slippage_percentage),
+        }
+    }
+
+    // Runs the hooks around the execution
+    fn execute_order(
+        &mut self,
+        order: Order,
+        market_price: f64,
+        current_time: &NaiveDateTime,
+    ) -> Result<Fill, String> {
+        if self.hooks.is_empty() {
+            return self.fill_order(order, market_price, current_time);
+        }
+
+        let mut hooks = std::mem::take(&mut self.hooks);
+        let price = hooks.iter_mut().try_fold(market_price, |price, hook| {
+            hook.on_before_execute(&order, price, current_time)
+        });
+        let result = price.and_then(|price| self.fill_order(order.clone(), price, current_time));
+        for hook in hooks.iter_mut() {
+            match &result {
+                Ok(fill) => hook.on_fill(fill),
+                Err(reason) => hook.on_reject(&order, reason),
+            }
+        }
+        self.hooks = hooks;
+        result
+    }
+
+    fn fill_order(
+        &mut self,
+        order: Order,
+        market_price: f64,
+        current_time: &NaiveDateTime,
+    ) -> Result<Fill, String> {
+        let execution_price = self.apply_slippage(market_price, &order.direction);
+        let slippage_diff = execution_price - market_price;
+
+        // Per unit, positive when the fill is better than the requested price
+        let price_improvement = match order.order_type {
+            OrderType::Market => None,
+            OrderType::Limit(price) | OrderType::Stop(price) => match order.direction {
+                OrderDirection::Buy => Some(price - execution_price),
+                OrderDirection::Sell => Some(execution_price - price),
+            },
+        };
+
+        // Account currency value of a one point move for one unit of order size
+        let point_value = self.contract.multiplier * self.contract.rate(execution_price);
+
+        // Cash and equity sizes are converted at the execution price
+        let equity = self.cash + self.value_at(market_price);
+        let mut size = order.size.quantity(execution_price, equity, point_value);
+        if !(size.is_finite() && size > 0.0) {
+            return Err("Invalid order size".to_string());
+        }
+        if let Some(lot_size) = self.lot_size {
+            let held = self.portfolio.get(&order.asset).map_or(0.0, |p| p.quantity);
+            let closing = order.direction == OrderDirection::Sell
+                && (held - size).abs() <= self.dust_threshold;
+            if !closing {
+                // The tolerance keeps quantities computed as whole lots from losing one
+                size = (size / lot_size + 1e-9).floor() * lot_size;
+                if size <= 0.0 {
+                    return Err("Order size below the lot size".to_string());
+                }
+            }
+        }
+
+        match order.direction {
+            OrderDirection::Buy => {
+                // Fees in kind are paid with part of the quantity bought instead of the cash
+                let in_kind = self.fee_currency == FeeCurrency::Asset && !self.contract.margin;
+                let (mut total_cost, mut fees, mut fee_quantity, mut total_spent) =
+                    self.buy_costs(size, execution_price, point_value, in_kind);
+                let requested = size;
+                let downsize = self.cash_shortfall == CashShortfall::Downsize
+                    && order.time_in_force != TimeInForce::Fok
+                    && !self.contract.margin
+                    && self.margin_account.is_none();
+                if downsize && total_spent > self.free_cash() {
+                    if let Some(affordable) =
+                        self.affordable_size(size, execution_price, point_value, in_kind)
+                    {
+                        size = affordable;
+                        (total_cost, fees, fee_quantity, total_spent) =
+                            self.buy_costs(size, execution_price, point_value, in_kind);
+                    }
+                }
+                if size <= fee_quantity {
+                    return Err("The fees exceed the quantity bought".to_string());
+                }
+
+                if let Some(requirement) = self.contract.margin_requirement {
+                    let position = self.portfolio.get(&order.asset);
+                    let quantity = position.map(|p| p.quantity).unwrap_or(0.0) + size;
+                    let unrealized = position
+                        .map(|p| (execution_price - p.average_price) * p.quantity * point_value)
+                        .unwrap_or(0.0);
+                    let equity = self.cash + unrealized - fees;
+                    if equity < requirement.initial * quantity * execution_price * point_value {
+                        return Err("Not enough margin".to_string());
+                    }
+                }
+
+                let position = self.portfolio.get(&order.asset);
+                let held = position.map_or(0.0, |p| p.quantity);
+                self.limits.check(
+                    position.is_none(),
+                    self.portfolio.len(),
+                    (held + size) * execution_price * point_value,
+                    equity,
+                )?;
+
+                // Margin accounts borrow the cash missing as long as the equity covers the margin
+                let affordable = match (&self.margin_account, self.contract.margin) {
+                    (Some(account), false) => {
+                        let long = self.long_value(execution_price) + total_cost;
+                        account.covers(self.free_cash() - total_spent + long, long)
+                    }
+                    _ => self.free_cash() >= total_spent,
+                };
+                if affordable {
+                    if size < requested {
+                        self.downsized_orders.push(DownsizedOrder {
+                            asset: order.asset.clone(),
+                            time: *current_time,
+                            requested,
+                            filled: size,
+                        });
+                    }
+                    self.cash -= total_spent;
+                    if in_kind {
+                        size -= fee_quantity;
+                        self.record_fee_in_kind(&order.asset, fee_quantity, fees);
+                    }
+
+                    let position = self
+                        .portfolio
+                        .entry(order.asset.clone())
+                        .or_insert_with(|| Position::new(0.0, execution_price));
+
+                    position.update(size, execution_price);
+
+                    self.trade_tracker.record_buy(
+                        &order.asset,
+                        *current_time,
+                        execution_price,
+                        size,
+                        fees,
+                        slippage_diff,
+                        price_improvement,
+                        point_value,
+                    );
+                    // A stop resting before the entry protects it as well
+                    let resting_stop = self.orders.iter().map(|resting| &resting.order).find_map(
+                        |resting| match (&resting.direction, &resting.order_type) {
+                            (OrderDirection::Sell, OrderType::Stop(price))
+                                if resting.asset == order.asset =>
+                            {
+                                Some(*price)
+                            }
+                            _ => None,
+                        },
+                    );
+                    if let Some(stop_price) = resting_stop {
+                        self.trade_tracker.attach_stop(&order.asset, stop_price);
+                    }
+                    let fill = Fill {
+                        asset: order.asset,
+                        direction: order.direction,
+                        time: *current_time,
+                        price: execution_price,
+                        size,
+                        fees,
+                        slippage: slippage_diff.abs(),
+                    };
+                    self.fills.push(fill.clone());
+                    Ok(fill)
+                } else if self.margin_account.is_some() {
+                    Err("Not enough margin".to_string())
+                } else {
+                    Err("Not enough cash".to_string())
+                }
+            }
+            OrderDirection::Sell => {
+                let Some(position) = self.portfolio.get(&order.asset) else {
+                    return Err("Position not found in portfolio".to_string());
+                };
+                let average_price = position.average_price;
+
+                // Fractional sizes rarely add up exactly, sell the residual along with the order
+                let residual = position.quantity - size;
+                if residual < -self.dust_threshold {
+                    return Err("Not enough quantity to sell".to_string());
+                }
+                if residual != 0.0 && residual.abs() <= self.dust_threshold {
+                    size = position.quantity;
+                    self.dust_closures.push(DustClosure {
+                        asset: order.asset.clone(),
+                        time: *current_time,
+                        quantity: residual,
+                    });
+                }
+
+                let total_raw_value = size * execution_price * point_value;
+                let fees = self.calculate_fees(total_raw_value, size);
+
+                let total_value = match self.contract.margin {
+                    true => (execution_price - average_price) * size * point_value - fees,
+                    false => total_raw_value - fees,
+                };
+
+                let position = self
+                    .portfolio
+                    .get_mut(&order.asset)
+                    .expect("Position checked above");
+                position.remove(size)?;
+                let closed = position.quantity == 0.0;
+                self.cash += total_value;
+
+                self.trade_tracker.record_sell(
+                    &order.asset,
+                    *current_time,
+                    execution_price,
+                    size,
+                    fees,
+                    market_price - execution_price,
+                    price_improvement,
+                    point_value,
+                );
+
+                if closed {
+                    self.portfolio.remove(&order.asset);
+                }
+                let fill = Fill {
+                    asset: order.asset,
+                    direction: order.direction,
+                    time: *current_time,
+                    price: execution_price,
+                    size,
+                    fees,
+                    slippage: slippage_diff.abs(),
+                };
+                self.fills.push(fill.clone());
+                Ok(fill)
+            }
+        }
+    }
+
+    // Hedges the net exposure back into its band and records it, called on every tick after the
+    // orders are matched
+    pub fn manage_exposure(&mut self, current_time: &NaiveDateTime, current_price: &OHLCVData) {
+        if self.warming_up() {
+            return;
+        }
+        let Some(mut exposure) = self.exposure.take() else {
+            return;
+        };
+
+        let point_value = self.contract.multiplier * self.contract.rate(current_price.close);
+        let long: f64 = self
+            .portfolio
+            .values()
+            .map(|position| position.quantity * current_price.close * point_value)
+            .sum();
+        // The monitor is taken out, `value_at` leaves the hedge out
+        let unhedged_equity = self.cash + self.value_at(current_price.close);
+
+        if let Some(hedge) = exposure.hedge.as_mut() {
+            if let Some(price) = hedge.mark(current_time) {
+                let short = hedge.liability();
+                // Trading the hedge moves cash against the short, the equity only pays the fees
+                let equity = unhedged_equity - short;
+                if let Some(target) = exposure.band.hedge_target(long, short, equity) {
+                    let quantity = target / price;
+                    let traded = (quantity - hedge.quantity).abs();
+                    let fees = self.calculate_fees(traded * price, traded);
+                    self.cash += hedge.rebalance(quantity, current_time, fees);
+                }
+            }
+        }
+
+        let short = exposure
+            .hedge
+            .as_ref()
+            .map_or(0.0, |hedge| hedge.liability());
+        let equity = self.cash + self.value_at(current_price.close) - short;
+        exposure.record(*current_time, long, short, equity);
+        self.exposure = Some(exposure);
+    }
+
+    pub fn exposure_report(&self) -> Option<ExposureReport> {
+        self.exposure.as_ref().map(ExposureMonitor::report)
+    }
+
+    // Rebalances the beta hedge on every new bar, called on every tick after the orders are matched
+    pub fn manage_beta_hedge(&mut self, current_time: &NaiveDateTime, current_price: &OHLCVData) {
+        if self.warming_up() {
+            return;
+        }
+        let Some(mut overlay) = self.beta_hedge.take() else {
+            return;
+        };
+
+        let point_value = self.contract.multiplier * self.contract.rate(current_price.close);
+        let long: f64 = self
+            .portfolio
+            .values()
+            .map(|position| position.quantity * current_price.close * point_value)
+            .sum();
+        if let (Some(price), Some(target)) = (
+            overlay.book.mark(current_time),
+            overlay.target(current_price, long),
+        ) {
+            let quantity = target / price;
+            let traded = (quantity - overlay.book.quantity).abs();
+            let fees = self.calculate_fees(traded * price, traded);
+            self.cash += overlay.book.rebalance(quantity, current_time, fees);
+        }
+        self.beta_hedge = Some(overlay);
+    }
+
+    pub fn beta_hedge_report(&self) -> Option<BetaHedgeReport> {
+        let equity_curve = self.trade_tracker.get_equity_curve();
+        self.beta_hedge
+            .as_ref()
+            .map(|overlay| overlay.report(equity_curve))
+    }
+
+    fn hedges(&self) -> impl Iterator<Item = &HedgeBook> {
+        let band = self.exposure.as_ref().and_then(|e| e.hedge.as_ref());
+        band.into_iter()
+            .chain(self.beta_hedge.as_ref().map(|overlay| &overlay.book))
+    }
+
+    // Cash the hedges brought in, 0 without one
+    pub fn hedge_cash_flow(&self) -> f64 {
+        self.hedges().map(HedgeBook::cash_flow).sum()
+    }
+
+    // Return the total value of all the positions at the current market price
+    pub fn portfolio_value(&self, data: &OHLCVData) -> f64 {
+        self.value_at(data.close)
+    }
+
+    // Value of the positions of a cash settled asset, leaving the hedges out
+    fn long_value(&self, current_price: f64) -> f64 {
+        let point_value = self.contract.multiplier * self.contract.rate(current_price);
+        self.portfolio
+            .values()
+            .map(|position| position.quantity * current_price * point_value)
+            .sum()
+    }
+
+    fn value_at(&self, current_price: f64) -> f64 {
+        let mut total_value = 0.0;
+        let point_value = self.contract.multiplier * self.contract.rate(current_price);
+
+        for position in self.portfolio.values() {
+            total_value += match self.contract.margin {
+                true => (current_price - position.average_price) * position.quantity * point_value,
+                false => position.quantity * current_price * point_value,
+            };
+            //println!(
+            //    "Asset: {}, Quantity: {}, Price: {}",
+            //    asset, position.quantity, current_price
+            //);
+        }
+
+        // Hedges are marked at their own price
+        for hedge in self.hedges() {
+            total_value -= hedge.liability();
+        }
+
+        total_value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::preset::BrokerPreset;
+    use chrono::NaiveDateTime;
+
+    fn create_dummy_price(open: f64, high: f64, low: f64, close: f64) -> OHLCVData {
+        OHLCVData {
+            timestamp: NaiveDateTime::parse_from_str("1999-11-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+                .expect("Invalid date"),
+            open,
+            high,
+            low,
+            close,
+            volume: 1000,
+        }
+    }
+
+    fn create_dummy_date(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").expect("Invalid date")
+    }
+
+    #[test]
+    fn is_order_placed() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.place_order(order);
+
+        assert_eq!(broker.analytics.total_placed_orders, 1);
+        assert_eq!(broker.analytics.total_exec_orders, 0);
+        assert_eq!(broker.orders.len(), 1);
+        assert_eq!(broker.orders[0].order.asset, "AAPL");
+        assert_eq!(broker.orders[0].order.direction, OrderDirection::Buy);
+        assert_eq!(broker.orders[0].order.size, SizeSpec::Quantity(1.0));
+        assert_eq!(broker.orders[0].order.order_type, OrderType::Market);
+    }
+
+    #[test]
+    fn is_buy_market_order_executed() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_cash(1000.0);
+        broker.set_fees(FeeType::Flat(1.0));
+        broker.place_order(order);
+
+        // Simulate next tick
+        let dummy_price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+
+        // Check the cash in our balance after the execution (order price + fees)
+        assert_eq!(broker.cash, 899.0);
+        assert_eq!(broker.portfolio_value(&dummy_price), 99.0);
+
+        // Check if the asset is in the portfolio
+        let position = broker.portfolio.get("AAPL").unwrap();
+        assert_eq!(position.quantity, 1.0);
+        assert_eq!(position.average_price, 100.0);
+    }
+
+    #[test]
+    fn not_enough_cash() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_fees(FeeType::Flat(1.0));
+        broker.place_order(order);
+
+        // Simulate next tick
+        let dummy_price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+
+        // Check the cash in our balance after the execution (order price + fees)
+        assert_eq!(broker.cash, 0.0);
+        assert_eq!(broker.portfolio_value(&dummy_price), 0.0);
+
+        // Check if there is no assets in the portolio
+        assert!(!broker.portfolio.contains_key("AAPL"));
+    }
+
+    #[test]
+    fn add_to_existing_position() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_cash(1000.0);
+        broker.set_fees(FeeType::Flat(1.0));
+        broker
+            .portfolio
+            .insert("AAPL".to_string(), Position::new(1.0, 100.0));
+
+        broker.place_order(order);
+
+        // Simulate next tick
+        let dummy_price = create_dummy_price(110.0, 111.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+
+        // Check if the assets are in the portfolio
+        let position = broker.portfolio.get("AAPL").unwrap();
+        assert_eq!(position.quantity, 2.0);
+
+        // Calculate the new average price: (100 * 1 + 110 * 1) / 2 = 105
+        assert_eq!(position.average_price, 105.0);
+    }
+
+    #[test]
+    fn fractional_sell_closes_dust() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        let order = |direction, size| Order {
+            asset: "BTCUSDT".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        // 0.1 + 0.2 leaves a few units of dust once 0.3 is sold
+        for (day, (direction, size)) in [
+            (OrderDirection::Buy, 0.1),
+            (OrderDirection::Buy, 0.2),
+            (OrderDirection::Sell, 0.3),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            broker.place_order(order(direction, size));
+            let date = format!("1999-11-0{} 00:00:00", day + 1);
+            broker.handle_unfulfilled_orders(&create_dummy_date(&date), &price);
+        }
+
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.dust_closures.len(), 1);
+        assert!(broker.dust_closures[0].quantity.abs() < 1e-15);
+        assert_eq!(broker.trade_tracker.get_closed_trades().len(), 2);
+        assert_eq!(broker.cash, 1000.0);
+    }
+
+    #[test]
+    fn cash_and_equity_sizes_resolve_at_execution() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        let order = |direction, size| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        broker.place_order(order(OrderDirection::Buy, SizeSpec::NotionalCash(500.0)));
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 5.0);
+
+        // Equity of 500 + 5 * 200 at the open, 20% of it sells 1.5 units
+        broker.place_order(order(OrderDirection::Sell, SizeSpec::PercentEquity(20.0)));
+        let price = create_dummy_price(200.0, 201.0, 198.0, 199.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 3.5);
+        assert_eq!(broker.cash, 800.0);
+    }
+
+    #[test]
+    fn close_all_positions_sells_the_book() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        for (asset, quantity) in [("AAPL", 2.5), ("MSFT", 1.0)] {
+            broker
+                .portfolio
+                .insert(asset.to_string(), Position::new(quantity, 100.0));
+        }
+
+        assert!(!broker.close_position("TSLA"));
+        assert_eq!(broker.close_all_positions(), 2);
+        assert_eq!(broker.orders[0].order.size, SizeSpec::Quantity(2.5));
+
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.cash, 1350.0);
+    }
+
+    #[test]
+    fn trades_measure_their_risk_from_the_stop() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        let order = |direction, order_type| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(10.0),
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        // The protective stop rests before the entry fills
+        broker.place_order(order(OrderDirection::Sell, OrderType::Stop(95.0)));
+        broker.place_order(order(OrderDirection::Buy, OrderType::Market));
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        broker.place_order(order(OrderDirection::Sell, OrderType::Market));
+        let price = create_dummy_price(110.0, 111.0, 109.0, 110.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &price);
+
+        let trade = &broker.trade_tracker.get_closed_trades()[0];
+        assert_eq!(trade.initial_stop_distance, Some(5.0));
+        assert_eq!(trade.r_multiple, Some(2.0));
+    }
+
+    #[test]
+    fn trades_split_their_costs() {
+        let mut broker = Broker::new();
+        broker.set_cash(2000.0);
+        broker.set_fees(FeeType::Flat(1.0));
+        broker.set_slippage(0.01, 0.01);
+        let order = |direction, size| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        broker.place_order(order(OrderDirection::Buy, 10.0));
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        broker.place_order(order(OrderDirection::Sell, 4.0));
+        let price = create_dummy_price(110.0, 111.0, 109.0, 110.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &price);
+
+        // Bought at 101 instead of 100, sold at 108.9 instead of 110
+        let trade = &broker.trade_tracker.get_closed_trades()[0];
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(trade.entry_fees, 0.4) && close(trade.exit_fees, 1.0));
+        assert!(close(trade.entry_slippage, 4.0) && close(trade.exit_slippage, 4.4));
+        assert!(close(trade.profit_loss.unwrap(), 30.2));
+        assert!(close(broker.fills[1].price, 108.9));
+        assert!(close(trade.gross_profit_loss.unwrap(), 40.0));
+    }
+
+    #[test]
+    fn fees_in_kind_reduce_the_quantity_bought() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_fees(FeeType::Percentage(0.01));
+        broker.set_fee_currency(FeeCurrency::Asset);
+        let order = |direction, size| Order {
+            asset: "BTC".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        broker.place_order(order(OrderDirection::Buy, 5.0));
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        // 1% of the 5 bought is kept by the exchange, the cash only pays the 500 of the buy
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(broker.cash, 500.0));
+        assert!(close(broker.portfolio["BTC"].quantity, 4.95));
+
+        broker.place_order(order(OrderDirection::Sell, 4.95));
+        let price = create_dummy_price(110.0, 111.0, 109.0, 110.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &price);
+        // The sell pays 1% of the 544.5 received in cash
+        assert!(close(broker.cash, 500.0 + 544.5 - 5.445));
+        let trade = &broker.trade_tracker.get_closed_trades()[0];
+        assert!(close(trade.profit_loss.unwrap(), 39.055));
+
+        let totals = broker.fee_totals();
+        assert_eq!(totals[0].currency, "account");
+        assert!(close(totals[0].amount, 5.445));
+        assert_eq!(totals[1].currency, "BTC");
+        assert!(close(totals[1].amount, 0.05) && close(totals[1].value, 5.0));
+        assert!(crate::analytics::reconciliation::reconcile(&broker, 110.0).consistent);
+    }
+
+    #[test]
+    fn preset_rounds_to_lots_and_charges_per_share() {
+        let preset = BrokerPreset::named("ibkr_pro_us_equities").unwrap();
+        let mut broker = Broker::new();
+        broker.set_cash(10000.0);
+        broker.set_fees(preset.fees);
+        broker.set_lot_size(preset.lot_size);
+        let order = |direction, size| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+
+        // 10.7 shares are rounded down to 10, paying the 1 USD minimum instead of 0.05
+        broker.place_order(order(OrderDirection::Buy, 10.7));
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 10.0);
+        assert_eq!(broker.cash, 10000.0 - 1000.0 - 1.0);
+
+        // Under a lot
+        broker.place_order(order(OrderDirection::Buy, 0.5));
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &price);
+        assert_eq!(broker.fills.len(), 1);
+
+        // A 0.1 USD stock pays 1% of the trade value at most
+        let penny = create_dummy_price(0.1, 0.1, 0.1, 0.1);
+        broker.place_order(order(OrderDirection::Buy, 5000.0));
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-03 00:00:00"), &penny);
+        assert!((broker.fills[1].fees - 5.0).abs() < 1e-9);
+
+        // Selling the whole position isn't rounded
+        broker.portfolio.get_mut("AAPL").unwrap().quantity += 0.25;
+        broker.place_order(order(OrderDirection::Sell, 5010.25));
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-04 00:00:00"), &penny);
+        assert!(broker.portfolio.is_empty());
+    }
+
+    #[test]
+    fn position_limits_reject_buys() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_limits(PositionLimits {
+            max_positions: Some(1),
+            max_position_percent: Some(50.0),
+        });
+        let order = |asset: &str, size| Order {
+            asset: asset.to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+
+        // 40% of the equity, then a second asset and a position above 50%
+        broker.place_order(order("AAPL", 4.0));
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &price);
+        broker.place_order(order("MSFT", 1.0));
+        broker.place_order(order("AAPL", 2.0));
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &price);
+
+        assert_eq!(broker.portfolio.len(), 1);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 4.0);
+        assert_eq!(broker.orders.len(), 2);
+    }
+
+    #[test]
+    fn order_waits_for_session_open() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_cash(1000.0);
+        let mut calendar = Calendar::new();
+        calendar.add_session("AAPL", crate::calendar::Session::preset("XNAS").unwrap());
+        broker.set_calendar(calendar);
+        broker.place_order(order);
+
+        let dummy_price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+
+        // Saturday, the market is closed
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-06 15:00:00"), &dummy_price);
+        assert_eq!(broker.orders.len(), 1);
+
+        // Monday 08:00 in New York, before the open
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-08 13:00:00"), &dummy_price);
+        assert_eq!(broker.orders.len(), 1);
+
+        // Monday 10:00 in New York
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-08 15:00:00"), &dummy_price);
+        assert_eq!(broker.orders.len(), 0);
+        assert_eq!(broker.portfolio.get("AAPL").unwrap().quantity, 1.0);
+    }
+
+    #[test]
+    fn buy_limit_filled_when_low_touches_limit() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Limit(99.0),
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_cash(1000.0);
+        broker.place_order(order);
+
+        // The open is above the limit but the price dips to it during the bar
+        let dummy_price = create_dummy_price(100.0, 101.0, 98.0, 99.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+
+        assert_eq!(broker.orders.len(), 0);
+        assert_eq!(broker.portfolio.get("AAPL").unwrap().average_price, 99.0);
+        assert_eq!(broker.trade_tracker.total_price_improvement, 0.0);
+    }
+
+    #[test]
+    fn limit_gap_records_price_improvement() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(2.0),
+            order_type: OrderType::Limit(99.0),
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_cash(1000.0);
+        broker.place_order(order);
+
+        // Gap down below the limit, filled at the open
+        let dummy_price = create_dummy_price(95.0, 96.0, 94.0, 95.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+
+        assert_eq!(broker.portfolio.get("AAPL").unwrap().average_price, 95.0);
+        assert_eq!(broker.trade_tracker.total_price_improvement, 8.0);
+    }
+
+    #[test]
+    fn sell_stop_gap_fills_at_open() {
+        let mut broker = Broker::new();
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Sell,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Stop(90.0),
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        broker.set_cash(1000.0);
+        broker
+            .portfolio
+            .insert("AAPL".to_string(), Position::new(1.0, 100.0));
+        broker.place_order(order);
+
+        let dummy_price = create_dummy_price(92.0, 93.0, 91.0, 92.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-01 00:00:00"), &dummy_price);
+        assert_eq!(broker.orders.len(), 1);
+
+        // Gap down through the stop, the fill is worse than the stop price
+        let dummy_price = create_dummy_price(85.0, 86.0, 84.0, 85.0);
+        broker.handle_unfulfilled_orders(&create_dummy_date("1999-11-02 00:00:00"), &dummy_price);
+        assert_eq!(broker.cash, 1085.0);
+        assert_eq!(broker.trade_tracker.total_price_improvement, -5.0);
+    }
+
+    #[test]
+    fn is_sell_market_order_executed() {
         let mut broker = Broker::new();
         let order = Order {
             asset: "AAPL".to_string(),
             direction: OrderDirection::Sell,
-            size: 1.0,
+            size: SizeSpec::Quantity(1.0),
             order_type: OrderType::Market,
-            valid_until: None,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
         };
         broker.set_cash(1000.0);
         broker.set_fees(FeeType::Flat(1.0));
@@ -411,4 +2308,660 @@ mod tests {
 
         assert_eq!(broker.portfolio.len(), 0);
     }
+
+    #[test]
+    fn forex_margin_pnl_and_swap_in_base_currency() {
+        use crate::broker::contract::{Conversion, Rollover};
+
+        let mut broker = Broker::new();
+        broker.set_cash(10000.0);
+        // EUR/USD traded from a EUR account, +0.5 pips per lot and night
+        broker.set_contract(Contract {
+            multiplier: 100_000.0,
+            margin: true,
+            conversion: Conversion::Base,
+            rollover: Some(Rollover {
+                time: chrono::NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+                timezone: chrono_tz::America::New_York,
+                long_rate: 0.5 * 0.0001,
+                triple_day: chrono::Weekday::Wed,
+            }),
+            ..Contract::default()
+        });
+
+        let order = |direction| Order {
+            asset: "EURUSD".to_string(),
+            direction,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        // Tuesday noon in New York
+        let price = create_dummy_price(1.10, 1.10, 1.10, 1.10);
+        broker.settle(&create_dummy_date("2024-01-02 17:00:00"), &price);
+        broker.place_order(order(OrderDirection::Buy));
+        broker.handle_unfulfilled_orders(&create_dummy_date("2024-01-02 17:00:00"), &price);
+        assert_eq!(broker.cash, 10000.0);
+
+        // Tuesday and Wednesday (triple) rollovers: 4 nights * 5 USD converted at 1.10
+        let price = create_dummy_price(1.12, 1.12, 1.12, 1.12);
+        broker.settle(&create_dummy_date("2024-01-04 17:00:00"), &price);
+        assert!((broker.trade_tracker.total_carry - 20.0 / 1.12).abs() < 1e-9);
+        assert!((broker.portfolio_value(&price) - 2000.0 / 1.12).abs() < 1e-9);
+
+        broker.place_order(order(OrderDirection::Sell));
+        broker.handle_unfulfilled_orders(&create_dummy_date("2024-01-04 17:00:00"), &price);
+        assert!((broker.cash - (10000.0 + 2020.0 / 1.12)).abs() < 1e-9);
+
+        let trade = &broker.trade_tracker.get_closed_trades()[0];
+        assert!((trade.profit_loss.unwrap() - 2000.0 / 1.12).abs() < 1e-9);
+    }
+
+    #[test]
+    fn perpetual_funding_and_liquidation() {
+        use crate::broker::contract::{Funding, MarginRequirement};
+
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        // 10x leverage, 0.01% funding every 8 hours
+        broker.set_contract(Contract {
+            margin: true,
+            margin_requirement: Some(MarginRequirement {
+                initial: 0.1,
+                maintenance: 0.005,
+            }),
+            funding: Some(Funding::Constant {
+                rate: 0.0001,
+                interval: chrono::Duration::hours(8),
+            }),
+            ..Contract::default()
+        });
+
+        let order = |size| Order {
+            asset: "BTCUSDT".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        let price = create_dummy_price(10000.0, 10000.0, 10000.0, 10000.0);
+        broker.settle(&create_dummy_date("2024-01-01 00:00:00"), &price);
+        broker.place_order(order(2.0));
+        broker.place_order(order(1.0));
+        broker.handle_unfulfilled_orders(&create_dummy_date("2024-01-01 00:00:00"), &price);
+        // Only the order covered by the initial margin is filled
+        assert_eq!(broker.portfolio["BTCUSDT"].quantity, 1.0);
+
+        // Two funding payments on a 10 000 notional
+        broker.settle(&create_dummy_date("2024-01-01 16:00:00"), &price);
+        assert!((broker.trade_tracker.total_carry + 2.0).abs() < 1e-9);
+        let liquidation_price = (10000.0 - 998.0) / 0.995;
+        assert!((broker.liquidation_price("BTCUSDT").unwrap() - liquidation_price).abs() < 1e-9);
+
+        let crash = create_dummy_price(9500.0, 9500.0, 9000.0, 9200.0);
+        broker.settle(&create_dummy_date("2024-01-01 17:00:00"), &crash);
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.analytics.total_liquidations, 1);
+        assert!((broker.cash - (998.0 + liquidation_price - 10000.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn margin_account_borrows_and_liquidates() {
+        use crate::broker::sweep::AnnualRate;
+
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_margin_account(MarginAccount {
+            leverage: 2.0,
+            maintenance_margin: 0.25,
+            interest_rate: Some(AnnualRate::Constant(0.0365)),
+        });
+        let order = |size| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        let price = create_dummy_price(100.0, 100.0, 100.0, 100.0);
+        broker.settle(&create_dummy_date("2024-01-01 00:00:00"), &price);
+        broker.place_order(order(15.0));
+        broker.place_order(order(6.0));
+        broker.handle_unfulfilled_orders(&create_dummy_date("2024-01-01 00:00:00"), &price);
+        // 2100 of positions would need more than 1000 of equity
+        assert_eq!(broker.portfolio["AAPL"].quantity, 15.0);
+        assert_eq!(broker.loan(), 500.0);
+        assert_eq!(broker.buying_power(), 500.0);
+
+        // A day of interest on the loan
+        broker.settle(&create_dummy_date("2024-01-02 00:00:00"), &price);
+        assert!((broker.cash + 500.05).abs() < 1e-9);
+        let liquidation_price = 500.05 / (15.0 * 0.75);
+        assert!((broker.liquidation_price("AAPL").unwrap() - liquidation_price).abs() < 1e-9);
+
+        // Sold at the liquidation price, on the same tick so without more interest
+        let crash = create_dummy_price(50.0, 50.0, 40.0, 45.0);
+        broker.settle(&create_dummy_date("2024-01-02 00:00:00"), &crash);
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.analytics.total_liquidations, 1);
+        assert!((broker.cash - (15.0 * liquidation_price - 500.05)).abs() < 1e-9);
+        let report = broker.margin_report().unwrap();
+        assert_eq!(report.loan, 0.0);
+        assert!((report.interest_paid - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sub_accounts_trade_their_own_cash() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.add_account("trend".to_string(), 500.0).unwrap();
+        broker.add_account("reversion".to_string(), 300.0).unwrap();
+        assert!(broker.add_account("carry".to_string(), 300.0).is_err());
+        let order = |direction, size| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        // The second sub-account can't afford its order
+        broker.enter_account(0);
+        broker.place_order(order(OrderDirection::Buy, 4.0));
+        broker.enter_account(1);
+        broker.place_order(order(OrderDirection::Buy, 4.0));
+        broker.leave_account();
+        let time = create_dummy_date("2024-01-01 00:00:00");
+        broker.handle_unfulfilled_orders(&time, &create_dummy_price(100.0, 100.0, 100.0, 100.0));
+        assert_eq!(broker.cash, 600.0);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 4.0);
+        assert_eq!(broker.orders.len(), 1);
+
+        // A strategy only sees the positions of its sub-account
+        broker.enter_account(1);
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.cash, 300.0);
+        broker.orders.clear();
+        broker.enter_account(0);
+        assert_eq!(broker.cash, 100.0);
+        broker.place_order(order(OrderDirection::Sell, 1.0));
+        broker.leave_account();
+        let time = create_dummy_date("2024-01-02 00:00:00");
+        broker.handle_unfulfilled_orders(&time, &create_dummy_price(110.0, 110.0, 110.0, 110.0));
+        assert_eq!(broker.cash, 710.0);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 3.0);
+
+        // Liquidated at the end, the proceeds go back to the sub-account
+        broker.liquidate(&create_dummy_price(120.0, 120.0, 120.0, 120.0));
+        let reports = broker.account_reports(120.0);
+        assert_eq!(reports[0].cash, 570.0);
+        assert_eq!(reports[0].net_profit, 70.0);
+        assert_eq!(reports[0].executed_orders, 3);
+        assert_eq!(reports[1].net_profit, 0.0);
+        assert_eq!(broker.cash, 1070.0);
+    }
+
+    #[test]
+    fn downsizes_the_buys_the_cash_does_not_cover() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_fees(FeeType::Flat(10.0));
+        broker.set_lot_size(2.0);
+        let order = |size| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let time = create_dummy_date("2024-01-01 00:00:00");
+        let price = create_dummy_price(100.0, 100.0, 100.0, 100.0);
+
+        broker.place_order(order(20.0));
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert_eq!(broker.orders.len(), 1);
+        assert!(broker.portfolio.is_empty());
+
+        // 9.9 units are affordable with the fee, 8 in whole lots
+        broker.set_cash_shortfall(CashShortfall::Downsize);
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 8.0);
+        assert_eq!(broker.cash, 190.0);
+        let downsized = &broker.downsized_orders[0];
+        assert_eq!((downsized.requested, downsized.filled), (20.0, 8.0));
+
+        // Less than a lot left
+        broker.place_order(order(2.0));
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert_eq!(broker.orders.len(), 1);
+        assert_eq!(broker.downsized_orders.len(), 1);
+    }
+
+    #[test]
+    fn refuses_the_buys_beyond_the_buying_power() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_fees(FeeType::Flat(1.0));
+        broker.set_check_buying_power(true);
+        let order = |direction, size| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let time = create_dummy_date("2024-01-01 00:00:00");
+        let price = create_dummy_price(100.0, 100.0, 100.0, 100.0);
+        broker.handle_unfulfilled_orders(&time, &price);
+
+        // 10 units would cost 1001 with the fee
+        broker.place_order(order(OrderDirection::Buy, 9.0));
+        assert!(!broker.last_order_rejected);
+        broker.place_order(order(OrderDirection::Buy, 10.0));
+        assert!(broker.last_order_rejected);
+        broker.place_order(order(OrderDirection::Sell, 4.0));
+        assert!(!broker.last_order_rejected);
+        assert_eq!(broker.orders.len(), 2);
+        assert_eq!(broker.execution_report(&[]).rejected_orders, 1);
+    }
+
+    #[test]
+    fn resting_buys_reserve_their_cash() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_fees(FeeType::Flat(1.0));
+        broker.set_reserve_cash(true);
+        let order = |size, order_type, time_in_force| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type,
+            time_in_force,
+            placed_at: None,
+        };
+        let first = create_dummy_date("2024-01-01 00:00:00");
+        let second = create_dummy_date("2024-01-02 00:00:00");
+        let price = create_dummy_price(100.0, 100.0, 95.0, 100.0);
+
+        // The limit sets 451 aside, leaving 549 for the market buy of 601
+        broker.place_order(order(5.0, OrderType::Limit(90.0), TimeInForce::Gtd(first)));
+        assert_eq!(broker.reserved_cash(None), 451.0);
+        broker.place_order(order(6.0, OrderType::Market, TimeInForce::Gtc));
+        broker.handle_unfulfilled_orders(&first, &price);
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.buying_power(), 549.0);
+
+        // Released once the limit expires
+        broker.handle_unfulfilled_orders(&second, &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 6.0);
+        assert_eq!(broker.reserved_cash(None), 0.0);
+        assert_eq!(broker.execution_report(&[]).peak_reserved_cash, 451.0);
+    }
+
+    #[test]
+    fn time_in_force_cancels_and_expires_orders() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_cash_shortfall(CashShortfall::Downsize);
+        let order = |size, order_type, time_in_force| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type,
+            time_in_force,
+            placed_at: None,
+        };
+        let first = create_dummy_date("2024-01-01 00:00:00");
+        let second = create_dummy_date("2024-01-02 00:00:00");
+        let price = create_dummy_price(100.0, 100.0, 95.0, 100.0);
+
+        // The IOC limit isn't reached and the FOK buy can't be downsized, both are cancelled
+        broker.place_order(order(1.0, OrderType::Limit(90.0), TimeInForce::Ioc));
+        broker.place_order(order(20.0, OrderType::Market, TimeInForce::Fok));
+        broker.place_order(order(1.0, OrderType::Limit(90.0), TimeInForce::Day));
+        broker.place_order(order(1.0, OrderType::Limit(90.0), TimeInForce::Gtc));
+        broker.handle_unfulfilled_orders(&first, &price);
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.orders.len(), 2);
+
+        // The day order is gone with the session of the first day
+        broker.handle_unfulfilled_orders(&second, &price);
+        assert_eq!(broker.orders.len(), 1);
+        assert_eq!(broker.orders[0].order.time_in_force, TimeInForce::Gtc);
+        assert_eq!(broker.execution_report(&[]).limit_orders.expired, 2);
+    }
+
+    #[test]
+    fn fills_a_share_of_the_bar_volume() {
+        let mut broker = Broker::new();
+        broker.set_cash(100000.0);
+        broker.set_volume_share(0.1);
+        let order = |size, time_in_force| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force,
+            placed_at: None,
+        };
+
+        // 100 of the 1000 traded on each bar
+        broker.place_order(order(250.0, TimeInForce::Gtc));
+        for day in 1..=3 {
+            let time = create_dummy_date(&format!("2024-01-0{} 00:00:00", day));
+            let mut price = create_dummy_price(100.0, 100.0, 100.0, 100.0);
+            price.timestamp = time;
+            broker.handle_unfulfilled_orders(&time, &price);
+            broker.handle_unfulfilled_orders(&time, &price);
+        }
+        assert!(broker.orders.is_empty());
+        assert_eq!(broker.portfolio["AAPL"].quantity, 250.0);
+        let remaining: Vec<f64> = broker
+            .trade_tracker
+            .partial_fills
+            .iter()
+            .map(|fill| fill.remaining)
+            .collect();
+        assert_eq!(remaining, [150.0, 50.0]);
+
+        // The rest of an IOC order is cancelled
+        let time = create_dummy_date("2024-01-04 00:00:00");
+        let mut price = create_dummy_price(100.0, 100.0, 100.0, 100.0);
+        price.timestamp = time;
+        broker.place_order(order(250.0, TimeInForce::Ioc));
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert!(broker.orders.is_empty());
+        assert_eq!(broker.portfolio["AAPL"].quantity, 350.0);
+        assert_eq!(broker.execution_report(&[]).capped_fills, 3);
+
+        // Each fill takes up to 5% of the bar, whatever the other orders took
+        let mut broker = Broker::new();
+        broker.set_cash(100000.0);
+        broker.set_max_participation(5.0);
+        broker.place_order(order(80.0, TimeInForce::Gtc));
+        broker.place_order(order(30.0, TimeInForce::Gtc));
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 80.0);
+        assert_eq!(broker.orders[0].order.size, SizeSpec::Quantity(30.0));
+        assert_eq!(broker.execution_report(&[]).capped_fills, 1);
+    }
+
+    #[test]
+    fn market_orders_fill_at_the_price_of_the_fill_model() {
+        let order = |direction| Order {
+            asset: "AAPL".to_string(),
+            direction,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let time = create_dummy_date("2024-01-02 00:00:00");
+        let mut price = create_dummy_price(100.0, 110.0, 90.0, 104.0);
+        price.timestamp = time;
+
+        let models = [
+            (FillModel::NextOpen, 100.0),
+            (FillModel::Close, 104.0),
+            (FillModel::Midpoint, 100.0),
+            (FillModel::VwapApprox, 101.33333333333333),
+            (FillModel::WorstCase, 110.0),
+        ];
+        for (model, expected) in models {
+            let mut broker = Broker::new();
+            broker.set_cash(1000.0);
+            broker.set_fill_model(model);
+            broker.place_order(order(OrderDirection::Buy));
+            broker.handle_unfulfilled_orders(&time, &price);
+            assert_eq!(broker.portfolio["AAPL"].average_price, expected);
+        }
+
+        // The worst case sells at the low
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_fill_model(FillModel::WorstCase);
+        broker.place_order(order(OrderDirection::Buy));
+        broker.place_order(order(OrderDirection::Sell));
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert_eq!(broker.cash, 980.0);
+    }
+
+    #[test]
+    fn limit_orders_at_the_touch_wait_in_the_queue() {
+        let mut broker = Broker::new();
+        broker.set_cash(10000.0);
+        broker.set_seed(1);
+        broker.set_queue_model(QueueModel {
+            ahead: 10000.0,
+            touch_share: None,
+            tick_size: None,
+        });
+        broker.place_order(Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Limit(95.00000001),
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        });
+
+        // 100 of the 1000 traded at the low of each bar, far from the 10000 ahead. The limit is
+        // a computed price a hair above the low, still only touched
+        for day in 1..=3 {
+            let time = create_dummy_date(&format!("2024-01-0{} 00:00:00", day));
+            let mut price = create_dummy_price(100.0, 100.0, 95.0, 100.0);
+            price.timestamp = time;
+            broker.handle_unfulfilled_orders(&time, &price);
+            broker.handle_unfulfilled_orders(&time, &price);
+        }
+        assert!(broker.portfolio.is_empty());
+        assert_eq!(broker.execution_report(&[]).queue_misses, 3);
+
+        // Going through the limit fills it
+        let time = create_dummy_date("2024-01-04 00:00:00");
+        let price = create_dummy_price(100.0, 100.0, 94.0, 100.0);
+        broker.handle_unfulfilled_orders(&time, &price);
+        assert_eq!(broker.portfolio["AAPL"].quantity, 1.0);
+    }
+
+    #[test]
+    fn orders_rest_across_the_sessions() {
+        use crate::calendar::Session;
+
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        let mut calendar = Calendar::new();
+        calendar.add_session("AAPL", Session::preset("XNAS").unwrap());
+        broker.set_calendar(calendar);
+        let order = |time_in_force| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Limit(90.0),
+            time_in_force,
+            placed_at: None,
+        };
+        let price = create_dummy_price(100.0, 100.0, 95.0, 100.0);
+        let tick = |broker: &mut Broker, time: &str| {
+            broker.handle_unfulfilled_orders(&create_dummy_date(time), &price)
+        };
+        let time_in_force = |broker: &Broker| -> Vec<TimeInForce> {
+            broker
+                .orders
+                .iter()
+                .map(|resting| resting.order.time_in_force)
+                .collect()
+        };
+
+        // Placed before the open, the day order waits for the session and expires at its close
+        broker.place_order(order(TimeInForce::Day));
+        broker.place_order(order(TimeInForce::Gtc));
+        tick(&mut broker, "2024-03-04 13:00:00");
+        tick(&mut broker, "2024-03-04 15:00:00");
+        assert_eq!(broker.orders.len(), 2);
+        tick(&mut broker, "2024-03-04 21:00:00");
+        assert_eq!(time_in_force(&broker), [TimeInForce::Gtc]);
+
+        // Placed after the close, it is kept for the session of the next day
+        broker.place_order(order(TimeInForce::Day));
+        tick(&mut broker, "2024-03-05 14:00:00");
+        tick(&mut broker, "2024-03-05 15:00:00");
+        tick(&mut broker, "2024-03-05 20:59:00");
+        assert_eq!(broker.orders.len(), 2);
+        tick(&mut broker, "2024-03-05 21:00:00");
+        tick(&mut broker, "2024-03-08 21:00:00");
+        assert_eq!(time_in_force(&broker), [TimeInForce::Gtc]);
+    }
+
+    #[test]
+    fn open_orders_are_cancelled_and_amended_by_id() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_reserve_cash(true);
+        let order = |order_type| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(2.0),
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let limit = broker.place_order(order(OrderType::Limit(90.0))).unwrap();
+        let market = broker.place_order(order(OrderType::Market)).unwrap();
+        assert_eq!((limit, market), (1, 2));
+        assert_eq!(broker.reserved_cash(None), 180.0);
+
+        broker.amend_order(limit, Some(3.0), Some(80.0)).unwrap();
+        assert_eq!(broker.orders[0].order.order_type, OrderType::Limit(80.0));
+        assert_eq!(broker.reserved_cash(None), 240.0);
+        assert!(broker.amend_order(market, None, Some(80.0)).is_err());
+
+        broker.cancel_order(limit).unwrap();
+        assert!(broker.cancel_order(limit).is_err());
+        let ids: Vec<u64> = broker.open_orders().map(|(id, _)| id).collect();
+        assert_eq!(ids, [market]);
+        assert_eq!(broker.reserved_cash(None), 0.0);
+        assert_eq!(broker.execution_report(&[]).limit_orders.cancelled, 1);
+    }
+
+    #[test]
+    fn only_the_symbol_of_the_run_is_traded() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_traded_asset("AAPL".to_string());
+        let order = |asset: &str| Order {
+            asset: asset.to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(1.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let algo = ExecutionAlgo::Twap {
+            duration_seconds: 86400,
+            slices: 2,
+        };
+
+        // MSFT would fill at the AAPL bar and be marked at its close
+        assert_eq!(broker.place_order(order("MSFT")), None);
+        assert!(broker.last_order_rejected);
+        assert_eq!(
+            broker.place_algo_order(order("MSFT"), algo),
+            Err(ASSET_REJECTION.to_string())
+        );
+        assert!(broker.place_order(order("AAPL")).is_some());
+        broker.handle_unfulfilled_orders(
+            &create_dummy_date("1999-11-01 00:00:00"),
+            &create_dummy_price(100.0, 101.0, 98.0, 99.0),
+        );
+        assert_eq!(broker.portfolio.keys().collect::<Vec<_>>(), ["AAPL"]);
+    }
+
+    #[test]
+    fn twap_slices_parent_and_reports_shortfall() {
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        let order = Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(4.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+        let algo = ExecutionAlgo::Twap {
+            duration_seconds: 3 * 86400,
+            slices: 4,
+        };
+        // Parents and resting orders share the ids, cancelling one leaves the other
+        let resting = broker
+            .place_order(Order {
+                order_type: OrderType::Limit(50.0),
+                ..order.clone()
+            })
+            .unwrap();
+        let parent = broker.place_algo_order(order.clone(), algo).unwrap();
+        assert_ne!(resting, parent);
+        broker.cancel_order(resting).unwrap();
+        assert_eq!(broker.algo_order_reports()[0].status, ParentStatus::Active);
+
+        let cancelled = broker.place_algo_order(order.clone(), algo).unwrap();
+        broker.cancel_order(cancelled).unwrap();
+        assert!(broker.cancel_order(cancelled).is_err());
+        assert_eq!(
+            broker.algo_order_reports()[1].status,
+            ParentStatus::Cancelled
+        );
+
+        for (day, open) in [100.0, 101.0, 102.0, 103.0].iter().enumerate() {
+            let time = create_dummy_date(&format!("1999-11-0{} 00:00:00", day + 1));
+            let mut price = create_dummy_price(*open, *open, *open, *open);
+            price.timestamp = time;
+            // Only one child per bar
+            broker.handle_unfulfilled_orders(&time, &price);
+            broker.handle_unfulfilled_orders(&time, &price);
+            assert_eq!(broker.portfolio["AAPL"].quantity, day as f64 + 1.0);
+        }
+
+        let report = &broker.algo_order_reports()[0];
+        assert_eq!(report.status, ParentStatus::Filled);
+        assert_eq!(report.id, parent);
+        assert_eq!(report.children, 4);
+        assert_eq!(report.average_price, Some(101.5));
+        assert_eq!(report.shortfall, Some(6.0));
+        assert_eq!(report.shortfall_bps, Some(150.0));
+    }
+
+    #[test]
+    fn marks_the_positions_at_the_last_matched_close() {
+        let mut broker = Broker::new();
+        broker.set_cash(10000.0);
+        assert_eq!(broker.total_equity(), 10000.0);
+
+        broker.place_order(Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(10.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        });
+        let date = create_dummy_date("2024-01-02 00:00:00");
+        broker.handle_unfulfilled_orders(&date, &create_dummy_price(100.0, 100.0, 100.0, 100.0));
+        broker.handle_unfulfilled_orders(&date, &create_dummy_price(110.0, 110.0, 110.0, 110.0));
+
+        assert_eq!(broker.portfolio["AAPL"].average_price, 100.0);
+        assert_eq!(broker.unrealized_pnl("AAPL"), Some(100.0));
+        assert_eq!(broker.unrealized_pnl("MSFT"), None);
+        assert_eq!(broker.total_equity(), 10100.0);
+    }
 }
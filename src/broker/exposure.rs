@@ -0,0 +1,250 @@
+use crate::broker::hedge::{HedgeBook, HedgeReport};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Band the net exposure is kept in, in percent of the equity
+#[derive(Deserialize, Debug, Clone)]
+pub struct ExposureBand {
+    pub min_net_percent: f64,
+    pub max_net_percent: f64,
+    // Long and short value together, only reported when exceeded
+    pub max_gross_percent: Option<f64>,
+    // Symbol of `data.assets` sold short to bring the net exposure back to the middle of the
+    // band, violations are only reported without it
+    pub hedge: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExposureLimit {
+    Net,
+    Gross,
+}
+
+// Consecutive ticks the exposure stayed out of its band, after hedging
+#[derive(Serialize, Debug, Clone)]
+pub struct ExposureViolation {
+    pub limit: ExposureLimit,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub ticks: u64,
+    // Exposure furthest from the band, in percent of the equity
+    pub worst_percent: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExposureReport {
+    pub min_net_percent: f64,
+    pub max_net_percent: f64,
+    pub max_gross_percent: Option<f64>,
+    pub average_net_percent: f64,
+    pub average_gross_percent: f64,
+    pub peak_gross_percent: f64,
+    pub violations: Vec<ExposureViolation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hedge: Option<HedgeReport>,
+}
+
+impl ExposureBand {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(self.min_net_percent.is_finite()
+            && self.max_net_percent.is_finite()
+            && self.min_net_percent <= self.max_net_percent)
+        {
+            return Err("The net exposure band needs a minimum below its maximum");
+        }
+        if self
+            .max_gross_percent
+            .is_some_and(|percent| !(percent.is_finite() && percent > 0.0))
+        {
+            return Err("The maximum gross exposure must be a positive percentage");
+        }
+        Ok(())
+    }
+
+    // Short value bringing the net exposure back to the middle of the band, None inside it
+    pub fn hedge_target(&self, long: f64, short: f64, equity: f64) -> Option<f64> {
+        if equity <= 0.0 {
+            return None;
+        }
+        let net = (long - short) / equity * 100.0;
+        if net >= self.min_net_percent && net <= self.max_net_percent {
+            return None;
+        }
+        let middle = (self.min_net_percent + self.max_net_percent) / 2.0;
+        Some((long - middle / 100.0 * equity).max(0.0))
+    }
+}
+
+// Band of a run with its hedge and what the exposure was on every tick
+pub struct ExposureMonitor {
+    pub band: ExposureBand,
+    pub hedge: Option<HedgeBook>,
+    ticks: u64,
+    net_sum: f64,
+    gross_sum: f64,
+    peak_gross: f64,
+    last_tick: Option<NaiveDateTime>,
+    violations: Vec<ExposureViolation>,
+}
+
+impl ExposureMonitor {
+    pub fn new(band: ExposureBand, hedge: Option<HedgeBook>) -> Self {
+        ExposureMonitor {
+            band,
+            hedge,
+            ticks: 0,
+            net_sum: 0.0,
+            gross_sum: 0.0,
+            peak_gross: 0.0,
+            last_tick: None,
+            violations: vec![],
+        }
+    }
+
+    pub fn record(&mut self, time: NaiveDateTime, long: f64, short: f64, equity: f64) {
+        if equity <= 0.0 {
+            return;
+        }
+        let net = (long - short) / equity * 100.0;
+        let gross = (long + short) / equity * 100.0;
+        self.ticks += 1;
+        self.net_sum += net;
+        self.gross_sum += gross;
+        self.peak_gross = self.peak_gross.max(gross);
+
+        if net < self.band.min_net_percent || net > self.band.max_net_percent {
+            self.violate(ExposureLimit::Net, time, net);
+        }
+        if self.band.max_gross_percent.is_some_and(|max| gross > max) {
+            self.violate(ExposureLimit::Gross, time, gross);
+        }
+        self.last_tick = Some(time);
+    }
+
+    // Extends the violation still open on the previous tick
+    fn violate(&mut self, limit: ExposureLimit, time: NaiveDateTime, percent: f64) {
+        let middle = (self.band.min_net_percent + self.band.max_net_percent) / 2.0;
+        let distance = |percent: f64| match limit {
+            ExposureLimit::Net => (percent - middle).abs(),
+            ExposureLimit::Gross => percent,
+        };
+        let open = self
+            .violations
+            .iter_mut()
+            .rev()
+            .find(|violation| violation.limit == limit)
+            .filter(|violation| Some(violation.end) == self.last_tick);
+        match open {
+            Some(violation) => {
+                violation.end = time;
+                violation.ticks += 1;
+                if distance(percent) > distance(violation.worst_percent) {
+                    violation.worst_percent = percent;
+                }
+            }
+            None => self.violations.push(ExposureViolation {
+                limit,
+                start: time,
+                end: time,
+                ticks: 1,
+                worst_percent: percent,
+            }),
+        }
+    }
+
+    pub fn report(&self) -> ExposureReport {
+        let average = |sum: f64| match self.ticks {
+            0 => 0.0,
+            ticks => sum / ticks as f64,
+        };
+        ExposureReport {
+            min_net_percent: self.band.min_net_percent,
+            max_net_percent: self.band.max_net_percent,
+            max_gross_percent: self.band.max_gross_percent,
+            average_net_percent: average(self.net_sum),
+            average_gross_percent: average(self.gross_sum),
+            peak_gross_percent: self.peak_gross,
+            violations: self.violations.clone(),
+            hedge: self.hedge.as_ref().map(HedgeBook::report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{
+        order::{Order, OrderDirection, OrderType, SizeSpec, TimeInForce},
+        Broker,
+    };
+    use crate::data::OHLCVData;
+    use chrono::Duration;
+
+    fn bar(timestamp: NaiveDateTime, price: f64) -> OHLCVData {
+        OHLCVData {
+            timestamp,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 1000,
+        }
+    }
+
+    #[test]
+    fn hedges_the_net_exposure_back_into_the_band() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let band = ExposureBand {
+            min_net_percent: -10.0,
+            max_net_percent: 10.0,
+            max_gross_percent: Some(150.0),
+            hedge: Some("SPY".to_string()),
+        };
+        let hedge = HedgeBook::new(
+            "SPY".to_string(),
+            (0..3)
+                .map(|day| bar(start + Duration::days(day), 50.0 + day as f64 * 2.0))
+                .collect(),
+        );
+
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.set_exposure(ExposureMonitor::new(band.clone(), Some(hedge)));
+        broker.place_order(Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(10.0),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        });
+        broker.handle_unfulfilled_orders(&start, &bar(start, 100.0));
+        broker.manage_exposure(&start, &bar(start, 100.0));
+
+        // Fully long, the whole book is hedged with 20 units at 50
+        let report = broker.exposure_report().unwrap();
+        assert_eq!(report.hedge.as_ref().unwrap().quantity, 20.0);
+        assert_eq!(broker.cash, 1000.0);
+        assert!((report.peak_gross_percent - 200.0).abs() < 1e-9);
+
+        // The hedge rallies 4%, the net stays in the band and the gross stays above its limit
+        let day = start + Duration::days(1);
+        broker.manage_exposure(&day, &bar(day, 100.0));
+        let report = broker.exposure_report().unwrap();
+        assert_eq!(report.hedge.as_ref().unwrap().trades, 1);
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].limit, ExposureLimit::Gross);
+        assert_eq!(report.violations[0].ticks, 2);
+
+        // Without a hedge the long book breaks the band on every tick
+        let mut unhedged = ExposureMonitor::new(band, None);
+        unhedged.record(start, 1000.0, 0.0, 1000.0);
+        unhedged.record(day, 1100.0, 0.0, 1100.0);
+        let report = unhedged.report();
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].ticks, 2);
+        assert_eq!(report.average_net_percent, 100.0);
+    }
+}
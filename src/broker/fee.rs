@@ -1,7 +1,33 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum FeeType {
     Flat(f64),
     Percentage(f64),
+    // Per unit traded, within a minimum per order and a maximum percent of the order value
+    PerUnit {
+        rate: f64,
+        minimum: f64,
+        maximum_percent: f64,
+    },
+}
+
+// Currency the fees of the buys are charged in, sells always pay them in the account currency
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeCurrency {
+    #[default]
+    Account,
+    // Taken out of the quantity bought, like most crypto exchanges do
+    Asset,
+}
+
+// Fees charged in one currency over the run
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FeeTotal {
+    // `account` or the symbol of the asset the fees were taken in
+    pub currency: String,
+    pub amount: f64,
+    // In the account currency, at the prices of the fills
+    pub value: f64,
 }
@@ -0,0 +1,69 @@
+use crate::calendar::Session;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+// Positions closed every day at a local time, to simulate day trading without overnight risk
+#[derive(Deserialize, Debug, Clone)]
+pub struct Flatten {
+    // 15:55:00
+    pub time: NaiveTime,
+    // Timezone of the time, the one of the session of `data.symbol` by default and UTC without one
+    pub timezone: Option<Tz>,
+}
+
+pub struct DailyFlatten {
+    time: NaiveTime,
+    timezone: Tz,
+    // Local day the positions were last flattened on
+    last: Option<NaiveDate>,
+}
+
+impl DailyFlatten {
+    pub fn new(settings: Flatten, session: Option<&Session>) -> Self {
+        DailyFlatten {
+            time: settings.time,
+            timezone: settings
+                .timezone
+                .or(session.map(|session| session.timezone))
+                .unwrap_or(chrono_tz::UTC),
+            last: None,
+        }
+    }
+
+    // True on the first tick at or past the time of each day
+    pub fn due(&mut self, time: &NaiveDateTime) -> bool {
+        let local = time.and_utc().with_timezone(&self.timezone).naive_local();
+        if local.time() < self.time || self.last == Some(local.date()) {
+            return false;
+        }
+        self.last = Some(local.date());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattens_once_a_day_in_the_session_timezone() {
+        let session = Session::preset("XNAS").unwrap();
+        let mut flatten = DailyFlatten::new(
+            Flatten {
+                time: NaiveTime::from_hms_opt(15, 55, 0).unwrap(),
+                timezone: None,
+            },
+            Some(&session),
+        );
+        let utc = |time: &str| {
+            NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").expect("Invalid date")
+        };
+
+        // 15:50, 15:55 and 15:56 in New York
+        assert!(!flatten.due(&utc("2024-01-02 20:50:00")));
+        assert!(flatten.due(&utc("2024-01-02 20:55:00")));
+        assert!(!flatten.due(&utc("2024-01-02 20:56:00")));
+        assert!(flatten.due(&utc("2024-01-03 20:58:00")));
+    }
+}
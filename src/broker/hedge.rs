@@ -0,0 +1,107 @@
+use crate::broker::order::{Fill, OrderDirection};
+use crate::data::OHLCVData;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+// Hedge trades worth less than this are skipped
+const MIN_TRADE_VALUE: f64 = 1e-6;
+
+// Short position in an instrument other than the traded one, kept by the broker against the
+// long book. It trades at the close of its own bars, outside of the order matching, and is
+// valued in the account currency
+pub struct HedgeBook {
+    pub asset: String,
+    bars: Vec<OHLCVData>,
+    // Units sold short
+    pub quantity: f64,
+    // Close of the last bar at or before the clock, None before the first bar
+    pub price: Option<f64>,
+    pub fills: Vec<Fill>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HedgeReport {
+    pub asset: String,
+    // Units still sold short at the end of the run
+    pub quantity: f64,
+    pub price: Option<f64>,
+    pub trades: usize,
+    pub fees: f64,
+    // Cash received net of the cost to cover what is left
+    pub profit_loss: f64,
+}
+
+impl HedgeBook {
+    pub fn new(asset: String, mut bars: Vec<OHLCVData>) -> Self {
+        bars.sort_by_key(|bar| bar.timestamp);
+        HedgeBook {
+            asset,
+            bars,
+            quantity: 0.0,
+            price: None,
+            fills: vec![],
+        }
+    }
+
+    // Moves the mark to the last bar at or before `time`
+    pub fn mark(&mut self, time: &NaiveDateTime) -> Option<f64> {
+        let i = self.bars.partition_point(|bar| &bar.timestamp <= time);
+        if let Some(bar) = i.checked_sub(1).map(|i| &self.bars[i]) {
+            self.price = Some(bar.close);
+        }
+        self.price
+    }
+
+    // Value owed to cover the short at the mark
+    pub fn liability(&self) -> f64 {
+        self.quantity * self.price.unwrap_or(0.0)
+    }
+
+    // Proceeds of the sells net of the buys and of the fees
+    pub fn cash_flow(&self) -> f64 {
+        self.fills
+            .iter()
+            .fold(0.0, |cash, fill| match fill.direction {
+                OrderDirection::Sell => cash + fill.price * fill.size - fill.fees,
+                OrderDirection::Buy => cash - fill.price * fill.size - fill.fees,
+            })
+    }
+
+    // Sells or covers at the mark to be short `quantity` units, returns the cash it moved
+    pub fn rebalance(&mut self, quantity: f64, time: &NaiveDateTime, fees: f64) -> f64 {
+        let Some(price) = self.price else {
+            return 0.0;
+        };
+        let quantity = if quantity > 0.0 { quantity } else { 0.0 };
+        let delta = quantity - self.quantity;
+        if delta.abs() * price < MIN_TRADE_VALUE {
+            return 0.0;
+        }
+
+        self.quantity = quantity;
+        self.fills.push(Fill {
+            asset: self.asset.clone(),
+            direction: match delta > 0.0 {
+                true => OrderDirection::Sell,
+                false => OrderDirection::Buy,
+            },
+            time: *time,
+            price,
+            size: delta.abs(),
+            fees,
+            slippage: 0.0,
+        });
+        delta * price - fees
+    }
+
+    pub fn report(&self) -> HedgeReport {
+        HedgeReport {
+            asset: self.asset.clone(),
+            quantity: self.quantity,
+            price: self.price,
+            trades: self.fills.len(),
+            fees: self.fills.iter().fold(0.0, |fees, fill| fees + fill.fees),
+            profit_loss: self.cash_flow() - self.liability(),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use super::order::{Fill, Order, OrderDirection, OrderType, SizeSpec};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+// Extension point around the order lifecycle, registered with `Broker::add_hook`. Hooks run in
+// registration order and every method defaults to doing nothing
+pub trait OrderHook: Send {
+    // Called before an order enters the book, an error rejects it
+    fn on_order_placed(&mut self, _order: &Order) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Called before every execution attempt (book orders, algo children and liquidations) with the
+    // price it would fill at. Returns the price to fill at, which lets a hook model the fills, or
+    // an error to reject the attempt
+    fn on_before_execute(
+        &mut self,
+        _order: &Order,
+        price: f64,
+        _time: &NaiveDateTime,
+    ) -> Result<f64, String> {
+        Ok(price)
+    }
+
+    fn on_fill(&mut self, _fill: &Fill) {}
+
+    // Called when a hook rejects an order or when an execution fails (e.g. not enough cash).
+    // Orders failing to execute stay in the book and are retried on the next bar
+    fn on_reject(&mut self, _order: &Order, _reason: &str) {}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum OrderEvent {
+    Placed {
+        // Tick the order was placed on
+        time: Option<NaiveDateTime>,
+        asset: String,
+        direction: OrderDirection,
+        order_type: OrderType,
+        size: SizeSpec,
+    },
+    Filled(Fill),
+    Rejected {
+        asset: String,
+        direction: OrderDirection,
+        reason: String,
+    },
+}
+
+// Records the lifecycle of every order, clones share the same events
+#[derive(Clone, Default)]
+pub struct OrderLog {
+    events: Arc<Mutex<Vec<OrderEvent>>>,
+}
+
+impl OrderLog {
+    pub fn events(&self) -> Vec<OrderEvent> {
+        self.events
+            .lock()
+            .map(|events| events.clone())
+            .unwrap_or_default()
+    }
+
+    fn push(&self, event: OrderEvent) {
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+}
+
+impl OrderHook for OrderLog {
+    fn on_order_placed(&mut self, order: &Order) -> Result<(), String> {
+        self.push(OrderEvent::Placed {
+            time: order.placed_at,
+            asset: order.asset.clone(),
+            direction: order.direction.clone(),
+            order_type: order.order_type.clone(),
+            size: order.size,
+        });
+        Ok(())
+    }
+
+    fn on_fill(&mut self, fill: &Fill) {
+        self.push(OrderEvent::Filled(fill.clone()));
+    }
+
+    fn on_reject(&mut self, order: &Order, reason: &str) {
+        self.push(OrderEvent::Rejected {
+            asset: order.asset.clone(),
+            direction: order.direction.clone(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::order::TimeInForce;
+    use crate::broker::Broker;
+    use crate::data::OHLCVData;
+
+    // Rejects orders above a size, fills a cent above the price and logs what happens
+    struct RiskDesk {
+        max_size: f64,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl OrderHook for RiskDesk {
+        fn on_order_placed(&mut self, order: &Order) -> Result<(), String> {
+            match order.size {
+                SizeSpec::Quantity(size) if size > self.max_size => {
+                    Err("Order too large".to_string())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn on_before_execute(
+            &mut self,
+            _order: &Order,
+            price: f64,
+            _time: &NaiveDateTime,
+        ) -> Result<f64, String> {
+            Ok(price + 0.01)
+        }
+
+        fn on_fill(&mut self, fill: &Fill) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("fill {}", fill.price));
+        }
+
+        fn on_reject(&mut self, _order: &Order, reason: &str) {
+            self.log.lock().unwrap().push(format!("reject {}", reason));
+        }
+    }
+
+    #[test]
+    fn hooks_see_the_order_lifecycle() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut broker = Broker::new();
+        broker.set_cash(1000.0);
+        broker.add_hook(Box::new(RiskDesk {
+            max_size: 5.0,
+            log: log.clone(),
+        }));
+        let order = |size| Order {
+            asset: "AAPL".to_string(),
+            direction: OrderDirection::Buy,
+            size: SizeSpec::Quantity(size),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            placed_at: None,
+        };
+
+        broker.place_order(order(10.0));
+        broker.place_order(order(2.0));
+        assert_eq!(broker.orders.len(), 1);
+
+        let time = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = OHLCVData {
+            timestamp: time,
+            open: 100.0,
+            high: 100.0,
+            low: 100.0,
+            close: 100.0,
+            volume: 0,
+        };
+        broker.handle_unfulfilled_orders(&time, &bar);
+
+        assert_eq!(broker.fills[0].price, 100.01);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["reject Order too large", "fill 100.01"]
+        );
+    }
+}
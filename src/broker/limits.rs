@@ -0,0 +1,43 @@
+use serde::Deserialize;
+
+// Mandate constraints checked when a buy executes
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub struct PositionLimits {
+    // Assets held at the same time
+    pub max_positions: Option<usize>,
+    // Value of a single position in percent of the equity
+    pub max_position_percent: Option<f64>,
+}
+
+impl PositionLimits {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.max_positions == Some(0) {
+            return Err("The maximum number of positions must be at least 1");
+        }
+        if let Some(percent) = self.max_position_percent {
+            if !(percent > 0.0 && percent <= 100.0) {
+                return Err("The maximum position size must be a percentage between 0 and 100");
+            }
+        }
+        Ok(())
+    }
+
+    // `positions` is the number of assets held before a buy that opens a new one or not
+    pub fn check(
+        &self,
+        opens_position: bool,
+        positions: usize,
+        position_value: f64,
+        equity: f64,
+    ) -> Result<(), String> {
+        if opens_position && self.max_positions.is_some_and(|max| positions >= max) {
+            return Err("Maximum number of positions reached".to_string());
+        }
+        if let Some(percent) = self.max_position_percent {
+            if position_value > percent / 100.0 * equity {
+                return Err(format!("Position would exceed {}% of the equity", percent));
+            }
+        }
+        Ok(())
+    }
+}
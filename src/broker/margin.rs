@@ -0,0 +1,106 @@
+use super::sweep::{AnnualRate, SECONDS_PER_YEAR};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Cash account lending against the positions, the cash goes negative by the loan
+#[derive(Deserialize, Debug, Clone)]
+pub struct MarginAccount {
+    // Positions worth up to `leverage` times the equity, the initial margin is `1 / leverage`
+    pub leverage: f64,
+    // Fraction of the positions value the equity must cover, they are liquidated otherwise
+    pub maintenance_margin: f64,
+    // Charged on the loan, nothing by default
+    pub interest_rate: Option<AnnualRate>,
+}
+
+// Loan taken along the run, returned with the result
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MarginReport {
+    pub leverage: f64,
+    // At the end of the run
+    pub loan: f64,
+    pub max_loan: f64,
+    // Highest positions value against the equity on the ticks
+    pub max_leverage: f64,
+    // Also counted in `total_carry`
+    pub interest_paid: f64,
+}
+
+impl MarginAccount {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(self.leverage.is_finite() && self.leverage >= 1.0) {
+            return Err("The leverage is at least 1");
+        }
+        if !(self.maintenance_margin >= 0.0 && self.maintenance_margin < self.initial_margin()) {
+            return Err("The maintenance margin is between 0 and the initial margin");
+        }
+        if let Some(rate) = &self.interest_rate {
+            rate.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn prepare(mut self) -> Self {
+        if let Some(rate) = &mut self.interest_rate {
+            rate.sort();
+        }
+        self
+    }
+
+    pub fn initial_margin(&self) -> f64 {
+        1.0 / self.leverage
+    }
+
+    // Positions worth `long` can be held with `equity`
+    pub fn covers(&self, equity: f64, long: f64) -> bool {
+        equity >= self.initial_margin() * long
+    }
+
+    // Cash the positions can still be bought with
+    pub fn buying_power(&self, equity: f64, long: f64) -> f64 {
+        (equity * self.leverage - long).max(0.0)
+    }
+
+    // Interest of the loan of a `cash` balance from `from` to `to` at the rate of `from`
+    pub fn interest(&self, cash: f64, from: &NaiveDateTime, to: &NaiveDateTime) -> f64 {
+        let Some(rate) = &self.interest_rate else {
+            return 0.0;
+        };
+        if cash >= 0.0 || to <= from {
+            return 0.0;
+        }
+        let years = (*to - *from).num_seconds() as f64 / SECONDS_PER_YEAR;
+        -cash * rate.at(from) * years
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn lends_up_to_the_leverage() {
+        let account = MarginAccount {
+            leverage: 2.0,
+            maintenance_margin: 0.25,
+            interest_rate: Some(AnnualRate::Constant(0.0365)),
+        };
+        assert!(account.validate().is_ok());
+        let invalid = MarginAccount {
+            maintenance_margin: 0.5,
+            ..account.clone()
+        };
+        assert!(invalid.validate().is_err());
+
+        // 10000 of equity holds 20000 of positions
+        assert!(account.covers(10000.0, 20000.0));
+        assert!(!account.covers(10000.0, 20001.0));
+        assert_eq!(account.buying_power(10000.0, 15000.0), 5000.0);
+
+        let start = NaiveDateTime::default();
+        let day = start + Duration::days(1);
+        assert!((account.interest(-10000.0, &start, &day) - 1.0).abs() < 1e-9);
+        assert_eq!(account.interest(500.0, &start, &day), 0.0);
+    }
+}
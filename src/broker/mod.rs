@@ -1,6 +1,19 @@
+pub mod account;
+pub mod algo;
+pub mod contract;
 pub mod execution;
+pub mod exposure;
 pub mod fee;
+pub mod flatten;
+pub mod hedge;
+pub mod hooks;
+pub mod limits;
+pub mod margin;
 pub mod order;
+pub mod overlay;
 pub mod position;
+pub mod preset;
+pub mod queue;
+pub mod sweep;
 
 pub use execution::Broker;
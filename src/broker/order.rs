@@ -1,24 +1,189 @@
 // TODO: add one-time order purchase and order sell fees
+use crate::data::OHLCVData;
 use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum OrderType {
     Market,
     Limit(f64),
     Stop(f64),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum OrderDirection {
     Buy,
     Sell,
 }
 
+// Price the market orders fill at on the first bar they can trade on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FillModel {
+    #[default]
+    NextOpen,
+    Close,
+    // Middle of the high and the low
+    Midpoint,
+    // Typical price (high + low + close) / 3, the VWAP of a bar without its intrabar volume
+    VwapApprox,
+    // High for the buys and low for the sells
+    WorstCase,
+}
+
+impl FillModel {
+    pub fn price(&self, direction: &OrderDirection, bar: &OHLCVData) -> f64 {
+        match (self, direction) {
+            (FillModel::NextOpen, _) => bar.open,
+            (FillModel::Close, _) => bar.close,
+            (FillModel::Midpoint, _) => (bar.high + bar.low) / 2.0,
+            (FillModel::VwapApprox, _) => (bar.high + bar.low + bar.close) / 3.0,
+            (FillModel::WorstCase, OrderDirection::Buy) => bar.high,
+            (FillModel::WorstCase, OrderDirection::Sell) => bar.low,
+        }
+    }
+}
+
+// How much to trade, converted to a quantity by the broker when the order executes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeSpec {
+    Quantity(f64),
+    // Account currency value of the order, excluding fees
+    NotionalCash(f64),
+    // Percent (0 to 100) of the equity at execution
+    PercentEquity(f64),
+}
+
 #[derive(Clone)]
 pub struct Order {
     pub asset: String,
     pub direction: OrderDirection,
-    pub size: f64,
+    pub size: SizeSpec,
     pub order_type: OrderType,
-    pub valid_until: Option<NaiveDateTime>,
+    pub time_in_force: TimeInForce,
+    // Time of the tick the order was placed on, set by the broker
+    pub placed_at: Option<NaiveDateTime>,
+}
+
+// How long an order stays in the book
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeInForce {
+    // Good till cancelled
+    #[default]
+    Gtc,
+    // Expires when the session of the asset it could first trade in closes
+    Day,
+    // Immediate or cancel: tried on the first bar it can trade on, then cancelled
+    Ioc,
+    // Fill or kill: as IOC, and never downsized to the cash
+    Fok,
+    // Good till the date, expires on the first tick past it
+    Gtd(NaiveDateTime),
+}
+
+impl TimeInForce {
+    // IOC and FOK orders don't rest in the book
+    pub fn is_immediate(&self) -> bool {
+        matches!(self, TimeInForce::Ioc | TimeInForce::Fok)
+    }
+}
+
+// What becomes of a buy whose cost and fees exceed the cash
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CashShortfall {
+    #[default]
+    Reject,
+    // Filled for the largest quantity the cash pays for, in whole lots
+    Downsize,
+}
+
+// Buy filled for less than its size under `CashShortfall::Downsize`
+#[derive(Serialize, Debug, Clone)]
+pub struct DownsizedOrder {
+    pub asset: String,
+    pub time: NaiveDateTime,
+    pub requested: f64,
+    pub filled: f64,
+}
+
+// Part of an order the volume of the bar allowed, the rest kept resting
+#[derive(Serialize, Debug, Clone)]
+pub struct PartialFill {
+    pub asset: String,
+    pub direction: OrderDirection,
+    pub time: NaiveDateTime,
+    pub price: f64,
+    pub filled: f64,
+    // Left in the order
+    pub remaining: f64,
+}
+
+// Executed order as reported to the session monitors
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fill {
+    pub asset: String,
+    pub direction: OrderDirection,
+    pub time: NaiveDateTime,
+    pub price: f64,
+    pub size: f64,
+    pub fees: f64,
+    // Per unit, against the market price
+    pub slippage: f64,
+}
+
+impl SizeSpec {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            SizeSpec::Quantity(value) | SizeSpec::NotionalCash(value) => {
+                value.is_finite() && value > 0.0
+            }
+            SizeSpec::PercentEquity(percent) => percent > 0.0 && percent <= 100.0,
+        }
+    }
+
+    // Quantity to trade at `price`, where one unit is worth `point_value` per point
+    pub fn quantity(&self, price: f64, equity: f64, point_value: f64) -> f64 {
+        match *self {
+            SizeSpec::Quantity(quantity) => quantity,
+            SizeSpec::NotionalCash(value) => value / (price * point_value),
+            SizeSpec::PercentEquity(percent) => percent / 100.0 * equity / (price * point_value),
+        }
+    }
+
+    // Quantity when it is known before the execution
+    pub fn fixed(&self) -> Option<f64> {
+        match *self {
+            SizeSpec::Quantity(quantity) => Some(quantity),
+            _ => None,
+        }
+    }
+}
+
+impl Order {
+    // Price the order trades at on this bar, None when it isn't triggered
+    pub fn fill_price(&self, bar: &OHLCVData, model: FillModel) -> Option<f64> {
+        match (&self.order_type, &self.direction) {
+            (OrderType::Market, direction) => Some(model.price(direction, bar)),
+            // Limits trade once the price reaches the limit, at the open when it gapped through
+            (OrderType::Limit(price), OrderDirection::Buy) if bar.low <= *price => {
+                Some(bar.open.min(*price))
+            }
+            (OrderType::Limit(price), OrderDirection::Sell) if bar.high >= *price => {
+                Some(bar.open.max(*price))
+            }
+            // Stops trigger once the price is touched, at the open when it gapped through
+            (OrderType::Stop(price), OrderDirection::Buy) if bar.high >= *price => {
+                Some(bar.open.max(*price))
+            }
+            (OrderType::Stop(price), OrderDirection::Sell) if bar.low <= *price => {
+                Some(bar.open.min(*price))
+            }
+            _ => None,
+        }
+    }
 }
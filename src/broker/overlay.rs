@@ -0,0 +1,204 @@
+use crate::broker::hedge::{HedgeBook, HedgeReport};
+use crate::data::OHLCVData;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// Bars of the beta window when not set
+const DEFAULT_WINDOW: usize = 60;
+
+// Short hedge sized by the rolling beta of the traded asset to a benchmark
+#[derive(Deserialize, Debug, Clone)]
+pub struct BetaHedgeSettings {
+    // Symbol of `data.assets` the beta is measured against
+    pub benchmark: String,
+    // Symbol of `data.assets` sold short, the benchmark itself by default (e.g. an index future
+    // tracking it)
+    pub hedge: Option<String>,
+    // Returns of the beta regression
+    pub window: Option<usize>,
+    // Share of the beta hedged, 1 by default
+    pub ratio: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BetaHedgeReport {
+    pub benchmark: String,
+    pub window: usize,
+    // Beta of the asset on the last bar and averaged over the bars it was hedged on
+    pub last_beta: Option<f64>,
+    pub average_beta: Option<f64>,
+    // Beta of the hedged equity to the benchmark over the run, close to 0 when the hedge
+    // isolates the alpha
+    pub residual_beta: Option<f64>,
+    pub hedge: HedgeReport,
+}
+
+impl BetaHedgeSettings {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.window.is_some_and(|window| window < 2) {
+            return Err("The beta window needs at least 2 bars");
+        }
+        if self
+            .ratio
+            .is_some_and(|ratio| !(ratio.is_finite() && ratio >= 0.0))
+        {
+            return Err("The hedge ratio must be a positive number");
+        }
+        Ok(())
+    }
+}
+
+pub struct BetaHedge {
+    benchmark: String,
+    benchmark_bars: Vec<OHLCVData>,
+    window: usize,
+    ratio: f64,
+    pub book: HedgeBook,
+    // Closes of the asset and the benchmark on the last bars of the asset
+    closes: VecDeque<(f64, f64)>,
+    last_bar: Option<NaiveDateTime>,
+    betas: Vec<f64>,
+}
+
+// Slope of `y` on `x`, None without variance in `x`
+fn beta(returns: &[(f64, f64)]) -> Option<f64> {
+    let n = returns.len() as f64;
+    if returns.len() < 2 {
+        return None;
+    }
+    let mean_y = returns.iter().map(|(y, _)| y).sum::<f64>() / n;
+    let mean_x = returns.iter().map(|(_, x)| x).sum::<f64>() / n;
+    let (covariance, variance) = returns.iter().fold((0.0, 0.0), |(cov, var), (y, x)| {
+        (
+            cov + (y - mean_y) * (x - mean_x),
+            var + (x - mean_x).powi(2),
+        )
+    });
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+fn returns(closes: impl Iterator<Item = (f64, f64)>) -> Vec<(f64, f64)> {
+    let closes: Vec<_> = closes.collect();
+    closes
+        .windows(2)
+        .filter(|w| w[0].0 != 0.0 && w[0].1 != 0.0)
+        .map(|w| (w[1].0 / w[0].0 - 1.0, w[1].1 / w[0].1 - 1.0))
+        .collect()
+}
+
+impl BetaHedge {
+    pub fn new(
+        settings: BetaHedgeSettings,
+        mut benchmark_bars: Vec<OHLCVData>,
+        hedge_bars: Vec<OHLCVData>,
+    ) -> Self {
+        benchmark_bars.sort_by_key(|bar| bar.timestamp);
+        let hedge = settings
+            .hedge
+            .clone()
+            .unwrap_or_else(|| settings.benchmark.clone());
+        BetaHedge {
+            benchmark: settings.benchmark,
+            benchmark_bars,
+            window: settings.window.unwrap_or(DEFAULT_WINDOW),
+            ratio: settings.ratio.unwrap_or(1.0),
+            book: HedgeBook::new(hedge, hedge_bars),
+            closes: VecDeque::new(),
+            last_bar: None,
+            betas: vec![],
+        }
+    }
+
+    fn benchmark_close(&self, time: &NaiveDateTime) -> Option<f64> {
+        let i = self
+            .benchmark_bars
+            .partition_point(|bar| &bar.timestamp <= time);
+        i.checked_sub(1).map(|i| self.benchmark_bars[i].close)
+    }
+
+    // Short value hedging `long` on a new bar of the asset, None until the window is filled or
+    // when the bar was already seen
+    pub fn target(&mut self, bar: &OHLCVData, long: f64) -> Option<f64> {
+        if self.last_bar == Some(bar.timestamp) {
+            return None;
+        }
+        self.last_bar = Some(bar.timestamp);
+        let benchmark = self.benchmark_close(&bar.timestamp)?;
+        self.closes.push_back((bar.close, benchmark));
+        if self.closes.len() > self.window + 1 {
+            self.closes.pop_front();
+        }
+        if self.closes.len() <= self.window {
+            return None;
+        }
+
+        let beta = beta(&returns(self.closes.iter().copied()))?;
+        self.betas.push(beta);
+        // A negative beta would need a long hedge, the book stays flat instead
+        Some((beta * self.ratio * long).max(0.0))
+    }
+
+    pub fn report(&self, equity_curve: &[(NaiveDateTime, f64)]) -> BetaHedgeReport {
+        let equity = equity_curve
+            .iter()
+            .filter_map(|(time, equity)| Some((*equity, self.benchmark_close(time)?)));
+        BetaHedgeReport {
+            benchmark: self.benchmark.clone(),
+            window: self.window,
+            last_beta: self.betas.last().copied(),
+            average_beta: (!self.betas.is_empty())
+                .then(|| self.betas.iter().sum::<f64>() / self.betas.len() as f64),
+            residual_beta: beta(&returns(equity)),
+            hedge: self.book.report(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_the_beta_to_the_benchmark() {
+        let returns = [(0.02, 0.01), (-0.04, -0.02), (0.06, 0.03), (0.0, 0.0)];
+        assert!((beta(&returns).unwrap() - 2.0).abs() < 1e-12);
+        assert_eq!(beta(&[(0.01, 0.0), (0.02, 0.0)]), None);
+
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |day: i64, close: f64| OHLCVData {
+            timestamp: start + chrono::Duration::days(day),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1000,
+        };
+        let benchmark = [100.0, 101.0, 99.0, 102.0];
+        let mut overlay = BetaHedge::new(
+            BetaHedgeSettings {
+                benchmark: "SPY".to_string(),
+                hedge: None,
+                window: Some(2),
+                ratio: Some(0.5),
+            },
+            benchmark
+                .iter()
+                .enumerate()
+                .map(|(day, close)| bar(day as i64, *close))
+                .collect(),
+            vec![],
+        );
+        // The asset moves twice as much as the benchmark
+        let asset = [50.0, 51.0, 49.0, 52.0];
+        let targets: Vec<_> = asset
+            .iter()
+            .enumerate()
+            .map(|(day, close)| overlay.target(&bar(day as i64, *close), 1000.0))
+            .collect();
+        assert_eq!(targets[..2], [None, None]);
+        assert!(targets[2].is_some_and(|target| (target - 1000.0).abs() < 10.0));
+        assert_eq!(overlay.target(&bar(3, 52.0), 1000.0), None);
+    }
+}
@@ -1,3 +1,9 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+// Quantity under which what is left of a position is treated as closed
+pub const DEFAULT_DUST_THRESHOLD: f64 = 1e-9;
+
 pub struct Position {
     pub quantity: f64,
     pub average_price: f64,
@@ -25,3 +31,12 @@ impl Position {
         Ok(())
     }
 }
+
+// Residual quantity swept into a sell to close the whole position, negative when the sell was
+// slightly larger than the position
+#[derive(Serialize, Debug, Clone)]
+pub struct DustClosure {
+    pub asset: String,
+    pub time: NaiveDateTime,
+    pub quantity: f64,
+}
@@ -0,0 +1,49 @@
+use crate::broker::fee::{FeeCurrency, FeeType};
+
+// Costs and trading rules of a venue, the broker settings given along with the preset override them
+#[derive(Clone)]
+pub struct BrokerPreset {
+    pub fees: FeeType,
+    pub fee_currency: FeeCurrency,
+    // Fractions of the price, like `broker.slippage`
+    pub slippage: (f64, f64),
+    pub lot_size: f64,
+    // Session preset of `data.symbol`
+    pub session: &'static str,
+}
+
+impl BrokerPreset {
+    // Fees of the lowest volume tier for taker orders
+    pub fn named(name: &str) -> Option<Self> {
+        let preset = match name {
+            "binance_spot" => BrokerPreset {
+                fees: FeeType::Percentage(0.001),
+                fee_currency: FeeCurrency::Asset,
+                slippage: (0.0, 0.0005),
+                lot_size: 0.00001,
+                session: "CRYPTO",
+            },
+            "coinbase_advanced" => BrokerPreset {
+                fees: FeeType::Percentage(0.012),
+                fee_currency: FeeCurrency::Account,
+                slippage: (0.0, 0.001),
+                lot_size: 0.00000001,
+                session: "CRYPTO",
+            },
+            // Fixed pricing: 0.005 USD a share, 1 USD at least and 1% of the trade value at most
+            "ibkr_pro_us_equities" => BrokerPreset {
+                fees: FeeType::PerUnit {
+                    rate: 0.005,
+                    minimum: 1.0,
+                    maximum_percent: 1.0,
+                },
+                fee_currency: FeeCurrency::Account,
+                slippage: (0.0, 0.0002),
+                lot_size: 1.0,
+                session: "XNYS",
+            },
+            _ => return None,
+        };
+        Some(preset)
+    }
+}
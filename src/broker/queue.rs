@@ -0,0 +1,100 @@
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+// Place of the resting limit orders in the queue at their price. A bar going through the limit
+// fills them, a bar only touching it does when the volume traded there reached them
+#[derive(Deserialize, Debug, Clone)]
+pub struct QueueModel {
+    // Quantity resting ahead of an order at its price when it joins the queue
+    pub ahead: f64,
+    // Share of the bar volume assumed traded at its low or high, 0.1 by default
+    pub touch_share: Option<f64>,
+    // Price increment of the asset, a low or high within half of it only touched the limit.
+    // A millionth of the limit by default
+    pub tick_size: Option<f64>,
+}
+
+// Queue of an order on the last bar touching its price
+#[derive(Debug, Clone, Copy)]
+pub struct QueuePlace {
+    pub ahead: f64,
+    pub bar: NaiveDateTime,
+    pub filled: bool,
+}
+
+impl QueueModel {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(self.ahead.is_finite() && self.ahead >= 0.0) {
+            return Err("The quantity ahead in the queue must be positive");
+        }
+        if self
+            .touch_share
+            .is_some_and(|share| !(share > 0.0 && share <= 1.0))
+        {
+            return Err("The touch share is between 0 and 1");
+        }
+        if self
+            .tick_size
+            .is_some_and(|tick_size| !(tick_size.is_finite() && tick_size > 0.0))
+        {
+            return Err("The tick size must be positive");
+        }
+        Ok(())
+    }
+
+    // Whether the low or high of a bar is at the limit rather than through it, the limits
+    // computed by a strategy rarely equal the prices of the data exactly
+    pub fn touches(&self, extreme: f64, limit: f64) -> bool {
+        let tolerance = self
+            .tick_size
+            .map_or(limit.abs() * 1e-6, |tick_size| tick_size / 2.0);
+        (extreme - limit).abs() <= tolerance
+    }
+
+    fn traded(&self, volume: f64) -> f64 {
+        volume * self.touch_share.unwrap_or(0.1)
+    }
+
+    // Share of an order of `quantity` the volume traded at the touch reaches past the queue
+    pub fn fill_probability(&self, ahead: f64, quantity: f64, volume: f64) -> f64 {
+        if quantity <= 0.0 {
+            return 0.0;
+        }
+        ((self.traded(volume) - ahead) / quantity).clamp(0.0, 1.0)
+    }
+
+    // Queue left ahead of an order after a touch
+    pub fn advance(&self, ahead: f64, volume: f64) -> f64 {
+        (ahead - self.traded(volume)).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_touches_work_through_the_queue() {
+        let model = QueueModel {
+            ahead: 150.0,
+            touch_share: Some(0.1),
+            tick_size: Some(0.01),
+        };
+        assert!(model.validate().is_ok());
+        assert!(model.touches(98.999999, 99.0));
+        assert!(!model.touches(98.99, 99.0));
+
+        // 100 traded at the touch of a bar of 1000, the order of 50 is behind 150
+        assert_eq!(model.fill_probability(150.0, 50.0, 1000.0), 0.0);
+        let ahead = model.advance(150.0, 1000.0);
+        assert_eq!(ahead, 50.0);
+        assert_eq!(model.fill_probability(ahead, 50.0, 1000.0), 1.0);
+        assert_eq!(model.fill_probability(ahead, 100.0, 1000.0), 0.5);
+
+        let invalid = QueueModel {
+            touch_share: Some(0.0),
+            ..model
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+pub(super) const SECONDS_PER_YEAR: f64 = 365.0 * 86400.0;
+
+// Annual rate, 0.04 for 4%, either constant or changing on given dates
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum AnnualRate {
+    Constant(f64),
+    // Each rate applies from its date to the next one, the first one also before it
+    Series(Vec<RateChange>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RateChange {
+    pub from: NaiveDateTime,
+    pub rate: f64,
+}
+
+impl AnnualRate {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let valid = match self {
+            AnnualRate::Constant(rate) => rate.is_finite(),
+            AnnualRate::Series(changes) => {
+                !changes.is_empty() && changes.iter().all(|change| change.rate.is_finite())
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err("A rate is either a number or a list of dated rates")
+        }
+    }
+
+    pub fn sort(&mut self) {
+        if let AnnualRate::Series(changes) = self {
+            changes.sort_by_key(|change| change.from);
+        }
+    }
+
+    pub fn at(&self, time: &NaiveDateTime) -> f64 {
+        match self {
+            AnnualRate::Constant(rate) => *rate,
+            AnnualRate::Series(changes) => {
+                let i = changes.partition_point(|change| &change.from <= time);
+                changes[i.saturating_sub(1)].rate
+            }
+        }
+    }
+
+    // Compounded growth from `from` to `to`, 1.04 over a year at 4%
+    pub fn growth(&self, from: &NaiveDateTime, to: &NaiveDateTime) -> f64 {
+        let mut boundaries: Vec<NaiveDateTime> = match self {
+            AnnualRate::Constant(_) => vec![],
+            AnnualRate::Series(changes) => changes
+                .iter()
+                .map(|change| change.from)
+                .filter(|date| date > from && date < to)
+                .collect(),
+        };
+        boundaries.push(*to);
+
+        let mut time = *from;
+        let mut growth = 1.0;
+        for boundary in boundaries {
+            let years = (boundary - time).num_seconds() as f64 / SECONDS_PER_YEAR;
+            growth *= (1.0 + self.at(&time)).powf(years);
+            time = boundary;
+        }
+        growth
+    }
+}
+
+// Uninvested cash swept into a money market yield, the cash earns nothing without it
+#[derive(Deserialize, Debug, Clone)]
+pub struct CashSweep {
+    pub rate: AnnualRate,
+    // Percent of the cash swept, the rest is restricted and held at zero, 100 by default
+    pub swept_percent: Option<f64>,
+}
+
+impl CashSweep {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        self.rate.validate()?;
+        if self
+            .swept_percent
+            .is_some_and(|percent| !(0.0..=100.0).contains(&percent))
+        {
+            return Err("The swept percent is between 0 and 100");
+        }
+        Ok(())
+    }
+
+    pub fn prepare(mut self) -> Self {
+        self.rate.sort();
+        self
+    }
+
+    // Yield of `cash` held from `from` to `to` at the rate of `from`, nothing on a debit balance
+    pub fn accrue(&self, cash: f64, from: &NaiveDateTime, to: &NaiveDateTime) -> f64 {
+        if cash <= 0.0 || to <= from {
+            return 0.0;
+        }
+        let swept = cash * self.swept_percent.unwrap_or(100.0) / 100.0;
+        let years = (*to - *from).num_seconds() as f64 / SECONDS_PER_YEAR;
+        swept * self.rate.at(from) * years
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn accrues_the_rate_of_the_period_on_the_swept_cash() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let sweep = CashSweep {
+            rate: AnnualRate::Series(vec![
+                RateChange {
+                    from: start + Duration::days(30),
+                    rate: 0.0365,
+                },
+                RateChange {
+                    from: start,
+                    rate: 0.073,
+                },
+            ]),
+            swept_percent: Some(50.0),
+        }
+        .prepare();
+        assert!(sweep.validate().is_ok());
+
+        let day = |days: i64| start + Duration::days(days);
+        // 0.073 / 365 a day on half of the cash
+        assert!((sweep.accrue(20000.0, &day(0), &day(1)) - 2.0).abs() < 1e-9);
+        assert!((sweep.accrue(20000.0, &day(30), &day(31)) - 1.0).abs() < 1e-9);
+        assert!((sweep.accrue(20000.0, &day(-5), &day(-4)) - 2.0).abs() < 1e-9);
+        assert_eq!(sweep.accrue(-100.0, &day(0), &day(1)), 0.0);
+    }
+}
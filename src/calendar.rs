@@ -0,0 +1,200 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Trading hours of a venue (MIC code presets), expressed in the venue local time
+#[derive(Deserialize, Debug, Clone)]
+pub struct Session {
+    pub timezone: Tz,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    #[serde(default = "weekdays")]
+    pub days: Vec<Weekday>,
+    // Local time the day orders expire at, the close by default. Later for the venues keeping
+    // them through the extended hours
+    pub day_orders_until: Option<NaiveTime>,
+}
+
+// A preset name (e.g. "XNAS", "CRYPTO") or a custom session
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum SessionSpec {
+    Preset(String),
+    Custom(Session),
+}
+
+fn weekdays() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ]
+}
+
+fn every_day() -> Vec<Weekday> {
+    let mut days = weekdays();
+    days.extend([Weekday::Sat, Weekday::Sun]);
+    days
+}
+
+impl Session {
+    pub fn preset(name: &str) -> Option<Self> {
+        let hm = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let (timezone, open, close, days) = match name {
+            "XNAS" | "XNYS" => (
+                chrono_tz::America::New_York,
+                hm(9, 30),
+                hm(16, 0),
+                weekdays(),
+            ),
+            "XLON" => (chrono_tz::Europe::London, hm(8, 0), hm(16, 30), weekdays()),
+            "XPAR" | "XETR" => (chrono_tz::Europe::Paris, hm(9, 0), hm(17, 30), weekdays()),
+            "XTKS" => (chrono_tz::Asia::Tokyo, hm(9, 0), hm(15, 30), weekdays()),
+            "CRYPTO" => (chrono_tz::UTC, hm(0, 0), hm(0, 0), every_day()),
+            _ => return None,
+        };
+
+        Some(Session {
+            timezone,
+            open,
+            close,
+            days,
+            day_orders_until: None,
+        })
+    }
+
+    pub fn from_spec(spec: SessionSpec) -> Result<Self, &'static str> {
+        match spec {
+            SessionSpec::Preset(name) => Session::preset(&name).ok_or("Unknown session preset"),
+            SessionSpec::Custom(session) => Ok(session),
+        }
+    }
+
+    // `time` is in UTC like the data feed, an open equal to the close means the session never closes
+    pub fn is_open(&self, time: &NaiveDateTime) -> bool {
+        let local = time.and_utc().with_timezone(&self.timezone);
+        if !self.days.contains(&local.weekday()) {
+            return false;
+        }
+
+        let now = local.time();
+        if self.open < self.close {
+            now >= self.open && now < self.close
+        } else {
+            // Overnight session like futures (18:00 - 17:00)
+            now >= self.open || now < self.close
+        }
+    }
+
+    // First close, or `day_orders_until`, after `time`, in UTC. Overnight sessions close the next
+    // day and sessions that never close roll over at their open
+    pub fn day_order_expiry(&self, time: &NaiveDateTime) -> NaiveDateTime {
+        let cutoff = self.day_orders_until.unwrap_or(self.close);
+        next_local_time(time, cutoff, self.timezone)
+    }
+}
+
+// Sessions of every asset in the simulation, assets without a session are always tradable
+#[derive(Default, Clone)]
+pub struct Calendar {
+    sessions: HashMap<String, Session>,
+}
+
+impl Calendar {
+    pub fn new() -> Self {
+        Calendar::default()
+    }
+
+    pub fn add_session(&mut self, asset: &str, session: Session) {
+        self.sessions.insert(asset.to_string(), session);
+    }
+
+    pub fn session(&self, asset: &str) -> Option<&Session> {
+        self.sessions.get(asset)
+    }
+
+    pub fn is_open(&self, asset: &str, time: &NaiveDateTime) -> bool {
+        self.session(asset)
+            .map(|session| session.is_open(time))
+            .unwrap_or(true)
+    }
+
+    // When a day order that could first trade at `time` expires, midnight UTC for the assets
+    // without a session
+    pub fn day_order_expiry(&self, asset: &str, time: &NaiveDateTime) -> NaiveDateTime {
+        match self.session(asset) {
+            Some(session) => session.day_order_expiry(time),
+            None => next_local_time(time, NaiveTime::MIN, chrono_tz::UTC),
+        }
+    }
+}
+
+// First time strictly after `time` the clock of `timezone` reads `local`, in UTC
+fn next_local_time(time: &NaiveDateTime, local: NaiveTime, timezone: Tz) -> NaiveDateTime {
+    let now = time.and_utc().with_timezone(&timezone).naive_local();
+    let mut next = now.date().and_time(local);
+    if next <= now {
+        next += Duration::days(1);
+    }
+    // A time skipped by a daylight saving change is taken an hour later
+    timezone
+        .from_local_datetime(&next)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(next + Duration::hours(1)))
+                .earliest()
+        })
+        .map_or(next, |next| next.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").expect("Invalid date")
+    }
+
+    #[test]
+    fn day_orders_expire_at_the_close_of_their_session() {
+        let mut calendar = Calendar::new();
+        let nasdaq = Session::preset("XNAS").unwrap();
+        calendar.add_session("AAPL", nasdaq.clone());
+        calendar.add_session("BTC", Session::preset("CRYPTO").unwrap());
+        // Futures trading from 18:00 to 17:00 the next day
+        let overnight = Session {
+            timezone: chrono_tz::America::Chicago,
+            open: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            days: every_day(),
+            day_orders_until: None,
+        };
+        calendar.add_session("ES", overnight);
+
+        // 16:00 in New York, before and after the daylight saving change
+        let expiry = |asset, time| calendar.day_order_expiry(asset, &utc(time));
+        assert_eq!(expiry("AAPL", "2024-03-08 15:00"), utc("2024-03-08 21:00"));
+        assert_eq!(expiry("AAPL", "2024-03-11 14:00"), utc("2024-03-11 20:00"));
+        // 18:00 in Chicago on Sunday belongs to the session closing on Monday
+        assert_eq!(expiry("ES", "2024-03-04 00:00"), utc("2024-03-04 23:00"));
+        assert_eq!(expiry("BTC", "2024-03-04 10:00"), utc("2024-03-05 00:00"));
+        assert_eq!(
+            expiry("EURUSD", "2024-03-04 10:00"),
+            utc("2024-03-05 00:00")
+        );
+
+        // Kept through the extended hours
+        let extended = Session {
+            day_orders_until: NaiveTime::from_hms_opt(20, 0, 0),
+            ..nasdaq
+        };
+        assert_eq!(
+            extended.day_order_expiry(&utc("2024-03-08 15:00")),
+            utc("2024-03-09 01:00")
+        );
+    }
+}
@@ -1,5 +1,85 @@
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+pub mod clean;
+pub mod quality;
+pub mod volume;
+
+// What to do when the data starts after or ends before the requested range
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingData {
+    // Refuse to run
+    Error,
+    // Simulate the part of the range with data only
+    #[default]
+    Shrink,
+    // Fill the missing part with flat bars at the first open or the last close
+    Pad,
+}
+
+// Part of the requested range actually simulated
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DataCoverage {
+    pub requested_start: NaiveDateTime,
+    pub requested_end: NaiveDateTime,
+    pub effective_start: NaiveDateTime,
+    pub effective_end: NaiveDateTime,
+    // Share of the requested range between the first and last real bars
+    pub coverage_percent: f64,
+    pub padded_bars: usize,
+}
+
+// What to do with bars sharing a timestamp
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Duplicates {
+    // Keep the bar that comes first in the feed
+    First,
+    // Keep the bar that comes last in the feed, usually a correction of the earlier ones
+    #[default]
+    Last,
+    // Combine them: first open, highest high, lowest low, last close and summed volume
+    Merge,
+    // Refuse to run
+    Error,
+}
+
+// Corrections made to the order of the feed and its duplicated timestamps
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct DataCorrections {
+    // Bars older than the bar before them in the feed
+    pub out_of_order_bars: usize,
+    pub duplicate_timestamps: usize,
+    // Bars dropped or merged into another one
+    pub removed_bars: usize,
+}
+
+// Which bar is kept when both series of a merge have one at the same time
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Conflict {
+    #[default]
+    Primary,
+    Secondary,
+    // The bar with the most volume, the more liquid print
+    Volume,
+}
+
+// Where the bars of a merged series come from
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DataSources {
+    pub primary_bars: usize,
+    // Bars missing from the primary series taken from the secondary one
+    pub backfilled_bars: usize,
+    // Timestamps present in both series, and how many of them kept the secondary bar
+    pub conflicts: usize,
+    pub replaced_bars: usize,
+    // Timestamps of every bar taken from the secondary series
+    pub secondary: Vec<NaiveDateTime>,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OHLCVData {
@@ -10,3 +90,401 @@ pub struct OHLCVData {
     pub close: f64,
     pub volume: u64,
 }
+
+// Bars of every asset a strategy can read, looked up by time
+#[derive(Debug, Clone, Default)]
+pub struct MarketData {
+    series: HashMap<String, Vec<OHLCVData>>,
+    // On-balance volume of every bar of the series, cumulative from their first bar
+    obv: HashMap<String, Vec<f64>>,
+}
+
+impl MarketData {
+    pub fn insert(&mut self, asset: String, mut bars: Vec<OHLCVData>) {
+        bars.sort_by_key(|bar| bar.timestamp);
+        self.obv.insert(asset.clone(), volume::obv(&bars));
+        self.series.insert(asset, bars);
+    }
+
+    // Last bar of `asset` at or before `time`, like the bar the engine ticks the strategy with
+    pub fn bar(&self, asset: &str, time: NaiveDateTime) -> Option<&OHLCVData> {
+        let bars = self.series.get(asset)?;
+        let i = bars.partition_point(|bar| bar.timestamp <= time);
+        i.checked_sub(1).map(|i| &bars[i])
+    }
+
+    // Last `count` bars of `asset` at or before `time`, None until there are as many
+    pub fn history(&self, asset: &str, time: NaiveDateTime, count: usize) -> Option<&[OHLCVData]> {
+        let bars = self.series.get(asset)?;
+        let end = bars.partition_point(|bar| bar.timestamp <= time);
+        let start = end.checked_sub(count).filter(|_| count > 0)?;
+        Some(&bars[start..end])
+    }
+
+    pub fn obv(&self, asset: &str, time: NaiveDateTime) -> Option<f64> {
+        let bars = self.series.get(asset)?;
+        let i = bars.partition_point(|bar| bar.timestamp <= time);
+        i.checked_sub(1).map(|i| self.obv[asset][i])
+    }
+}
+
+// Content hash identifying the data of a run, along with the bytes it is stored as
+pub fn snapshot(data: &[OHLCVData]) -> (String, Vec<u8>) {
+    let bytes = serde_json::to_vec(data).unwrap_or_default();
+    let hash = hex::encode(Sha256::digest(&bytes));
+    (hash, bytes)
+}
+
+// Sorts the feed by time, keeping the feed order of bars at the same time, and applies `policy`
+// to the duplicated timestamps
+pub fn normalize(
+    data: &mut Vec<OHLCVData>,
+    policy: Duplicates,
+) -> Result<DataCorrections, &'static str> {
+    let mut corrections = DataCorrections {
+        out_of_order_bars: data
+            .windows(2)
+            .filter(|w| w[1].timestamp < w[0].timestamp)
+            .count(),
+        ..DataCorrections::default()
+    };
+    // Stable, duplicates stay in the feed order
+    data.sort_by_key(|bar| bar.timestamp);
+
+    let mut counted = None;
+    for bar in std::mem::take(data) {
+        let Some(last) = data
+            .last_mut()
+            .filter(|last| last.timestamp == bar.timestamp)
+        else {
+            data.push(bar);
+            continue;
+        };
+        if counted != Some(bar.timestamp) {
+            counted = Some(bar.timestamp);
+            corrections.duplicate_timestamps += 1;
+        }
+        match policy {
+            Duplicates::Error => return Err("The data has duplicated timestamps"),
+            Duplicates::First => {}
+            Duplicates::Last => *last = bar,
+            Duplicates::Merge => {
+                last.high = last.high.max(bar.high);
+                last.low = last.low.min(bar.low);
+                last.close = bar.close;
+                last.volume = last.volume.saturating_add(bar.volume);
+            }
+        }
+        corrections.removed_bars += 1;
+    }
+    Ok(corrections)
+}
+
+// Median time between consecutive bars, the resolution of the feed. Duplicated timestamps are ignored
+pub fn median_spacing(data: &[OHLCVData]) -> Option<Duration> {
+    let mut spacings: Vec<Duration> = data
+        .windows(2)
+        .map(|w| w[1].timestamp - w[0].timestamp)
+        .filter(|spacing| *spacing > Duration::zero())
+        .collect();
+    if spacings.is_empty() {
+        return None;
+    }
+    spacings.sort();
+    Some(spacings[spacings.len() / 2])
+}
+
+// Fills the gaps of `primary` with the bars of `secondary`, `conflict` decides which bar is kept
+// when both have one at the same time. Both series should have the same resolution
+pub fn merge(
+    mut primary: Vec<OHLCVData>,
+    mut secondary: Vec<OHLCVData>,
+    conflict: Conflict,
+) -> (Vec<OHLCVData>, DataSources) {
+    primary.sort_by_key(|bar| bar.timestamp);
+    secondary.sort_by_key(|bar| bar.timestamp);
+    secondary.dedup_by_key(|bar| bar.timestamp);
+
+    let mut sources = DataSources {
+        primary_bars: 0,
+        backfilled_bars: 0,
+        conflicts: 0,
+        replaced_bars: 0,
+        secondary: vec![],
+    };
+    let mut merged = Vec::with_capacity(primary.len().max(secondary.len()));
+    let mut primary = primary.into_iter().peekable();
+    let mut secondary = secondary.into_iter().peekable();
+    loop {
+        // Which series the next bar comes from, and if it replaces a primary bar
+        let (from_secondary, replaces) = match (primary.peek(), secondary.peek()) {
+            (None, None) => break,
+            (Some(_), None) => (false, false),
+            (None, Some(_)) => (true, false),
+            (Some(a), Some(b)) if a.timestamp < b.timestamp => (false, false),
+            (Some(a), Some(b)) if a.timestamp > b.timestamp => (true, false),
+            (Some(a), Some(b)) => {
+                sources.conflicts += 1;
+                let keep_secondary = match conflict {
+                    Conflict::Primary => false,
+                    Conflict::Secondary => true,
+                    Conflict::Volume => b.volume > a.volume,
+                };
+                // Drop the other bar of the pair
+                match keep_secondary {
+                    true => primary.next(),
+                    false => secondary.next(),
+                };
+                (keep_secondary, keep_secondary)
+            }
+        };
+
+        if from_secondary {
+            let bar = secondary.next().expect("Bar peeked above");
+            match replaces {
+                true => sources.replaced_bars += 1,
+                false => sources.backfilled_bars += 1,
+            }
+            sources.secondary.push(bar.timestamp);
+            merged.push(bar);
+        } else {
+            sources.primary_bars += 1;
+            merged.push(primary.next().expect("Bar peeked above"));
+        }
+    }
+    (merged, sources)
+}
+
+// Checks the data against the requested range and applies `policy` to what is missing. Gaps at
+// the edges up to a few bars plus a long weekend don't count as missing
+pub fn cover(
+    data: &mut Vec<OHLCVData>,
+    (start, end): (NaiveDateTime, NaiveDateTime),
+    policy: MissingData,
+) -> Result<DataCoverage, &'static str> {
+    let (Some(first), Some(last)) = (data.first().cloned(), data.last().cloned()) else {
+        return Err("The data feed is empty");
+    };
+    // Not from the largest spacing, a gap inside the data (e.g. a halt) would hide as long a gap
+    // at the edges
+    let spacing = median_spacing(data).unwrap_or_else(Duration::zero);
+    let tolerance = spacing * 2 + Duration::days(3);
+    let missing_start = first.timestamp - start > tolerance;
+    let missing_end = end - last.timestamp > tolerance;
+
+    let requested = (end - start).num_seconds();
+    let covered = (end.min(last.timestamp) - start.max(first.timestamp))
+        .num_seconds()
+        .max(0);
+    let coverage_percent = match requested > 0 {
+        true => covered as f64 / requested as f64 * 100.0,
+        false => 100.0,
+    };
+
+    let mut coverage = DataCoverage {
+        requested_start: start,
+        requested_end: end,
+        effective_start: start,
+        effective_end: end,
+        coverage_percent,
+        padded_bars: 0,
+    };
+    if !missing_start && !missing_end {
+        return Ok(coverage);
+    }
+
+    match policy {
+        MissingData::Error => return Err("The data doesn't cover the requested range"),
+        MissingData::Shrink => {
+            if missing_start {
+                coverage.effective_start = first.timestamp;
+            }
+            if missing_end {
+                coverage.effective_end = last.timestamp;
+            }
+        }
+        MissingData::Pad => {
+            if spacing <= Duration::zero() {
+                return Err("Cannot pad the data without a bar spacing");
+            }
+            let flat = |timestamp: NaiveDateTime, price: f64| OHLCVData {
+                timestamp,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: 0,
+            };
+
+            let mut before = vec![];
+            let mut timestamp = first.timestamp - spacing;
+            while missing_start && timestamp >= start {
+                before.push(flat(timestamp, first.open));
+                timestamp -= spacing;
+            }
+            before.reverse();
+
+            let mut after = vec![];
+            let mut timestamp = last.timestamp + spacing;
+            while missing_end && timestamp <= end {
+                after.push(flat(timestamp, last.close));
+                timestamp += spacing;
+            }
+
+            coverage.padded_bars = before.len() + after.len();
+            data.splice(0..0, before);
+            data.extend(after);
+        }
+    }
+    Ok(coverage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_spacing_skips_weekends() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        // Monday to Friday, then the next Monday
+        let bar = |days: i64| OHLCVData {
+            timestamp: start + Duration::days(days),
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 0,
+        };
+        let data: Vec<OHLCVData> = [0, 1, 2, 3, 4, 7].into_iter().map(bar).collect();
+
+        assert_eq!(median_spacing(&data), Some(Duration::days(1)));
+        assert_eq!(median_spacing(&data[..1]), None);
+    }
+
+    #[test]
+    fn pads_a_symbol_listed_mid_range() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |days: i64| OHLCVData {
+            timestamp: start + Duration::days(days),
+            open: 10.0,
+            high: 12.0,
+            low: 9.0,
+            close: 11.0,
+            volume: 100,
+        };
+        // Listed on day 6 of a 10 day range
+        let mut data: Vec<OHLCVData> = (6..=10).map(bar).collect();
+        let range = (start, start + Duration::days(10));
+
+        assert!(cover(&mut data, range, MissingData::Error).is_err());
+
+        let coverage = cover(&mut data, range, MissingData::Shrink).unwrap();
+        assert_eq!(coverage.effective_start, start + Duration::days(6));
+        assert_eq!(coverage.coverage_percent, 40.0);
+
+        let coverage = cover(&mut data, range, MissingData::Pad).unwrap();
+        assert_eq!(coverage.padded_bars, 6);
+        assert_eq!(data.len(), 11);
+        assert_eq!((data[0].timestamp, data[0].close), (start, 10.0));
+
+        // A halt inside the data doesn't hide a shorter gap at the start
+        let mut data: Vec<OHLCVData> = [8, 9, 20, 21, 22].into_iter().map(bar).collect();
+        let range = (start, start + Duration::days(22));
+        assert!(cover(&mut data, range, MissingData::Error).is_err());
+    }
+
+    #[test]
+    fn backfills_the_gaps_of_the_primary_series() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 09:30:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |minutes: i64, close: f64, volume: u64| OHLCVData {
+            timestamp: start + Duration::minutes(minutes),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume,
+        };
+        // Thinly traded primary series missing minutes 1 and 3
+        let primary = vec![bar(0, 10.0, 50), bar(2, 10.2, 10), bar(4, 10.4, 80)];
+        let secondary = vec![bar(1, 20.1, 5), bar(2, 20.2, 40), bar(3, 20.3, 5)];
+
+        let (merged, sources) = merge(primary.clone(), secondary.clone(), Conflict::Primary);
+        let closes: Vec<f64> = merged.iter().map(|bar| bar.close).collect();
+        assert_eq!(closes, vec![10.0, 20.1, 10.2, 20.3, 10.4]);
+        assert_eq!((sources.primary_bars, sources.backfilled_bars), (3, 2));
+        assert_eq!((sources.conflicts, sources.replaced_bars), (1, 0));
+
+        let (merged, sources) = merge(primary, secondary, Conflict::Volume);
+        assert_eq!(merged[2].close, 20.2);
+        assert_eq!((sources.primary_bars, sources.replaced_bars), (2, 1));
+        assert_eq!(sources.secondary.len(), 3);
+    }
+
+    #[test]
+    fn normalizes_unordered_and_duplicated_bars() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |days: i64, close: f64| OHLCVData {
+            timestamp: start + Duration::days(days),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 10,
+        };
+        // Day 1 is sent twice, the second time as a correction, and day 0 arrives late
+        let feed = vec![bar(1, 11.0), bar(2, 12.0), bar(1, 10.5), bar(0, 10.0)];
+
+        let mut data = feed.clone();
+        let corrections = normalize(&mut data, Duplicates::Last).unwrap();
+        let closes: Vec<f64> = data.iter().map(|bar| bar.close).collect();
+        assert_eq!(closes, vec![10.0, 10.5, 12.0]);
+        assert_eq!(
+            corrections,
+            DataCorrections {
+                out_of_order_bars: 2,
+                duplicate_timestamps: 1,
+                removed_bars: 1,
+            }
+        );
+
+        let mut data = feed.clone();
+        normalize(&mut data, Duplicates::Merge).unwrap();
+        assert_eq!(
+            (data[1].open, data[1].high, data[1].close),
+            (11.0, 11.0, 10.5)
+        );
+        assert_eq!(data[1].volume, 20);
+
+        assert!(normalize(&mut feed.clone(), Duplicates::Error).is_err());
+    }
+
+    #[test]
+    fn market_data_never_looks_ahead() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |hours: i64, close: f64| OHLCVData {
+            timestamp: start + Duration::hours(hours),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 0,
+        };
+        let mut market = MarketData::default();
+        market.insert("ETH".to_string(), vec![bar(2, 20.0), bar(0, 10.0)]);
+
+        let close = |hours: i64| {
+            market
+                .bar("ETH", start + Duration::hours(hours))
+                .map(|bar| bar.close)
+        };
+        assert_eq!(close(-1), None);
+        assert_eq!(close(1), Some(10.0));
+        assert_eq!(close(2), Some(20.0));
+        assert!(market.bar("BTC", start).is_none());
+    }
+}
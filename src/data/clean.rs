@@ -0,0 +1,203 @@
+use super::OHLCVData;
+use serde::{Deserialize, Serialize};
+
+// Changes listed in the log, the counts cover all of them
+const MAX_LOGGED_CHANGES: usize = 100;
+
+// Filters of bad prints, applied to the sorted bars before the run
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Cleaning {
+    // Drops bars with a zero, negative or non finite price
+    pub invalid_prices: bool,
+    // Drops bars whose close moves more than this percent from the previous close and comes
+    // back within it on the next bar
+    pub spike_percent: Option<f64>,
+    // Widens the high and low of the bars not containing their open and close
+    pub repair_ranges: bool,
+    // Drops bars without volume
+    pub zero_volume: bool,
+    // Caps the volumes above this multiple of the median volume
+    pub max_volume_multiple: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CleaningFilter {
+    InvalidPrice,
+    Spike,
+    Range,
+    ZeroVolume,
+    VolumeCap,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CleaningChange {
+    pub symbol: Option<String>,
+    pub filter: CleaningFilter,
+    pub removed: bool,
+    // The bar before the change
+    pub bar: OHLCVData,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CleaningLog {
+    pub removed_bars: usize,
+    pub modified_bars: usize,
+    pub changes: Vec<CleaningChange>,
+}
+
+impl Cleaning {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self
+            .spike_percent
+            .is_some_and(|percent| !(percent.is_finite() && percent > 0.0))
+        {
+            return Err("The spike percent must be positive");
+        }
+        if self
+            .max_volume_multiple
+            .is_some_and(|multiple| !(multiple.is_finite() && multiple >= 1.0))
+        {
+            return Err("The volume multiple must be at least 1");
+        }
+        Ok(())
+    }
+
+    // Cleans the `bars` sorted by time of `symbol`, logging every change
+    pub fn apply(&self, symbol: Option<&str>, bars: &mut Vec<OHLCVData>, log: &mut CleaningLog) {
+        let mut record = |filter: CleaningFilter, removed: bool, bar: &OHLCVData| {
+            if removed {
+                log.removed_bars += 1;
+            } else {
+                log.modified_bars += 1;
+            }
+            if log.changes.len() < MAX_LOGGED_CHANGES {
+                log.changes.push(CleaningChange {
+                    symbol: symbol.map(str::to_string),
+                    filter,
+                    removed,
+                    bar: bar.clone(),
+                });
+            }
+        };
+
+        if self.invalid_prices {
+            bars.retain(|bar| {
+                let valid = [bar.open, bar.high, bar.low, bar.close]
+                    .iter()
+                    .all(|price| price.is_finite() && *price > 0.0);
+                if !valid {
+                    record(CleaningFilter::InvalidPrice, true, bar);
+                }
+                valid
+            });
+        }
+
+        if self.zero_volume {
+            bars.retain(|bar| {
+                if bar.volume == 0 {
+                    record(CleaningFilter::ZeroVolume, true, bar);
+                }
+                bar.volume > 0
+            });
+        }
+
+        if let Some(percent) = self.spike_percent {
+            let threshold = percent / 100.0;
+            let moves = |from: f64, to: f64| from != 0.0 && (to / from - 1.0).abs() > threshold;
+            let mut kept: Vec<OHLCVData> = Vec::with_capacity(bars.len());
+            for (i, bar) in bars.iter().enumerate() {
+                let spike = match (kept.last(), bars.get(i + 1)) {
+                    (Some(previous), Some(next)) => {
+                        moves(previous.close, bar.close) && !moves(previous.close, next.close)
+                    }
+                    _ => false,
+                };
+                if spike {
+                    record(CleaningFilter::Spike, true, bar);
+                } else {
+                    kept.push(bar.clone());
+                }
+            }
+            *bars = kept;
+        }
+
+        if self.repair_ranges {
+            for bar in bars.iter_mut() {
+                let high = bar.high.max(bar.open).max(bar.close);
+                let low = bar.low.min(bar.open).min(bar.close);
+                if high != bar.high || low != bar.low {
+                    record(CleaningFilter::Range, false, bar);
+                    bar.high = high;
+                    bar.low = low;
+                }
+            }
+        }
+
+        if let Some(multiple) = self.max_volume_multiple {
+            let mut volumes: Vec<u64> = bars.iter().map(|bar| bar.volume).collect();
+            volumes.sort_unstable();
+            let Some(median) = volumes.get(volumes.len() / 2) else {
+                return;
+            };
+            let cap = (*median as f64 * multiple) as u64;
+            for bar in bars.iter_mut().filter(|bar| bar.volume > cap) {
+                record(CleaningFilter::VolumeCap, false, bar);
+                bar.volume = cap;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDateTime};
+
+    #[test]
+    fn removes_bad_prints_and_logs_them() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let mut bars: Vec<_> = [100.0, 101.0, 0.0, 102.0, 150.0, 103.0, 104.0, 140.0]
+            .iter()
+            .enumerate()
+            .map(|(day, close)| OHLCVData {
+                timestamp: start + Duration::days(day as i64),
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                volume: if day == 6 { 1_000_000 } else { 1000 },
+            })
+            .collect();
+        bars[1].high = 100.5;
+
+        let cleaning = Cleaning {
+            invalid_prices: true,
+            spike_percent: Some(20.0),
+            repair_ranges: true,
+            zero_volume: false,
+            max_volume_multiple: Some(10.0),
+        };
+        let mut log = CleaningLog::default();
+        cleaning.apply(Some("AAPL"), &mut bars, &mut log);
+
+        let closes: Vec<f64> = bars.iter().map(|bar| bar.close).collect();
+        // The last move can't be told from a real one without the next bar
+        assert_eq!(closes, [100.0, 101.0, 102.0, 103.0, 104.0, 140.0]);
+        assert_eq!((log.removed_bars, log.modified_bars), (2, 2));
+        let filters: Vec<_> = log.changes.iter().map(|change| change.filter).collect();
+        assert_eq!(
+            filters,
+            [
+                CleaningFilter::InvalidPrice,
+                CleaningFilter::Spike,
+                CleaningFilter::Range,
+                CleaningFilter::VolumeCap,
+            ]
+        );
+        assert_eq!(bars[1].high, 101.0);
+        assert_eq!(bars[4].volume, 10_000);
+    }
+}
@@ -0,0 +1,147 @@
+use super::{median_spacing, OHLCVData};
+use chrono::{Datelike, Duration, NaiveDateTime, Weekday};
+use serde::Serialize;
+
+// Standard deviations of the bar returns beyond which a bar is flagged, when not set
+pub const DEFAULT_OUTLIER_Z_SCORE: f64 = 4.0;
+
+// Outliers listed in a summary, the count covers all of them
+const MAX_LISTED_OUTLIERS: usize = 20;
+
+// Bars of a symbol checked for what could distort a run
+#[derive(Serialize, Debug, Clone)]
+pub struct DataQuality {
+    // None for the primary series without `data.symbol`
+    pub symbol: Option<String>,
+    pub bars: usize,
+    // Missing bars: weekdays without a bar for daily data, a spacing over 1.5 times the
+    // resolution within a day for intraday data, and between bars for coarser data
+    pub gaps: usize,
+    pub zero_volume_bars: usize,
+    pub outlier_z_score: f64,
+    pub outlier_count: usize,
+    pub outliers: Vec<Outlier>,
+    // 100 minus the percent of the bars with an issue
+    pub score: f64,
+}
+
+// Close to close return far from the others, the print of the bar may be bad
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Outlier {
+    pub timestamp: NaiveDateTime,
+    pub bar_return: f64,
+    pub z_score: f64,
+}
+
+fn weekdays_between(start: &NaiveDateTime, end: &NaiveDateTime) -> usize {
+    let mut day = start.date() + Duration::days(1);
+    let mut count = 0;
+    while day < end.date() {
+        if !matches!(day.weekday(), Weekday::Sat | Weekday::Sun) {
+            count += 1;
+        }
+        day += Duration::days(1);
+    }
+    count
+}
+
+impl DataQuality {
+    // `bars` sorted by time
+    pub fn new(symbol: Option<String>, bars: &[OHLCVData], outlier_z_score: f64) -> Self {
+        let gaps = match median_spacing(bars) {
+            Some(resolution) if resolution < Duration::days(1) => bars
+                .windows(2)
+                .filter(|w| {
+                    w[0].timestamp.date() == w[1].timestamp.date()
+                        && (w[1].timestamp - w[0].timestamp) * 2 > resolution * 3
+                })
+                .count(),
+            Some(resolution) if resolution == Duration::days(1) => bars
+                .windows(2)
+                .map(|w| weekdays_between(&w[0].timestamp, &w[1].timestamp))
+                .sum(),
+            Some(resolution) => bars
+                .windows(2)
+                .filter(|w| (w[1].timestamp - w[0].timestamp) * 2 > resolution * 3)
+                .count(),
+            None => 0,
+        };
+
+        let returns: Vec<(NaiveDateTime, f64)> = bars
+            .windows(2)
+            .filter(|w| w[0].close != 0.0)
+            .map(|w| (w[1].timestamp, w[1].close / w[0].close - 1.0))
+            .collect();
+        let n = returns.len() as f64;
+        let mean = returns.iter().map(|(_, r)| r).sum::<f64>() / n.max(1.0);
+        let deviation =
+            (returns.iter().map(|(_, r)| (r - mean).powi(2)).sum::<f64>() / n.max(1.0)).sqrt();
+        let outliers: Vec<Outlier> = returns
+            .iter()
+            .filter(|_| deviation > 0.0)
+            .map(|&(timestamp, bar_return)| Outlier {
+                timestamp,
+                bar_return,
+                z_score: (bar_return - mean) / deviation,
+            })
+            .filter(|outlier| outlier.z_score.abs() > outlier_z_score)
+            .collect();
+
+        let zero_volume_bars = bars.iter().filter(|bar| bar.volume == 0).count();
+        let issues = (gaps + zero_volume_bars + outliers.len()) as f64;
+        let score = match bars.len() {
+            0 => 0.0,
+            n => (100.0 - issues / n as f64 * 100.0).max(0.0),
+        };
+
+        DataQuality {
+            symbol,
+            bars: bars.len(),
+            gaps,
+            zero_volume_bars,
+            outlier_z_score,
+            outlier_count: outliers.len(),
+            outliers: outliers.into_iter().take(MAX_LISTED_OUTLIERS).collect(),
+            score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_gaps_idle_bars_and_outliers() {
+        // Monday
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bar = |day: i64, close: f64, volume: u64| OHLCVData {
+            timestamp: start + Duration::days(day),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume,
+        };
+        // Weekdays of three weeks, the Wednesday of the second one missing and a bad print on
+        // the Tuesday of the third one
+        let mut bars = vec![];
+        for day in (0..19).filter(|day| day % 7 < 5 && *day != 9) {
+            let close = if day == 15 {
+                200.0
+            } else {
+                100.0 + (day % 2) as f64
+            };
+            bars.push(bar(day, close, if day == 3 { 0 } else { 1000 }));
+        }
+
+        let quality = DataQuality::new(Some("AAPL".to_string()), &bars, 3.0);
+        assert_eq!(quality.bars, 14);
+        assert_eq!(quality.gaps, 1);
+        assert_eq!(quality.zero_volume_bars, 1);
+        assert_eq!(quality.outlier_count, 1);
+        assert_eq!(quality.outliers[0].timestamp, start + Duration::days(15));
+        assert!((quality.score - (100.0 - 3.0 / 14.0 * 100.0)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,178 @@
+use super::OHLCVData;
+
+// Price bins of the volume profile between the lowest low and the highest high of the window
+pub const PROFILE_BINS: usize = 24;
+// Share of the volume traded within the value area
+pub const VALUE_AREA: f64 = 0.7;
+
+// Volume weighted average of the typical price (high + low + close) / 3, None without volume
+pub fn vwap(bars: &[OHLCVData]) -> Option<f64> {
+    let volume: f64 = bars.iter().map(|bar| bar.volume as f64).sum();
+    if volume == 0.0 {
+        return None;
+    }
+    let value: f64 = bars
+        .iter()
+        .map(|bar| (bar.high + bar.low + bar.close) / 3.0 * bar.volume as f64)
+        .sum();
+    Some(value / volume)
+}
+
+// On-balance volume of every bar: the volume is added on up closes and subtracted on down closes,
+// starting from 0 on the first bar
+pub fn obv(bars: &[OHLCVData]) -> Vec<f64> {
+    let mut total = 0.0;
+    bars.iter()
+        .enumerate()
+        .map(|(i, bar)| {
+            if i > 0 {
+                let previous = bars[i - 1].close;
+                if bar.close > previous {
+                    total += bar.volume as f64;
+                } else if bar.close < previous {
+                    total -= bar.volume as f64;
+                }
+            }
+            total
+        })
+        .collect()
+}
+
+// Buying against selling pressure from -1 to 1, each bar's volume weighted by where it closes
+// within its range (1 on the high, -1 on the low). None without volume
+pub fn imbalance(bars: &[OHLCVData]) -> Option<f64> {
+    let volume: f64 = bars.iter().map(|bar| bar.volume as f64).sum();
+    if volume == 0.0 {
+        return None;
+    }
+    let pressure: f64 = bars
+        .iter()
+        .filter(|bar| bar.high > bar.low)
+        .map(|bar| {
+            let location = ((bar.close - bar.low) - (bar.high - bar.close)) / (bar.high - bar.low);
+            location * bar.volume as f64
+        })
+        .sum();
+    Some(pressure / volume)
+}
+
+// Price levels of the volume profile of a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeProfile {
+    // Middle of the bin with the most volume, the point of control
+    pub poc: f64,
+    // Bounds of the bins around the point of control holding `VALUE_AREA` of the volume
+    pub value_area_low: f64,
+    pub value_area_high: f64,
+}
+
+// Spreads the volume of each bar evenly over the bins its range covers, None without volume or
+// price range
+pub fn volume_profile(bars: &[OHLCVData]) -> Option<VolumeProfile> {
+    let low = bars.iter().map(|bar| bar.low).fold(f64::INFINITY, f64::min);
+    let high = bars
+        .iter()
+        .map(|bar| bar.high)
+        .fold(f64::NEG_INFINITY, f64::max);
+    if high <= low {
+        return None;
+    }
+    let width = (high - low) / PROFILE_BINS as f64;
+    let bin = |price: f64| (((price - low) / width) as usize).min(PROFILE_BINS - 1);
+
+    let mut volumes = [0.0; PROFILE_BINS];
+    for bar in bars {
+        let (first, last) = (bin(bar.low), bin(bar.high));
+        let share = bar.volume as f64 / (last - first + 1) as f64;
+        for volume in &mut volumes[first..=last] {
+            *volume += share;
+        }
+    }
+    let total: f64 = volumes.iter().sum();
+    if total == 0.0 {
+        return None;
+    }
+
+    // The first of the fullest bins, the area grows towards the fuller neighbour
+    let poc = (0..PROFILE_BINS).fold(0, |best, i| match volumes[i] > volumes[best] {
+        true => i,
+        false => best,
+    });
+    let (mut first, mut last) = (poc, poc);
+    let mut area = volumes[poc];
+    while area < VALUE_AREA * total {
+        let below = first.checked_sub(1).map(|i| volumes[i]);
+        let above = (last + 1 < PROFILE_BINS).then(|| volumes[last + 1]);
+        match (below, above) {
+            (Some(below), Some(above)) if below > above => {
+                first -= 1;
+                area += below;
+            }
+            (_, Some(above)) => {
+                last += 1;
+                area += above;
+            }
+            (Some(below), None) => {
+                first -= 1;
+                area += below;
+            }
+            (None, None) => break,
+        }
+    }
+
+    Some(VolumeProfile {
+        poc: low + (poc as f64 + 0.5) * width,
+        value_area_low: low + first as f64 * width,
+        value_area_high: low + (last + 1) as f64 * width,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn bar(low: f64, high: f64, close: f64, volume: u64) -> OHLCVData {
+        OHLCVData {
+            timestamp: NaiveDateTime::default(),
+            open: close,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn volume_indicators() {
+        let bars = [
+            bar(9.0, 11.0, 10.0, 100),
+            bar(10.0, 12.0, 12.0, 300),
+            bar(10.0, 12.0, 11.0, 100),
+            bar(11.0, 11.0, 11.0, 0),
+        ];
+
+        // Typical prices 10, 34/3 and 11
+        let expected = (10.0 * 100.0 + 34.0 / 3.0 * 300.0 + 11.0 * 100.0) / 500.0;
+        assert!((vwap(&bars).unwrap() - expected).abs() < 1e-9);
+        assert_eq!(vwap(&bars[3..]), None);
+
+        // Unchanged closes leave the OBV as it is
+        assert_eq!(obv(&bars), [0.0, 300.0, 200.0, 200.0]);
+
+        // Closes in the middle weigh 0, on the high 1
+        assert!((imbalance(&bars).unwrap() - 300.0 / 500.0).abs() < 1e-9);
+
+        // Bins of 0.125 from 9 to 12, the three bars overlap from 10 to 11
+        let profile = volume_profile(&bars).unwrap();
+        assert_eq!(
+            profile,
+            VolumeProfile {
+                poc: 10.0625,
+                value_area_low: 10.0,
+                value_area_high: 11.5
+            }
+        );
+        assert_eq!(volume_profile(&bars[3..]), None);
+    }
+}
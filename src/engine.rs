@@ -1,22 +1,250 @@
-use crate::analytics::{metrics::GlobalMetrics, trade::Trade};
-use crate::broker::Broker;
-use crate::data::OHLCVData;
-use crate::strategy::Strategy;
+use crate::analytics::{
+    benchmark::{self, Benchmark},
+    campaign::{self, CampaignReport},
+    drawdown::{self, Drawdown},
+    execution::ExecutionReport,
+    gap_risk::{self, GapRisk},
+    growth::RollingCagr,
+    inflation::Inflation,
+    metrics::{self, GlobalMetrics, MetricsInputs, MetricsMap, METRICS_VERSION},
+    plugin::MetricRegistry,
+    reconciliation::{self, Reconciliation},
+    seasonality::{Seasonality, SeasonalitySettings},
+    slice::{Slice, SliceReport},
+    trade::{self, Trade},
+};
+use crate::broker::{
+    account::SubAccountReport,
+    algo::AlgoOrderReport,
+    exposure::ExposureReport,
+    fee::FeeTotal,
+    hooks::{OrderEvent, OrderLog},
+    margin::MarginReport,
+    order::{DownsizedOrder, PartialFill},
+    overlay::BetaHedgeReport,
+    position::DustClosure,
+    Broker,
+};
+use crate::data::{
+    clean::CleaningLog, quality::DataQuality, DataCorrections, DataCoverage, DataSources, OHLCVData,
+};
+use crate::provider::PriceBasis;
+use crate::strategy::{RunClock, Strategy, StrategyError, StrategyWarning};
 use chrono::{Duration, NaiveDateTime};
-use serde::Serialize;
+use profile::{EngineProfile, Profiler, Section};
+use serde::{Deserialize, Serialize};
+use ticks::{TickBar, TickCoverage};
+
+pub mod profile;
+pub mod response;
+pub mod ticks;
+pub mod vectorized;
+
+// Annual rate used for the Sharpe ratios
+pub const RISK_FREE_RATE: f64 = 0.03;
 
 #[derive(Serialize)]
 pub struct BacktestResult {
+    pub id: Option<String>,
     pub trades: Vec<Trade>,
     pub metrics: GlobalMetrics,
+    // The same metrics keyed by name with their units
+    pub metrics_map: MetricsMap,
+    // Version of the metrics, stored runs of an older one can be recomputed
+    pub metrics_version: u32,
+    // What the metrics are computed from along with the trades and the equity curve
+    pub metrics_inputs: MetricsInputs,
+    pub equity_curve: Vec<EquityPoint>,
+    // Parent orders of the execution algos with their implementation shortfall
+    pub algo_orders: Vec<AlgoOrderReport>,
+    // Fills against their arrival price and limit order fill rates
+    pub execution: ExecutionReport,
+    // Ticks of the range with a new bar, the last bar again or no bar yet, and the orders placed
+    // without a new bar
+    pub tick_coverage: TickCoverage,
+    // Residual quantities closed with a sell instead of being left open
+    pub dust_closures: Vec<DustClosure>,
+    // Buys filled for less than their size, with `broker.cash_shortfall` set to `downsize`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub downsized_orders: Vec<DownsizedOrder>,
+    // Orders filled across bars, with `broker.volume_share`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub partial_fills: Vec<PartialFill>,
+    // Fees by the currency they were charged in, the traded assets for `broker.fee_currency`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fee_totals: Vec<FeeTotal>,
+    // Loan of the margin account, `broker.margin`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub margin: Option<MarginReport>,
+    // Cash, equity and drawdown of the capital of each strategy of `strategy.sub_accounts`, the
+    // metrics are those of the consolidated account
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sub_accounts: Vec<SubAccountReport>,
+    // Fills grouped from flat to flat, for strategies scaling in and out of positions
+    pub campaigns: CampaignReport,
+    // Broker totals checked against the trades, flags accounting discrepancies
+    pub reconciliation: Reconciliation,
+    // Requested range against the simulated one and how much of it the data covers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<DataCoverage>,
+    // Bars of the feed reordered and duplicated timestamps removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corrections: Option<DataCorrections>,
+    // Content hash of the data, rerun the exact same bars with `data.snapshot`
+    pub data_hash: Option<String>,
+    // Bars removed or modified by `data.cleaning`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleaning: Option<CleaningLog>,
+    // Gaps, idle bars and outliers of the series of each symbol
+    pub data_quality: Vec<DataQuality>,
+    // Bars taken from the backfill series when the data was merged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<DataSources>,
+    // Adjusted or raw prices, and the provider they come from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prices: Option<PriceBasis>,
+    // Every fall of the equity below its previous high, from the peak to the recovery
+    pub drawdowns: Vec<Drawdown>,
+    // CAGR over the trailing year and three years, by day
+    pub rolling_cagr: RollingCagr,
+    // Metrics without the liquidation of `parameters.liquidate_at_end`, the positions still
+    // open being valued at the last close
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held_to_end: Option<GlobalMetrics>,
+    // P&L of the trades made in the gaps between the sessions against the one made while trading
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap_risk: Option<GapRisk>,
+    // Mean returns of the symbol and of the equity by month, day of the month and around the
+    // events of `parameters.seasonality`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seasonality: Option<Seasonality>,
+    // Metrics of each slice of `parameters.slices`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub slices: Vec<SliceReport>,
+    // Buy and hold of the asset through the same broker, the drawdown and Sharpe ratio to
+    // compare the strategy against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<Benchmark>,
+    // Lifecycle of every order, when `parameters.order_log` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_log: Option<Vec<OrderEvent>>,
+    // Time spent in each part of the run, when `parameters.profile` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<EngineProfile>,
+    // Error that stopped the run, the rest of the result covers the ticks before it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<RunFailure>,
+    // Orders and host calls of the strategy refused by its capabilities
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub strategy_warnings: Vec<StrategyWarning>,
+    // Bars ticked before the start to warm the strategy up, left out of the metrics
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<Warmup>,
+    // Net and gross exposure against the band of `broker.exposure`, with its hedge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<ExposureReport>,
+    // Beta of the book to the benchmark of `broker.beta_hedge` and the hedge sized by it, the
+    // metrics are those of the hedged book
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beta_hedge: Option<BetaHedgeReport>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Warmup {
+    pub requested_bars: usize,
+    // Fewer than requested when the data starts too late
+    pub bars: usize,
+    // First tick of the warm-up, None without any warm-up bar
+    pub start: Option<NaiveDateTime>,
+    // Orders the strategy placed while warming up
+    pub dropped_orders: i32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RunFailure {
+    pub error: String,
+    // Tick the run stopped on and the bar it was processing
+    pub time: NaiveDateTime,
+    pub bar: Option<OHLCVData>,
+    pub ticks: u64,
+    // Trap and backtrace when the strategy raised the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy_error: Option<StrategyError>,
+    // Equity up to the failure, the result has no curve otherwise
+    pub equity_curve: Vec<EquityPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EquityPoint {
+    pub timestamp: NaiveDateTime,
+    pub equity: f64,
+}
+
+// What the strategy receives on ticks without a new bar
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GapPolicy {
+    // Tick the strategy anyway with the last known bar
+    #[default]
+    Heartbeat,
+    // Don't call the strategy until a new bar arrives
+    Skip,
+    // Call `on_gap` while the market is open, closed sessions are skipped
+    Notify,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    // Call the strategy on every tick
+    #[default]
+    Tick,
+    // Compute the whole run from the strategy signals at once
+    Vectorized,
 }
 
 pub struct Engine {
     pub broker: Broker,
     pub data_feed: Vec<OHLCVData>,
+    pub symbol: Option<String>,
+    pub gap_policy: GapPolicy,
+    pub mode: RunMode,
     pub strategy: Box<dyn Strategy + Send>,
     pub time_range: (NaiveDateTime, NaiveDateTime),
     pub tick: Duration,
+    pub current_time: NaiveDateTime,
+    // Metrics computed on top of `GlobalMetrics`, register plugins here
+    pub metric_plugins: MetricRegistry,
+    data_index: usize,
+    // Index of the first bar of the range and past its last one
+    range_bars: (usize, usize),
+    last_bar: Option<(usize, NaiveDateTime)>,
+    finished: bool,
+    profiler: Profiler,
+    coverage: Option<DataCoverage>,
+    sources: Option<DataSources>,
+    corrections: Option<DataCorrections>,
+    data_hash: Option<String>,
+    price_basis: Option<PriceBasis>,
+    cleaning: Option<CleaningLog>,
+    data_quality: Vec<DataQuality>,
+    // Price level of the real returns
+    inflation: Option<Inflation>,
+    slices: Vec<Slice>,
+    seasonality: Option<SeasonalitySettings>,
+    // Sell the positions left open at the last close before the metrics
+    liquidate_at_end: bool,
+    // Jump to the next bar when no order can trade on the ticks before it
+    adaptive_ticks: bool,
+    order_log: Option<OrderLog>,
+    ticks: u64,
+    tick_coverage: TickCoverage,
+    // Bars ticked before the range without trading, and how many the data had
+    warmup_bars: usize,
+    warmup: Option<(usize, NaiveDateTime)>,
+    // Error that stopped the run, with the tick it happened on
+    failure: Option<(String, NaiveDateTime, Option<StrategyError>)>,
+    strategy_warnings: Vec<StrategyWarning>,
 }
 
 impl Engine {
@@ -27,17 +255,59 @@ impl Engine {
         Engine {
             broker: Broker::new(),
             data_feed: vec![],
+            symbol: None,
+            gap_policy: GapPolicy::default(),
+            mode: RunMode::default(),
             strategy,
             time_range,
             tick: Duration::minutes(1),
+            current_time: time_range.0,
+            metric_plugins: MetricRegistry::default(),
+            data_index: 0,
+            range_bars: (0, 0),
+            last_bar: None,
+            finished: false,
+            profiler: Profiler::default(),
+            coverage: None,
+            sources: None,
+            corrections: None,
+            data_hash: None,
+            price_basis: None,
+            cleaning: None,
+            data_quality: vec![],
+            inflation: None,
+            slices: vec![],
+            seasonality: None,
+            liquidate_at_end: false,
+            adaptive_ticks: false,
+            order_log: None,
+            ticks: 0,
+            tick_coverage: TickCoverage::default(),
+            warmup_bars: 0,
+            warmup: None,
+            failure: None,
+            strategy_warnings: vec![],
         }
     }
 
-    pub fn add_data(&mut self, data: Vec<OHLCVData>) {
-        // TODO: sort the data by timestamp (oldest to newest)
+    // Sorted oldest to newest, bars at the same time keep their order
+    pub fn add_data(&mut self, mut data: Vec<OHLCVData>) {
+        data.sort_by_key(|bar| bar.timestamp);
         self.data_feed = data;
     }
 
+    pub fn set_symbol(&mut self, symbol: String) {
+        self.symbol = Some(symbol);
+    }
+
+    pub fn set_gap_policy(&mut self, gap_policy: GapPolicy) {
+        self.gap_policy = gap_policy;
+    }
+
+    pub fn set_mode(&mut self, mode: RunMode) {
+        self.mode = mode;
+    }
+
     pub fn set_broker(&mut self, broker: Broker) {
         self.broker = broker;
     }
@@ -46,96 +316,981 @@ impl Engine {
         self.tick = tick;
     }
 
+    pub fn set_coverage(&mut self, coverage: DataCoverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn set_corrections(&mut self, corrections: DataCorrections) {
+        self.corrections = Some(corrections);
+    }
+
+    pub fn set_sources(&mut self, sources: DataSources) {
+        self.sources = Some(sources);
+    }
+
+    // Bars before the range the strategy is ticked on without trading, from the data feed
+    pub fn set_warmup_bars(&mut self, bars: usize) {
+        self.warmup_bars = bars;
+    }
+
+    pub fn set_data_hash(&mut self, hash: String) {
+        self.data_hash = Some(hash);
+    }
+
+    pub fn set_price_basis(&mut self, basis: PriceBasis) {
+        self.price_basis = Some(basis);
+    }
+
+    pub fn set_cleaning(&mut self, log: CleaningLog) {
+        self.cleaning = Some(log);
+    }
+
+    pub fn set_data_quality(&mut self, quality: Vec<DataQuality>) {
+        self.data_quality = quality;
+    }
+
+    pub fn set_inflation(&mut self, inflation: Inflation) {
+        self.inflation = Some(inflation.prepare());
+    }
+
+    pub fn set_liquidate_at_end(&mut self, enabled: bool) {
+        self.liquidate_at_end = enabled;
+    }
+
+    pub fn set_adaptive_ticks(&mut self, enabled: bool) {
+        self.adaptive_ticks = enabled;
+    }
+
+    pub fn set_slices(&mut self, slices: Vec<Slice>) {
+        self.slices = slices;
+    }
+
+    pub fn set_seasonality(&mut self, settings: SeasonalitySettings) {
+        self.seasonality = Some(settings);
+    }
+
+    // Registers the log on the current broker, set the broker first
+    pub fn set_order_log(&mut self, enabled: bool) {
+        self.order_log = enabled.then(|| {
+            let log = OrderLog::default();
+            self.broker.add_hook(Box::new(log.clone()));
+            log
+        });
+    }
+
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = Profiler::new(enabled);
+    }
+
+    // Ticks `run` will simulate, or bars processed at once in the vectorized mode. Adaptive
+    // ticks count the ticks on the bars, the orders waiting to trade add some in between
+    pub fn planned_iterations(&self) -> u64 {
+        let (start, end) = self.time_range;
+        if self.mode == RunMode::Vectorized {
+            return self.range_bar_times().count() as u64;
+        }
+        if self.adaptive_ticks {
+            return self.bar_ticks().len() as u64;
+        }
+
+        let Some(last) = self.data_feed.last() else {
+            return 0;
+        };
+        let end = end.min(last.timestamp);
+        if start > end {
+            return 0;
+        }
+        ((end - start).num_seconds() / self.tick.num_seconds().max(1)) as u64 + 1
+    }
+
+    // Time of a planned iteration, counted from 0
+    pub fn iteration_time(&self, iteration: u64) -> Option<NaiveDateTime> {
+        let index = iteration as usize;
+        if self.mode == RunMode::Vectorized {
+            return self.range_bar_times().nth(index);
+        }
+        if self.adaptive_ticks {
+            return self.bar_ticks().get(index).copied();
+        }
+        (iteration < self.planned_iterations())
+            .then(|| self.time_range.0 + self.tick * iteration as i32)
+    }
+
+    fn range_bar_times(&self) -> impl Iterator<Item = NaiveDateTime> + '_ {
+        let (start, end) = self.time_range;
+        self.data_feed
+            .iter()
+            .map(|bar| bar.timestamp)
+            .filter(move |time| *time >= start && *time <= end)
+    }
+
+    // Tick of each bar of the range after the one at the start: the first of the grid at or
+    // past the bar, and after the tick of the previous bar since a tick takes a single bar
+    fn bar_ticks(&self) -> Vec<NaiveDateTime> {
+        let start = self.time_range.0;
+        let Some(last) = self.data_feed.last() else {
+            return vec![];
+        };
+        let end = self.time_range.1.min(last.timestamp);
+        if start > end {
+            return vec![];
+        }
+        let tick = self.tick.num_seconds().max(1);
+        let mut ticks = vec![start];
+        let mut current = 0;
+        for time in self.range_bar_times().filter(|time| *time > start) {
+            let gap = (time - start).num_seconds();
+            current = ((gap + tick - 1) / tick * tick).max(current + tick);
+            let time = start + Duration::seconds(current);
+            if time > end {
+                break;
+            }
+            ticks.push(time);
+        }
+        ticks
+    }
+
     // TODO: cut loop time by optimizing time with trading days for equities (45% time decrease)
     pub fn run(&mut self) -> Result<BacktestResult, &'static str> {
+        if self.mode == RunMode::Vectorized {
+            return self.run_vectorized();
+        }
+
         let timer = std::time::Instant::now();
 
+        self.start()?;
+        while self.step() {}
+
+        println!("Backtest completed in: {:?}", timer.elapsed());
+
+        Ok(self.finish())
+    }
+
+    // Initialize the strategy and rewind the simulation clock to the start of the range
+    pub fn start(&mut self) -> Result<(), &'static str> {
+        self.profiler.reset();
         self.strategy.init();
 
         if self.data_feed.is_empty() {
             return Err("Error: Data feed is empty.");
         }
 
-        let (start_time, end_time) = self.time_range;
-
-        let mut current_timestamp = start_time.and_utc().timestamp();
-        let end_timestamp = end_time.and_utc().timestamp();
-        let tick_seconds = self.tick.num_seconds();
-        let last_data_timestamp = self
+        self.current_time = self.time_range.0;
+        self.warmup = None;
+        let first = self
             .data_feed
-            .last()
-            .unwrap()
-            .timestamp
-            .and_utc()
-            .timestamp();
-        let mut data_index = 0;
-
-        while current_timestamp <= end_timestamp {
-            let current_time = chrono::DateTime::from_timestamp(current_timestamp, 0)
-                .expect("Invalid timestamp")
-                .naive_utc();
-
-            if data_index + 1 < self.data_feed.len() {
-                let next_data = &self.data_feed[data_index + 1];
-                if next_data.timestamp.and_utc().timestamp() <= current_timestamp {
-                    data_index += 1;
-                }
+            .partition_point(|bar| bar.timestamp < self.time_range.0);
+        let last = self
+            .data_feed
+            .partition_point(|bar| bar.timestamp <= self.time_range.1);
+        self.range_bars = (first, last);
+        let warmup_index = first.saturating_sub(self.warmup_bars);
+        if warmup_index < first {
+            // Whole ticks back so the range still starts on a tick
+            let tick = self.tick.num_seconds().max(1);
+            let back = (self.time_range.0 - self.data_feed[warmup_index].timestamp).num_seconds();
+            let start = self.time_range.0 - Duration::seconds((back + tick - 1) / tick * tick);
+            self.current_time = start;
+            self.warmup = Some((first - warmup_index, start));
+            self.broker.set_trading_start(self.time_range.0);
+        }
+        // Bars before the first tick aren't replayed
+        self.data_index = self
+            .data_feed
+            .partition_point(|bar| bar.timestamp <= self.current_time)
+            .saturating_sub(1);
+        self.last_bar = None;
+        self.ticks = 0;
+        self.tick_coverage = TickCoverage::default();
+        self.failure = None;
+        self.finished = self.time_range.0 > self.time_range.1;
+        self.strategy_warnings = self.strategy.take_warnings();
+        // A strategy failing to initialize isn't ticked
+        if let Some(error) = self.strategy.take_error() {
+            self.fail(error.message.clone(), self.time_range.0, Some(error));
+        }
+
+        Ok(())
+    }
+
+    // Simulate a single tick, returns false once the end of the range or of the data is reached
+    pub fn step(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        self.profiler.tick();
+        let current_time = self.current_time;
+        let current_timestamp = current_time.and_utc().timestamp();
+
+        let timer = self.profiler.start();
+        if self.data_index + 1 < self.data_feed.len() {
+            let next_data = &self.data_feed[self.data_index + 1];
+            if next_data.timestamp.and_utc().timestamp() <= current_timestamp {
+                self.data_index += 1;
             }
+        }
+        self.profiler.record(Section::DataIndexing, timer);
 
-            if let Some(current_price) = self.data_feed.get(data_index) {
-                self.broker
-                    .handle_unfulfilled_orders(&current_time, current_price);
+        // Bars with prices the broker can't trade on stop the run
+        if let Some(bar) = self.data_feed.get(self.data_index) {
+            if ![bar.open, bar.high, bar.low, bar.close]
+                .iter()
+                .all(|p| p.is_finite())
+            {
+                let error = format!("Non finite price in the bar of {}", bar.timestamp);
+                self.fail(error, current_time, None);
+                return false;
+            }
+        }
+        self.ticks += 1;
 
+        if let Some(current_price) = self.data_feed.get(self.data_index) {
+            let timer = self.profiler.start();
+            self.broker.settle(&current_time, current_price);
+            self.broker
+                .handle_unfulfilled_orders(&current_time, current_price);
+            self.broker.manage_exposure(&current_time, current_price);
+            self.broker.manage_beta_hedge(&current_time, current_price);
+            self.broker.manage_flatten(&current_time, current_price);
+            self.profiler.record(Section::OrderMatching, timer);
+
+            // The equity curve starts with the range, after the warm-up
+            if current_time >= self.time_range.0 {
+                let timer = self.profiler.start();
+                // The loan of a margin account is the negative cash
                 let total_equity = self.broker.cash + self.broker.portfolio_value(current_price);
                 self.broker
                     .trade_tracker
                     .record_equity_snapshot(current_time, total_equity);
+                self.broker.record_margin(current_price);
+                self.broker.record_accounts(current_price);
+                self.profiler.record(Section::EquitySnapshots, timer);
             }
+        }
+
+        let current_candle = self.data_feed.get(self.data_index);
+        let is_new_bar = current_candle.is_some_and(|candle| {
+            candle.timestamp <= current_time
+                && self.last_bar.map(|(index, _)| index) != Some(self.data_index)
+        });
+        let last_bar_time = self
+            .last_bar
+            .map(|(_, time)| time)
+            .unwrap_or(self.time_range.0);
+        if is_new_bar {
+            self.last_bar = current_candle.map(|candle| (self.data_index, candle.timestamp));
+        }
+
+        let (first, last) = self.range_bars;
+        self.strategy.set_clock(RunClock {
+            bar_index: self.data_index as i64 - first as i64,
+            bars_remaining: last.saturating_sub((self.data_index + 1).max(first)) as i64,
+        });
+
+        let placed_orders = |broker: &Broker| {
+            broker.analytics.total_placed_orders as u64 + broker.algo_orders.len() as u64
+        };
+        let placed_before = placed_orders(&self.broker);
 
-            let current_candle = self.data_feed.get(data_index);
+        let timer = self.profiler.start();
+        if is_new_bar || self.gap_policy == GapPolicy::Heartbeat {
             self.strategy
                 .tick(&current_time, current_candle, &mut self.broker);
+        } else if self.gap_policy == GapPolicy::Notify {
+            let market_open = self
+                .symbol
+                .as_ref()
+                .map(|symbol| self.broker.calendar.is_open(symbol, &current_time))
+                .unwrap_or(true);
+            if market_open {
+                self.strategy.on_gap(
+                    &current_time,
+                    current_time - last_bar_time,
+                    &mut self.broker,
+                );
+            }
+        }
+        self.profiler.record(Section::Strategy, timer);
+        let error = self.strategy.take_error();
+        self.strategy_warnings.extend(self.strategy.take_warnings());
 
-            current_timestamp += tick_seconds;
+        if current_time >= self.time_range.0 {
+            let bar = match current_candle {
+                _ if is_new_bar => TickBar::New,
+                Some(candle) if candle.timestamp <= current_time => TickBar::Stale,
+                _ => TickBar::Missing,
+            };
+            let orders = placed_orders(&self.broker) - placed_before;
+            self.tick_coverage.record(bar, orders);
+        }
 
-            if current_timestamp > last_data_timestamp {
-                break;
+        let mut next_timestamp = current_timestamp + self.tick.num_seconds();
+        if self.adaptive_ticks {
+            let tick = self.tick.num_seconds().max(1);
+            let idle = current_candle
+                .is_none_or(|candle| !self.broker.can_trade_before(&current_time, candle));
+            if let Some(next_bar) = self.data_feed.get(self.data_index + 1).filter(|_| idle) {
+                // First tick of the grid at or past the next bar
+                let gap = next_bar.timestamp.and_utc().timestamp() - current_timestamp;
+                next_timestamp = current_timestamp + ((gap + tick - 1) / tick).max(1) * tick;
             }
         }
+        let last_data_timestamp = self
+            .data_feed
+            .last()
+            .unwrap()
+            .timestamp
+            .and_utc()
+            .timestamp();
 
-        println!("Backtest completed in: {:?}", timer.elapsed());
+        self.finished = next_timestamp > self.time_range.1.and_utc().timestamp()
+            || next_timestamp > last_data_timestamp;
+        self.current_time = chrono::DateTime::from_timestamp(next_timestamp, 0)
+            .expect("Invalid timestamp")
+            .naive_utc();
+
+        if let Some(error) = error {
+            self.fail(error.message.clone(), current_time, Some(error));
+        }
+        true
+    }
+
+    fn fail(&mut self, error: String, time: NaiveDateTime, strategy_error: Option<StrategyError>) {
+        self.failure = Some((error, time, strategy_error));
+        self.finished = true;
+    }
+
+    // Error that stopped the run, if any
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_ref().map(|(error, ..)| error.as_str())
+    }
 
-        let last_tick = self.data_feed.last().expect("No data found");
+    // Candle used by the last simulated tick
+    pub fn current_candle(&self) -> Option<&OHLCVData> {
+        self.data_feed.get(self.data_index)
+    }
+
+    // Closed trades and the metrics of the broker as it is
+    fn metrics(
+        &self,
+        last_tick: &OHLCVData,
+        benchmark: Option<&Benchmark>,
+    ) -> (Vec<Trade>, MetricsInputs, GlobalMetrics, MetricsMap) {
         let tracker = &self.broker.trade_tracker;
+        let mut closed_trades: Vec<Trade> = tracker.get_closed_trades().to_vec();
+        trade::count_bars_held(&mut closed_trades, &self.data_feed);
+        trade::measure_efficiency(&mut closed_trades, &self.data_feed, self.symbol.as_deref());
 
-        let closed_trades: Vec<Trade> = tracker.get_closed_trades().to_vec();
-        let equity_curve = tracker.get_equity_curve();
+        let inputs = MetricsInputs {
+            initial_capital: tracker.initial_capital,
+            risk_free_rate: RISK_FREE_RATE,
+            cash: self.broker.cash,
+            portfolio_value: self.broker.portfolio_value(last_tick),
+            num_orders_placed: self.broker.analytics.total_placed_orders,
+            num_orders_executed: self.broker.analytics.total_exec_orders,
+            num_liquidations: self.broker.analytics.total_liquidations,
+            total_fees: tracker.total_fees,
+            total_slippage: tracker.total_slippage,
+            total_price_improvement: tracker.total_price_improvement,
+            total_carry: tracker.total_carry,
+            total_cash_yield: tracker.total_cash_yield,
+            buy_hold_roi: benchmark.map_or(0.0, |b| b.roi),
+            buy_hold_final_value: benchmark.map_or(0.0, |b| b.final_value),
+            buy_hold_net_profit: benchmark.map_or(0.0, |b| b.net_profit),
+            inflation: self.inflation.clone(),
+        };
+        let (metrics, metrics_map) = metrics::compute(
+            &closed_trades,
+            tracker.get_equity_curve(),
+            &inputs,
+            &self.metric_plugins,
+        );
+        (closed_trades, inputs, metrics, metrics_map)
+    }
 
-        let cash = self.broker.cash;
-        let portfolio_value = self.broker.portfolio_value(last_tick);
+    pub fn finish(&mut self) -> BacktestResult {
+        let last_tick = self.data_feed.last().expect("No data found").clone();
 
-        let first_price = self.data_feed.first().map(|d| d.open);
-        let last_price = self.data_feed.last().map(|d| d.close);
+        let timer = self.profiler.start();
+        let benchmark = benchmark::buy_and_hold(
+            self.broker.benchmark(),
+            self.symbol.as_deref().unwrap_or("benchmark"),
+            &self.data_feed,
+            self.time_range,
+            self.broker.trade_tracker.get_equity_curve(),
+            RISK_FREE_RATE,
+        );
 
-        let metrics = GlobalMetrics::calculate(
+        // Metrics of the positions kept open, before they are sold at the last close
+        let held_to_end = self.liquidate_at_end.then(|| {
+            let (_, _, metrics, _) = self.metrics(&last_tick, benchmark.as_ref());
+            self.broker.liquidate(&last_tick);
+            metrics
+        });
+        let (closed_trades, metrics_inputs, metrics, metrics_map) =
+            self.metrics(&last_tick, benchmark.as_ref());
+        let equity_curve = self.broker.trade_tracker.get_equity_curve();
+        let slices: Vec<SliceReport> = self
+            .slices
+            .iter()
+            .map(|slice| {
+                slice.report(
+                    &closed_trades,
+                    equity_curve,
+                    RISK_FREE_RATE,
+                    self.inflation.as_ref(),
+                    &self.metric_plugins,
+                )
+            })
+            .collect();
+        let timezone = self
+            .symbol
+            .as_deref()
+            .and_then(|symbol| self.broker.calendar.session(symbol))
+            .map_or(chrono_tz::UTC, |session| session.timezone);
+        let gap_risk = gap_risk::gap_risk(
             &closed_trades,
-            equity_curve,
-            tracker.initial_capital,
-            0.03,
-            cash,
-            portfolio_value,
-            self.broker.analytics.total_placed_orders,
-            self.broker.analytics.total_exec_orders,
-            tracker.total_fees,
-            tracker.total_slippage,
-            first_price,
-            last_price,
-            &self.broker.fee_type,
+            &self.data_feed,
+            self.symbol.as_deref(),
+            &self.broker.contract,
+            timezone,
         );
+        let seasonality = self.seasonality.as_ref().map(|settings| {
+            let prices: Vec<(NaiveDateTime, f64)> = self
+                .data_feed
+                .iter()
+                .filter(|bar| (self.time_range.0..=self.time_range.1).contains(&bar.timestamp))
+                .map(|bar| (bar.timestamp, bar.close))
+                .collect();
+            Seasonality::new(&prices, equity_curve, settings, timezone)
+        });
+        self.profiler.record(Section::Metrics, timer);
+
+        let algo_orders = self.broker.algo_order_reports();
+        let execution = self.broker.execution_report(&algo_orders);
+        let reconciliation = reconciliation::reconcile(&self.broker, last_tick.close);
+        if !reconciliation.consistent {
+            eprintln!(
+                "Run accounting doesn't reconcile: {:?}",
+                reconciliation.checks
+            );
+        }
 
-        Ok(BacktestResult {
+        BacktestResult {
+            id: None,
             trades: closed_trades,
             metrics,
-        })
+            metrics_map,
+            metrics_version: METRICS_VERSION,
+            metrics_inputs,
+            held_to_end,
+            equity_curve: equity_curve
+                .iter()
+                .map(|&(timestamp, equity)| EquityPoint { timestamp, equity })
+                .collect(),
+            algo_orders,
+            execution,
+            tick_coverage: self.tick_coverage.clone(),
+            dust_closures: self.broker.dust_closures.clone(),
+            downsized_orders: self.broker.downsized_orders.clone(),
+            partial_fills: self.broker.trade_tracker.partial_fills.clone(),
+            fee_totals: self.broker.fee_totals(),
+            margin: self.broker.margin_report(),
+            sub_accounts: self.broker.account_reports(last_tick.close),
+            campaigns: campaign::campaigns(
+                &self.broker.fills,
+                &self.broker.contract,
+                self.broker.dust_threshold,
+            ),
+            reconciliation,
+            coverage: self.coverage.clone(),
+            corrections: self.corrections.clone(),
+            data_hash: self.data_hash.clone(),
+            cleaning: self.cleaning.clone(),
+            data_quality: self.data_quality.clone(),
+            sources: self.sources.clone(),
+            prices: self.price_basis.clone(),
+            drawdowns: drawdown::drawdowns(equity_curve),
+            rolling_cagr: RollingCagr::new(equity_curve),
+            gap_risk,
+            seasonality,
+            slices,
+            benchmark,
+            order_log: self.order_log.as_ref().map(OrderLog::events),
+            profile: self.profiler.report(),
+            failure: self
+                .failure
+                .as_ref()
+                .map(|(error, time, strategy_error)| RunFailure {
+                    error: error.clone(),
+                    time: *time,
+                    bar: self.current_candle().cloned(),
+                    ticks: self.ticks,
+                    strategy_error: strategy_error.clone(),
+                    equity_curve: equity_curve
+                        .iter()
+                        .map(|&(timestamp, equity)| EquityPoint { timestamp, equity })
+                        .collect(),
+                }),
+            strategy_warnings: self.strategy_warnings.clone(),
+            warmup: (self.warmup_bars > 0).then(|| Warmup {
+                requested_bars: self.warmup_bars,
+                bars: self.warmup.map_or(0, |(bars, _)| bars),
+                start: self.warmup.map(|(_, start)| start),
+                dropped_orders: self.broker.analytics.warmup_orders,
+            }),
+            exposure: self.broker.exposure_report(),
+            beta_hedge: self.broker.beta_hedge_report(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fails on its third tick
+    struct Failing {
+        ticks: u32,
+        error: Option<StrategyError>,
+    }
+
+    impl Strategy for Failing {
+        fn init(&mut self) {}
+
+        fn tick(&mut self, _: &NaiveDateTime, _: Option<&OHLCVData>, _: &mut Broker) {
+            self.ticks += 1;
+            if self.ticks == 3 {
+                self.error = Some(StrategyError {
+                    message: "unreachable".to_string(),
+                    trap: None,
+                    backtrace: vec![],
+                    time: None,
+                });
+            }
+        }
+
+        fn take_error(&mut self) -> Option<StrategyError> {
+            self.error.take()
+        }
+    }
+
+    #[test]
+    fn stops_on_the_first_strategy_error() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let strategy = Failing {
+            ticks: 0,
+            error: None,
+        };
+        let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(9)));
+        engine.set_tick(Duration::days(1));
+        engine.broker.set_cash(1000.0);
+        engine.add_data(
+            (0..10)
+                .map(|days| OHLCVData {
+                    timestamp: start + Duration::days(days),
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+
+        let failure = engine.run().unwrap().failure.unwrap();
+        assert_eq!(failure.error, "unreachable");
+        assert_eq!(failure.time, start + Duration::days(2));
+        assert_eq!((failure.ticks, failure.equity_curve.len()), (3, 3));
+    }
+
+    // Buys one unit on every bar
+    struct Buyer;
+
+    impl Strategy for Buyer {
+        fn init(&mut self) {}
+
+        fn tick(&mut self, _: &NaiveDateTime, _: Option<&OHLCVData>, broker: &mut Broker) {
+            broker.place_order(crate::broker::order::Order {
+                asset: "AAPL".to_string(),
+                direction: crate::broker::order::OrderDirection::Buy,
+                size: crate::broker::order::SizeSpec::Quantity(1.0),
+                order_type: crate::broker::order::OrderType::Market,
+                time_in_force: crate::broker::order::TimeInForce::Gtc,
+                placed_at: None,
+            });
+        }
+    }
+
+    #[test]
+    fn warms_up_without_trading() {
+        let data_start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let start = data_start + Duration::days(5);
+        let mut engine = Engine::new(Box::new(Buyer), (start, start + Duration::days(4)));
+        engine.set_tick(Duration::days(1));
+        engine.set_warmup_bars(3);
+        engine.broker.set_cash(1000.0);
+        engine.add_data(
+            (0..10)
+                .map(|days| OHLCVData {
+                    timestamp: data_start + Duration::days(days),
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+
+        let result = engine.run().unwrap();
+        let warmup = result.warmup.unwrap();
+        assert_eq!(warmup.bars, 3);
+        assert_eq!(warmup.start, Some(start - Duration::days(3)));
+        assert_eq!(warmup.dropped_orders, 3);
+        // Only the orders of the range fill, on the next bar, the last one is still pending
+        assert_eq!(engine.broker.fills.len(), 4);
+        assert_eq!(engine.broker.fills[0].time, start + Duration::days(1));
+        assert_eq!(engine.broker.trade_tracker.get_equity_curve()[0].0, start);
+    }
+
+    #[test]
+    fn adaptive_ticks_skip_to_the_next_bar() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let run = |adaptive: bool| {
+            let mut engine = Engine::new(Box::new(Buyer), (start, start + Duration::days(4)));
+            engine.set_tick(Duration::hours(1));
+            engine.set_gap_policy(GapPolicy::Skip);
+            engine.set_adaptive_ticks(adaptive);
+            engine.broker.set_cash(1000.0);
+            engine.add_data(
+                (0..5)
+                    .map(|days| OHLCVData {
+                        timestamp: start + Duration::days(days),
+                        open: 10.0 + days as f64,
+                        high: 12.0 + days as f64,
+                        low: 9.0 + days as f64,
+                        close: 11.0 + days as f64,
+                        volume: 100,
+                    })
+                    .collect(),
+            );
+            engine.run().unwrap();
+            let fills: Vec<_> = engine
+                .broker
+                .fills
+                .iter()
+                .map(|fill| (fill.time, fill.price))
+                .collect();
+            (engine.ticks, fills, engine.broker.cash)
+        };
+
+        let (fixed_ticks, fixed_fills, fixed_cash) = run(false);
+        let (adaptive_ticks, adaptive_fills, adaptive_cash) = run(true);
+        assert_eq!(fixed_ticks, 97);
+        // The bar and the tick filling the order placed on it
+        assert_eq!(adaptive_ticks, 9);
+        assert_eq!(adaptive_fills, fixed_fills);
+        assert_eq!(adaptive_cash, fixed_cash);
+    }
+
+    #[test]
+    fn counts_the_ticks_without_a_new_bar() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let first_bar = start + Duration::hours(2);
+        let mut engine = Engine::new(Box::new(Buyer), (start, first_bar + Duration::days(2)));
+        engine.set_tick(Duration::hours(1));
+        engine.broker.set_cash(1000.0);
+        engine.add_data(
+            (0..3)
+                .map(|days| OHLCVData {
+                    timestamp: first_bar + Duration::days(days),
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+
+        let coverage = engine.run().unwrap().tick_coverage;
+        assert_eq!(coverage.ticks, 51);
+        assert_eq!(
+            (
+                coverage.new_bar_ticks,
+                coverage.stale_bar_ticks,
+                coverage.no_bar_ticks
+            ),
+            (3, 46, 2)
+        );
+        // The heartbeat ticks the buyer on every tick
+        assert_eq!(
+            (coverage.orders_on_stale_bars, coverage.orders_without_bar),
+            (46, 2)
+        );
+        assert_eq!(coverage.ticks_per_bar, Some(17.0));
+    }
+
+    #[test]
+    fn liquidates_the_positions_at_the_end() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let mut engine = Engine::new(Box::new(Buyer), (start, start + Duration::days(4)));
+        engine.set_tick(Duration::days(1));
+        engine.set_liquidate_at_end(true);
+        engine.broker.set_cash(1000.0);
+        engine
+            .broker
+            .set_fees(crate::broker::fee::FeeType::Flat(1.0));
+        engine.add_data(
+            (0..5)
+                .map(|days| OHLCVData {
+                    timestamp: start + Duration::days(days),
+                    open: 10.0,
+                    high: 12.0,
+                    low: 10.0,
+                    close: 12.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+
+        let result = engine.run().unwrap();
+        assert!(engine.broker.portfolio.is_empty());
+        assert!(result.reconciliation.consistent);
+        // Four buys filled at the open of 10, sold at the last close of 12
+        assert_eq!(result.metrics.total_trades, 4);
+        assert_eq!(result.metrics.total_fees, 5.0);
+        assert_eq!(
+            result.equity_curve.last().unwrap().equity,
+            1000.0 + 8.0 - 5.0
+        );
+        assert_eq!(result.held_to_end.unwrap().total_trades, 0);
+    }
+
+    // Keeps the clock of every tick
+    struct Clocked(std::sync::Arc<std::sync::Mutex<Vec<RunClock>>>);
+
+    impl Strategy for Clocked {
+        fn init(&mut self) {}
+
+        fn set_clock(&mut self, clock: RunClock) {
+            self.0.lock().unwrap().push(clock);
+        }
+
+        fn tick(&mut self, _: &NaiveDateTime, _: Option<&OHLCVData>, _: &mut Broker) {}
+    }
+
+    #[test]
+    fn counts_the_bars_of_the_range() {
+        let data_start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let start = data_start + Duration::days(5);
+        let clocks = std::sync::Arc::default();
+        let strategy = Clocked(std::sync::Arc::clone(&clocks));
+        let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(2)));
+        engine.set_tick(Duration::days(1));
+        engine.set_warmup_bars(2);
+        engine.add_data(
+            (0..10)
+                .map(|days| OHLCVData {
+                    timestamp: data_start + Duration::days(days),
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+
+        engine.run().unwrap();
+        let clocks: Vec<_> = clocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|clock| (clock.bar_index, clock.bars_remaining))
+            .collect();
+        // The whole range is ahead during the warm-up
+        assert_eq!(clocks, [(-2, 3), (-1, 3), (0, 2), (1, 1), (2, 0)]);
+    }
+
+    // Buys 100 units on its first tick
+    struct BuyOnce(bool);
+
+    impl Strategy for BuyOnce {
+        fn init(&mut self) {}
+
+        fn tick(&mut self, _: &NaiveDateTime, _: Option<&OHLCVData>, broker: &mut Broker) {
+            if std::mem::replace(&mut self.0, true) {
+                return;
+            }
+            broker.place_order(crate::broker::order::Order {
+                asset: "AAPL".to_string(),
+                direction: crate::broker::order::OrderDirection::Buy,
+                size: crate::broker::order::SizeSpec::Quantity(100.0),
+                order_type: crate::broker::order::OrderType::Market,
+                time_in_force: crate::broker::order::TimeInForce::Gtc,
+                placed_at: None,
+            });
+        }
+    }
+
+    #[test]
+    fn caps_the_participation_over_the_ticks_of_a_bar() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let mut engine = Engine::new(Box::new(BuyOnce(false)), (start, start + Duration::days(4)));
+        engine.set_tick(Duration::hours(1));
+        engine.broker.set_cash(10000.0);
+        engine.broker.set_max_participation(10.0);
+        engine.add_data(
+            (0..5)
+                .map(|days| OHLCVData {
+                    timestamp: start + Duration::days(days),
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+
+        engine.run().unwrap();
+        // 10 of each daily bar however many hourly ticks it lasts
+        let fills: Vec<_> = engine.broker.fills.iter().map(|fill| fill.size).collect();
+        assert_eq!(fills, [10.0; 5]);
+        assert_eq!(engine.broker.portfolio["AAPL"].quantity, 50.0);
+    }
+
+    // Records the gaps it is notified of
+    struct GapRecorder {
+        gaps: std::sync::Arc<std::sync::Mutex<Vec<(NaiveDateTime, Duration)>>>,
+    }
+
+    impl Strategy for GapRecorder {
+        fn init(&mut self) {}
+
+        fn tick(&mut self, _: &NaiveDateTime, _: Option<&OHLCVData>, _: &mut Broker) {}
+
+        fn on_gap(&mut self, current_time: &NaiveDateTime, duration: Duration, _: &mut Broker) {
+            self.gaps.lock().unwrap().push((*current_time, duration));
+        }
+    }
+
+    #[test]
+    fn notifies_the_gaps_while_the_market_is_open() {
+        // A Monday at the open
+        let start = NaiveDateTime::parse_from_str("2024-01-01 09:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let run = |session: Option<crate::calendar::Session>| {
+            let gaps = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+            let strategy = GapRecorder { gaps: gaps.clone() };
+            let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(1)));
+            engine.set_tick(Duration::hours(4));
+            engine.set_gap_policy(GapPolicy::Notify);
+            engine.set_symbol("AAPL".to_string());
+            if let Some(session) = session {
+                let mut calendar = crate::calendar::Calendar::new();
+                calendar.add_session("AAPL", session);
+                engine.broker.set_calendar(calendar);
+            }
+            engine.broker.set_cash(1000.0);
+            engine.add_data(
+                (0..2)
+                    .map(|days| OHLCVData {
+                        timestamp: start + Duration::days(days),
+                        open: 10.0,
+                        high: 10.0,
+                        low: 10.0,
+                        close: 10.0,
+                        volume: 100,
+                    })
+                    .collect(),
+            );
+            engine.run().unwrap();
+            let gaps = gaps.lock().unwrap().clone();
+            gaps
+        };
+
+        // Every tick between the bars without a session
+        let always = run(None);
+        assert_eq!(always.len(), 5);
+        assert_eq!(
+            always[4],
+            (start + Duration::hours(20), Duration::hours(20))
+        );
+
+        // The ticks of the night are skipped, the duration runs from the last bar
+        let session = crate::calendar::Session {
+            timezone: chrono_tz::UTC,
+            open: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            close: chrono::NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            days: vec![chrono::Weekday::Mon, chrono::Weekday::Tue],
+            day_orders_until: None,
+        };
+        assert_eq!(
+            run(Some(session)),
+            [
+                (start + Duration::hours(4), Duration::hours(4)),
+                (start + Duration::hours(8), Duration::hours(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn plans_the_ticks_on_the_bars_of_adaptive_runs() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let run = |adaptive: bool| {
+            let strategy = GapRecorder {
+                gaps: Default::default(),
+            };
+            let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(4)));
+            engine.set_tick(Duration::hours(1));
+            engine.set_adaptive_ticks(adaptive);
+            engine.broker.set_cash(1000.0);
+            // Bars off the grid wait for its next tick, and the next one if it is taken
+            let times = [0, 90, 100, 150, 2880];
+            engine.add_data(
+                times
+                    .iter()
+                    .map(|minutes| OHLCVData {
+                        timestamp: start + Duration::minutes(*minutes),
+                        open: 10.0,
+                        high: 10.0,
+                        low: 10.0,
+                        close: 10.0,
+                        volume: 100,
+                    })
+                    .collect(),
+            );
+            let planned = engine.planned_iterations();
+            let times: Vec<_> = (0..planned)
+                .map(|iteration| engine.iteration_time(iteration).unwrap())
+                .collect();
+            assert!(engine.iteration_time(planned).is_none());
+            engine.run().unwrap();
+            (planned, engine.ticks, times)
+        };
+
+        let (planned, ticks, times) = run(true);
+        assert_eq!((planned, ticks), (5, 5));
+        let hours: Vec<_> = times
+            .iter()
+            .map(|time| (*time - start).num_hours())
+            .collect();
+        assert_eq!(hours, [0, 2, 3, 4, 48]);
+
+        let (planned, ticks, times) = run(false);
+        assert_eq!((planned, ticks), (49, 49));
+        assert_eq!(times[48], start + Duration::hours(48));
     }
 }
@@ -0,0 +1,109 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+// Parts of a run the profiler measures
+#[derive(Debug, Clone, Copy)]
+pub enum Section {
+    DataIndexing,
+    // Settlements, liquidations and pending orders
+    OrderMatching,
+    EquitySnapshots,
+    Strategy,
+    Metrics,
+}
+
+// Wall time spent in each section, nothing is measured unless enabled
+#[derive(Default)]
+pub struct Profiler {
+    enabled: bool,
+    started: Option<Instant>,
+    ticks: u64,
+    sections: [Duration; 5],
+}
+
+// Breakdown of a run returned with the result when profiling is enabled
+#[derive(Serialize, Debug, Clone)]
+pub struct EngineProfile {
+    pub ticks: u64,
+    pub total_ms: f64,
+    pub data_indexing_ms: f64,
+    pub order_matching_ms: f64,
+    pub equity_snapshots_ms: f64,
+    pub strategy_ms: f64,
+    pub metrics_ms: f64,
+    // Everything else (setup, vectorized computations, ...)
+    pub other_ms: f64,
+}
+
+impl Profiler {
+    pub fn new(enabled: bool) -> Self {
+        Profiler {
+            enabled,
+            ..Profiler::default()
+        }
+    }
+
+    // Restarts the measures for a new run
+    pub fn reset(&mut self) {
+        *self = Profiler::new(self.enabled);
+        self.started = self.start();
+    }
+
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+
+    pub fn record(&mut self, section: Section, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.sections[section as usize] += start.elapsed();
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    pub fn report(&self) -> Option<EngineProfile> {
+        let total = self.started?.elapsed();
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let measured: Duration = self.sections.iter().sum();
+
+        Some(EngineProfile {
+            ticks: self.ticks,
+            total_ms: ms(total),
+            data_indexing_ms: ms(self.sections[Section::DataIndexing as usize]),
+            order_matching_ms: ms(self.sections[Section::OrderMatching as usize]),
+            equity_snapshots_ms: ms(self.sections[Section::EquitySnapshots as usize]),
+            strategy_ms: ms(self.sections[Section::Strategy as usize]),
+            metrics_ms: ms(self.sections[Section::Metrics as usize]),
+            other_ms: ms(total.saturating_sub(measured)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_measures_when_enabled() {
+        let mut disabled = Profiler::new(false);
+        disabled.reset();
+        let timer = disabled.start();
+        disabled.record(Section::Strategy, timer);
+        assert!(timer.is_none());
+        assert!(disabled.report().is_none());
+
+        let mut profiler = Profiler::new(true);
+        profiler.reset();
+        profiler.tick();
+        let timer = profiler.start();
+        std::thread::sleep(Duration::from_millis(2));
+        profiler.record(Section::Strategy, timer);
+
+        let report = profiler.report().unwrap();
+        assert_eq!(report.ticks, 1);
+        assert!(report.strategy_ms >= 2.0);
+        assert!(report.total_ms >= report.strategy_ms + report.other_ms - 1e-9);
+    }
+}
@@ -0,0 +1,156 @@
+use super::BacktestResult;
+use serde::Deserialize;
+use serde_json::Value;
+
+// Parts of the result `/run` responds with, the other summaries always are
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseField {
+    // Metrics, their map with the units and their inputs
+    Metrics,
+    // Trades, campaigns and drawdowns
+    Trades,
+    // Equity curve, rolling CAGR and benchmark curve
+    Equity,
+    // Algo parent orders, dust closures, downsized orders and partial fills
+    Orders,
+    // Order log, data cleaning log and strategy warnings
+    Logs,
+}
+
+// Keys of the result each part is made of, as paths from its root
+const PARTS: [(ResponseField, &[&[&str]]); 5] = [
+    (
+        ResponseField::Metrics,
+        &[&["metrics"], &["metrics_map"], &["metrics_inputs"]],
+    ),
+    (
+        ResponseField::Trades,
+        &[&["trades"], &["campaigns", "campaigns"], &["drawdowns"]],
+    ),
+    (
+        ResponseField::Equity,
+        &[
+            &["equity_curve"],
+            &["rolling_cagr"],
+            &["benchmark", "curve"],
+            &["failure", "equity_curve"],
+        ],
+    ),
+    (
+        ResponseField::Orders,
+        &[
+            &["algo_orders"],
+            &["dust_closures"],
+            &["downsized_orders"],
+            &["partial_fills"],
+        ],
+    ),
+    (
+        ResponseField::Logs,
+        &[&["order_log"], &["cleaning"], &["strategy_warnings"]],
+    ),
+];
+
+impl BacktestResult {
+    // The result without the parts left out of `fields`, which are omitted rather than empty.
+    // The stored run keeps all of it
+    pub fn retain(&self, fields: &[ResponseField]) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        for (field, paths) in PARTS {
+            if fields.contains(&field) {
+                continue;
+            }
+            for path in paths {
+                let (key, parents) = path.split_last().expect("Empty path");
+                let parent = parents
+                    .iter()
+                    .try_fold(&mut value, |value, parent| value.get_mut(*parent));
+                if let Some(Value::Object(parent)) = parent {
+                    parent.remove(*key);
+                }
+            }
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::strategy::rules::{OrderAction, RuleSpec, RuleStrategy};
+    use chrono::{Duration, NaiveDateTime};
+
+    #[test]
+    fn omits_the_parts_left_out() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let strategy = RuleStrategy::new(
+            "AAPL".to_string(),
+            &[RuleSpec {
+                action: OrderAction::Buy,
+                when: "close > 0".to_string(),
+                size: "10% equity".to_string(),
+                execution: None,
+            }],
+        )
+        .unwrap();
+        let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(4)));
+        engine.set_tick(Duration::days(1));
+        engine.set_order_log(true);
+        engine.broker.set_cash(1000.0);
+        engine.add_data(
+            (0..5)
+                .map(|days| crate::data::OHLCVData {
+                    timestamp: start + Duration::days(days),
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    volume: 100,
+                })
+                .collect(),
+        );
+        let result = engine.run().unwrap();
+
+        let keys = |value: &Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let full = result.retain(&[
+            ResponseField::Metrics,
+            ResponseField::Trades,
+            ResponseField::Equity,
+            ResponseField::Orders,
+            ResponseField::Logs,
+        ]);
+        assert_eq!(keys(&full), keys(&serde_json::to_value(&result).unwrap()));
+
+        let trimmed = result.retain(&[ResponseField::Trades]);
+        for key in [
+            "metrics",
+            "metrics_map",
+            "metrics_inputs",
+            "equity_curve",
+            "rolling_cagr",
+            "algo_orders",
+            "dust_closures",
+            "order_log",
+        ] {
+            assert!(full.get(key).is_some(), "{}", key);
+            assert!(trimmed.get(key).is_none(), "{}", key);
+        }
+        assert!(trimmed["trades"].is_array());
+        assert!(trimmed["campaigns"].get("closed").is_some());
+        assert!(trimmed["campaigns"].get("campaigns").is_some());
+        assert!(trimmed["benchmark"].get("curve").is_none());
+        assert!(trimmed["reconciliation"].is_object());
+
+        let metrics = result.retain(&[ResponseField::Metrics]);
+        assert!(metrics["metrics"].is_object());
+        assert!(metrics.get("trades").is_none());
+        assert!(metrics["campaigns"].get("campaigns").is_none());
+    }
+}
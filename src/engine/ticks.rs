@@ -0,0 +1,46 @@
+use serde::Serialize;
+
+// What a tick of the range had to show the strategy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickBar {
+    // A bar the strategy hadn't seen yet
+    New,
+    // The last bar again, the data being coarser than the tick or missing
+    Stale,
+    // No bar yet, the data starting after the tick
+    Missing,
+}
+
+// Ticks of the range against the bars of the data. Ticks finer than the data tick the strategy
+// again with bars it already saw, and orders placed on them act on stale prices
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TickCoverage {
+    pub ticks: u64,
+    pub new_bar_ticks: u64,
+    pub stale_bar_ticks: u64,
+    pub no_bar_ticks: u64,
+    // Orders the strategy placed on ticks without a new bar, algo parents included
+    pub orders_on_stale_bars: u64,
+    pub orders_without_bar: u64,
+    // Above 1 when the tick is finer than the data, None without any new bar
+    pub ticks_per_bar: Option<f64>,
+}
+
+impl TickCoverage {
+    pub fn record(&mut self, bar: TickBar, orders: u64) {
+        self.ticks += 1;
+        match bar {
+            TickBar::New => self.new_bar_ticks += 1,
+            TickBar::Stale => {
+                self.stale_bar_ticks += 1;
+                self.orders_on_stale_bars += orders;
+            }
+            TickBar::Missing => {
+                self.no_bar_ticks += 1;
+                self.orders_without_bar += orders;
+            }
+        }
+        self.ticks_per_bar =
+            (self.new_bar_ticks > 0).then(|| self.ticks as f64 / self.new_bar_ticks as f64);
+    }
+}
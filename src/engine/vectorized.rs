@@ -0,0 +1,270 @@
+use super::profile::Section;
+use super::{BacktestResult, Engine};
+use crate::broker::order::{Fill, OrderDirection};
+use crate::broker::position::Position;
+use crate::data::OHLCVData;
+use ndarray::{s, Array1};
+
+impl Engine {
+    // Simulate the whole series at once from the strategy signals instead of ticking. Exposures are
+    // long only and decided on bar closes, so only the rebalancing points are iterated
+    pub fn run_vectorized(&mut self) -> Result<BacktestResult, &'static str> {
+        self.profiler.reset();
+        self.failure = None;
+        self.strategy.init();
+
+        let (start_time, end_time) = self.time_range;
+        let bars: Vec<OHLCVData> = self
+            .data_feed
+            .iter()
+            .filter(|bar| bar.timestamp >= start_time && bar.timestamp <= end_time)
+            .cloned()
+            .collect();
+        if bars.is_empty() {
+            return Err("Error: Data feed is empty.");
+        }
+
+        let profile_timer = self.profiler.start();
+        let signals = self.strategy.signals(&bars);
+        self.profiler.record(Section::Strategy, profile_timer);
+        self.strategy_warnings = self.strategy.take_warnings();
+        // A trap fails the run like on a tick, with an empty curve since nothing was simulated
+        if let Some(error) = self.strategy.take_error() {
+            self.fail(error.message.clone(), start_time, Some(error));
+            return Ok(self.finish());
+        }
+        let signals = signals.ok_or("Error: Strategy is not vectorizable.")?;
+        if signals.len() != bars.len() {
+            return Err("Error: Strategy must return one signal per bar.");
+        }
+
+        let n = bars.len();
+        let closes = Array1::from_iter(bars.iter().map(|bar| bar.close));
+        let signals = Array1::from(signals).mapv(|signal| {
+            if signal.is_finite() {
+                signal.clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        });
+
+        // The exposure decided on a bar close is held from the next bar onwards
+        let mut exposure = Array1::<f64>::zeros(n);
+        exposure
+            .slice_mut(s![1..])
+            .assign(&signals.slice(s![..n - 1]));
+        let rebalances: Vec<usize> = (1..n).filter(|&t| exposure[t] != exposure[t - 1]).collect();
+
+        let asset = self.symbol.clone().unwrap_or_default();
+        let mut quantities = Array1::<f64>::zeros(n);
+        let mut cash = Array1::from_elem(n, self.broker.cash);
+        let mut held = 0.0;
+
+        for (k, &t) in rebalances.iter().enumerate() {
+            let segment_end = rebalances.get(k + 1).copied().unwrap_or(n);
+            // Sized on the close of the decision and filled at the open of the next bar, as the
+            // tick engine does with the next_open fill model
+            let decision = closes[t - 1];
+            let target = exposure[t] * (self.broker.cash + held * decision) / decision;
+            let price = bars[t].open;
+            let time = bars[t].timestamp;
+
+            self.broker.analytics.total_placed_orders += 1;
+            let direction = match target > held {
+                true => OrderDirection::Buy,
+                false => OrderDirection::Sell,
+            };
+            let execution_price = self.broker.apply_slippage(price, &direction);
+            let slippage = (execution_price - price).abs();
+
+            if target > held {
+                // Scale down so the fees are covered, fees never grow when the order shrinks
+                let full_fees = self
+                    .broker
+                    .calculate_fees((target - held) * execution_price, target - held);
+                let quantity =
+                    (target - held).min((self.broker.cash - full_fees) / execution_price);
+                let fees = self
+                    .broker
+                    .calculate_fees(quantity * execution_price, quantity);
+                if quantity > 0.0 && quantity * execution_price + fees <= self.broker.cash {
+                    self.broker.cash -= quantity * execution_price + fees;
+                    held += quantity;
+                    self.broker
+                        .portfolio
+                        .entry(asset.clone())
+                        .or_insert_with(|| Position::new(0.0, execution_price))
+                        .update(quantity, execution_price);
+                    self.broker.trade_tracker.record_buy(
+                        &asset,
+                        time,
+                        execution_price,
+                        quantity,
+                        fees,
+                        execution_price - price,
+                        None,
+                        1.0,
+                    );
+                    self.broker.fills.push(Fill {
+                        asset: asset.clone(),
+                        direction: OrderDirection::Buy,
+                        time,
+                        price: execution_price,
+                        size: quantity,
+                        fees,
+                        slippage,
+                    });
+                    self.broker.analytics.total_exec_orders += 1;
+                }
+            } else {
+                let quantity = held - target;
+                let fees = self
+                    .broker
+                    .calculate_fees(quantity * execution_price, quantity);
+                self.broker.cash += quantity * execution_price - fees;
+                held -= quantity;
+                if let Some(position) = self.broker.portfolio.get_mut(&asset) {
+                    position.quantity = held;
+                }
+                if held <= 0.0 {
+                    held = 0.0;
+                    self.broker.portfolio.remove(&asset);
+                }
+                self.broker.trade_tracker.record_sell(
+                    &asset,
+                    time,
+                    execution_price,
+                    quantity,
+                    fees,
+                    price - execution_price,
+                    None,
+                    1.0,
+                );
+                self.broker.fills.push(Fill {
+                    asset: asset.clone(),
+                    direction: OrderDirection::Sell,
+                    time,
+                    price: execution_price,
+                    size: quantity,
+                    fees,
+                    slippage,
+                });
+                self.broker.analytics.total_exec_orders += 1;
+            }
+
+            quantities.slice_mut(s![t..segment_end]).fill(held);
+            cash.slice_mut(s![t..segment_end]).fill(self.broker.cash);
+        }
+
+        let profile_timer = self.profiler.start();
+        let equity = &cash + &(&quantities * &closes);
+        for (bar, value) in bars.iter().zip(equity.iter()) {
+            self.broker
+                .trade_tracker
+                .record_equity_snapshot(bar.timestamp, *value);
+        }
+        self.profiler
+            .record(Section::EquitySnapshots, profile_timer);
+
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::RunMode;
+    use crate::strategy::rules::{OrderAction, RuleSpec, RuleStrategy};
+    use chrono::{Duration, NaiveDateTime};
+
+    fn run(mode: RunMode, buy_size: &str) -> Result<BacktestResult, &'static str> {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let rule = |action, when: &str, size: &str| RuleSpec {
+            action,
+            when: when.to_string(),
+            size: size.to_string(),
+            execution: None,
+        };
+        let strategy = RuleStrategy::new(
+            "AAPL".to_string(),
+            &[
+                rule(OrderAction::Buy, "close crosses_above 104", buy_size),
+                rule(
+                    OrderAction::Sell,
+                    "close crosses_below 106",
+                    "100% position",
+                ),
+            ],
+        )
+        .unwrap();
+        let closes = [
+            100.0, 102.0, 105.0, 108.0, 110.0, 107.0, 105.0, 103.0, 101.0, 100.0,
+        ];
+        let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(9)));
+        engine.set_tick(Duration::days(1));
+        engine.set_symbol("AAPL".to_string());
+        engine.set_mode(mode);
+        engine.broker.set_cash(10000.0);
+        engine.add_data(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(i, close)| OHLCVData {
+                    timestamp: start + Duration::days(i as i64),
+                    // Opens below the previous close, so the fill price is told apart and the
+                    // buys sized on the close are covered
+                    open: closes[i.saturating_sub(1)] - 0.5,
+                    high: close + 1.0,
+                    low: close - 1.0,
+                    close: *close,
+                    volume: 1000,
+                })
+                .collect(),
+        );
+        engine.run()
+    }
+
+    #[test]
+    fn matches_the_tick_engine_on_the_same_rules() {
+        let tick = run(RunMode::Tick, "100% equity").unwrap();
+        let vectorized = run(RunMode::Vectorized, "100% equity").unwrap();
+
+        let fills = |result: &BacktestResult| {
+            result
+                .trades
+                .iter()
+                .map(|trade| {
+                    (
+                        trade.entry_time,
+                        trade.entry_price,
+                        trade.exit_time,
+                        trade.exit_price,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tick.trades.len(), 1);
+        assert_eq!(fills(&vectorized), fills(&tick));
+        // Bought at the open after the close above 104 and sold at the open after the one below 106
+        assert_eq!(vectorized.trades[0].entry_price, 104.5);
+        assert_eq!(vectorized.trades[0].exit_price, Some(104.5));
+
+        let equity = |result: &BacktestResult| {
+            result
+                .equity_curve
+                .iter()
+                .map(|point| (point.timestamp, (point.equity * 1e6).round()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(equity(&vectorized), equity(&tick));
+    }
+
+    #[test]
+    fn refuses_the_strategies_without_signals() {
+        assert_eq!(
+            run(RunMode::Vectorized, "10").err(),
+            Some("Error: Strategy is not vectorizable.")
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::broker::contract::Contract;
+use crate::data::OHLCVData;
+use serde::Deserialize;
+
+pub mod forex;
+pub mod leveraged;
+pub mod perpetual;
+
+// What is actually traded on top of the data feed, the feed itself by default
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Instrument {
+    // Daily reset leveraged/inverse product synthesized from the feed
+    Leveraged(leveraged::Leveraged),
+    // Currency pair, the feed is the pair rate
+    Forex(forex::Forex),
+    // Perpetual future on the feed with funding and liquidations
+    Perpetual(perpetual::Perpetual),
+}
+
+impl Instrument {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            Instrument::Leveraged(leveraged) => leveraged.validate(),
+            Instrument::Forex(forex) => forex.contract().map(|_| ()),
+            Instrument::Perpetual(perpetual) => perpetual.contract().map(|_| ()),
+        }
+    }
+
+    // Series the broker trades on
+    pub fn series(&self, data: Vec<OHLCVData>) -> Vec<OHLCVData> {
+        match self {
+            Instrument::Leveraged(leveraged) => leveraged.synthesize(&data),
+            Instrument::Forex(_) | Instrument::Perpetual(_) => data,
+        }
+    }
+
+    // How the broker sizes, settles and charges the positions
+    pub fn contract(&self) -> Result<Contract, &'static str> {
+        match self {
+            Instrument::Leveraged(_) => Ok(Contract::default()),
+            Instrument::Forex(forex) => forex.contract(),
+            Instrument::Perpetual(perpetual) => perpetual.contract(),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::broker::contract::{Contract, Conversion, Rollover};
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+// Currency pair quoted as `quote` per unit of `base` (EUR/USD: 1.10 USD per EUR), traded in lots on margin
+#[derive(Deserialize, Debug, Clone)]
+pub struct Forex {
+    pub base: String,
+    pub quote: String,
+    // Defaults to the quote currency
+    pub account_currency: Option<String>,
+    // Quote to account rate, required when the account is in neither currency of the pair
+    pub conversion_rate: Option<f64>,
+    // Defaults to 0.01 for JPY quoted pairs, 0.0001 otherwise
+    pub pip_size: Option<f64>,
+    // Units of the base currency per lot, order sizes are in lots
+    #[serde(default = "standard_lot")]
+    pub lot_size: f64,
+    // Swap of a long position in pips per lot and night, positive is a credit
+    #[serde(default)]
+    pub swap_long: f64,
+    // Swaps are charged at the rollover time, three times on `triple_swap_day` for the weekend
+    #[serde(default = "rollover_time")]
+    pub rollover_time: NaiveTime,
+    #[serde(default = "rollover_timezone")]
+    pub rollover_timezone: Tz,
+    #[serde(default = "triple_swap_day")]
+    pub triple_swap_day: Weekday,
+}
+
+fn standard_lot() -> f64 {
+    100_000.0
+}
+
+// 5pm New York, the usual end of the FX trading day
+fn rollover_time() -> NaiveTime {
+    NaiveTime::from_hms_opt(17, 0, 0).unwrap()
+}
+
+fn rollover_timezone() -> Tz {
+    chrono_tz::America::New_York
+}
+
+fn triple_swap_day() -> Weekday {
+    Weekday::Wed
+}
+
+impl Forex {
+    pub fn pip_size(&self) -> f64 {
+        self.pip_size.unwrap_or(match self.quote.as_str() {
+            "JPY" => 0.01,
+            _ => 0.0001,
+        })
+    }
+
+    pub fn contract(&self) -> Result<Contract, &'static str> {
+        if self.base.eq_ignore_ascii_case(&self.quote) {
+            return Err("The base and quote currencies must differ");
+        }
+        if [self.lot_size, self.pip_size()]
+            .iter()
+            .any(|size| !size.is_finite() || *size <= 0.0)
+        {
+            return Err("Invalid lot or pip size");
+        }
+
+        let account = self.account_currency.as_deref().unwrap_or(&self.quote);
+        let conversion = if account.eq_ignore_ascii_case(&self.quote) {
+            Conversion::Quote
+        } else if account.eq_ignore_ascii_case(&self.base) {
+            Conversion::Base
+        } else {
+            match self.conversion_rate {
+                Some(rate) if rate > 0.0 => Conversion::Rate(rate),
+                _ => return Err("A conversion rate is required for the account currency"),
+            }
+        };
+
+        Ok(Contract {
+            multiplier: self.lot_size,
+            margin: true,
+            conversion,
+            rollover: Some(Rollover {
+                time: self.rollover_time,
+                timezone: self.rollover_timezone,
+                long_rate: self.swap_long * self.pip_size(),
+                triple_day: self.triple_swap_day,
+            }),
+            ..Contract::default()
+        })
+    }
+}
@@ -0,0 +1,128 @@
+use crate::data::OHLCVData;
+use serde::Deserialize;
+
+// N× (or inverse with a negative factor) product rebalanced at every daily close
+#[derive(Deserialize, Debug, Clone)]
+pub struct Leveraged {
+    pub factor: f64,
+    // Annual fee, charged pro rata on each day
+    #[serde(default)]
+    pub expense_ratio: f64,
+}
+
+impl Leveraged {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !self.factor.is_finite() || self.factor == 0.0 {
+            return Err("Invalid leverage factor");
+        }
+        if !(0.0..1.0).contains(&self.expense_ratio) {
+            return Err("Invalid expense ratio");
+        }
+        Ok(())
+    }
+
+    // The product starts at the first open of the underlying, each bar moves it by `factor` times
+    // the underlying move since the previous daily close, so the decay of volatile paths comes
+    // from the daily compounding. The product can't go below zero
+    pub fn synthesize(&self, underlying: &[OHLCVData]) -> Vec<OHLCVData> {
+        let Some(first) = underlying.first() else {
+            return vec![];
+        };
+
+        let mut reference_price = first.open;
+        let mut reference_value = first.open;
+        let mut reference_date = first.timestamp.date();
+        let mut previous_close = first.open;
+
+        let mut product = Vec::with_capacity(underlying.len());
+        for bar in underlying {
+            let date = bar.timestamp.date();
+            if date != reference_date {
+                // Rebalance on the last close of the previous day and charge the fees since then
+                let days = (date - reference_date).num_days() as f64;
+                let fees = 1.0 - self.expense_ratio * days / 365.0;
+                let last = product
+                    .last()
+                    .map(|b: &OHLCVData| b.close)
+                    .unwrap_or(reference_value);
+
+                reference_value = last * fees;
+                reference_price = previous_close;
+                reference_date = date;
+            }
+
+            let value = |price: f64| {
+                let underlying_return = price / reference_price - 1.0;
+                (reference_value * (1.0 + self.factor * underlying_return)).max(0.0)
+            };
+            let (high, low) = match self.factor > 0.0 {
+                true => (value(bar.high), value(bar.low)),
+                false => (value(bar.low), value(bar.high)),
+            };
+
+            product.push(OHLCVData {
+                timestamp: bar.timestamp,
+                open: value(bar.open),
+                high,
+                low,
+                close: value(bar.close),
+                volume: bar.volume,
+            });
+            previous_close = bar.close;
+        }
+
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn daily_bars(closes: &[f64]) -> Vec<OHLCVData> {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let mut previous = closes[0];
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, close)| {
+                let bar = OHLCVData {
+                    timestamp: start + chrono::Duration::days(i as i64),
+                    open: previous,
+                    high: previous.max(*close),
+                    low: previous.min(*close),
+                    close: *close,
+                    volume: 1000,
+                };
+                previous = *close;
+                bar
+            })
+            .collect()
+    }
+
+    #[test]
+    fn daily_reset_decays_on_volatile_path() {
+        // +10% then -9.0909% brings the underlying back to 100
+        let underlying = daily_bars(&[100.0, 110.0, 100.0]);
+        let leveraged = Leveraged {
+            factor: 3.0,
+            expense_ratio: 0.0,
+        };
+        let product = leveraged.synthesize(&underlying);
+
+        assert!((product[1].close - 130.0).abs() < 1e-9);
+        // 130 * (1 - 3 * 0.090909) = 94.5454
+        assert!((product[2].close - 94.545454).abs() < 1e-4);
+        assert_eq!(product[2].high, product[2].open);
+
+        let inverse = Leveraged {
+            factor: -1.0,
+            expense_ratio: 0.0,
+        };
+        let product = inverse.synthesize(&underlying);
+        assert!((product[1].close - 90.0).abs() < 1e-9);
+        assert_eq!(product[1].low, product[1].close);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::broker::contract::{Contract, Funding, MarginRequirement};
+use chrono::{Duration, NaiveDateTime};
+use serde::Deserialize;
+
+// Perpetual future margined in the quote currency, with periodic funding payments
+#[derive(Deserialize, Debug, Clone)]
+pub struct Perpetual {
+    // Units of the underlying per contract, order sizes are in contracts
+    #[serde(default = "one")]
+    pub contract_size: f64,
+    // Initial margin is 1 / leverage of the notional
+    #[serde(default = "one")]
+    pub leverage: f64,
+    #[serde(default = "maintenance_margin")]
+    pub maintenance_margin: f64,
+    // Constant rate paid every `funding_interval_hours`
+    pub funding_rate: Option<f64>,
+    #[serde(default = "funding_interval_hours")]
+    pub funding_interval_hours: i64,
+    // Historical rates, used instead of `funding_rate`
+    pub funding: Option<Vec<FundingRate>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FundingRate {
+    pub timestamp: NaiveDateTime,
+    pub rate: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn maintenance_margin() -> f64 {
+    0.005
+}
+
+fn funding_interval_hours() -> i64 {
+    8
+}
+
+impl Perpetual {
+    pub fn contract(&self) -> Result<Contract, &'static str> {
+        if !self.contract_size.is_finite() || self.contract_size <= 0.0 {
+            return Err("Invalid contract size");
+        }
+        if !self.leverage.is_finite() || self.leverage < 1.0 {
+            return Err("Leverage must be at least 1");
+        }
+        // The position would be liquidated as soon as it is opened otherwise
+        if !(0.0..1.0 / self.leverage).contains(&self.maintenance_margin) {
+            return Err("The maintenance margin must be below the initial margin");
+        }
+
+        let funding = match (&self.funding, self.funding_rate) {
+            (Some(schedule), _) => Some(Funding::Schedule(
+                schedule.iter().map(|f| (f.timestamp, f.rate)).collect(),
+            )),
+            (None, Some(rate)) => {
+                if self.funding_interval_hours <= 0 {
+                    return Err("Invalid funding interval");
+                }
+                Some(Funding::Constant {
+                    rate,
+                    interval: Duration::hours(self.funding_interval_hours),
+                })
+            }
+            (None, None) => None,
+        };
+
+        Ok(Contract {
+            multiplier: self.contract_size,
+            margin: true,
+            margin_requirement: Some(MarginRequirement {
+                initial: 1.0 / self.leverage,
+                maintenance: self.maintenance_margin,
+            }),
+            funding,
+            ..Contract::default()
+        })
+    }
+}
@@ -0,0 +1,223 @@
+use crate::broker::algo::ParentStatus;
+use crate::broker::order::{OrderDirection, OrderType, SizeSpec, TimeInForce};
+use crate::engine::Engine;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+// State of a running paper session, published on every tick
+#[derive(Serialize, Debug, Clone)]
+pub struct LiveMetrics {
+    pub session: String,
+    pub time: Option<NaiveDateTime>,
+    pub running: bool,
+    pub cash: f64,
+    pub equity: f64,
+    // Equity at the end of the previous (UTC) day, the initial capital on the first one
+    pub day_start_equity: f64,
+    pub today_pnl: f64,
+    pub total_pnl: f64,
+    pub positions: Vec<LivePosition>,
+    pub open_orders: Vec<LiveOrder>,
+    pub active_algo_orders: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LivePosition {
+    pub asset: String,
+    pub quantity: f64,
+    pub average_price: f64,
+    pub market_price: f64,
+    pub unrealized_pnl: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LiveOrder {
+    pub id: u64,
+    pub asset: String,
+    pub direction: OrderDirection,
+    pub order_type: &'static str,
+    pub price: Option<f64>,
+    pub size: SizeSpec,
+    pub time_in_force: TimeInForce,
+    pub placed_at: Option<NaiveDateTime>,
+}
+
+impl LiveMetrics {
+    // Metrics before the first tick
+    pub fn start(session: &str, engine: &Engine) -> Self {
+        let initial_capital = engine.broker.trade_tracker.initial_capital;
+        Self::build(session, engine, None, initial_capital)
+    }
+
+    // Metrics after the last simulated tick, the day start carries over within a day
+    pub fn next(&self, engine: &Engine) -> Self {
+        let time = engine.current_time - engine.tick;
+        let day_start_equity = match self.time {
+            Some(previous) if previous.date() == time.date() => self.day_start_equity,
+            Some(_) => self.equity,
+            None => self.day_start_equity,
+        };
+        Self::build(&self.session, engine, Some(time), day_start_equity)
+    }
+
+    fn build(
+        session: &str,
+        engine: &Engine,
+        time: Option<NaiveDateTime>,
+        day_start_equity: f64,
+    ) -> Self {
+        let broker = &engine.broker;
+        let candle = engine.current_candle();
+        let equity = broker.cash + candle.map(|c| broker.portfolio_value(c)).unwrap_or(0.0);
+
+        let positions = broker
+            .portfolio
+            .iter()
+            .map(|(asset, position)| {
+                let market_price = candle.map(|c| c.close).unwrap_or(position.average_price);
+                let point_value = broker.contract.multiplier * broker.contract.rate(market_price);
+                LivePosition {
+                    asset: asset.clone(),
+                    quantity: position.quantity,
+                    average_price: position.average_price,
+                    market_price,
+                    unrealized_pnl: (market_price - position.average_price)
+                        * position.quantity
+                        * point_value,
+                }
+            })
+            .collect();
+
+        let open_orders = broker
+            .open_orders()
+            .map(|(id, order)| {
+                let (order_type, price) = match order.order_type {
+                    OrderType::Market => ("market", None),
+                    OrderType::Limit(price) => ("limit", Some(price)),
+                    OrderType::Stop(price) => ("stop", Some(price)),
+                };
+                LiveOrder {
+                    id,
+                    asset: order.asset.clone(),
+                    direction: order.direction.clone(),
+                    order_type,
+                    price,
+                    size: order.size,
+                    time_in_force: order.time_in_force,
+                    placed_at: order.placed_at,
+                }
+            })
+            .collect();
+
+        LiveMetrics {
+            session: session.to_string(),
+            time,
+            running: true,
+            cash: broker.cash,
+            equity,
+            day_start_equity,
+            today_pnl: equity - day_start_equity,
+            total_pnl: equity - broker.trade_tracker.initial_capital,
+            positions,
+            open_orders,
+            active_algo_orders: broker
+                .algo_orders
+                .iter()
+                .filter(|parent| parent.status == ParentStatus::Active)
+                .count(),
+        }
+    }
+}
+
+// Running paper sessions, each one publishes its latest metrics on a watch channel
+pub struct LiveSessions {
+    sessions: Mutex<HashMap<String, watch::Sender<LiveMetrics>>>,
+}
+
+impl LiveSessions {
+    pub fn new() -> Self {
+        LiveSessions {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn register(&self, metrics: LiveMetrics) -> watch::Sender<LiveMetrics> {
+        let id = metrics.session.clone();
+        let (sender, _) = watch::channel(metrics);
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.insert(id, sender.clone());
+        }
+        sender
+    }
+
+    pub fn subscribe(&self, id: &str) -> Option<watch::Receiver<LiveMetrics>> {
+        let sessions = self.sessions.lock().ok()?;
+        sessions.get(id).map(|sender| sender.subscribe())
+    }
+
+    // Subscribers receive the final metrics and then see the channel close
+    pub fn remove(&self, id: &str) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::position::Position;
+    use crate::data::OHLCVData;
+    use crate::strategy::rules::{OrderAction, RuleSpec, RuleStrategy};
+    use chrono::Duration;
+
+    #[test]
+    fn today_pnl_resets_each_day() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let idle = RuleSpec {
+            action: OrderAction::Buy,
+            when: "close > 1000000".to_string(),
+            size: "1".to_string(),
+            execution: None,
+        };
+        let strategy = RuleStrategy::new("AAPL".to_string(), &[idle]).unwrap();
+        let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(2)));
+        engine.set_tick(Duration::hours(12));
+        engine.add_data(
+            [100.0, 102.0, 101.0, 105.0]
+                .iter()
+                .enumerate()
+                .map(|(i, close)| OHLCVData {
+                    timestamp: start + Duration::hours(12 * i as i64),
+                    open: *close,
+                    high: *close,
+                    low: *close,
+                    close: *close,
+                    volume: 1000,
+                })
+                .collect(),
+        );
+        engine.broker.set_cash(1000.0);
+        engine.broker.cash = 0.0;
+        engine
+            .broker
+            .portfolio
+            .insert("AAPL".to_string(), Position::new(10.0, 100.0));
+        engine.start().unwrap();
+
+        let mut metrics = LiveMetrics::start("test", &engine);
+        let mut today = vec![];
+        while engine.step() {
+            metrics = metrics.next(&engine);
+            today.push(metrics.today_pnl);
+        }
+
+        assert_eq!(today, vec![0.0, 20.0, -10.0, 30.0]);
+        assert_eq!(metrics.total_pnl, 50.0);
+        assert_eq!(metrics.positions[0].unrealized_pnl, 50.0);
+    }
+}
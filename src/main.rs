@@ -1,16 +1,99 @@
-use crate::routes::run::run;
-use axum::{routing::post, Router};
+use crate::analytics::plugin::MetricRegistry;
+use crate::live::LiveSessions;
+use crate::routes::{
+    aggregate::aggregate,
+    cors::{cors, Cors},
+    dashboard::{self, dashboard},
+    ensemble::ensemble,
+    estimate::estimate,
+    experiments::{create_experiment, get_experiment},
+    fixtures::{record_fixture, verify_fixture},
+    live::{get_live_metrics, live_metrics_ws},
+    optimize::optimize,
+    pipeline::{get_pipeline, pipeline},
+    replay::replay,
+    run::run,
+    runs::{annotate_run, get_run, get_tax_report, list_runs, recompute_run},
+    simulate::simulate,
+    strategies::{get_strategy, list_strategies, upload_strategy, MAX_UPLOAD_BYTES},
+    sweep::cost_sweep,
+    tournament::tournament,
+    AppState,
+};
+use crate::storage::Storage;
+use crate::store::RunStore;
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
 
+mod alerts;
 mod analytics;
 mod broker;
+mod calendar;
 mod data;
 mod engine;
+mod instrument;
+mod live;
+mod provider;
 mod routes;
+mod storage;
+mod store;
 mod strategy;
+mod webhook;
 
 #[tokio::main]
 async fn main() {
-    let app = Router::new().route("/run", post(run));
+    let storage = Storage::from_env().expect("Invalid storage configuration");
+    let store = RunStore::from_env()
+        .await
+        .expect("Failed to initialize the run store");
+    let state = AppState {
+        storage: storage.map(Arc::new),
+        store: Arc::new(store),
+        live: Arc::new(LiveSessions::new()),
+        metric_plugins: Arc::new(MetricRegistry::default()),
+    };
+
+    let mut app = Router::new()
+        .route("/run", post(run))
+        .route("/replay", get(replay))
+        .route("/live/{session}/metrics", get(get_live_metrics))
+        .route("/live/{session}/ws", get(live_metrics_ws))
+        .route("/runs", get(list_runs))
+        .route("/runs/{id}", get(get_run).patch(annotate_run))
+        .route("/runs/{id}/tax-report", get(get_tax_report))
+        .route("/runs/{id}/recompute", post(recompute_run))
+        .route(
+            "/strategies",
+            post(upload_strategy)
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES))
+                .get(list_strategies),
+        )
+        .route("/strategies/{id}", get(get_strategy))
+        .route("/experiments", post(create_experiment))
+        .route("/experiments/{id}", get(get_experiment))
+        .route("/tournament", post(tournament))
+        .route("/sweep/costs", post(cost_sweep))
+        .route("/aggregate", post(aggregate))
+        .route("/ensemble", post(ensemble))
+        .route("/estimate", post(estimate))
+        .route("/optimize", post(optimize))
+        .route("/simulate", post(simulate))
+        .route("/fixtures/record", post(record_fixture))
+        .route("/fixtures/verify", post(verify_fixture))
+        .route("/pipelines", post(pipeline))
+        .route("/pipelines/{id}", get(get_pipeline))
+        .with_state(state);
+    if dashboard::enabled() {
+        app = app.route("/dashboard", get(dashboard));
+    }
+    if let Some(origins) = Cors::from_env() {
+        app = app.layer(middleware::from_fn_with_state(Arc::new(origins), cors));
+    }
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
     println!("Listening on port {}", port);
@@ -0,0 +1,184 @@
+use crate::data::OHLCVData;
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+pub mod polygon;
+pub mod symbol;
+
+// Market data fetched by the server instead of being embedded in the request
+#[derive(Deserialize, Clone)]
+#[serde(tag = "name", rename_all = "lowercase")]
+pub enum Provider {
+    Polygon {
+        // Any common format (BTC-USD, BTCUSDT, EUR/USD, BRK-B, ...), the data symbol by default
+        ticker: Option<String>,
+        // Bars of `multiplier` `timespan` (minute, hour, day, ...), one day by default
+        multiplier: Option<u32>,
+        timespan: Option<String>,
+        // Same as `data.prices`, which it has to agree with when both are set
+        adjusted: Option<bool>,
+        // Key of the caller, so each user consumes their own quota. Falls back to the
+        // server wide `KRONOS_POLYGON_API_KEY`
+        api_key: Option<String>,
+    },
+}
+
+// Prices adjusted by the provider for the splits and dividends, or as traded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceAdjustment {
+    Adjusted,
+    Raw,
+}
+
+// Adjustment of the prices of a run, recorded with its result
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PriceBasis {
+    // None for bars given without `data.prices`
+    pub adjustment: Option<PriceAdjustment>,
+    // Provider of the primary series
+    pub provider: Option<&'static str>,
+    pub warnings: Vec<&'static str>,
+}
+
+impl PriceBasis {
+    pub fn new(adjustment: Option<PriceAdjustment>) -> Self {
+        let mut basis = PriceBasis {
+            adjustment,
+            ..PriceBasis::default()
+        };
+        if adjustment == Some(PriceAdjustment::Raw) {
+            // The broker doesn't pay dividends, adjusted prices are what account for them
+            basis
+                .warnings
+                .push("Raw prices show the splits as price jumps and leave the dividends out");
+        }
+        basis
+    }
+}
+
+impl Provider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::Polygon { .. } => "polygon",
+        }
+    }
+
+    // Adjustment fetched given the one of `data.prices`, providers adjust by default
+    pub fn adjustment(
+        &self,
+        requested: Option<PriceAdjustment>,
+    ) -> Result<PriceAdjustment, &'static str> {
+        let own = match self {
+            Provider::Polygon { adjusted, .. } => adjusted.map(|adjusted| match adjusted {
+                true => PriceAdjustment::Adjusted,
+                false => PriceAdjustment::Raw,
+            }),
+        };
+        match (own, requested) {
+            (Some(own), Some(requested)) if own != requested => {
+                Err("The adjustment of the provider contradicts data.prices")
+            }
+            (own, requested) => Ok(own.or(requested).unwrap_or(PriceAdjustment::Adjusted)),
+        }
+    }
+
+    // Start of the fetch so `bars` bars precede `start`, with room for the closed sessions
+    pub fn warmup_start(&self, start: NaiveDateTime, bars: usize) -> NaiveDateTime {
+        if bars == 0 {
+            return start;
+        }
+        match self {
+            Provider::Polygon {
+                multiplier,
+                timespan,
+                ..
+            } => {
+                let bar = polygon::bar_duration(
+                    multiplier.unwrap_or(1),
+                    timespan.as_deref().unwrap_or("day"),
+                );
+                let span = bar * bars as i32;
+                // Equities don't trade at night, on weekends and on holidays
+                let span = match bar < Duration::days(1) {
+                    true => span * 4 + Duration::days(4),
+                    false => span * 7 / 5 + Duration::days(5),
+                };
+                start.checked_sub_signed(span).unwrap_or(NaiveDateTime::MIN)
+            }
+        }
+    }
+
+    // What a fetch asks the provider for, without the API key, to key the data cache
+    pub fn request(
+        &self,
+        symbol: Option<&str>,
+        range: (NaiveDateTime, NaiveDateTime),
+        adjustment: PriceAdjustment,
+    ) -> String {
+        match self {
+            Provider::Polygon {
+                ticker,
+                multiplier,
+                timespan,
+                ..
+            } => format!(
+                "polygon/{}/{}/{}/{:?}/{}/{}",
+                ticker.as_deref().or(symbol).unwrap_or_default(),
+                multiplier.unwrap_or(1),
+                timespan.as_deref().unwrap_or("day"),
+                adjustment,
+                range.0,
+                range.1,
+            ),
+        }
+    }
+
+    // Bars of `symbol` unless the provider names its own ticker
+    pub async fn fetch(
+        &self,
+        symbol: Option<&str>,
+        range: (NaiveDateTime, NaiveDateTime),
+        adjustment: PriceAdjustment,
+    ) -> Result<Vec<OHLCVData>, ProviderError> {
+        match self {
+            Provider::Polygon {
+                ticker,
+                multiplier,
+                timespan,
+                api_key,
+                ..
+            } => {
+                let api_key = api_key
+                    .clone()
+                    .or_else(|| std::env::var("KRONOS_POLYGON_API_KEY").ok())
+                    .ok_or(ProviderError::MissingKey)?;
+                let symbol = ticker.as_deref().or(symbol).ok_or(ProviderError::Symbol(
+                    "A ticker or a data symbol is required",
+                ))?;
+                let ticker = symbol::Symbol::parse(symbol)
+                    .and_then(|symbol| polygon::ticker(&symbol))
+                    .map_err(ProviderError::Symbol)?;
+                polygon::fetch(
+                    &ticker,
+                    multiplier.unwrap_or(1),
+                    timespan.as_deref().unwrap_or("day"),
+                    adjustment == PriceAdjustment::Adjusted,
+                    &api_key,
+                    range,
+                )
+                .await
+                .map_err(ProviderError::Request)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    // Neither the request nor the server has a key for the provider
+    MissingKey,
+    // The symbol can't be translated to the provider format
+    Symbol(&'static str),
+    Request(String),
+}
@@ -0,0 +1,139 @@
+use super::symbol::Symbol;
+use crate::data::OHLCVData;
+use chrono::{Duration, NaiveDateTime};
+use serde::Deserialize;
+
+const BASE_URL: &str = "https://api.polygon.io";
+
+// Exchanges of the equities Polygon provides
+const EXCHANGES: &[&str] = &["XNAS", "XNYS", "XASE", "ARCX", "BATS", "IEXG"];
+
+// Polygon ticker of a symbol, crypto and forex pairs are prefixed with their market
+pub fn ticker(symbol: &Symbol) -> Result<String, &'static str> {
+    match symbol {
+        Symbol::Equity {
+            exchange: Some(mic),
+            ..
+        } if !EXCHANGES.contains(&mic.as_str()) => Err("Polygon only provides US equities"),
+        Symbol::Equity { ticker, .. } => Ok(ticker.clone()),
+        Symbol::Crypto { base, quote } => Ok(format!("X:{}{}", base, quote)),
+        Symbol::Forex { base, quote } => Ok(format!("C:{}{}", base, quote)),
+    }
+}
+
+#[derive(Deserialize)]
+struct AggregatesPage {
+    #[serde(default)]
+    results: Vec<Aggregate>,
+    next_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Aggregate {
+    // Start of the bar in milliseconds since the epoch
+    t: i64,
+    o: f64,
+    h: f64,
+    l: f64,
+    c: f64,
+    v: f64,
+}
+
+impl Aggregate {
+    fn bar(&self) -> Option<OHLCVData> {
+        Some(OHLCVData {
+            timestamp: chrono::DateTime::from_timestamp_millis(self.t)?.naive_utc(),
+            open: self.o,
+            high: self.h,
+            low: self.l,
+            close: self.c,
+            volume: self.v as u64,
+        })
+    }
+}
+
+// Length of a bar of `multiplier` `timespan`, months and longer ones are approximate
+pub fn bar_duration(multiplier: u32, timespan: &str) -> Duration {
+    let unit = match timespan {
+        "second" => Duration::seconds(1),
+        "minute" => Duration::minutes(1),
+        "hour" => Duration::hours(1),
+        "week" => Duration::weeks(1),
+        "month" => Duration::days(31),
+        "quarter" => Duration::days(92),
+        "year" => Duration::days(366),
+        _ => Duration::days(1),
+    };
+    unit * multiplier as i32
+}
+
+// Aggregate bars of the range, following the pagination
+pub async fn fetch(
+    ticker: &str,
+    multiplier: u32,
+    timespan: &str,
+    adjusted: bool,
+    api_key: &str,
+    (start, end): (NaiveDateTime, NaiveDateTime),
+) -> Result<Vec<OHLCVData>, String> {
+    let client = reqwest::Client::new();
+    let mut url = format!(
+        "{}/v2/aggs/ticker/{}/range/{}/{}/{}/{}?adjusted={}&sort=asc&limit=50000",
+        BASE_URL,
+        ticker,
+        multiplier,
+        timespan,
+        start.and_utc().timestamp_millis(),
+        end.and_utc().timestamp_millis(),
+        adjusted
+    );
+
+    let mut bars = vec![];
+    loop {
+        let response = client
+            .get(&url)
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Polygon responded with {}", response.status()));
+        }
+        let page: AggregatesPage = response.json().await.map_err(|e| e.to_string())?;
+        bars.extend(page.results.iter().filter_map(Aggregate::bar));
+
+        match page.next_url {
+            Some(next_url) => url = next_url,
+            None => return Ok(bars),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aggregates_page() {
+        let page: AggregatesPage = serde_json::from_str(
+            r#"{"ticker":"AAPL","status":"OK","results":[
+                {"v":70790813,"vw":131.6292,"o":130.465,"c":130.15,"h":133.41,"l":129.89,"t":1673240400000,"n":645365}
+            ],"next_url":"https://api.polygon.io/v2/aggs/ticker/AAPL/range/1/day/1673240400000/1673413200000?cursor=abc"}"#,
+        )
+        .unwrap();
+
+        let bar = page.results[0].bar().unwrap();
+        assert_eq!(bar.timestamp.to_string(), "2023-01-09 05:00:00");
+        assert_eq!(
+            (bar.open, bar.close, bar.volume),
+            (130.465, 130.15, 70790813)
+        );
+        assert!(page.next_url.is_some());
+
+        let ticker = |raw: &str| ticker(&Symbol::parse(raw).unwrap());
+        assert_eq!(ticker("btc-usd"), Ok("X:BTCUSD".to_string()));
+        assert_eq!(ticker("EUR/USD"), Ok("C:EURUSD".to_string()));
+        assert_eq!(ticker("BRK-B"), Ok("BRK.B".to_string()));
+        assert!(ticker("VOD.L").is_err());
+    }
+}
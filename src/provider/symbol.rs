@@ -0,0 +1,175 @@
+// Instrument identifiers in the formats users and providers write them, parsed to a common form
+
+const FIAT: &[&str] = &[
+    "USD", "EUR", "GBP", "JPY", "CHF", "CAD", "AUD", "NZD", "SEK", "NOK", "DKK", "HKD", "SGD",
+    "CNH", "MXN", "ZAR", "TRY", "PLN",
+];
+
+// Quote currencies of crypto pairs besides the fiat ones
+const CRYPTO_QUOTES: &[&str] = &["USDT", "USDC", "BUSD", "DAI", "BTC", "ETH"];
+
+// Bases recognized in pairs written without a separator and quoted in fiat (e.g. BTCUSD)
+const CRYPTO_BASES: &[&str] = &[
+    "BTC", "ETH", "SOL", "XRP", "ADA", "DOGE", "LTC", "BNB", "DOT", "AVAX", "LINK", "MATIC", "TRX",
+    "BCH", "XLM",
+];
+
+// Exchange suffixes (e.g. VOD.L) and the market identifier code of their exchange
+const EXCHANGE_SUFFIXES: &[(&str, &str)] = &[
+    ("L", "XLON"),
+    ("PA", "XPAR"),
+    ("DE", "XETR"),
+    ("F", "XFRA"),
+    ("AS", "XAMS"),
+    ("MI", "XMIL"),
+    ("SW", "XSWX"),
+    ("T", "XTKS"),
+    ("HK", "XHKG"),
+    ("TO", "XTSE"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    // `ticker` keeps the share class after a dot (BRK.B), `exchange` is a MIC when known
+    Equity {
+        ticker: String,
+        exchange: Option<String>,
+    },
+    Crypto {
+        base: String,
+        quote: String,
+    },
+    Forex {
+        base: String,
+        quote: String,
+    },
+}
+
+impl Symbol {
+    // Accepts AAPL, aapl, AAPL.US, VOD.L, XLON:VOD, BRK-B, BTC-USD, BTC/USDT, BTCUSDT, X:BTCUSD,
+    // EURUSD, EUR/USD and C:EURUSD
+    pub fn parse(raw: &str) -> Result<Symbol, &'static str> {
+        let symbol = raw.trim().to_uppercase();
+        if symbol.is_empty() {
+            return Err("Empty symbol");
+        }
+
+        // Prefixed with their market, like Polygon tickers
+        if let Some((prefix, rest)) = symbol.split_once(':') {
+            return match prefix {
+                "X" => split_pair(rest)
+                    .map(|(base, quote)| Symbol::Crypto { base, quote })
+                    .ok_or("Unrecognized crypto pair"),
+                "C" => split_pair(rest)
+                    .map(|(base, quote)| Symbol::Forex { base, quote })
+                    .ok_or("Unrecognized currency pair"),
+                mic if mic.len() == 4 && !rest.is_empty() => Ok(Symbol::Equity {
+                    ticker: rest.to_string(),
+                    exchange: Some(mic.to_string()),
+                }),
+                _ => Err("Unrecognized symbol prefix"),
+            };
+        }
+
+        if let Some((base, quote)) = symbol.split_once(['-', '/', '_', ' ']) {
+            if is_quote(quote) && base.len() >= 2 {
+                return Ok(pair(base, quote));
+            }
+            // Share classes, BRK-B or BRK/B
+            if quote.len() == 1 && !base.is_empty() {
+                return Ok(Symbol::Equity {
+                    ticker: format!("{}.{}", base, quote),
+                    exchange: None,
+                });
+            }
+            return Err("Unrecognized symbol");
+        }
+
+        if let Some((ticker, suffix)) = symbol.rsplit_once('.') {
+            if suffix == "US" {
+                return Ok(Symbol::Equity {
+                    ticker: ticker.to_string(),
+                    exchange: None,
+                });
+            }
+            if let Some((_, mic)) = EXCHANGE_SUFFIXES.iter().find(|(s, _)| *s == suffix) {
+                return Ok(Symbol::Equity {
+                    ticker: ticker.to_string(),
+                    exchange: Some(mic.to_string()),
+                });
+            }
+        }
+
+        if let Some((base, quote)) = split_pair(&symbol) {
+            let fiat = FIAT.contains(&base.as_str()) && FIAT.contains(&quote.as_str());
+            if fiat
+                || CRYPTO_BASES.contains(&base.as_str())
+                || CRYPTO_QUOTES.contains(&quote.as_str())
+            {
+                return Ok(pair(&base, &quote));
+            }
+        }
+        Ok(Symbol::Equity {
+            ticker: symbol,
+            exchange: None,
+        })
+    }
+}
+
+fn is_quote(currency: &str) -> bool {
+    FIAT.contains(&currency) || CRYPTO_QUOTES.contains(&currency)
+}
+
+// Currencies on both sides are forex, anything else quoted in a currency is crypto
+fn pair(base: &str, quote: &str) -> Symbol {
+    let (base, quote) = (base.to_string(), quote.to_string());
+    match FIAT.contains(&base.as_str()) {
+        true => Symbol::Forex { base, quote },
+        false => Symbol::Crypto { base, quote },
+    }
+}
+
+// Splits a pair written without a separator on its quote currency, longest quotes first
+fn split_pair(symbol: &str) -> Option<(String, String)> {
+    let mut quotes: Vec<&str> = FIAT.iter().chain(CRYPTO_QUOTES).copied().collect();
+    quotes.sort_by_key(|quote| std::cmp::Reverse(quote.len()));
+    quotes.into_iter().find_map(|quote| {
+        let base = symbol.strip_suffix(quote)?;
+        (base.len() >= 2).then(|| (base.to_string(), quote.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_formats_of_every_source() {
+        let crypto = Symbol::Crypto {
+            base: "BTC".to_string(),
+            quote: "USDT".to_string(),
+        };
+        for raw in ["BTCUSDT", "btc-usdt", "BTC/USDT", "X:BTCUSDT"] {
+            assert_eq!(Symbol::parse(raw), Ok(crypto.clone()));
+        }
+
+        let forex = Symbol::Forex {
+            base: "EUR".to_string(),
+            quote: "USD".to_string(),
+        };
+        for raw in ["EURUSD", "EUR/USD", "C:EURUSD"] {
+            assert_eq!(Symbol::parse(raw), Ok(forex.clone()));
+        }
+
+        let equity = |ticker: &str, exchange: Option<&str>| Symbol::Equity {
+            ticker: ticker.to_string(),
+            exchange: exchange.map(str::to_string),
+        };
+        assert_eq!(Symbol::parse(" aapl "), Ok(equity("AAPL", None)));
+        assert_eq!(Symbol::parse("AAPL.US"), Ok(equity("AAPL", None)));
+        assert_eq!(Symbol::parse("VOD.L"), Ok(equity("VOD", Some("XLON"))));
+        assert_eq!(Symbol::parse("XLON:VOD"), Ok(equity("VOD", Some("XLON"))));
+        assert_eq!(Symbol::parse("BRK-B"), Ok(equity("BRK.B", None)));
+        assert_eq!(Symbol::parse("BRK.B"), Ok(equity("BRK.B", None)));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::analytics::{
+    cross_section::{self, Distribution},
+    metrics::GlobalMetrics,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::run::{
+    prepare, run_all, BatchError, Body, BrokerSettings, DataInput, Response, SimulationParameters,
+    StrategyConfig,
+};
+use super::AppState;
+
+pub(super) const DEFAULT_METRICS: [&str; 4] = ["sharpe_ratio", "roi", "net_profit", "max_drawdown"];
+
+#[derive(Deserialize)]
+pub struct AggregateBody {
+    parameters: SimulationParameters,
+    // One feed per symbol, the strategy trades each one separately
+    datasets: Vec<DataInput>,
+    broker: BrokerSettings,
+    strategy: StrategyConfig,
+    // Metrics summarized across the symbols
+    metrics: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct AggregateResult {
+    seed: u64,
+    runs: Vec<SymbolRun>,
+    // Share of the symbols with a positive net profit
+    profitable_share: f64,
+    statistics: BTreeMap<String, Option<Distribution>>,
+}
+
+#[derive(Serialize)]
+struct SymbolRun {
+    symbol: String,
+    metrics: serde_json::Map<String, serde_json::Value>,
+}
+
+// Named numeric fields of the run metrics, with their values appended to `values`
+pub(super) fn select_metrics(
+    metrics: &GlobalMetrics,
+    names: &[String],
+    values: &mut BTreeMap<String, Vec<f64>>,
+) -> Result<serde_json::Map<String, serde_json::Value>, (StatusCode, &'static str)> {
+    let all = serde_json::to_value(metrics).unwrap_or_default();
+    let mut selected = serde_json::Map::new();
+    for name in names {
+        match all.get(name).and_then(|value| value.as_f64()) {
+            Some(value) => {
+                selected.insert(name.clone(), all[name].clone());
+                values.entry(name.clone()).or_default().push(value);
+            }
+            None => return Err((StatusCode::BAD_REQUEST, "Unknown metric")),
+        }
+    }
+    Ok(selected)
+}
+
+pub async fn aggregate(
+    State(state): State<AppState>,
+    Json(mut payload): Json<AggregateBody>,
+) -> (StatusCode, Json<Response<AggregateResult>>) {
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        return (status, Json(Response::Error(error)));
+    }
+    let warmup_bars = payload.strategy.warmup_bars();
+    for data in &mut payload.datasets {
+        if let Err((status, error)) = data
+            .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+            .await
+        {
+            return (status, Json(Response::Error(error)));
+        }
+    }
+    match execute(payload).await {
+        Ok(result) => (StatusCode::OK, Json(Response::Success(result))),
+        Err(error) => error.respond(),
+    }
+}
+
+async fn execute(payload: AggregateBody) -> Result<AggregateResult, BatchError> {
+    if payload.datasets.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "At least one dataset is required").into());
+    }
+
+    let metrics: Vec<String> = match payload.metrics {
+        Some(metrics) if !metrics.is_empty() => metrics,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "At least one metric is required").into()),
+        None => DEFAULT_METRICS.iter().map(|m| m.to_string()).collect(),
+    };
+
+    // Every symbol gets the same slippage draws
+    let mut broker = payload.broker;
+    let seed = *broker.seed.get_or_insert_with(rand::random);
+
+    let mut symbols = vec![];
+    let mut prepared = vec![];
+    for (i, data) in payload.datasets.into_iter().enumerate() {
+        let symbol = data
+            .symbol
+            .clone()
+            .unwrap_or_else(|| format!("dataset_{}", i + 1));
+        let body = Body::new(
+            payload.parameters.clone(),
+            data,
+            broker.clone(),
+            payload.strategy.clone(),
+        );
+        symbols.push(symbol);
+        prepared.push(prepare(body)?);
+    }
+
+    let results = run_all(prepared, |_, result| result).await?;
+
+    let mut runs = vec![];
+    let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for (symbol, result) in symbols.iter().zip(&results) {
+        let selected = select_metrics(&result.metrics, &metrics, &mut values)?;
+        runs.push(SymbolRun {
+            symbol: symbol.clone(),
+            metrics: selected,
+        });
+    }
+
+    let profitable = results
+        .iter()
+        .filter(|result| result.metrics.net_profit > 0.0)
+        .count();
+
+    Ok(AggregateResult {
+        seed,
+        runs,
+        profitable_share: profitable as f64 / results.len() as f64,
+        statistics: values
+            .into_iter()
+            .map(|(metric, values)| (metric, cross_section::distribution(&values)))
+            .collect(),
+    })
+}
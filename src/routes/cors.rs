@@ -0,0 +1,182 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+const ALLOWED_METHODS: &str = "GET, POST, PATCH, OPTIONS";
+const ALLOWED_HEADERS: &str = "content-type, if-none-match";
+// Read by the dashboards to revalidate the runs they poll
+const EXPOSED_HEADERS: &str = "etag";
+const PREFLIGHT_MAX_AGE: &str = "600";
+
+// Browser origins allowed to call the API, from `KRONOS_CORS_ORIGINS`
+pub struct Cors {
+    // None allows any origin
+    origins: Option<Vec<String>>,
+}
+
+impl Cors {
+    // Comma separated origins (`https://app.example.com,http://localhost:5173`) or `*`, cross
+    // origin requests are left to the browser to block when unset
+    pub fn from_env() -> Option<Self> {
+        let origins = std::env::var("KRONOS_CORS_ORIGINS").ok()?;
+        if origins.trim() == "*" {
+            return Some(Cors { origins: None });
+        }
+        let origins: Vec<String> = origins
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/').to_string())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+        (!origins.is_empty()).then_some(Cors {
+            origins: Some(origins),
+        })
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins
+            .as_ref()
+            .is_none_or(|origins| origins.iter().any(|allowed| allowed == origin))
+    }
+}
+
+// Whether the origin is allowed varies the response, caches must key it on the origin even
+// when it isn't. Appended so the Vary headers of the handlers are kept
+fn mark(headers: &mut HeaderMap, origin: Option<HeaderValue>) {
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    if let Some(origin) = origin {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(EXPOSED_HEADERS),
+        );
+    }
+}
+
+fn preflight(origin: HeaderValue) -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    let headers = response.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static(ALLOWED_METHODS),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static(ALLOWED_HEADERS),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_MAX_AGE,
+        HeaderValue::from_static(PREFLIGHT_MAX_AGE),
+    );
+    response
+}
+
+// Allowed origin of a request, and whether it is a preflight of it
+fn classify(cors: &Cors, method: &Method, headers: &HeaderMap) -> (Option<HeaderValue>, bool) {
+    let origin = headers
+        .get(header::ORIGIN)
+        .filter(|origin| origin.to_str().is_ok_and(|origin| cors.allows(origin)))
+        .cloned();
+    let preflight = origin.is_some()
+        && method == Method::OPTIONS
+        && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    (origin, preflight)
+}
+
+// Answers the preflight requests and marks the responses to the allowed origins
+pub async fn cors(State(cors): State<Arc<Cors>>, request: Request, next: Next) -> Response {
+    let (origin, is_preflight) = classify(&cors, request.method(), request.headers());
+    if let (Some(origin), true) = (&origin, is_preflight) {
+        return preflight(origin.clone());
+    }
+
+    let mut response = next.run(request).await;
+    mark(response.headers_mut(), origin);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(origin: &str, preflight: bool) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ORIGIN, HeaderValue::from_str(origin).unwrap());
+        if preflight {
+            headers.insert(
+                header::ACCESS_CONTROL_REQUEST_METHOD,
+                HeaderValue::from_static("POST"),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn marks_the_allowed_origins_and_varies_every_response() {
+        let cors = Cors {
+            origins: Some(vec!["https://app.example.com".to_string()]),
+        };
+        let vary = |headers: &HeaderMap| {
+            headers
+                .get_all(header::VARY)
+                .iter()
+                .map(|value| value.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Allowed, the Vary of the handler is kept
+        let (origin, is_preflight) = classify(
+            &cors,
+            &Method::GET,
+            &request("https://app.example.com", false),
+        );
+        assert!(!is_preflight);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        mark(&mut headers, origin);
+        assert_eq!(vary(&headers), ["accept-encoding", "origin"]);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_EXPOSE_HEADERS], "etag");
+
+        // Disallowed, even its preflight reaches the routes
+        let (origin, is_preflight) = classify(
+            &cors,
+            &Method::OPTIONS,
+            &request("https://evil.example.com", true),
+        );
+        assert_eq!((origin.clone(), is_preflight), (None, false));
+        let mut headers = HeaderMap::new();
+        mark(&mut headers, origin);
+        assert_eq!(vary(&headers), ["origin"]);
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        // Preflight of an allowed origin
+        let (origin, is_preflight) = classify(
+            &cors,
+            &Method::OPTIONS,
+            &request("https://app.example.com", true),
+        );
+        assert!(is_preflight);
+        let response = preflight(origin.unwrap());
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(vary(headers), ["origin"]);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            ALLOWED_METHODS
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], PREFLIGHT_MAX_AGE);
+    }
+}
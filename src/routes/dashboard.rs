@@ -0,0 +1,15 @@
+use axum::{http::header, response::IntoResponse};
+
+// Single page viewer of the stored runs, served when `KRONOS_DASHBOARD` is set
+const DASHBOARD: &str = include_str!("../../dashboard/index.html");
+
+pub fn enabled() -> bool {
+    std::env::var("KRONOS_DASHBOARD").is_ok_and(|value| matches!(value.as_str(), "1" | "true"))
+}
+
+pub async fn dashboard() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        DASHBOARD,
+    )
+}
@@ -0,0 +1,125 @@
+use crate::analytics::cross_section::{self, Distribution};
+use axum::{extract::State, http::StatusCode, Json};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::aggregate::{select_metrics, DEFAULT_METRICS};
+use super::run::{
+    prepare, run_all, BatchError, Body, BrokerSettings, DataInput, Response, SimulationParameters,
+    StrategyConfig,
+};
+use super::AppState;
+
+const MAX_RUNS: usize = 200;
+
+#[derive(Deserialize)]
+pub struct EnsembleBody {
+    parameters: SimulationParameters,
+    data: DataInput,
+    broker: BrokerSettings,
+    strategy: StrategyConfig,
+    // Number of backtests, each with its own slippage draws
+    runs: usize,
+    // Metrics summarized across the runs
+    metrics: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct EnsembleResult {
+    // Seed the run seeds are derived from
+    seed: u64,
+    runs: Vec<SeedRun>,
+    statistics: BTreeMap<String, Option<Distribution>>,
+}
+
+#[derive(Serialize)]
+struct SeedRun {
+    seed: u64,
+    metrics: serde_json::Map<String, serde_json::Value>,
+}
+
+pub async fn ensemble(
+    State(state): State<AppState>,
+    Json(mut payload): Json<EnsembleBody>,
+) -> (StatusCode, Json<Response<EnsembleResult>>) {
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        return (status, Json(Response::Error(error)));
+    }
+    let warmup_bars = payload.strategy.warmup_bars();
+    if let Err((status, error)) = payload
+        .data
+        .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        return (status, Json(Response::Error(error)));
+    }
+    match execute(payload).await {
+        Ok(result) => (StatusCode::OK, Json(Response::Success(result))),
+        Err(error) => error.respond(),
+    }
+}
+
+async fn execute(payload: EnsembleBody) -> Result<EnsembleResult, BatchError> {
+    if !(2..=MAX_RUNS).contains(&payload.runs) {
+        return Err((StatusCode::BAD_REQUEST, "An ensemble takes 2 to 200 runs").into());
+    }
+    // The seeds only change the slippage draws
+    if !payload
+        .broker
+        .slippage
+        .as_ref()
+        .is_some_and(|slippage| slippage.min < slippage.max)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "An ensemble needs a slippage range to draw from",
+        )
+            .into());
+    }
+
+    let metrics: Vec<String> = match payload.metrics {
+        Some(metrics) if !metrics.is_empty() => metrics,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "At least one metric is required").into()),
+        None => DEFAULT_METRICS.iter().map(|m| m.to_string()).collect(),
+    };
+
+    let seed = payload.broker.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut seeds = vec![];
+    let mut prepared = vec![];
+    for _ in 0..payload.runs {
+        let run_seed: u64 = rng.random();
+        let mut broker = payload.broker.clone();
+        broker.seed = Some(run_seed);
+        let body = Body::new(
+            payload.parameters.clone(),
+            payload.data.clone(),
+            broker,
+            payload.strategy.clone(),
+        );
+        seeds.push(run_seed);
+        prepared.push(prepare(body)?);
+    }
+
+    let results = run_all(prepared, |_, result| result).await?;
+
+    let mut runs = vec![];
+    let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for (seed, result) in seeds.into_iter().zip(&results) {
+        runs.push(SeedRun {
+            seed,
+            metrics: select_metrics(&result.metrics, &metrics, &mut values)?,
+        });
+    }
+
+    Ok(EnsembleResult {
+        seed,
+        runs,
+        statistics: values
+            .into_iter()
+            .map(|(metric, values)| (metric, cross_section::distribution(&values)))
+            .collect(),
+    })
+}
@@ -0,0 +1,141 @@
+use crate::data::OHLCVData;
+use crate::engine::RunMode;
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::time::Instant;
+
+use super::run::{prepare, Body, PreparedRun, Response};
+use super::validation;
+use super::AppState;
+
+// Iterations actually run to measure the speed of the strategy
+const CALIBRATION_ITERATIONS: u64 = 500;
+
+#[derive(Serialize)]
+pub struct Estimate {
+    bars: usize,
+    bars_in_range: usize,
+    // Ticks simulated, or bars in the vectorized mode
+    iterations: u64,
+    tick_seconds: i64,
+    // Bars and equity snapshots held during the run
+    estimated_memory_bytes: u64,
+    // Loading the strategy
+    setup_ms: f64,
+    calibration_iterations: u64,
+    estimated_duration_ms: f64,
+}
+
+pub async fn estimate(
+    State(state): State<AppState>,
+    Json(mut payload): Json<Body>,
+) -> (StatusCode, Json<Response<Estimate>>) {
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        return (status, Json(Response::Error(error)));
+    }
+    let warmup_bars = payload.strategy.warmup_bars();
+    if let Err((status, error)) = payload
+        .data
+        .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        return (status, Json(Response::Error(error)));
+    }
+    if let Err(errors) = validation::validate(&payload) {
+        return (StatusCode::BAD_REQUEST, Json(Response::Invalid { errors }));
+    }
+    match execute(payload) {
+        Ok(estimate) => (StatusCode::OK, Json(Response::Success(estimate))),
+        Err((status, error)) => (status, Json(Response::Error(error))),
+    }
+}
+
+fn execute(payload: Body) -> Result<Estimate, (StatusCode, &'static str)> {
+    let timer = Instant::now();
+    let PreparedRun { mut engine, .. } = prepare(payload)?;
+    let setup_ms = timer.elapsed().as_secs_f64() * 1000.0;
+
+    let (start, end) = engine.time_range;
+    let bars = engine.data_feed.len();
+    let in_range: Vec<NaiveDateTime> = engine
+        .data_feed
+        .iter()
+        .map(|bar| bar.timestamp)
+        .filter(|time| *time >= start && *time <= end)
+        .collect();
+    let iterations = engine.planned_iterations();
+    let equity_snapshots = match engine.mode {
+        RunMode::Vectorized => in_range.len() as u64,
+        RunMode::Tick => iterations,
+    };
+    let estimated_memory_bytes = (bars * size_of::<OHLCVData>()) as u64
+        + equity_snapshots * size_of::<(NaiveDateTime, f64)>() as u64;
+
+    // Run the start of the range and extrapolate
+    let calibration_iterations = iterations.min(CALIBRATION_ITERATIONS);
+    let mut estimated_duration_ms = 0.0;
+    if calibration_iterations > 0 {
+        if let Some(time) = engine.iteration_time(calibration_iterations - 1) {
+            engine.time_range.1 = time;
+        }
+        let timer = Instant::now();
+        engine
+            .run()
+            .map_err(|error| (StatusCode::BAD_REQUEST, error))?;
+        let elapsed_ms = timer.elapsed().as_secs_f64() * 1000.0;
+        estimated_duration_ms = elapsed_ms * iterations as f64 / calibration_iterations as f64;
+    }
+
+    Ok(Estimate {
+        bars,
+        bars_in_range: in_range.len(),
+        iterations,
+        tick_seconds: engine.tick.num_seconds(),
+        estimated_memory_bytes,
+        setup_ms,
+        calibration_iterations,
+        estimated_duration_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_the_ticks_adaptive_runs_make() {
+        let body = |adaptive: bool| -> Body {
+            let bars: Vec<_> = (1..=5)
+                .map(|day| {
+                    serde_json::json!({
+                        "timestamp": format!("2024-01-0{}T00:00:00", day),
+                        "open": 100.0, "high": 101.0, "low": 99.0, "close": 100.0, "volume": 1000
+                    })
+                })
+                .collect();
+            serde_json::from_value(serde_json::json!({
+                "parameters": {
+                    "start_date": "2024-01-01 00:00:00",
+                    "end_date": "2024-01-05 00:00:00",
+                    "tick": "3600s",
+                    "adaptive_ticks": adaptive
+                },
+                "data": { "symbol": "AAPL", "source": bars },
+                "broker": { "cash": 10000 },
+                "strategy": { "asset": "AAPL", "rules": [{ "action": "buy", "when": "close > 1000", "size": "1" }] }
+            }))
+            .unwrap()
+        };
+
+        let fixed = execute(body(false)).unwrap();
+        assert_eq!((fixed.iterations, fixed.calibration_iterations), (97, 97));
+        // One tick per bar, none in between since no order waits to trade
+        let adaptive = execute(body(true)).unwrap();
+        assert_eq!(
+            (adaptive.iterations, adaptive.calibration_iterations),
+            (5, 5)
+        );
+        assert!(adaptive.estimated_memory_bytes < fixed.estimated_memory_bytes);
+    }
+}
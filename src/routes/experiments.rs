@@ -0,0 +1,95 @@
+use super::run::{new_run_id, Response};
+use super::AppState;
+use crate::store::{
+    experiment::{Experiment, ExperimentSummary},
+    RunFilter, RunRecord,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct ExperimentBody {
+    description: Option<String>,
+    #[serde(default)]
+    parameters: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub struct ExperimentReport {
+    #[serde(flatten)]
+    experiment: Experiment,
+    summary: ExperimentSummary,
+    // Member runs, most recent first
+    runs: Vec<RunRecord>,
+}
+
+pub async fn create_experiment(
+    State(state): State<AppState>,
+    Json(payload): Json<ExperimentBody>,
+) -> (StatusCode, Json<Response<Experiment>>) {
+    let experiment = Experiment {
+        id: new_run_id(),
+        created_at: chrono::Utc::now().naive_utc(),
+        description: payload.description,
+        parameters: payload.parameters,
+    };
+
+    match state.store.add_experiment(&experiment).await {
+        Ok(()) => (StatusCode::OK, Json(Response::Success(experiment))),
+        Err(e) => {
+            eprintln!("Failed to add experiment: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to add experiment")),
+            )
+        }
+    }
+}
+
+pub async fn get_experiment(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<Response<ExperimentReport>>) {
+    let experiment = match state.store.experiment(&id).await {
+        Ok(Some(experiment)) => experiment,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(Response::Error("Experiment not found")),
+            )
+        }
+        Err(e) => {
+            eprintln!("Failed to load experiment {}: {}", id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to load the experiment")),
+            );
+        }
+    };
+
+    let filter = RunFilter {
+        experiment: Some(id.clone()),
+        ..Default::default()
+    };
+    match state.store.list(&filter).await {
+        Ok(runs) => (
+            StatusCode::OK,
+            Json(Response::Success(ExperimentReport {
+                experiment,
+                summary: ExperimentSummary::new(&runs),
+                runs,
+            })),
+        ),
+        Err(e) => {
+            eprintln!("Failed to list the runs of experiment {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to load the experiment")),
+            )
+        }
+    }
+}
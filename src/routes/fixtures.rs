@@ -0,0 +1,108 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+
+use super::run::{
+    prepare, Body, BrokerSettings, DataInput, PreparedRun, Response, SimulationParameters,
+    StrategyConfig,
+};
+use super::AppState;
+use crate::analytics::regression::{Fixture, Verification, FIXTURE_VERSION};
+use crate::engine::BacktestResult;
+
+#[derive(Deserialize)]
+pub struct FixtureBody {
+    parameters: SimulationParameters,
+    data: DataInput,
+    broker: BrokerSettings,
+    strategy: StrategyConfig,
+    // Recorded fixture the run is verified against
+    fixture: Option<Fixture>,
+}
+
+impl FixtureBody {
+    async fn load(&mut self, state: &AppState) -> Result<(), (StatusCode, &'static str)> {
+        self.strategy.load(&state.store).await?;
+        let warmup_bars = self.strategy.warmup_bars();
+        self.data
+            .load(&self.parameters, warmup_bars, state.storage.as_deref())
+            .await
+    }
+}
+
+pub async fn record_fixture(
+    State(state): State<AppState>,
+    Json(mut payload): Json<FixtureBody>,
+) -> (StatusCode, Json<Response<Fixture>>) {
+    if let Err((status, error)) = payload.load(&state).await {
+        return (status, Json(Response::Error(error)));
+    }
+    let seed = *payload.broker.seed.get_or_insert_with(rand::random);
+    match execute(payload) {
+        Ok((result, strategy_hash)) => (
+            StatusCode::OK,
+            Json(Response::Success(Fixture {
+                version: FIXTURE_VERSION,
+                strategy_hash,
+                data_hash: result.data_hash,
+                seed,
+                events: result.order_log.unwrap_or_default(),
+            })),
+        ),
+        Err((status, error)) => (status, Json(Response::Error(error))),
+    }
+}
+
+pub async fn verify_fixture(
+    State(state): State<AppState>,
+    Json(mut payload): Json<FixtureBody>,
+) -> (StatusCode, Json<Response<Verification>>) {
+    let Some(fixture) = payload.fixture.take() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(Response::Error("A fixture to verify against is required")),
+        );
+    };
+    if fixture.version != FIXTURE_VERSION {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(Response::Error(
+                "The fixture was recorded by another version, record it again",
+            )),
+        );
+    }
+    if let Err((status, error)) = payload.load(&state).await {
+        return (status, Json(Response::Error(error)));
+    }
+    payload.broker.seed = Some(fixture.seed);
+    match execute(payload) {
+        Ok((result, strategy_hash)) => {
+            let verification = fixture.verify(
+                &strategy_hash,
+                result.data_hash.as_deref(),
+                result.order_log.unwrap_or_default(),
+            );
+            (StatusCode::OK, Json(Response::Success(verification)))
+        }
+        Err((status, error)) => (status, Json(Response::Error(error))),
+    }
+}
+
+// Runs with the order log, a run stopped by an error records the orders before it
+fn execute(payload: FixtureBody) -> Result<(BacktestResult, String), (StatusCode, &'static str)> {
+    let body = Body::new(
+        payload.parameters,
+        payload.data,
+        payload.broker,
+        payload.strategy,
+    );
+    let PreparedRun {
+        mut engine,
+        strategy_hash,
+        ..
+    } = prepare(body)?;
+    engine.set_order_log(true);
+    let result = engine
+        .run()
+        .map_err(|error| (StatusCode::BAD_REQUEST, error))?;
+    Ok((result, strategy_hash))
+}
@@ -0,0 +1,71 @@
+use super::run::Response;
+use super::AppState;
+use crate::live::LiveMetrics;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response as HttpResponse},
+    Json,
+};
+use tokio::sync::watch;
+
+pub async fn get_live_metrics(
+    State(state): State<AppState>,
+    Path(session): Path<String>,
+) -> (StatusCode, Json<Response<LiveMetrics>>) {
+    match state.live.subscribe(&session) {
+        Some(metrics) => {
+            let metrics = metrics.borrow().clone();
+            (StatusCode::OK, Json(Response::Success(metrics)))
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(Response::Error("Session not found")),
+        ),
+    }
+}
+
+// Streams the metrics of a session on every tick until it ends
+pub async fn live_metrics_ws(
+    State(state): State<AppState>,
+    Path(session): Path<String>,
+    ws: WebSocketUpgrade,
+) -> HttpResponse {
+    match state.live.subscribe(&session) {
+        Some(metrics) => ws.on_upgrade(move |socket| stream_metrics(socket, metrics)),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(Response::<()>::Error("Session not found")),
+        )
+            .into_response(),
+    }
+}
+
+async fn stream_metrics(mut socket: WebSocket, mut metrics: watch::Receiver<LiveMetrics>) {
+    'session: loop {
+        let text = match serde_json::to_string(&*metrics.borrow_and_update()) {
+            Ok(text) => text,
+            Err(_) => return,
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            return;
+        }
+
+        loop {
+            tokio::select! {
+                changed = metrics.changed() => match changed {
+                    Ok(_) => break,
+                    Err(_) => break 'session,
+                },
+                message = socket.recv() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    _ => {}
+                },
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
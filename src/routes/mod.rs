@@ -1 +1,34 @@
+pub mod aggregate;
+pub mod cors;
+pub mod dashboard;
+pub mod ensemble;
+pub mod estimate;
+pub mod experiments;
+pub mod fixtures;
+pub mod live;
+pub mod optimize;
+pub mod pipeline;
+pub mod replay;
 pub mod run;
+pub mod runs;
+pub mod simulate;
+pub mod strategies;
+pub mod sweep;
+pub mod tournament;
+pub mod validation;
+
+use crate::analytics::plugin::MetricRegistry;
+use crate::live::LiveSessions;
+use crate::storage::Storage;
+use crate::store::RunStore;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub storage: Option<Arc<Storage>>,
+    pub store: Arc<RunStore>,
+    // Running paper sessions, opened through `/replay`
+    pub live: Arc<LiveSessions>,
+    // Metric plugins the stored runs are recomputed with
+    pub metric_plugins: Arc<MetricRegistry>,
+}
@@ -0,0 +1,160 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::aggregate::{select_metrics, DEFAULT_METRICS};
+use super::run::{
+    prepare, run_all, BatchError, Body, BrokerSettings, DataInput, Response, SimulationParameters,
+    StrategyConfig,
+};
+use super::tournament::LOWER_IS_BETTER;
+use super::AppState;
+
+const MAX_RUNS: usize = 200;
+
+#[derive(Deserialize)]
+pub struct OptimizeBody {
+    parameters: SimulationParameters,
+    data: DataInput,
+    broker: BrokerSettings,
+    // Parameters given in `strategy.parameters` are held, the others cover their manifest range
+    strategy: StrategyConfig,
+    // Metrics reported for each parameter set, the first one ranks them
+    metrics: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct OptimizeResult {
+    seed: u64,
+    rank_by: String,
+    runs: Vec<Trial>,
+}
+
+#[derive(Serialize)]
+struct Trial {
+    rank: usize,
+    parameters: BTreeMap<String, f64>,
+    metrics: serde_json::Map<String, serde_json::Value>,
+}
+
+pub async fn optimize(
+    State(state): State<AppState>,
+    Json(mut payload): Json<OptimizeBody>,
+) -> (StatusCode, Json<Response<OptimizeResult>>) {
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        return (status, Json(Response::Error(error)));
+    }
+    let warmup_bars = payload.strategy.warmup_bars();
+    if let Err((status, error)) = payload
+        .data
+        .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        return (status, Json(Response::Error(error)));
+    }
+    match execute(payload).await {
+        Ok(result) => (StatusCode::OK, Json(Response::Success(result))),
+        Err(error) => error.respond(),
+    }
+}
+
+async fn execute(payload: OptimizeBody) -> Result<OptimizeResult, BatchError> {
+    let manifest = match payload.strategy.manifest() {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The strategy manifest declares no parameters to optimize",
+            )
+                .into())
+        }
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid strategy manifest").into()),
+    };
+
+    let metrics: Vec<String> = match payload.metrics {
+        Some(metrics) if !metrics.is_empty() => metrics,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "At least one metric is required").into()),
+        None => DEFAULT_METRICS.iter().map(|m| m.to_string()).collect(),
+    };
+
+    // Cartesian product of the parameter grids
+    let held = payload.strategy.parameters.clone().unwrap_or_default();
+    let mut grid = vec![HashMap::new()];
+    for parameter in &manifest.parameters {
+        let values = match held.get(&parameter.name) {
+            Some(value) => vec![*value],
+            None => parameter.grid(),
+        };
+        if grid.len() * values.len() > MAX_RUNS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The parameter grid holds more than 200 runs",
+            )
+                .into());
+        }
+        grid = grid
+            .into_iter()
+            .flat_map(|set| {
+                values.iter().map(move |value| {
+                    let mut set = set.clone();
+                    set.insert(parameter.name.clone(), *value);
+                    set
+                })
+            })
+            .collect();
+    }
+
+    // Every parameter set gets the same slippage draws
+    let mut broker = payload.broker;
+    let seed = *broker.seed.get_or_insert_with(rand::random);
+
+    let mut sets = vec![];
+    let mut prepared = vec![];
+    for set in grid {
+        let mut strategy = payload.strategy.clone();
+        strategy.parameters = Some(set.clone());
+        let body = Body::new(
+            payload.parameters.clone(),
+            payload.data.clone(),
+            broker.clone(),
+            strategy,
+        );
+        sets.push(set);
+        prepared.push(prepare(body)?);
+    }
+
+    let results = sets
+        .into_iter()
+        .zip(run_all(prepared, |_, result| result).await?);
+
+    let mut values = BTreeMap::new();
+    let mut trials = vec![];
+    for (set, result) in results {
+        trials.push(Trial {
+            rank: 0,
+            parameters: set.into_iter().collect(),
+            metrics: select_metrics(&result.metrics, &metrics, &mut values)?,
+        });
+    }
+
+    let rank_by = metrics[0].clone();
+    let descending = !LOWER_IS_BETTER.contains(&rank_by.as_str());
+    let score = |trial: &Trial| trial.metrics[&rank_by].as_f64().unwrap_or(f64::NAN);
+    trials.sort_by(|a, b| {
+        let ordering = score(a).total_cmp(&score(b));
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    for (i, trial) in trials.iter_mut().enumerate() {
+        trial.rank = i + 1;
+    }
+
+    Ok(OptimizeResult {
+        seed,
+        rank_by,
+        runs: trials,
+    })
+}
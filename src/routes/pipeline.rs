@@ -0,0 +1,469 @@
+use crate::analytics::{
+    cross_section::{self, Distribution},
+    screen::{self, ScreenCriteria, ScreenStats},
+};
+use crate::data;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::aggregate::{select_metrics, DEFAULT_METRICS};
+use super::run::{
+    new_run_id, prepare, run_all, BatchError, Body, BrokerSettings, DataInput, Response,
+    SimulationParameters, StrategyConfig,
+};
+use super::tournament::LOWER_IS_BETTER;
+use super::AppState;
+
+const MAX_DATASETS: usize = 200;
+
+pub fn pipeline_key(id: &str) -> String {
+    format!("pipelines/{}.json", id)
+}
+
+#[derive(Deserialize)]
+pub struct PipelineBody {
+    name: Option<String>,
+    parameters: SimulationParameters,
+    broker: BrokerSettings,
+    strategy: StrategyConfig,
+    // Run in order, each stage takes the artifacts of the previous ones
+    stages: Vec<Stage>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "stage", rename_all = "lowercase")]
+enum Stage {
+    // Loads the datasets from their provider, snapshot or bars, as `data` of `/run`
+    Fetch {
+        datasets: Vec<DataInput>,
+    },
+    // Keeps the datasets passing the criteria
+    Screen(ScreenCriteria),
+    // Backtests the strategy on every dataset left
+    Backtest {
+        metrics: Option<Vec<String>>,
+    },
+    // Ranks the backtests by a metric, the first one of the backtest by default
+    Report {
+        rank_by: Option<String>,
+        top: Option<usize>,
+    },
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Fetch { .. } => "fetch",
+            Stage::Screen(_) => "screen",
+            Stage::Backtest { .. } => "backtest",
+            Stage::Report { .. } => "report",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PipelineResult {
+    id: String,
+    name: Option<String>,
+    // Seed of the slippage draws of every backtest
+    seed: u64,
+    // Artifacts each stage took and produced, datasets are identified by their content hash
+    lineage: Vec<StageLineage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    screen: Option<Vec<ScreenStats>>,
+    runs: Vec<PipelineRun>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<PipelineReport>,
+}
+
+#[derive(Serialize)]
+struct StageLineage {
+    stage: &'static str,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    elapsed_ms: u128,
+}
+
+#[derive(Serialize)]
+struct PipelineRun {
+    artifact: String,
+    symbol: String,
+    metrics: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct PipelineReport {
+    rank_by: String,
+    ranking: Vec<RankedRun>,
+    statistics: BTreeMap<String, Option<Distribution>>,
+}
+
+#[derive(Serialize)]
+struct RankedRun {
+    rank: usize,
+    symbol: String,
+    value: Option<f64>,
+}
+
+struct Dataset {
+    artifact: String,
+    symbol: String,
+    data: DataInput,
+}
+
+// Artifacts are named by their kind, symbol and the start of a hash
+fn artifact(kind: &str, symbol: &str, hashes: &[&str]) -> String {
+    let hashes: Vec<&str> = hashes
+        .iter()
+        .map(|hash| &hash[..hash.len().min(12)])
+        .collect();
+    format!("{}:{}:{}", kind, symbol, hashes.join(":"))
+}
+
+// Drops the provider keys of the document, stored documents are served to anyone
+fn redact_credentials(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.remove("api_key");
+            fields.values_mut().for_each(redact_credentials);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_credentials),
+        _ => {}
+    }
+}
+
+pub async fn pipeline(
+    State(state): State<AppState>,
+    Json(document): Json<serde_json::Value>,
+) -> (StatusCode, Json<Response<PipelineResult>>) {
+    let payload: PipelineBody = match serde_json::from_value(document.clone()) {
+        Ok(payload) => payload,
+        Err(e) => {
+            eprintln!("Invalid pipeline document: {}", e);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(Response::Error("Invalid pipeline document")),
+            );
+        }
+    };
+    let result = match execute(&state, payload).await {
+        Ok(result) => result,
+        Err(error) => return error.respond(),
+    };
+
+    // The document and the lineage reproduce the study, fetched data is kept as snapshots
+    if let Some(storage) = &state.storage {
+        let mut document = document;
+        redact_credentials(&mut document);
+        let stored = serde_json::json!({ "document": document, "result": &result });
+        if let Err(e) = storage
+            .put(&pipeline_key(&result.id), stored.to_string().into_bytes())
+            .await
+        {
+            eprintln!("Failed to store pipeline {}: {}", result.id, e);
+        }
+    }
+    (StatusCode::OK, Json(Response::Success(result)))
+}
+
+async fn execute(
+    state: &AppState,
+    mut payload: PipelineBody,
+) -> Result<PipelineResult, BatchError> {
+    if payload.stages.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A pipeline needs at least one stage",
+        )
+            .into());
+    }
+    payload.strategy.load(&state.store).await?;
+    let warmup_bars = payload.strategy.warmup_bars();
+    let parse = |date: &str| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S");
+    let (Ok(start), Ok(end)) = (
+        parse(&payload.parameters.start_date),
+        parse(&payload.parameters.end_date),
+    ) else {
+        return Err((StatusCode::BAD_REQUEST, "Invalid simulation dates").into());
+    };
+    let seed = *payload.broker.seed.get_or_insert_with(rand::random);
+
+    let mut datasets: Vec<Dataset> = vec![];
+    let mut screened = None;
+    let mut runs: Option<Vec<PipelineRun>> = None;
+    let mut report = None;
+    let mut lineage = vec![];
+
+    for stage in std::mem::take(&mut payload.stages) {
+        let timer = std::time::Instant::now();
+        let name = stage.name();
+        let (inputs, outputs) = match stage {
+            Stage::Fetch { datasets: inputs } => {
+                if datasets.len() + inputs.len() > MAX_DATASETS {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "A pipeline takes up to 200 datasets",
+                    )
+                        .into());
+                }
+                let mut outputs = vec![];
+                for mut data in inputs {
+                    data.load(&payload.parameters, warmup_bars, state.storage.as_deref())
+                        .await?;
+                    let symbol = data
+                        .symbol
+                        .clone()
+                        .unwrap_or_else(|| format!("dataset_{}", datasets.len() + 1));
+                    let (hash, _) = data::snapshot(&data.source);
+                    let dataset = Dataset {
+                        artifact: artifact("data", &symbol, &[&hash]),
+                        symbol,
+                        data,
+                    };
+                    outputs.push(dataset.artifact.clone());
+                    datasets.push(dataset);
+                }
+                (vec![], outputs)
+            }
+            Stage::Screen(criteria) => {
+                if datasets.is_empty() {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "The screen stage needs the datasets of a fetch stage",
+                    )
+                        .into());
+                }
+                let inputs = datasets.iter().map(|d| d.artifact.clone()).collect();
+                let mut stats: Vec<ScreenStats> = datasets
+                    .iter()
+                    .map(|d| ScreenStats::new(d.symbol.clone(), &d.data.source, (start, end)))
+                    .collect();
+                screen::screen(&criteria, &mut stats);
+                let mut selected = stats.iter().map(|stats| stats.selected);
+                datasets.retain(|_| selected.next().unwrap_or(false));
+                screened = Some(stats);
+                (
+                    inputs,
+                    datasets.iter().map(|d| d.artifact.clone()).collect(),
+                )
+            }
+            Stage::Backtest { metrics } => {
+                if datasets.is_empty() {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "The backtest stage needs datasets, none were fetched or all were screened out",
+                    ).into());
+                }
+                let metrics: Vec<String> = match metrics {
+                    Some(metrics) if !metrics.is_empty() => metrics,
+                    Some(_) => {
+                        return Err(
+                            (StatusCode::BAD_REQUEST, "At least one metric is required").into()
+                        )
+                    }
+                    None => DEFAULT_METRICS.iter().map(|m| m.to_string()).collect(),
+                };
+                let backtests = backtest(&payload, &datasets, &metrics).await?;
+                let inputs = datasets.iter().map(|d| d.artifact.clone()).collect();
+                let outputs = backtests.iter().map(|run| run.artifact.clone()).collect();
+                runs = Some(backtests);
+                (inputs, outputs)
+            }
+            Stage::Report { rank_by, top } => {
+                let Some(runs) = &runs else {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "The report stage needs the runs of a backtest stage",
+                    )
+                        .into());
+                };
+                let pipeline_report = rank(runs, rank_by, top)?;
+                let inputs = runs.iter().map(|run| run.artifact.clone()).collect();
+                let outputs = vec![format!("report:{}", pipeline_report.rank_by)];
+                report = Some(pipeline_report);
+                (inputs, outputs)
+            }
+        };
+        lineage.push(StageLineage {
+            stage: name,
+            inputs,
+            outputs,
+            elapsed_ms: timer.elapsed().as_millis(),
+        });
+    }
+
+    Ok(PipelineResult {
+        id: new_run_id(),
+        name: payload.name,
+        seed,
+        lineage,
+        screen: screened,
+        runs: runs.unwrap_or_default(),
+        report,
+    })
+}
+
+async fn backtest(
+    payload: &PipelineBody,
+    datasets: &[Dataset],
+    metrics: &[String],
+) -> Result<Vec<PipelineRun>, BatchError> {
+    let mut prepared = vec![];
+    for dataset in datasets {
+        let body = Body::new(
+            payload.parameters.clone(),
+            dataset.data.clone(),
+            payload.broker.clone(),
+            payload.strategy.clone(),
+        );
+        prepared.push(prepare(body)?);
+    }
+
+    let results = run_all(prepared, |run, result| (run.strategy_hash, result)).await?;
+
+    let mut values = BTreeMap::new();
+    datasets
+        .iter()
+        .zip(results)
+        .map(|(dataset, (strategy_hash, result))| {
+            let data_hash = result.data_hash.clone().unwrap_or_default();
+            Ok(PipelineRun {
+                artifact: artifact("run", &dataset.symbol, &[&strategy_hash, &data_hash]),
+                symbol: dataset.symbol.clone(),
+                metrics: select_metrics(&result.metrics, metrics, &mut values)?,
+            })
+        })
+        .collect()
+}
+
+fn rank(
+    runs: &[PipelineRun],
+    rank_by: Option<String>,
+    top: Option<usize>,
+) -> Result<PipelineReport, (StatusCode, &'static str)> {
+    let rank_by = match rank_by {
+        Some(metric) => metric,
+        None => runs
+            .first()
+            .and_then(|run| run.metrics.keys().next().cloned())
+            .unwrap_or_default(),
+    };
+    if runs.iter().any(|run| !run.metrics.contains_key(&rank_by)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The report ranks by a metric of the backtest stage",
+        ));
+    }
+
+    let value = |run: &PipelineRun| run.metrics[&rank_by].as_f64();
+    let descending = !LOWER_IS_BETTER.contains(&rank_by.as_str());
+    let mut ranked: Vec<&PipelineRun> = runs.iter().collect();
+    ranked.sort_by(|a, b| {
+        let ordering = value(a)
+            .unwrap_or(f64::NAN)
+            .total_cmp(&value(b).unwrap_or(f64::NAN));
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    let mut statistics: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for run in runs {
+        for (metric, value) in &run.metrics {
+            if let Some(value) = value.as_f64() {
+                statistics.entry(metric.clone()).or_default().push(value);
+            }
+        }
+    }
+
+    Ok(PipelineReport {
+        ranking: ranked
+            .into_iter()
+            .take(top.unwrap_or(usize::MAX))
+            .enumerate()
+            .map(|(i, run)| RankedRun {
+                rank: i + 1,
+                symbol: run.symbol.clone(),
+                value: value(run),
+            })
+            .collect(),
+        rank_by,
+        statistics: statistics
+            .into_iter()
+            .map(|(metric, values)| (metric, cross_section::distribution(&values)))
+            .collect(),
+    })
+}
+
+// Stored document and result of a pipeline
+pub async fn get_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<Response<serde_json::Value>>) {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(Response::Error("Invalid pipeline id")),
+        );
+    }
+    let Some(storage) = &state.storage else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(Response::Error(
+                "Pipelines are only kept with a storage backend",
+            )),
+        );
+    };
+    match storage.get(&pipeline_key(&id)).await {
+        Ok(Some(bytes)) => match serde_json::from_slice(&bytes) {
+            Ok(stored) => (StatusCode::OK, Json(Response::Success(stored))),
+            Err(e) => {
+                eprintln!("Failed to parse pipeline {}: {}", id, e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(Response::Error("Failed to read the pipeline")),
+                )
+            }
+        },
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(Response::Error("Pipeline not found")),
+        ),
+        Err(e) => {
+            eprintln!("Failed to read pipeline {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to read the pipeline")),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_documents_leave_the_provider_keys_out() {
+        let mut document = serde_json::json!({
+            "datasets": [{ "provider": { "name": "polygon", "ticker": "AAPL", "api_key": "secret" } }],
+            "backfill": { "provider": { "name": "polygon", "api_key": "secret" } },
+        });
+        redact_credentials(&mut document);
+        assert_eq!(
+            document,
+            serde_json::json!({
+                "datasets": [{ "provider": { "name": "polygon", "ticker": "AAPL" } }],
+                "backfill": { "provider": { "name": "polygon" } },
+            })
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use super::run::{new_run_id, prepare, Body, PreparedRun};
+use super::AppState;
+use crate::alerts::{self, AlertMonitor, AlertRule};
+use crate::data::OHLCVData;
+use crate::engine::{BacktestResult, Engine};
+use crate::live::{LiveMetrics, LiveOrder, LivePosition, LiveSessions};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response as HttpResponse,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+// A `/run` body, with the alerts raised during the session
+#[derive(Deserialize)]
+struct ReplayBody {
+    #[serde(flatten)]
+    run: Body,
+    #[serde(default)]
+    alerts: Vec<AlertRule>,
+    // Ticks between two snapshots of the positions and open orders, 0 for none
+    book_interval: Option<u64>,
+}
+
+// Ticks between two book snapshots when not set
+const DEFAULT_BOOK_INTERVAL: u64 = 50;
+
+// Bounds of the speed, the pause between two ticks must stay a valid duration
+const SPEEDS: std::ops::RangeInclusive<f64> = 0.01..=10000.0;
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Command {
+    // Simulated time elapsed per wall clock second
+    Speed { value: f64 },
+    Pause,
+    Resume,
+    // Advance a single tick while paused
+    Step,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Event<'a> {
+    // `session` identifies the replay on the `/live` endpoints
+    Ready {
+        session: String,
+    },
+    Tick {
+        time: NaiveDateTime,
+        candle: Option<&'a OHLCVData>,
+        cash: f64,
+        equity: f64,
+        open_orders: usize,
+    },
+    // What the strategy holds and has pending, every `book_interval` ticks
+    Book {
+        time: NaiveDateTime,
+        positions: &'a [LivePosition],
+        open_orders: &'a [LiveOrder],
+    },
+    Done {
+        result: Box<BacktestResult>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+// Replay a recorded session through the strategy, paced like live data
+pub async fn replay(State(state): State<AppState>, ws: WebSocketUpgrade) -> HttpResponse {
+    ws.on_upgrade(move |socket| handle_session(socket, state))
+}
+
+// Unregisters the session however the replay ends
+struct Registration {
+    live: Arc<LiveSessions>,
+    session: String,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.live.remove(&self.session);
+    }
+}
+
+async fn send(socket: &mut WebSocket, event: &Event<'_>) -> bool {
+    match serde_json::to_string(event) {
+        Ok(text) => socket.send(Message::Text(text.into())).await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+async fn send_error(socket: &mut WebSocket, message: &str) {
+    send(
+        socket,
+        &Event::Error {
+            message: message.to_string(),
+        },
+    )
+    .await;
+}
+
+// Counts the ticks between two book snapshots
+struct BookSnapshots {
+    interval: u64,
+    ticks: u64,
+}
+
+impl BookSnapshots {
+    fn due(&mut self) -> bool {
+        self.ticks += 1;
+        self.interval > 0 && self.ticks.is_multiple_of(self.interval)
+    }
+}
+
+// Simulates the next tick, returning its time. Adaptive ticks don't step by the tick, so it is
+// read before the clock moves on
+fn step(engine: &mut Engine) -> Option<NaiveDateTime> {
+    let time = engine.current_time;
+    engine.step().then_some(time)
+}
+
+// Simulate the next tick and publish it, returns false once the replay is over
+async fn advance(
+    engine: &mut Engine,
+    socket: &mut WebSocket,
+    metrics: &watch::Sender<LiveMetrics>,
+    monitor: &mut AlertMonitor,
+    book: &mut BookSnapshots,
+) -> bool {
+    let Some(time) = step(engine) else {
+        return false;
+    };
+
+    let next = metrics.borrow().next(engine);
+    if !monitor.is_empty() {
+        let error = engine.failure().map(str::to_string);
+        for (notifier, alert) in monitor.check(&next, &engine.broker.fills, error) {
+            alerts::notifier::notify(notifier, alert);
+        }
+    }
+    let book = book
+        .due()
+        .then(|| (next.positions.clone(), next.open_orders.clone()));
+    metrics.send_replace(next);
+
+    let candle = engine.current_candle();
+    let portfolio_value = candle
+        .map(|c| engine.broker.portfolio_value(c))
+        .unwrap_or(0.0);
+    let event = Event::Tick {
+        time,
+        candle,
+        cash: engine.broker.cash,
+        equity: engine.broker.cash + portfolio_value,
+        open_orders: engine.broker.orders.len(),
+    };
+    if !send(socket, &event).await {
+        return false;
+    }
+
+    match book {
+        Some((positions, open_orders)) => {
+            let event = Event::Book {
+                time,
+                positions: &positions,
+                open_orders: &open_orders,
+            };
+            send(socket, &event).await
+        }
+        None => true,
+    }
+}
+
+async fn handle_session(mut socket: WebSocket, state: AppState) {
+    // The first message configures the session exactly like a /run request
+    let mut payload = match socket.recv().await {
+        Some(Ok(Message::Text(text))) => match serde_json::from_str::<ReplayBody>(text.as_str()) {
+            Ok(payload) => payload,
+            Err(e) => return send_error(&mut socket, &e.to_string()).await,
+        },
+        _ => return,
+    };
+
+    if let Err(e) = alerts::validate(&payload.alerts) {
+        return send_error(&mut socket, e).await;
+    }
+    let mut monitor = AlertMonitor::new(payload.alerts);
+    let mut book = BookSnapshots {
+        interval: payload.book_interval.unwrap_or(DEFAULT_BOOK_INTERVAL),
+        ticks: 0,
+    };
+
+    let run = &mut payload.run;
+    if let Err((_, e)) = run.strategy.load(&state.store).await {
+        return send_error(&mut socket, e).await;
+    }
+    let warmup_bars = run.strategy.warmup_bars();
+    if let Err((_, e)) = run
+        .data
+        .load(&run.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        return send_error(&mut socket, e).await;
+    }
+
+    let PreparedRun { mut engine, .. } = match prepare(payload.run) {
+        Ok(prepared) => prepared,
+        Err((_, e)) => return send_error(&mut socket, e).await,
+    };
+
+    if let Err(e) = engine.start() {
+        return send_error(&mut socket, e).await;
+    }
+
+    let session = new_run_id();
+    let metrics = state.live.register(LiveMetrics::start(&session, &engine));
+    let _registration = Registration {
+        live: state.live,
+        session: session.clone(),
+    };
+    if !send(&mut socket, &Event::Ready { session }).await {
+        return;
+    }
+
+    let tick = engine.tick.to_std().unwrap_or_default();
+    let mut speed = 1.0;
+    let mut playing = true;
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Command>(text.as_str()) {
+                    Ok(Command::Speed { value }) if SPEEDS.contains(&value) => speed = value,
+                    Ok(Command::Speed { .. }) => send_error(&mut socket, "The speed is between 0.01 and 10000").await,
+                    Ok(Command::Pause) => playing = false,
+                    Ok(Command::Resume) => playing = true,
+                    Ok(Command::Step) => {
+                        if !playing && !advance(&mut engine, &mut socket, &metrics, &mut monitor, &mut book).await {
+                            break;
+                        }
+                    }
+                    _ => send_error(&mut socket, "Invalid command").await,
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                _ => {}
+            },
+            _ = tokio::time::sleep(tick.div_f64(speed)), if playing => {
+                if !advance(&mut engine, &mut socket, &metrics, &mut monitor, &mut book).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    metrics.send_modify(|metrics| metrics.running = false);
+    let result = Box::new(engine.finish());
+    send(&mut socket, &Event::Done { result }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::rules::{OrderAction, RuleSpec, RuleStrategy};
+    use chrono::Duration;
+
+    #[test]
+    fn ticks_are_timed_by_the_simulated_clock() {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let times = |adaptive: bool| {
+            let strategy = RuleStrategy::new(
+                "AAPL".to_string(),
+                &[RuleSpec {
+                    action: OrderAction::Buy,
+                    when: "close > 1000".to_string(),
+                    size: "1".to_string(),
+                    execution: None,
+                }],
+            )
+            .unwrap();
+            let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(3)));
+            engine.set_tick(Duration::hours(1));
+            engine.set_adaptive_ticks(adaptive);
+            engine.add_data(
+                (0..4)
+                    .map(|days| OHLCVData {
+                        timestamp: start + Duration::days(days),
+                        open: 10.0,
+                        high: 10.0,
+                        low: 10.0,
+                        close: 10.0,
+                        volume: 100,
+                    })
+                    .collect(),
+            );
+            engine.start().unwrap();
+            std::iter::from_fn(|| step(&mut engine)).collect::<Vec<_>>()
+        };
+
+        // Without orders to trade, the adaptive ticks land on the bars
+        let bars: Vec<_> = (0..4).map(|days| start + Duration::days(days)).collect();
+        assert_eq!(times(true), bars);
+        let fixed = times(false);
+        assert_eq!(fixed.len(), 73);
+        assert_eq!(fixed[1], start + Duration::hours(1));
+        assert_eq!(fixed.last(), Some(&(start + Duration::days(3))));
+    }
+}
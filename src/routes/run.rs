@@ -1,47 +1,546 @@
-use crate::broker::{fee::FeeType, Broker};
-use crate::data::OHLCVData;
-use crate::engine::{BacktestResult, Engine};
-use crate::strategy::wasm::WasmStrategy;
-use axum::{http::StatusCode, Json};
+use crate::analytics::inflation::Inflation;
+use crate::analytics::seasonality::SeasonalitySettings;
+use crate::analytics::slice::{self, Slice};
+use crate::broker::{
+    contract::Contract,
+    exposure::{ExposureBand, ExposureMonitor},
+    fee::{FeeCurrency, FeeType},
+    flatten::{DailyFlatten, Flatten},
+    hedge::HedgeBook,
+    limits::PositionLimits,
+    margin::MarginAccount,
+    order::{CashShortfall, FillModel},
+    overlay::{BetaHedge, BetaHedgeSettings},
+    preset::BrokerPreset,
+    queue::QueueModel,
+    sweep::CashSweep,
+    Broker,
+};
+use crate::calendar::{Calendar, Session, SessionSpec};
+use crate::data::{
+    self,
+    clean::{Cleaning, CleaningLog},
+    quality::{DataQuality, DEFAULT_OUTLIER_Z_SCORE},
+    Conflict, DataSources, Duplicates, MarketData, MissingData, OHLCVData,
+};
+use crate::engine::{
+    response::ResponseField, BacktestResult, Engine, GapPolicy, RunFailure, RunMode,
+};
+use crate::instrument::Instrument;
+use crate::provider::{PriceAdjustment, PriceBasis, Provider, ProviderError};
+use crate::storage::Storage;
+use crate::store::{RunRecord, RunStore};
+use crate::strategy::{
+    ensemble::{EnsembleSettings, EnsembleStrategy},
+    manifest::{self, Manifest},
+    multi::MultiStrategy,
+    rules::{RuleSpec, RuleStrategy},
+    wasm::{Capabilities, WasmStrategy},
+    Strategy,
+};
+use crate::webhook::{self, RunStatus, RunSummary};
+use axum::{extract::State, http::StatusCode, Json};
 use chrono::{Duration, NaiveDateTime};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use super::validation::{self, FieldError};
+use super::{runs::artifact_key, AppState};
 
 #[derive(Deserialize)]
 pub struct Body {
-    parameters: SimulationParameters,
-    data: DataInput,
-    broker: BrokerSettings,
-    strategy: StrategyConfig,
+    pub(super) parameters: SimulationParameters,
+    pub(super) data: DataInput,
+    pub(super) broker: BrokerSettings,
+    pub(super) strategy: StrategyConfig,
+    callback_url: Option<String>,
+    // Experiment of `POST /experiments` the run is grouped under
+    experiment_id: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct DataInput {
+#[derive(Deserialize, Clone)]
+pub(super) struct DataInput {
+    pub(super) symbol: Option<String>,
+    session: Option<SessionSpec>,
+    // Product traded instead of the source itself (e.g. a leveraged ETF of it)
+    instrument: Option<Instrument>,
+    #[serde(default)]
+    pub(super) source: Vec<OHLCVData>,
+    // Fetch the bars from a market data provider instead of `source`
+    provider: Option<Provider>,
+    // `adjusted` or `raw` prices fetched from the providers, or what `source` holds
+    prices: Option<PriceAdjustment>,
+    // Content hash of a stored snapshot to run on instead of `source` or `provider`
+    snapshot: Option<String>,
+    // Secondary series filling the bars missing from the primary one
+    backfill: Option<Backfill>,
+    // Bars of other assets by symbol, read by WASM strategies with `get_bar`
+    #[serde(default)]
+    assets: HashMap<String, Vec<OHLCVData>>,
+    // Filters of bad prints applied to every series before the run
+    cleaning: Option<Cleaning>,
+    // Fetch from the providers again instead of reading the data cache
+    #[serde(default)]
+    refresh: bool,
+    #[serde(skip)]
+    sources: Option<DataSources>,
+    #[serde(skip)]
+    price_basis: Option<PriceBasis>,
+}
+
+#[derive(Deserialize, Clone)]
+struct Backfill {
+    #[serde(default)]
     source: Vec<OHLCVData>,
+    provider: Option<Provider>,
+    #[serde(default)]
+    conflict: Conflict,
 }
 
-#[derive(Deserialize)]
-struct StrategyConfig {
-    wasm: String,
+pub(super) fn snapshot_key(hash: &str) -> String {
+    format!("snapshots/{}.json", hash)
 }
 
-#[derive(Deserialize)]
-struct SimulationParameters {
-    start_date: String,
-    end_date: String,
-    tick: Option<String>,
+fn cache_key(request: &str) -> String {
+    format!("cache/{}.json", hex::encode(Sha256::digest(request)))
 }
 
-#[derive(Deserialize)]
-struct BrokerSettings {
+impl DataInput {
+    // Fill `source` from a snapshot or the providers and merge the backfill series. Providers are
+    // fetched from `warmup_bars` bars before the start, or read from the data cache. Fetched data
+    // is stored as a snapshot.
+    // Dates that don't parse are left to the validation
+    pub(super) async fn load(
+        &mut self,
+        parameters: &SimulationParameters,
+        warmup_bars: usize,
+        storage: Option<&Storage>,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        if let Some(hash) = self.snapshot.take() {
+            if !self.source.is_empty() || self.provider.is_some() || self.backfill.is_some() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "A data snapshot replaces data.source, data.provider and data.backfill",
+                ));
+            }
+            self.source = load_snapshot(&hash, storage).await?;
+            return Ok(());
+        }
+
+        if self.provider.is_none() && self.backfill.is_none() {
+            return Ok(());
+        }
+        let parse = |date: &str| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S");
+        let (Ok(start), Ok(end)) = (parse(&parameters.start_date), parse(&parameters.end_date))
+        else {
+            return Ok(());
+        };
+
+        let mut fetched = false;
+        let mut basis = PriceBasis::new(self.prices);
+        if let Some(provider) = self.provider.take() {
+            if !self.source.is_empty() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Either data.source or data.provider is expected, not both",
+                ));
+            }
+            let adjustment = provider
+                .adjustment(self.prices)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            basis = PriceBasis::new(Some(adjustment));
+            basis.provider = Some(provider.name());
+            let range = (provider.warmup_start(start, warmup_bars), end);
+            self.source = fetch(
+                &provider,
+                self.symbol.as_deref(),
+                range,
+                adjustment,
+                storage,
+                self.refresh,
+            )
+            .await?;
+            fetched = true;
+        }
+
+        if let Some(backfill) = self.backfill.take() {
+            let secondary = match backfill.provider {
+                Some(_) if !backfill.source.is_empty() => {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "Either data.backfill.source or data.backfill.provider is expected, not both",
+                    ));
+                }
+                Some(provider) => {
+                    fetched = true;
+                    let adjustment = provider
+                        .adjustment(self.prices)
+                        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+                    if basis
+                        .adjustment
+                        .is_some_and(|primary| primary != adjustment)
+                    {
+                        basis.warnings.push(
+                            "The backfill series doesn't have the price adjustment of the primary one",
+                        );
+                    }
+                    let range = (provider.warmup_start(start, warmup_bars), end);
+                    fetch(
+                        &provider,
+                        self.symbol.as_deref(),
+                        range,
+                        adjustment,
+                        storage,
+                        self.refresh,
+                    )
+                    .await?
+                }
+                None => backfill.source,
+            };
+            let primary = std::mem::take(&mut self.source);
+            let (merged, sources) = data::merge(primary, secondary, backfill.conflict);
+            self.source = merged;
+            self.sources = Some(sources);
+        }
+        self.price_basis = Some(basis);
+
+        // Providers may revise their history, keep what this run saw
+        if let (true, Some(storage)) = (fetched, storage) {
+            let (hash, bytes) = data::snapshot(&self.source);
+            if let Err(e) = storage.put(&snapshot_key(&hash), bytes).await {
+                eprintln!("Failed to store data snapshot {}: {}", hash, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn load_snapshot(
+    hash: &str,
+    storage: Option<&Storage>,
+) -> Result<Vec<OHLCVData>, (StatusCode, &'static str)> {
+    let Some(storage) = storage else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Data snapshots need a storage backend",
+        ));
+    };
+    // The hash is part of a storage key
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid data snapshot hash"));
+    }
+
+    let bytes = match storage.get(&snapshot_key(&hash.to_lowercase())).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Data snapshot not found")),
+        Err(e) => {
+            eprintln!("Failed to read data snapshot {}: {}", hash, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read the data snapshot",
+            ));
+        }
+    };
+    let source: Vec<OHLCVData> = serde_json::from_slice(&bytes).map_err(|e| {
+        eprintln!("Failed to parse data snapshot {}: {}", hash, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read the data snapshot",
+        )
+    })?;
+    if !data::snapshot(&source).0.eq_ignore_ascii_case(hash) {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "The data snapshot doesn't match its hash",
+        ));
+    }
+    Ok(source)
+}
+
+// Bars of the provider, read from the data cache of the storage when the same request was
+// fetched before. Only ranges ending before today are cached, the last bars may still change
+async fn fetch(
+    provider: &Provider,
+    symbol: Option<&str>,
+    range: (NaiveDateTime, NaiveDateTime),
+    adjustment: PriceAdjustment,
+    storage: Option<&Storage>,
+    refresh: bool,
+) -> Result<Vec<OHLCVData>, (StatusCode, &'static str)> {
+    let today = chrono::Utc::now().date_naive();
+    let cache = storage.filter(|_| range.1.date() < today).map(|storage| {
+        (
+            storage,
+            cache_key(&provider.request(symbol, range, adjustment)),
+        )
+    });
+    if let (Some((storage, key)), false) = (&cache, refresh) {
+        match storage.get(key).await {
+            Ok(Some(bytes)) => match serde_json::from_slice(&bytes) {
+                Ok(bars) => return Ok(bars),
+                Err(e) => eprintln!("Failed to parse cached data {}: {}", key, e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to read cached data {}: {}", key, e),
+        }
+    }
+
+    let bars = provider
+        .fetch(symbol, range, adjustment)
+        .await
+        .map_err(|error| match error {
+            ProviderError::MissingKey => (
+                StatusCode::BAD_REQUEST,
+                "No API key for the data provider, set its api_key",
+            ),
+            ProviderError::Symbol(error) => (StatusCode::BAD_REQUEST, error),
+            ProviderError::Request(error) => {
+                eprintln!("Failed to fetch the data: {}", error);
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "Failed to fetch the data from the provider",
+                )
+            }
+        })?;
+    if let Some((storage, key)) = cache {
+        match serde_json::to_vec(&bars) {
+            Ok(bytes) => {
+                if let Err(e) = storage.put(&key, bytes).await {
+                    eprintln!("Failed to cache data {}: {}", key, e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize data {}: {}", key, e),
+        }
+    }
+    Ok(bars)
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct StrategyConfig {
+    pub(super) wasm: Option<String>,
+    // Module of the strategy library run instead of `wasm`
+    #[serde(alias = "strategy_id")]
+    id: Option<String>,
+    // Native rule strategy trading `asset` (defaults to the data symbol)
+    pub(super) rules: Option<Vec<RuleSpec>>,
+    asset: Option<String>,
+    // Restricts what a WASM strategy may call, for untrusted strategies
+    pub(super) capabilities: Option<Capabilities>,
+    // Values of the parameters declared in the manifest, the defaults otherwise
+    pub(super) parameters: Option<HashMap<String, f64>>,
+    // Strategies voting on the orders instead of a single one
+    pub(super) ensemble: Option<EnsembleConfig>,
+    // Strategies trading side by side, each from a sub-account of the broker
+    pub(super) sub_accounts: Option<Vec<SubAccountMember>>,
+    #[serde(skip)]
+    pub(super) module: Option<Vec<u8>>,
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct EnsembleConfig {
+    #[serde(flatten)]
+    settings: EnsembleSettings,
+    pub(super) members: Vec<EnsembleMember>,
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct SubAccountMember {
+    name: String,
+    // Taken out of `broker.cash`
     cash: f64,
-    fees: Option<FeeType>,
-    slippage: Option<SlippageSettings>,
+    #[serde(flatten)]
+    pub(super) strategy: StrategyConfig,
 }
 
-#[derive(Deserialize)]
-struct SlippageSettings {
-    min: f64,
-    max: f64,
+#[derive(Deserialize, Clone)]
+pub(super) struct EnsembleMember {
+    #[serde(flatten)]
+    pub(super) strategy: StrategyConfig,
+    // 1 by default
+    weight: Option<f64>,
+}
+
+impl StrategyConfig {
+    // Fetch the modules of `id` and of the ensemble members from the strategy library
+    pub(super) async fn load(
+        &mut self,
+        store: &RunStore,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        self.load_module(store).await?;
+        for member in self.members_mut() {
+            member.load_module(store).await?;
+        }
+        Ok(())
+    }
+
+    async fn load_module(&mut self, store: &RunStore) -> Result<(), (StatusCode, &'static str)> {
+        let Some(id) = self.id.take() else {
+            return Ok(());
+        };
+        if self.wasm.is_some() || self.rules.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "A strategy id replaces strategy.wasm and strategy.rules",
+            ));
+        }
+        match store.strategy(&id).await {
+            Ok(Some((_, module))) => {
+                self.module = Some(module);
+                Ok(())
+            }
+            Ok(None) => Err((StatusCode::NOT_FOUND, "Strategy not found")),
+            Err(e) => {
+                eprintln!("Failed to load strategy {}: {}", id, e);
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to load the strategy",
+                ))
+            }
+        }
+    }
+
+    // Manifest of the WASM module, None for rule strategies and modules without one
+    pub(super) fn manifest(&self) -> Result<Option<Manifest>, String> {
+        match self.wasm_bytes() {
+            Ok(Some(wasm)) => manifest::read(&wasm),
+            _ => Ok(None),
+        }
+    }
+
+    // Strategies of the ensemble or of the sub-accounts
+    pub(super) fn members(&self) -> Vec<&StrategyConfig> {
+        let ensemble = self.ensemble.iter().flat_map(|ensemble| &ensemble.members);
+        let sub_accounts = self.sub_accounts.iter().flatten();
+        ensemble
+            .map(|member| &member.strategy)
+            .chain(sub_accounts.map(|member| &member.strategy))
+            .collect()
+    }
+
+    fn members_mut(&mut self) -> Vec<&mut StrategyConfig> {
+        let ensemble = self
+            .ensemble
+            .iter_mut()
+            .flat_map(|ensemble| &mut ensemble.members);
+        let sub_accounts = self.sub_accounts.iter_mut().flatten();
+        ensemble
+            .map(|member| &mut member.strategy)
+            .chain(sub_accounts.map(|member| &mut member.strategy))
+            .collect()
+    }
+
+    // Bars the strategy needs before the start according to its manifest, the most of the
+    // members for an ensemble or sub-accounts
+    pub(super) fn warmup_bars(&self) -> usize {
+        let warmup_bars = match self.manifest() {
+            Ok(Some(manifest)) => manifest.warmup_bars,
+            _ => 0,
+        };
+        self.members()
+            .iter()
+            .map(|member| member.warmup_bars())
+            .fold(warmup_bars, usize::max)
+    }
+
+    // The WASM module, loaded from the library or decoded from `wasm`
+    pub(super) fn wasm_bytes(&self) -> Result<Option<Vec<u8>>, (StatusCode, &'static str)> {
+        if let Some(module) = &self.module {
+            return Ok(Some(module.clone()));
+        }
+        self.wasm
+            .as_ref()
+            .map(|wasm| {
+                base64::Engine::decode(&base64::engine::general_purpose::STANDARD, wasm)
+                    .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid base64 encoded WASM"))
+            })
+            .transpose()
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct SimulationParameters {
+    pub(super) start_date: String,
+    pub(super) end_date: String,
+    // Seconds (`60s`) or nanoseconds (`500ns`), inferred from the bar spacing when unset or `auto`
+    tick: Option<String>,
+    gaps: Option<GapPolicy>,
+    mode: Option<RunMode>,
+    // Return the time spent in each part of the engine with the result
+    profile: Option<bool>,
+    // Return the placed, filled and rejected orders with the result
+    order_log: Option<bool>,
+    // When the data doesn't cover the dates: `error`, `shrink` (default) or `pad`
+    missing_data: Option<MissingData>,
+    // Bars at the same time: `first`, `last` (default), `merge` or `error`
+    duplicates: Option<Duplicates>,
+    // Inflation rate or price index the real returns are measured with
+    inflation: Option<Inflation>,
+    // Standard deviations of the bar returns flagging a bar in the data quality, 4 by default
+    outlier_z_score: Option<f64>,
+    // Sell the positions left open at the last close, the result also has the metrics without it
+    liquidate_at_end: Option<bool>,
+    // Skip the ticks between two bars when no order can trade on them
+    adaptive_ticks: Option<bool>,
+    // Parts of the result to respond with, all of them by default
+    response_fields: Option<Vec<ResponseField>>,
+    // Named parts of the range with metrics of their own, e.g. in-sample and out-of-sample
+    #[serde(default)]
+    slices: Vec<Slice>,
+    // Mean returns by month, day of the month and around recurring events
+    seasonality: Option<SeasonalitySettings>,
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct BrokerSettings {
+    pub(super) cash: f64,
+    // Venue whose fees, slippage, lot size and session fill the settings left out
+    pub(super) preset: Option<String>,
+    pub(super) fees: Option<FeeType>,
+    // `account` (default) or `asset` to take the fees of the buys out of the quantity bought
+    fee_currency: Option<FeeCurrency>,
+    pub(super) slippage: Option<SlippageSettings>,
+    // Seed of the slippage draws, random when not set
+    pub(super) seed: Option<u64>,
+    // Residual quantity closed along with a sell, 1e-9 by default
+    dust_threshold: Option<f64>,
+    // Order quantities are rounded down to a multiple of it
+    pub(super) lot_size: Option<f64>,
+    // `reject` (default) or `downsize` the buys the cash doesn't cover
+    cash_shortfall: Option<CashShortfall>,
+    // Refuse the buys beyond the buying power as soon as they are placed
+    check_buying_power: Option<bool>,
+    // Set the cost of the resting buys aside until they fill, expire or are cancelled
+    reserve_cash: Option<bool>,
+    // Price of the market orders on their bar: `next_open` (default), `close`, `midpoint`,
+    // `vwap_approx` or `worst_case`
+    fill_model: Option<FillModel>,
+    // Share of the volume of each bar the orders can trade, from 0 to 1
+    volume_share: Option<f64>,
+    // Percent of the volume of each bar a single fill can take
+    max_participation: Option<f64>,
+    // Queue ahead of the limit orders, filled on a touch only when the volume reaches them
+    queue: Option<QueueModel>,
+    // Maximum positions held at once and size of each one
+    #[serde(flatten)]
+    limits: PositionLimits,
+    // Net exposure band, hedged with a symbol of `data.assets`
+    exposure: Option<ExposureBand>,
+    // Short hedge sized by the rolling beta to a symbol of `data.assets`
+    beta_hedge: Option<BetaHedgeSettings>,
+    // Yield of the uninvested cash
+    cash_sweep: Option<CashSweep>,
+    // Buy with borrowed cash up to a leverage of the equity
+    margin: Option<MarginAccount>,
+    // Sell every position at a local time of each day
+    flatten: Option<Flatten>,
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct SlippageSettings {
+    pub(super) min: f64,
+    pub(super) max: f64,
 }
 
 #[derive(serde::Serialize)]
@@ -49,9 +548,558 @@ struct SlippageSettings {
 pub enum Response<T> {
     Success(T),
     Error(&'static str),
+    // Field level errors of an invalid body
+    Invalid {
+        errors: Vec<FieldError>,
+    },
+    // Run stopped by an error, with what it simulated before
+    Failed {
+        error: String,
+        partial: T,
+    },
+    // Batch stopped by the failure of one of its runs, numbered in the order of the request
+    RunFailed {
+        error: String,
+        run: usize,
+        failure: Box<RunFailure>,
+    },
+}
+
+// Error of the batch routes, which fail as a whole when one of their runs does
+pub(super) enum BatchError {
+    Request(StatusCode, &'static str),
+    Run {
+        run: usize,
+        failure: Box<RunFailure>,
+    },
+}
+
+impl From<(StatusCode, &'static str)> for BatchError {
+    fn from((status, error): (StatusCode, &'static str)) -> Self {
+        BatchError::Request(status, error)
+    }
+}
+
+impl BatchError {
+    pub(super) fn respond<T>(self) -> (StatusCode, Json<Response<T>>) {
+        match self {
+            BatchError::Request(status, error) => (status, Json(Response::Error(error))),
+            BatchError::Run { run, failure } => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::RunFailed {
+                    error: failure.error.clone(),
+                    run,
+                    failure,
+                }),
+            ),
+        }
+    }
+}
+
+impl BrokerSettings {
+    // Fills the costs and the lot size left out with those of the preset
+    fn apply_preset(&mut self, preset: &BrokerPreset) {
+        self.fees.get_or_insert_with(|| preset.fees.clone());
+        self.fee_currency.get_or_insert(preset.fee_currency);
+        self.slippage.get_or_insert(SlippageSettings {
+            min: preset.slippage.0,
+            max: preset.slippage.1,
+        });
+        self.lot_size.get_or_insert(preset.lot_size);
+    }
+
+    // Same broker with other trading costs, the slippage is fixed instead of drawn from a range
+    pub(super) fn with_costs(&self, fees: Option<FeeType>, slippage: f64) -> Self {
+        BrokerSettings {
+            cash: self.cash,
+            preset: self.preset.clone(),
+            fees,
+            fee_currency: self.fee_currency,
+            slippage: Some(SlippageSettings {
+                min: slippage,
+                max: slippage,
+            }),
+            seed: self.seed,
+            dust_threshold: self.dust_threshold,
+            lot_size: self.lot_size,
+            cash_shortfall: self.cash_shortfall,
+            check_buying_power: self.check_buying_power,
+            reserve_cash: self.reserve_cash,
+            fill_model: self.fill_model,
+            volume_share: self.volume_share,
+            max_participation: self.max_participation,
+            queue: self.queue.clone(),
+            limits: self.limits,
+            exposure: self.exposure.clone(),
+            beta_hedge: self.beta_hedge.clone(),
+            cash_sweep: self.cash_sweep.clone(),
+            margin: self.margin.clone(),
+            flatten: self.flatten.clone(),
+        }
+    }
+}
+
+// Strategy of a run with the bytes it is hashed from
+struct BuiltStrategy {
+    strategy: Box<dyn Strategy + Send>,
+    bytes: Vec<u8>,
+    warmup_bars: usize,
+}
+
+impl Body {
+    pub(super) fn new(
+        parameters: SimulationParameters,
+        data: DataInput,
+        broker: BrokerSettings,
+        strategy: StrategyConfig,
+    ) -> Self {
+        Body {
+            parameters,
+            data,
+            broker,
+            strategy,
+            callback_url: None,
+            experiment_id: None,
+        }
+    }
+
+    // Parameter values of `strategy` checked against its manifest, errors name the field at fault
+    pub(super) fn strategy_parameters(
+        &self,
+        strategy: &StrategyConfig,
+        manifest: Option<&Manifest>,
+    ) -> Result<HashMap<String, f64>, (&'static str, String)> {
+        let values = strategy.parameters.clone().unwrap_or_default();
+        let Some(manifest) = manifest else {
+            if values.is_empty() {
+                return Ok(values);
+            }
+            return Err((
+                "strategy.parameters",
+                "The strategy declares no parameters".to_string(),
+            ));
+        };
+        for asset in &manifest.assets {
+            if self.data.symbol.as_ref() != Some(asset) && !self.data.assets.contains_key(asset) {
+                return Err((
+                    "data.assets",
+                    format!("The strategy reads `{}`, which has no data", asset),
+                ));
+            }
+        }
+        manifest
+            .resolve(&values)
+            .map_err(|error| ("strategy.parameters", error))
+    }
+
+    // Rule strategies trade from their first bar
+    fn build_strategy(
+        &self,
+        strategy: &StrategyConfig,
+    ) -> Result<BuiltStrategy, (StatusCode, &'static str)> {
+        if let Some(ensemble) = &strategy.ensemble {
+            return self.build_ensemble(strategy, ensemble);
+        }
+        if let Some(sub_accounts) = &strategy.sub_accounts {
+            return self.build_sub_accounts(strategy, sub_accounts);
+        }
+        match (strategy.wasm_bytes()?, &strategy.rules) {
+            (Some(wasm_bytes), None) => {
+                let manifest = manifest::read(&wasm_bytes)
+                    .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid strategy manifest"))?;
+                let parameters = self
+                    .strategy_parameters(strategy, manifest.as_ref())
+                    .map_err(|_| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            "The run doesn't match the strategy manifest",
+                        )
+                    })?;
+                match WasmStrategy::new(
+                    &wasm_bytes,
+                    strategy.capabilities.clone().unwrap_or_default(),
+                ) {
+                    Ok(mut s) => {
+                        s.set_parameters(parameters);
+                        let warmup_bars = manifest.map_or(0, |manifest| manifest.warmup_bars);
+                        Ok(BuiltStrategy {
+                            strategy: Box::new(s),
+                            bytes: wasm_bytes,
+                            warmup_bars,
+                        })
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to load WASM strategy: {:?}", e);
+                        Err((StatusCode::BAD_REQUEST, "Failed to load WASM strategy"))
+                    }
+                }
+            }
+            (None, Some(rules)) => {
+                let Some(asset) = strategy
+                    .asset
+                    .clone()
+                    .or_else(|| self.data.symbol.clone())
+                else {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "An asset or a data symbol is required for rule strategies",
+                    ));
+                };
+
+                match RuleStrategy::new(asset, rules) {
+                    Ok(s) => Ok(BuiltStrategy {
+                        strategy: Box::new(s),
+                        bytes: serde_json::to_vec(rules).unwrap_or_default(),
+                        warmup_bars: 0,
+                    }),
+                    Err(e) => {
+                        eprintln!("Failed to parse strategy rules: {}", e);
+                        Err((StatusCode::BAD_REQUEST, "Invalid strategy rules"))
+                    }
+                }
+            }
+            _ => Err((
+                StatusCode::BAD_REQUEST,
+                "Either strategy.wasm, strategy.id, strategy.rules or strategy.ensemble is required",
+            )),
+        }
+    }
+
+    // Hashed from the settings followed by the bytes of every member
+    fn build_ensemble(
+        &self,
+        strategy: &StrategyConfig,
+        ensemble: &EnsembleConfig,
+    ) -> Result<BuiltStrategy, (StatusCode, &'static str)> {
+        if strategy.wasm.is_some() || strategy.module.is_some() || strategy.rules.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "An ensemble replaces strategy.wasm, strategy.id and strategy.rules",
+            ));
+        }
+        let mut bytes = serde_json::to_vec(&ensemble.settings).unwrap_or_default();
+        let mut members = vec![];
+        let mut warmup_bars = 0;
+        for member in &ensemble.members {
+            if !member.strategy.members().is_empty() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Ensemble members can't be ensembles or have sub-accounts",
+                ));
+            }
+            let built = self.build_strategy(&member.strategy)?;
+            bytes.extend(built.bytes);
+            warmup_bars = warmup_bars.max(built.warmup_bars);
+            members.push((built.strategy, member.weight.unwrap_or(1.0)));
+        }
+        let ensemble = EnsembleStrategy::new(members, ensemble.settings.clone())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        Ok(BuiltStrategy {
+            strategy: Box::new(ensemble),
+            bytes,
+            warmup_bars,
+        })
+    }
+
+    // Hashed from the names and cash of the sub-accounts followed by the bytes of every member,
+    // the broker opens the sub-accounts in the same order
+    fn build_sub_accounts(
+        &self,
+        strategy: &StrategyConfig,
+        sub_accounts: &[SubAccountMember],
+    ) -> Result<BuiltStrategy, (StatusCode, &'static str)> {
+        if strategy.wasm.is_some()
+            || strategy.module.is_some()
+            || strategy.rules.is_some()
+            || strategy.ensemble.is_some()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Sub-accounts replace strategy.wasm, strategy.id, strategy.rules and strategy.ensemble",
+            ));
+        }
+        if sub_accounts.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "No sub-account strategy"));
+        }
+        let mut bytes = vec![];
+        let mut members = vec![];
+        let mut warmup_bars = 0;
+        for member in sub_accounts {
+            if member.strategy.sub_accounts.is_some() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Sub-account strategies can't have sub-accounts",
+                ));
+            }
+            let built = self.build_strategy(&member.strategy)?;
+            bytes.extend(serde_json::to_vec(&(&member.name, member.cash)).unwrap_or_default());
+            bytes.extend(built.bytes);
+            warmup_bars = warmup_bars.max(built.warmup_bars);
+            members.push(built.strategy);
+        }
+        Ok(BuiltStrategy {
+            strategy: Box::new(MultiStrategy::new(members)),
+            bytes,
+            warmup_bars,
+        })
+    }
+}
+
+pub async fn run(
+    State(state): State<AppState>,
+    Json(mut payload): Json<Body>,
+) -> (StatusCode, Json<Response<serde_json::Value>>) {
+    // Told about the failures before the run starts as well
+    let callback_url = payload.callback_url.take();
+    if let Some(Err(error)) = callback_url.as_deref().map(webhook::validate) {
+        return (StatusCode::BAD_REQUEST, Json(Response::Error(error)));
+    }
+    if let Some(id) = &payload.experiment_id {
+        match state.store.experiment(id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                notify_failure(callback_url, "Experiment not found");
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(Response::Error("Experiment not found")),
+                );
+            }
+            Err(e) => {
+                eprintln!("Failed to read experiment {}: {}", id, e);
+                notify_failure(callback_url, "Failed to read the experiment");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(Response::Error("Failed to read the experiment")),
+                );
+            }
+        }
+    }
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        notify_failure(callback_url, error);
+        return (status, Json(Response::Error(error)));
+    }
+    let warmup_bars = payload.strategy.warmup_bars();
+    if let Err((status, error)) = payload
+        .data
+        .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        notify_failure(callback_url, error);
+        return (status, Json(Response::Error(error)));
+    }
+    if let Err(errors) = validation::validate(&payload) {
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect();
+        notify_failure(callback_url, &messages.join("; "));
+        return (StatusCode::BAD_REQUEST, Json(Response::Invalid { errors }));
+    }
+
+    let experiment_id = payload.experiment_id.take();
+    let response_fields = payload.parameters.response_fields.take();
+    let result = execute(payload);
+
+    // Partial runs aren't kept
+    if let Ok((result, record)) = &result {
+        if result.failure.is_none() {
+            let record = RunRecord {
+                experiment_id,
+                ..record.clone()
+            };
+            persist(&state, result, record).await;
+        }
+    }
+    let result = result.map(|(result, _)| result);
+
+    match &result {
+        Ok(result) => {
+            if let Some(url) = callback_url {
+                let summary = match &result.failure {
+                    None => RunSummary {
+                        run_id: result.id.clone(),
+                        status: RunStatus::Completed,
+                        error: None,
+                        metrics: Some(result.metrics.clone()),
+                    },
+                    Some(failure) => RunSummary {
+                        run_id: None,
+                        status: RunStatus::Failed,
+                        error: Some(failure.error.clone()),
+                        metrics: Some(result.metrics.clone()),
+                    },
+                };
+                webhook::notify(url, summary);
+            }
+        }
+        Err((_, error)) => notify_failure(callback_url, error),
+    }
+
+    match result {
+        Ok(mut result) => {
+            let error = result.failure.as_ref().map(|failure| failure.error.clone());
+            if error.is_some() {
+                result.id = None;
+            }
+            let response = match &response_fields {
+                Some(fields) => result.retain(fields),
+                None => serde_json::to_value(&result).unwrap_or_default(),
+            };
+            match error {
+                None => (StatusCode::OK, Json(Response::Success(response))),
+                Some(error) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(Response::Failed {
+                        error,
+                        partial: response,
+                    }),
+                ),
+            }
+        }
+        Err((status, error)) => (status, Json(Response::Error(error))),
+    }
+}
+
+// Failed run summary without metrics, for the runs that didn't start
+fn notify_failure(callback_url: Option<String>, error: &str) {
+    if let Some(url) = callback_url {
+        webhook::notify(
+            url,
+            RunSummary {
+                run_id: None,
+                status: RunStatus::Failed,
+                error: Some(error.to_string()),
+                metrics: None,
+            },
+        );
+    }
+}
+
+// Time ordered so artifacts are listed chronologically
+pub(super) fn new_run_id() -> String {
+    format!(
+        "{:012x}{:08x}",
+        chrono::Utc::now().timestamp_millis(),
+        rand::random::<u32>()
+    )
+}
+
+async fn persist(state: &AppState, result: &BacktestResult, record: RunRecord) {
+    let run_id = record.id.clone();
+    let value = match serde_json::to_value(result) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Failed to serialize run {}: {}", run_id, e);
+            return;
+        }
+    };
+
+    if let Some(storage) = &state.storage {
+        if let Err(e) = storage
+            .put(&artifact_key(&run_id), value.to_string().into_bytes())
+            .await
+        {
+            eprintln!("Failed to store run {}: {}", run_id, e);
+        }
+    }
+
+    if let Err(e) = state.store.insert(record, &result.trades, value).await {
+        eprintln!("Failed to index run {}: {}", run_id, e);
+    }
+}
+
+// Engine ready to be run along with what identifies the run
+pub struct PreparedRun {
+    pub engine: Engine,
+    pub symbol: Option<String>,
+    pub strategy_hash: String,
+    // Content hashes of the bars of `data.assets` by symbol
+    pub asset_hashes: BTreeMap<String, String>,
+}
+
+// Runs a batch off the async runtime with at most one run per core at a time. `finish` gets each
+// complete run back with its result, the results keep the order of the runs. A run stopped by an
+// error fails the batch, partial runs would be ranked as complete ones
+pub(super) async fn run_all<T: Send + 'static>(
+    runs: Vec<PreparedRun>,
+    finish: fn(PreparedRun, BacktestResult) -> T,
+) -> Result<Vec<T>, BatchError> {
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |cores| cores.get())
+        .min(runs.len());
+    let results = tokio::task::spawn_blocking(move || {
+        let count = runs.len();
+        let queue = Mutex::new(runs.into_iter().enumerate());
+        let results = Mutex::new((0..count).map(|_| None).collect::<Vec<_>>());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let Some((i, mut run)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let result = match run.engine.run() {
+                        Ok(mut result) => match result.failure.take() {
+                            Some(failure) => Err(BatchError::Run {
+                                run: i,
+                                failure: Box::new(failure),
+                            }),
+                            None => Ok(finish(run, result)),
+                        },
+                        // The engine only refuses what the body asks for
+                        Err(error) => Err((StatusCode::BAD_REQUEST, error).into()),
+                    };
+                    results.lock().unwrap()[i] = Some(result);
+                });
+            }
+        });
+        results.into_inner().unwrap()
+    })
+    .await;
+    let panicked = || (StatusCode::INTERNAL_SERVER_ERROR, "Strategy panicked").into();
+    results.map_err(|_| panicked()).and_then(|results| {
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(panicked())))
+            .collect()
+    })
+}
+
+fn execute(payload: Body) -> Result<(BacktestResult, RunRecord), (StatusCode, &'static str)> {
+    let PreparedRun {
+        mut engine,
+        symbol,
+        strategy_hash,
+        asset_hashes,
+    } = prepare(payload)?;
+
+    let mut result = engine
+        .run()
+        .map_err(|error_message| (StatusCode::BAD_REQUEST, error_message))?;
+
+    let run_id = new_run_id();
+    result.id = Some(run_id.clone());
+
+    let record = RunRecord {
+        id: run_id,
+        created_at: chrono::Utc::now().naive_utc(),
+        symbol,
+        start_date: engine.time_range.0,
+        end_date: engine.time_range.1,
+        strategy_hash,
+        metrics: serde_json::to_value(&result.metrics).unwrap_or_default(),
+        notes: None,
+        tags: vec![],
+        starred: false,
+        experiment_id: None,
+        kronos_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        data_hash: result.data_hash.clone(),
+        asset_hashes,
+    };
+
+    Ok((result, record))
 }
 
-pub async fn run(Json(payload): Json<Body>) -> (StatusCode, Json<Response<BacktestResult>>) {
+pub fn prepare(mut payload: Body) -> Result<PreparedRun, (StatusCode, &'static str)> {
     let parse_time = |time_str: &str| -> Result<NaiveDateTime, &'static str> {
         NaiveDateTime::parse_from_str(time_str, "%Y-%m-%d %H:%M:%S")
             .map_err(|_| "Invalid date format")
@@ -59,86 +1107,488 @@ pub async fn run(Json(payload): Json<Body>) -> (StatusCode, Json<Response<Backte
 
     let start_date = match parse_time(&payload.parameters.start_date) {
         Ok(date) => date,
-        Err(e) => return (StatusCode::BAD_REQUEST, Json(Response::Error(e))),
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
     };
 
     let end_date = match parse_time(&payload.parameters.end_date) {
         Ok(date) => date,
-        Err(e) => return (StatusCode::BAD_REQUEST, Json(Response::Error(e))),
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
     };
 
-    let wasm_bytes = match base64::Engine::decode(
-        &base64::engine::general_purpose::STANDARD,
-        &payload.strategy.wasm,
-    ) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(Response::Error("Invalid base64 encoded WASM")),
-            );
+    let BuiltStrategy {
+        strategy,
+        bytes: strategy_bytes,
+        warmup_bars,
+    } = payload.build_strategy(&payload.strategy)?;
+    let mut engine = Engine::new(strategy, (start_date, end_date));
+    engine.set_warmup_bars(warmup_bars);
+
+    if let Some(name) = &payload.broker.preset {
+        let preset =
+            BrokerPreset::named(name).ok_or((StatusCode::BAD_REQUEST, "Unknown broker preset"))?;
+        payload.broker.apply_preset(&preset);
+        if payload.data.symbol.is_some() && payload.data.session.is_none() {
+            payload.data.session = Some(SessionSpec::Preset(preset.session.to_string()));
         }
-    };
+    }
 
-    let strategy = match WasmStrategy::new(&wasm_bytes) {
-        Ok(s) => Box::new(s),
-        Err(e) => {
-            eprintln!("Failed to load WASM strategy: {:?}", e);
-            return (
+    let mut calendar = Calendar::new();
+    if let Some(spec) = payload.data.session {
+        let Some(symbol) = &payload.data.symbol else {
+            return Err((
                 StatusCode::BAD_REQUEST,
-                Json(Response::Error("Failed to load WASM strategy")),
-            );
+                "A symbol is required to attach a session",
+            ));
+        };
+        let session = Session::from_spec(spec).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        calendar.add_session(symbol, session);
+    }
+
+    let (data_hash, _) = data::snapshot(&payload.data.source);
+    let asset_hashes = payload
+        .data
+        .assets
+        .iter()
+        .map(|(asset, bars)| (asset.clone(), data::snapshot(bars).0))
+        .collect();
+    let corrections = data::normalize(
+        &mut payload.data.source,
+        payload.parameters.duplicates.unwrap_or_default(),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let outlier_z_score = payload
+        .parameters
+        .outlier_z_score
+        .unwrap_or(DEFAULT_OUTLIER_Z_SCORE);
+    if !(outlier_z_score.is_finite() && outlier_z_score > 0.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The outlier z-score must be positive",
+        ));
+    }
+    let cleaning = payload.data.cleaning.take().unwrap_or_default();
+    cleaning
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut cleaning_log = CleaningLog::default();
+    cleaning.apply(
+        payload.data.symbol.as_deref(),
+        &mut payload.data.source,
+        &mut cleaning_log,
+    );
+    if payload.data.source.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "No bars are left after the cleaning",
+        ));
+    }
+    let mut quality = vec![DataQuality::new(
+        payload.data.symbol.clone(),
+        &payload.data.source,
+        outlier_z_score,
+    )];
+    let mut assets: Vec<_> = payload.data.assets.iter_mut().collect();
+    assets.sort_by(|a, b| a.0.cmp(b.0));
+    for (asset, bars) in assets {
+        bars.sort_by_key(|bar| bar.timestamp);
+        cleaning.apply(Some(asset), bars, &mut cleaning_log);
+        quality.push(DataQuality::new(Some(asset.clone()), bars, outlier_z_score));
+    }
+    if cleaning_log.removed_bars + cleaning_log.modified_bars > 0 {
+        engine.set_cleaning(cleaning_log);
+    }
+    engine.set_data_quality(quality);
+    let (mut source, contract) = match &payload.data.instrument {
+        Some(instrument) => {
+            instrument
+                .validate()
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let contract = instrument
+                .contract()
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            (instrument.series(payload.data.source), contract)
         }
+        None => (payload.data.source, Contract::default()),
     };
+    if contract.margin && payload.parameters.mode == Some(RunMode::Vectorized) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The vectorized mode only supports cash settled instruments",
+        ));
+    }
+    let coverage = data::cover(
+        &mut source,
+        (start_date, end_date),
+        payload.parameters.missing_data.unwrap_or_default(),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    engine.time_range = (coverage.effective_start, coverage.effective_end);
+    engine.set_coverage(coverage);
+    engine.set_data_hash(data_hash);
+    engine.set_corrections(corrections);
+    if let Some(sources) = payload.data.sources {
+        engine.set_sources(sources);
+    }
+    let prices = payload.data.prices;
+    engine.set_price_basis(
+        payload
+            .data
+            .price_basis
+            .unwrap_or_else(|| PriceBasis::new(prices)),
+    );
+    engine.add_data(source);
+    // The tick follows the data resolution unless it is set
+    match payload.parameters.tick.as_deref() {
+        None | Some("auto") => {
+            if let Some(spacing) = data::median_spacing(&engine.data_feed) {
+                engine.set_tick(spacing);
+            }
+        }
+        Some(tick) => {
+            let duration = match tick.trim_end_matches(['s', 'n']).parse::<i64>() {
+                Ok(value) => {
+                    if tick.ends_with("ns") {
+                        Duration::new(0, value as u32)
+                    } else {
+                        Duration::new(value, 0)
+                    }
+                }
+                Err(_) => {
+                    return Err((StatusCode::BAD_REQUEST, "Cannot parse tick duration"));
+                }
+            };
 
-    let mut engine = Engine::new(strategy, (start_date, end_date));
-
-    if let Some(tick) = &payload.parameters.tick {
-        let duration = match tick.trim_end_matches(['s', 'n']).parse::<i64>() {
-            Ok(value) => {
-                if tick.ends_with("ns") {
-                    Duration::new(0, value as u32)
-                } else {
-                    Duration::new(value, 0)
+            match duration {
+                Some(d) => engine.set_tick(d),
+                None => {
+                    return Err((StatusCode::BAD_REQUEST, "Invalid tick duration value"));
                 }
             }
-            Err(_) => {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(Response::Error("Cannot parse tick duration")),
-                );
-            }
-        };
-
-        match duration {
-            Some(d) => engine.set_tick(d),
+        }
+    }
+    let hedge = match payload
+        .broker
+        .exposure
+        .as_ref()
+        .and_then(|band| band.hedge.clone())
+    {
+        Some(asset) => match payload.data.assets.get(&asset) {
+            Some(bars) => Some(HedgeBook::new(asset, bars.clone())),
             None => {
-                return (
+                return Err((
                     StatusCode::BAD_REQUEST,
-                    Json(Response::Error("Invalid tick duration value")),
-                );
+                    "The hedge instrument needs its bars in data.assets",
+                ))
             }
+        },
+        None => None,
+    };
+    let beta_hedge = match payload.broker.beta_hedge.take() {
+        Some(_) if payload.broker.exposure.is_some() => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Either broker.exposure or broker.beta_hedge is expected, not both",
+            ));
         }
+        Some(settings) => {
+            settings
+                .validate()
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let hedge = settings.hedge.as_ref().unwrap_or(&settings.benchmark);
+            let (Some(benchmark), Some(hedge)) = (
+                payload.data.assets.get(&settings.benchmark),
+                payload.data.assets.get(hedge),
+            ) else {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "The beta benchmark and hedge need their bars in data.assets",
+                ));
+            };
+            let (benchmark, hedge) = (benchmark.clone(), hedge.clone());
+            Some(BetaHedge::new(settings, benchmark, hedge))
+        }
+        None => None,
+    };
+    let mut market = MarketData::default();
+    for (asset, bars) in payload.data.assets {
+        market.insert(asset, bars);
+    }
+    if let Some(symbol) = &payload.data.symbol {
+        market.insert(symbol.clone(), engine.data_feed.clone());
+        engine.set_symbol(symbol.clone());
+    }
+    engine.strategy.subscribe(Arc::new(market));
+    if let Some(gap_policy) = payload.parameters.gaps {
+        engine.set_gap_policy(gap_policy);
+    }
+    if let Some(adaptive) = payload.parameters.adaptive_ticks {
+        if adaptive && payload.parameters.gaps == Some(GapPolicy::Notify) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Adaptive ticks skip the gaps `on_gap` is called on",
+            ));
+        }
+        engine.set_adaptive_ticks(adaptive);
+    }
+    if let Some(mode) = payload.parameters.mode {
+        engine.set_mode(mode);
+    }
+    if let Some(profile) = payload.parameters.profile {
+        engine.set_profiling(profile);
     }
-
-    engine.add_data(payload.data.source);
 
     let mut broker = Broker::new();
     broker.set_cash(payload.broker.cash);
+    if let Some(seed) = payload.broker.seed {
+        broker.set_seed(seed);
+    }
     if let Some(fees) = payload.broker.fees {
         broker.set_fees(fees);
     }
+    if let Some(currency) = payload.broker.fee_currency {
+        if currency == FeeCurrency::Asset && contract.margin {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Fees in the traded asset need a cash settled instrument",
+            ));
+        }
+        if currency == FeeCurrency::Asset && payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support fees in the traded asset",
+            ));
+        }
+        broker.set_fee_currency(currency);
+    }
     if let Some(slippage) = &payload.broker.slippage {
+        // The batch routes prepare the runs without validating the bodies first
+        if !(slippage.min.is_finite() && slippage.max.is_finite() && slippage.min <= slippage.max) {
+            return Err((StatusCode::BAD_REQUEST, "Invalid slippage range"));
+        }
         broker.set_slippage(slippage.min, slippage.max);
     }
+    if let Some(lot_size) = payload.broker.lot_size {
+        if !(lot_size.is_finite() && lot_size > 0.0) {
+            return Err((StatusCode::BAD_REQUEST, "Invalid lot size"));
+        }
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support lot sizes",
+            ));
+        }
+        broker.set_lot_size(lot_size);
+    }
+    if let Some(threshold) = payload.broker.dust_threshold {
+        if !(threshold.is_finite() && threshold >= 0.0) {
+            return Err((StatusCode::BAD_REQUEST, "Invalid dust threshold"));
+        }
+        broker.set_dust_threshold(threshold);
+    }
+    if let Some(policy) = payload.broker.cash_shortfall {
+        broker.set_cash_shortfall(policy);
+    }
+    if let Some(enabled) = payload.broker.check_buying_power {
+        broker.set_check_buying_power(enabled);
+    }
+    if let Some(enabled) = payload.broker.reserve_cash {
+        broker.set_reserve_cash(enabled);
+    }
+    if let Some(model) = payload.broker.fill_model {
+        if model != FillModel::NextOpen && payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support fill models",
+            ));
+        }
+        broker.set_fill_model(model);
+    }
+    if let Some(share) = payload.broker.volume_share {
+        if !(share > 0.0 && share <= 1.0) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The volume share is between 0 and 1",
+            ));
+        }
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support volume shares",
+            ));
+        }
+        broker.set_volume_share(share);
+    }
+    if let Some(percent) = payload.broker.max_participation {
+        if !(percent > 0.0 && percent <= 100.0) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The maximum participation is between 0 and 100",
+            ));
+        }
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support the maximum participation",
+            ));
+        }
+        broker.set_max_participation(percent);
+    }
+    if let Some(model) = payload.broker.queue.clone() {
+        model.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support the queue model",
+            ));
+        }
+        broker.set_queue_model(model);
+    }
+    payload
+        .broker
+        .limits
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if payload.broker.limits.max_position_percent.is_some()
+        && payload.parameters.mode == Some(RunMode::Vectorized)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The vectorized mode doesn't support position limits",
+        ));
+    }
+    broker.set_limits(payload.broker.limits);
+    if let Some(band) = payload.broker.exposure {
+        band.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support exposure bands",
+            ));
+        }
+        broker.set_exposure(ExposureMonitor::new(band, hedge));
+    }
+    if let Some(beta_hedge) = beta_hedge {
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support the beta hedge",
+            ));
+        }
+        broker.set_beta_hedge(beta_hedge);
+    }
+    if let Some(sweep) = payload.broker.cash_sweep {
+        sweep.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support the cash sweep",
+            ));
+        }
+        broker.set_cash_sweep(sweep);
+    }
+    for member in payload.strategy.sub_accounts.iter().flatten() {
+        if contract.margin || payload.broker.margin.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Sub-accounts need a cash account and a cash settled instrument",
+            ));
+        }
+        broker
+            .add_account(member.name.clone(), member.cash)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    if let Some(account) = payload.broker.margin {
+        account
+            .validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if contract.margin {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Margin accounts need a cash settled instrument",
+            ));
+        }
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support margin accounts",
+            ));
+        }
+        broker.set_margin_account(account);
+    }
+    if let Some(flatten) = payload.broker.flatten {
+        if payload.parameters.mode == Some(RunMode::Vectorized) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The vectorized mode doesn't support the flattening",
+            ));
+        }
+        let session = payload
+            .data
+            .symbol
+            .as_deref()
+            .and_then(|symbol| calendar.session(symbol));
+        broker.set_flatten(DailyFlatten::new(flatten, session));
+    }
+    broker.set_calendar(calendar);
+    broker.set_contract(contract);
+    if let Some(symbol) = &payload.data.symbol {
+        broker.set_traded_asset(symbol.clone());
+    }
 
     engine.set_broker(broker);
+    if let Some(inflation) = payload.parameters.inflation {
+        inflation
+            .validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        engine.set_inflation(inflation);
+    }
+    if let Some(liquidate) = payload.parameters.liquidate_at_end {
+        engine.set_liquidate_at_end(liquidate);
+    }
+    slice::validate(&payload.parameters.slices).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    engine.set_slices(payload.parameters.slices);
+    if let Some(seasonality) = payload.parameters.seasonality {
+        seasonality
+            .validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        engine.set_seasonality(seasonality);
+    }
+    if let Some(order_log) = payload.parameters.order_log {
+        engine.set_order_log(order_log);
+    }
 
-    match engine.run() {
-        Ok(result) => (StatusCode::OK, Json(Response::Success(result))),
-        Err(error_message) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(Response::Error(error_message)),
-        ),
+    Ok(PreparedRun {
+        engine,
+        symbol: payload.data.symbol,
+        strategy_hash: hex::encode(Sha256::digest(&strategy_bytes)),
+        asset_hashes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepare_rejects_an_inverted_slippage_range() {
+        let body = |slippage: serde_json::Value| -> Body {
+            serde_json::from_value(serde_json::json!({
+                "parameters": { "start_date": "2024-01-01 00:00:00", "end_date": "2024-01-03 00:00:00" },
+                "data": { "symbol": "AAPL", "source": [
+                    { "timestamp": "2024-01-01T00:00:00", "open": 100.0, "high": 101.0, "low": 99.0, "close": 100.5, "volume": 1000 },
+                    { "timestamp": "2024-01-02T00:00:00", "open": 101.0, "high": 102.0, "low": 100.0, "close": 101.5, "volume": 1000 }
+                ] },
+                "broker": { "cash": 10000, "slippage": slippage },
+                "strategy": { "asset": "AAPL", "rules": [{ "action": "buy", "when": "close > 0", "size": "1" }] }
+            }))
+            .unwrap()
+        };
+
+        let inverted = prepare(body(serde_json::json!({ "min": 0.02, "max": 0.01 })));
+        assert_eq!(
+            inverted.err(),
+            Some((StatusCode::BAD_REQUEST, "Invalid slippage range"))
+        );
+        assert!(prepare(body(serde_json::json!({ "min": 0.01, "max": 0.02 }))).is_ok());
     }
 }
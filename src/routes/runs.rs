@@ -0,0 +1,487 @@
+use super::run::Response;
+use super::AppState;
+use crate::analytics::{
+    drawdown::{self, Drawdown},
+    growth::RollingCagr,
+    locale::ReportLocale,
+    metrics::{self, GlobalMetrics, MetricsInputs, MetricsMap, METRICS_VERSION},
+    plugin::MetricRegistry,
+    tax,
+    trade::Trade,
+};
+use crate::engine::EquityPoint;
+use crate::store::{Annotation, RunFilter, RunRecord};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response as HttpResponse},
+    Json,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Deserialize)]
+pub struct RunQuery {
+    symbol: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    min_sharpe: Option<f64>,
+    strategy_hash: Option<String>,
+    tag: Option<String>,
+    starred: Option<bool>,
+    experiment: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+// Runs listed by `GET /runs` without a `limit`, and the most it can ask for
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+pub fn artifact_key(run_id: &str) -> String {
+    format!("runs/{}.json", run_id)
+}
+
+pub async fn list_runs(
+    State(state): State<AppState>,
+    Query(query): Query<RunQuery>,
+) -> (StatusCode, Json<Response<Vec<RunRecord>>>) {
+    let parse_time = |time_str: &Option<String>| -> Result<Option<NaiveDateTime>, &'static str> {
+        time_str
+            .as_deref()
+            .map(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))
+            .transpose()
+            .map_err(|_| "Invalid date format")
+    };
+
+    let (from, to) = match (parse_time(&query.from), parse_time(&query.to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return (StatusCode::BAD_REQUEST, Json(Response::Error(e))),
+    };
+
+    let filter = RunFilter {
+        symbol: query.symbol,
+        from,
+        to,
+        min_sharpe: query.min_sharpe,
+        strategy_hash: query.strategy_hash,
+        tag: query.tag,
+        starred: query.starred,
+        experiment: query.experiment,
+        limit: Some(query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)),
+        offset: query.offset.unwrap_or_default(),
+    };
+
+    match state.store.list(&filter).await {
+        Ok(runs) => (StatusCode::OK, Json(Response::Success(runs))),
+        Err(e) => {
+            eprintln!("Failed to list runs: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to list runs")),
+            )
+        }
+    }
+}
+
+pub async fn get_run(
+    State(state): State<AppState>,
+    Path(run_id): Path<String>,
+    request: HeaderMap,
+) -> HttpResponse {
+    match load_run(&state, &run_id).await {
+        Ok(result) => cached(
+            &request,
+            vec![(header::CONTENT_TYPE, "application/json".to_string())],
+            serde_json::to_vec(&result).unwrap_or_default(),
+        ),
+        Err((status, error)) => (status, Json(Response::<()>::Error(error))).into_response(),
+    }
+}
+
+// Responds with the content tagged by its hash, or 304 without it when the client already holds
+// it, clients revalidate before reusing it
+fn cached(request: &HeaderMap, headers: Vec<(HeaderName, String)>, body: Vec<u8>) -> HttpResponse {
+    let hash = hex::encode(Sha256::digest(&body));
+    let etag = format!("\"{}\"", hash);
+    let matched = request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*");
+
+    let mut response = match matched {
+        true => StatusCode::NOT_MODIFIED.into_response(),
+        false => (StatusCode::OK, body).into_response(),
+    };
+    let response_headers = response.headers_mut();
+    if !matched {
+        for (name, value) in headers {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                response_headers.insert(name, value);
+            }
+        }
+    }
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+// Notes, tags and star of a run, runs only kept in the artifact storage can't be annotated
+pub async fn annotate_run(
+    State(state): State<AppState>,
+    Path(run_id): Path<String>,
+    Json(mut annotation): Json<Annotation>,
+) -> (StatusCode, Json<Response<RunRecord>>) {
+    annotation.normalize();
+    match state.store.annotate(&run_id, &annotation).await {
+        Ok(Some(record)) => (StatusCode::OK, Json(Response::Success(record))),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(Response::Error("Run not found")),
+        ),
+        Err(e) => {
+            eprintln!("Failed to annotate run {}: {}", run_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to annotate run")),
+            )
+        }
+    }
+}
+
+// Parts of a stored result the analytics are computed from
+#[derive(Deserialize)]
+struct StoredAnalytics {
+    #[serde(default)]
+    metrics_version: u32,
+    metrics: serde_json::Value,
+    trades: Vec<Trade>,
+    equity_curve: Vec<EquityPoint>,
+    metrics_inputs: MetricsInputs,
+}
+
+#[derive(Serialize)]
+pub struct Recomputation {
+    id: String,
+    // Version of the stored metrics, 0 for runs stored before the versioning
+    stored_version: u32,
+    metrics_version: u32,
+    metrics: GlobalMetrics,
+    metrics_map: MetricsMap,
+    drawdowns: Vec<Drawdown>,
+    rolling_cagr: RollingCagr,
+    // Metrics added since the run or with another value than the stored one
+    changed: Vec<String>,
+}
+
+// Analytics of a stored run computed again from its trades and equity curve, without running the
+// strategy. The stored result is left as it is
+pub async fn recompute_run(
+    State(state): State<AppState>,
+    Path(run_id): Path<String>,
+) -> (StatusCode, Json<Response<Recomputation>>) {
+    let result = match load_run(&state, &run_id).await {
+        Ok(result) => result,
+        Err((status, error)) => return (status, Json(Response::Error(error))),
+    };
+    let stored: StoredAnalytics = match serde_json::from_value(result) {
+        Ok(stored) => stored,
+        Err(e) => {
+            eprintln!("Failed to read the analytics of run {}: {}", run_id, e);
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(Response::Error(
+                    "The run was stored without its equity curve, run it again instead",
+                )),
+            );
+        }
+    };
+
+    (
+        StatusCode::OK,
+        Json(Response::Success(recompute(
+            run_id,
+            stored,
+            &state.metric_plugins,
+        ))),
+    )
+}
+
+fn recompute(id: String, stored: StoredAnalytics, plugins: &MetricRegistry) -> Recomputation {
+    let equity_curve: Vec<_> = stored
+        .equity_curve
+        .iter()
+        .map(|point| (point.timestamp, point.equity))
+        .collect();
+    let (metrics, metrics_map) = metrics::compute(
+        &stored.trades,
+        &equity_curve,
+        &stored.metrics_inputs,
+        plugins,
+    );
+    let changed = match serde_json::to_value(&metrics) {
+        Ok(serde_json::Value::Object(values)) => values
+            .into_iter()
+            .filter(|(name, value)| stored.metrics.get(name) != Some(value))
+            .map(|(name, _)| name)
+            .collect(),
+        _ => vec![],
+    };
+
+    Recomputation {
+        id,
+        stored_version: stored.metrics_version,
+        metrics_version: METRICS_VERSION,
+        metrics,
+        metrics_map,
+        drawdowns: drawdown::drawdowns(&equity_curve),
+        rolling_cagr: RollingCagr::new(&equity_curve),
+        changed,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TaxReportQuery {
+    // Holding period after which a gain is long term, 12 months by default
+    long_term_months: Option<u32>,
+    // Number and date formats of a locale (`de-DE`, `en-US`, ...), ISO dates by default
+    locale: Option<String>,
+    // Overrides of the locale formats
+    decimal: Option<char>,
+    date_format: Option<String>,
+    currency: Option<String>,
+}
+
+impl TaxReportQuery {
+    fn report_locale(&self) -> Result<ReportLocale, &'static str> {
+        let mut locale = match &self.locale {
+            Some(tag) => ReportLocale::preset(tag).ok_or("Unknown locale")?,
+            None => ReportLocale::default(),
+        };
+        match self.decimal {
+            Some(decimal @ ('.' | ',')) => {
+                locale.decimal = decimal;
+                // The decimal mark can't also group the thousands
+                if locale.thousands == Some(decimal) {
+                    locale.thousands = Some(if decimal == ',' { '.' } else { ',' });
+                }
+            }
+            Some(_) => return Err("The decimal mark is either `.` or `,`"),
+            None => {}
+        }
+        if let Some(date_format) = &self.date_format {
+            locale.set_date_format(date_format)?;
+        }
+        if let Some(currency) = &self.currency {
+            locale.currency = Some(currency.clone()).filter(|currency| !currency.is_empty());
+        }
+        Ok(locale)
+    }
+}
+
+pub async fn get_tax_report(
+    State(state): State<AppState>,
+    Path(run_id): Path<String>,
+    Query(query): Query<TaxReportQuery>,
+    request: HeaderMap,
+) -> HttpResponse {
+    let locale = match query.report_locale() {
+        Ok(locale) => locale,
+        Err(error) => {
+            return (StatusCode::BAD_REQUEST, Json(Response::<()>::Error(error))).into_response();
+        }
+    };
+    let trades = match load_run(&state, &run_id).await {
+        Ok(mut result) => serde_json::from_value::<Vec<Trade>>(result["trades"].take()),
+        Err((status, error)) => {
+            return (status, Json(Response::<()>::Error(error))).into_response();
+        }
+    };
+    let trades = match trades {
+        Ok(trades) => trades,
+        Err(e) => {
+            eprintln!("Failed to read the trades of run {}: {}", run_id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::<()>::Error("Failed to read run")),
+            )
+                .into_response();
+        }
+    };
+
+    let gains = tax::realized_gains(&trades, query.long_term_months.unwrap_or(12));
+    cached(
+        &request,
+        vec![
+            (header::CONTENT_TYPE, "text/csv".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}-tax-report.csv\"", run_id),
+            ),
+        ],
+        tax::to_csv(&gains, &locale).into_bytes(),
+    )
+}
+
+// Result of a run from the store, or from the artifact storage for runs of a previous process
+async fn load_run(
+    state: &AppState,
+    run_id: &str,
+) -> Result<serde_json::Value, (StatusCode, &'static str)> {
+    if run_id.is_empty() || !run_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid run id"));
+    }
+
+    match state.store.get(run_id).await {
+        Ok(Some(result)) => return Ok(result),
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to read run {} from the store: {}", run_id, e),
+    }
+
+    let Some(storage) = &state.storage else {
+        return Err((StatusCode::NOT_FOUND, "Run not found"));
+    };
+
+    match storage.get(&artifact_key(run_id)).await {
+        Ok(Some(bytes)) => serde_json::from_slice(&bytes).map_err(|e| {
+            eprintln!("Failed to parse run {}: {}", run_id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read run")
+        }),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Run not found")),
+        Err(e) => {
+            eprintln!("Failed to read run {}: {}", run_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to read run"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Registered by the server in addition to the built-in plugins
+    struct TradeCount;
+
+    impl crate::analytics::plugin::MetricPlugin for TradeCount {
+        fn compute(&self, input: &crate::analytics::plugin::MetricInput) -> Vec<(String, f64)> {
+            vec![("trade_count".to_string(), input.trades.len() as f64)]
+        }
+    }
+
+    #[test]
+    fn recomputes_a_stored_run_with_the_plugins_of_the_server() {
+        use crate::engine::Engine;
+        use crate::strategy::rules::{OrderAction, RuleSpec, RuleStrategy};
+        use chrono::Duration;
+
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let rule = |action, when: &str, size: &str| RuleSpec {
+            action,
+            when: when.to_string(),
+            size: size.to_string(),
+            execution: None,
+        };
+        let strategy = RuleStrategy::new(
+            "AAPL".to_string(),
+            &[
+                rule(OrderAction::Buy, "close < 11", "50% equity"),
+                rule(OrderAction::Sell, "close > 11", "100% position"),
+            ],
+        )
+        .unwrap();
+        let mut engine = Engine::new(Box::new(strategy), (start, start + Duration::days(5)));
+        engine.set_tick(Duration::days(1));
+        engine.broker.set_cash(1000.0);
+        engine.add_data(
+            [10.0, 12.0, 10.0, 12.0, 10.0, 12.0]
+                .iter()
+                .enumerate()
+                .map(|(days, close)| crate::data::OHLCVData {
+                    timestamp: start + Duration::days(days as i64),
+                    open: *close,
+                    high: *close,
+                    low: *close,
+                    close: *close,
+                    volume: 100,
+                })
+                .collect(),
+        );
+        let result = engine.run().unwrap();
+        let stored = serde_json::from_value(serde_json::to_value(&result).unwrap()).unwrap();
+
+        let mut plugins = MetricRegistry::default();
+        plugins.register(Box::new(TradeCount));
+        let recomputed = recompute("run".to_string(), stored, &plugins);
+
+        assert!(!result.trades.is_empty());
+        assert_eq!(recomputed.stored_version, METRICS_VERSION);
+        // Only the metric of the added plugin is new, the others are computed the same
+        assert_eq!(recomputed.changed, ["trade_count"]);
+        assert_eq!(
+            recomputed.metrics_map.values.get("trade_count"),
+            Some(&(result.trades.len() as f64))
+        );
+        assert_eq!(
+            recomputed.metrics_map.values.get("expectancy"),
+            result.metrics_map.values.get("expectancy")
+        );
+    }
+
+    #[tokio::test]
+    async fn revalidates_the_runs_by_their_etag() {
+        let body = br#"{"id":"run"}"#.to_vec();
+        let etag = format!("\"{}\"", hex::encode(Sha256::digest(&body)));
+        let respond = |if_none_match: Option<&str>| {
+            let mut request = HeaderMap::new();
+            if let Some(value) = if_none_match {
+                request.insert(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+            }
+            cached(
+                &request,
+                vec![(header::CONTENT_TYPE, "application/json".to_string())],
+                body.clone(),
+            )
+        };
+
+        let matching = [
+            etag.clone(),
+            format!("W/{}", etag),
+            "*".to_string(),
+            format!("\"other\", W/{} ,\"another\"", etag),
+        ];
+        for if_none_match in &matching {
+            let response = respond(Some(if_none_match));
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_MODIFIED,
+                "{}",
+                if_none_match
+            );
+            assert_eq!(response.headers()[header::ETAG], etag.as_str());
+            assert!(!response.headers().contains_key(header::CONTENT_TYPE));
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(bytes.is_empty());
+        }
+
+        for if_none_match in [None, Some("\"other\""), Some("W/\"other\", \"another\"")] {
+            let response = respond(if_none_match);
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::ETAG], etag.as_str());
+            assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+            assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(bytes.as_ref(), body.as_slice());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::run::{Response, StrategyConfig};
+use super::AppState;
+use crate::data::OHLCVData;
+use crate::strategy::{
+    manifest,
+    simulate::{SimulatedSession, SimulationReport},
+};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Sessions are meant for a handful of bars, longer tests belong in `/run`
+const MAX_BARS: usize = 10_000;
+
+#[derive(Deserialize)]
+pub struct SimulateBody {
+    strategy: StrategyConfig,
+    // Symbol the bars are read under with `get_bar`
+    symbol: Option<String>,
+    bars: Vec<OHLCVData>,
+    // Bars of other assets by symbol, as `data.assets` of `/run`
+    #[serde(default)]
+    assets: HashMap<String, Vec<OHLCVData>>,
+    // What `get_cash` and `get_position` answer, orders don't change them
+    #[serde(default)]
+    cash: f64,
+    #[serde(default)]
+    positions: HashMap<String, f64>,
+}
+
+pub async fn simulate(
+    State(state): State<AppState>,
+    Json(mut payload): Json<SimulateBody>,
+) -> (StatusCode, Json<Response<SimulationReport>>) {
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        return (status, Json(Response::Error(error)));
+    }
+    match execute(payload) {
+        Ok(report) => (StatusCode::OK, Json(Response::Success(report))),
+        Err((status, error)) => (status, Json(Response::Error(error))),
+    }
+}
+
+fn execute(payload: SimulateBody) -> Result<SimulationReport, (StatusCode, &'static str)> {
+    if payload.bars.is_empty() || payload.bars.len() > MAX_BARS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A simulation takes between 1 and 10000 bars",
+        ));
+    }
+    let Some(wasm_bytes) = payload.strategy.wasm_bytes()? else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Either strategy.wasm or strategy.id is required",
+        ));
+    };
+
+    let values = payload.strategy.parameters.unwrap_or_default();
+    let parameters = match manifest::read(&wasm_bytes) {
+        Ok(Some(manifest)) => manifest.resolve(&values).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "The parameters don't match the strategy manifest",
+            )
+        })?,
+        Ok(None) if values.is_empty() => values,
+        Ok(None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The strategy declares no parameters",
+            ))
+        }
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid strategy manifest")),
+    };
+
+    let capabilities = payload.strategy.capabilities.unwrap_or_default();
+    let mut session = match SimulatedSession::new(&wasm_bytes, capabilities) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Failed to load WASM strategy: {:?}", e);
+            return Err((StatusCode::BAD_REQUEST, "Failed to load WASM strategy"));
+        }
+    };
+    session.set_parameters(parameters);
+    session.set_cash(payload.cash);
+    for (asset, quantity) in payload.positions {
+        session.set_position(asset, quantity);
+    }
+    for (asset, bars) in payload.assets {
+        session.add_asset(asset, bars);
+    }
+    Ok(session.run(payload.symbol, payload.bars))
+}
@@ -0,0 +1,142 @@
+use super::run::{new_run_id, Response};
+use super::AppState;
+use crate::store::{StrategyFilter, StrategyRecord};
+use crate::strategy::{
+    manifest,
+    wasm::{Capabilities, WasmStrategy},
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// Uploads carry the whole module in base64
+pub const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct UploadBody {
+    // Defaults to the name of the manifest
+    name: Option<String>,
+    // Base64 encoded module, as `strategy.wasm` of `/run`
+    wasm: String,
+    owner: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct StrategyQuery {
+    name: Option<String>,
+    owner: Option<String>,
+}
+
+pub async fn upload_strategy(
+    State(state): State<AppState>,
+    Json(payload): Json<UploadBody>,
+) -> (StatusCode, Json<Response<StrategyRecord>>) {
+    let module =
+        match base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &payload.wasm) {
+            Ok(module) => module,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(Response::Error("Invalid base64 encoded WASM")),
+                )
+            }
+        };
+    // Modules that wouldn't load in a run are rejected now rather than on every run
+    if let Err(e) = WasmStrategy::new(&module, Capabilities::default()) {
+        eprintln!("Failed to load WASM strategy: {:?}", e);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(Response::Error("Failed to load WASM strategy")),
+        );
+    }
+
+    let manifest = match manifest::read(&module) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Invalid strategy manifest: {}", e);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(Response::Error("Invalid strategy manifest")),
+            );
+        }
+    };
+    let name = payload
+        .name
+        .or_else(|| manifest.as_ref().and_then(|manifest| manifest.name.clone()))
+        .filter(|name| !name.trim().is_empty());
+    let Some(name) = name else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(Response::Error("A strategy name is required")),
+        );
+    };
+
+    let record = StrategyRecord {
+        id: new_run_id(),
+        name,
+        version: 0,
+        owner: payload.owner,
+        description: payload.description,
+        hash: hex::encode(Sha256::digest(&module)),
+        size: module.len() as i64,
+        created_at: chrono::Utc::now().naive_utc(),
+        manifest,
+    };
+
+    match state.store.add_strategy(record, module).await {
+        Ok(record) => (StatusCode::OK, Json(Response::Success(record))),
+        Err(e) => {
+            eprintln!("Failed to add strategy: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to add strategy")),
+            )
+        }
+    }
+}
+
+pub async fn list_strategies(
+    State(state): State<AppState>,
+    Query(query): Query<StrategyQuery>,
+) -> (StatusCode, Json<Response<Vec<StrategyRecord>>>) {
+    let filter = StrategyFilter {
+        name: query.name,
+        owner: query.owner,
+    };
+
+    match state.store.list_strategies(&filter).await {
+        Ok(strategies) => (StatusCode::OK, Json(Response::Success(strategies))),
+        Err(e) => {
+            eprintln!("Failed to list strategies: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to list strategies")),
+            )
+        }
+    }
+}
+
+pub async fn get_strategy(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<Response<StrategyRecord>>) {
+    match state.store.strategy(&id).await {
+        Ok(Some((record, _))) => (StatusCode::OK, Json(Response::Success(record))),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(Response::Error("Strategy not found")),
+        ),
+        Err(e) => {
+            eprintln!("Failed to load strategy {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Response::Error("Failed to load the strategy")),
+            )
+        }
+    }
+}
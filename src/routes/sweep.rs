@@ -0,0 +1,167 @@
+use crate::broker::fee::FeeType;
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use super::run::{
+    prepare, run_all, BatchError, Body, BrokerSettings, DataInput, Response, SimulationParameters,
+    StrategyConfig,
+};
+use super::AppState;
+
+const DEFAULT_SLIPPAGE_LEVELS: [f64; 6] = [0.0, 0.0005, 0.001, 0.002, 0.005, 0.01];
+
+const MAX_GRID_SIZE: usize = 200;
+
+#[derive(Deserialize)]
+pub struct CostSweepBody {
+    parameters: SimulationParameters,
+    data: DataInput,
+    broker: BrokerSettings,
+    strategy: StrategyConfig,
+    // Fee levels to try, defaults to the broker fees
+    fees: Option<Vec<FeeType>>,
+    // Fixed slippage levels (fraction of the price) to try
+    slippage: Option<Vec<f64>>,
+}
+
+#[derive(Serialize)]
+pub struct CostSweepResult {
+    grid: Vec<CostLevel>,
+    break_even: Vec<BreakEven>,
+}
+
+#[derive(Serialize)]
+struct CostLevel {
+    fees: Option<FeeType>,
+    slippage: f64,
+    net_profit: f64,
+    roi: f64,
+    sharpe_ratio: f64,
+    total_fees: f64,
+    total_slippage: f64,
+}
+
+// Slippage where the net profit crosses zero for a fee level, interpolated between the grid levels
+#[derive(Serialize)]
+struct BreakEven {
+    fees: Option<FeeType>,
+    // None when it can't be estimated (e.g. already losing at the lowest level)
+    slippage: Option<f64>,
+    // Still profitable at the highest level, linearly extended from the last two levels
+    extrapolated: bool,
+}
+
+pub async fn cost_sweep(
+    State(state): State<AppState>,
+    Json(mut payload): Json<CostSweepBody>,
+) -> (StatusCode, Json<Response<CostSweepResult>>) {
+    if let Err((status, error)) = payload.strategy.load(&state.store).await {
+        return (status, Json(Response::Error(error)));
+    }
+    let warmup_bars = payload.strategy.warmup_bars();
+    if let Err((status, error)) = payload
+        .data
+        .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        return (status, Json(Response::Error(error)));
+    }
+    match execute(payload).await {
+        Ok(result) => (StatusCode::OK, Json(Response::Success(result))),
+        Err(error) => error.respond(),
+    }
+}
+
+async fn execute(payload: CostSweepBody) -> Result<CostSweepResult, BatchError> {
+    let fee_levels: Vec<Option<FeeType>> = match payload.fees {
+        Some(fees) if !fees.is_empty() => fees.into_iter().map(Some).collect(),
+        _ => vec![payload.broker.fees.clone()],
+    };
+    let mut slippage_levels = payload
+        .slippage
+        .filter(|levels| !levels.is_empty())
+        .unwrap_or_else(|| DEFAULT_SLIPPAGE_LEVELS.to_vec());
+    if slippage_levels
+        .iter()
+        .any(|level| !level.is_finite() || *level < 0.0)
+    {
+        return Err((StatusCode::BAD_REQUEST, "Invalid slippage level").into());
+    }
+    slippage_levels.sort_by(f64::total_cmp);
+    slippage_levels.dedup();
+
+    if fee_levels.len() * slippage_levels.len() > MAX_GRID_SIZE {
+        return Err((StatusCode::BAD_REQUEST, "The cost grid is too large").into());
+    }
+
+    // Only the costs change between the runs
+    let mut broker = payload.broker;
+    broker.seed.get_or_insert_with(rand::random);
+
+    let mut levels = vec![];
+    let mut prepared = vec![];
+    for fees in &fee_levels {
+        for slippage in &slippage_levels {
+            let body = Body::new(
+                payload.parameters.clone(),
+                payload.data.clone(),
+                broker.with_costs(fees.clone(), *slippage),
+                payload.strategy.clone(),
+            );
+            levels.push((fees.clone(), *slippage));
+            prepared.push(prepare(body)?);
+        }
+    }
+
+    let grid: Vec<CostLevel> = run_all(prepared, |_, result| result.metrics)
+        .await?
+        .into_iter()
+        .zip(levels)
+        .map(|(metrics, (fees, slippage))| CostLevel {
+            fees,
+            slippage,
+            net_profit: metrics.net_profit,
+            roi: metrics.roi,
+            sharpe_ratio: metrics.sharpe_ratio,
+            total_fees: metrics.total_fees,
+            total_slippage: metrics.total_slippage,
+        })
+        .collect();
+
+    let break_even = grid
+        .chunks(slippage_levels.len())
+        .map(|levels| {
+            let (slippage, extrapolated) = break_even_slippage(levels);
+            BreakEven {
+                fees: levels[0].fees.clone(),
+                slippage,
+                extrapolated,
+            }
+        })
+        .collect();
+
+    Ok(CostSweepResult { grid, break_even })
+}
+
+// Levels are sorted by slippage
+fn break_even_slippage(levels: &[CostLevel]) -> (Option<f64>, bool) {
+    let zero_crossing = |a: &CostLevel, b: &CostLevel| {
+        let ratio = a.net_profit / (a.net_profit - b.net_profit);
+        a.slippage + ratio * (b.slippage - a.slippage)
+    };
+
+    let crossing = levels.windows(2).find_map(|pair| {
+        let (a, b) = (&pair[0], &pair[1]);
+        (a.net_profit > 0.0 && b.net_profit <= 0.0).then(|| zero_crossing(a, b))
+    });
+    if crossing.is_some() {
+        return (crossing, false);
+    }
+
+    match levels {
+        [.., a, b] if b.net_profit > 0.0 && b.net_profit < a.net_profit => {
+            (Some(zero_crossing(a, b)), true)
+        }
+        _ => (None, false),
+    }
+}
@@ -0,0 +1,227 @@
+use crate::analytics::significance::{self, PairedTest};
+use crate::engine::BacktestResult;
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use super::run::{
+    prepare, run_all, BatchError, Body, BrokerSettings, DataInput, PreparedRun, Response,
+    SimulationParameters, StrategyConfig,
+};
+use super::AppState;
+
+// Metrics where a lower value ranks higher (drawdowns are negative percentages)
+pub(super) const LOWER_IS_BETTER: [&str; 3] =
+    ["max_drawdown_duration_days", "total_fees", "total_slippage"];
+
+const DEFAULT_METRICS: [&str; 4] = ["sharpe_ratio", "roi", "net_profit", "max_drawdown"];
+
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+#[derive(Deserialize)]
+pub struct TournamentBody {
+    parameters: SimulationParameters,
+    data: DataInput,
+    broker: BrokerSettings,
+    strategies: Vec<Entrant>,
+    // Metrics reported on the leaderboard, the first one ranks the strategies
+    metrics: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Entrant {
+    name: Option<String>,
+    #[serde(flatten)]
+    strategy: StrategyConfig,
+}
+
+#[derive(Serialize)]
+pub struct TournamentResult {
+    seed: u64,
+    rank_by: String,
+    leaderboard: Vec<Standing>,
+    pairwise: Vec<Comparison>,
+}
+
+#[derive(Serialize)]
+struct Standing {
+    rank: usize,
+    name: String,
+    strategy_hash: String,
+    metrics: serde_json::Map<String, serde_json::Value>,
+}
+
+// Paired t-test of the per tick equity returns of two strategies
+#[derive(Serialize)]
+struct Comparison {
+    a: String,
+    b: String,
+    #[serde(flatten)]
+    test: PairedTest,
+    significant: bool,
+}
+
+struct Contender {
+    name: String,
+    strategy_hash: String,
+    result: BacktestResult,
+    returns: Vec<f64>,
+}
+
+pub async fn tournament(
+    State(state): State<AppState>,
+    Json(mut payload): Json<TournamentBody>,
+) -> (StatusCode, Json<Response<TournamentResult>>) {
+    for entrant in &mut payload.strategies {
+        if let Err((status, error)) = entrant.strategy.load(&state.store).await {
+            return (status, Json(Response::Error(error)));
+        }
+    }
+    // Enough bars for the strategy with the longest warm-up
+    let warmup_bars = payload
+        .strategies
+        .iter()
+        .map(|entrant| entrant.strategy.warmup_bars())
+        .max()
+        .unwrap_or(0);
+    if let Err((status, error)) = payload
+        .data
+        .load(&payload.parameters, warmup_bars, state.storage.as_deref())
+        .await
+    {
+        return (status, Json(Response::Error(error)));
+    }
+    match execute(payload).await {
+        Ok(result) => (StatusCode::OK, Json(Response::Success(result))),
+        Err(error) => error.respond(),
+    }
+}
+
+async fn execute(payload: TournamentBody) -> Result<TournamentResult, BatchError> {
+    if payload.strategies.len() < 2 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A tournament needs at least two strategies",
+        )
+            .into());
+    }
+
+    let metrics: Vec<String> = match payload.metrics {
+        Some(metrics) if !metrics.is_empty() => metrics,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "At least one metric is required").into()),
+        None => DEFAULT_METRICS.iter().map(|m| m.to_string()).collect(),
+    };
+
+    // Every strategy gets the same slippage draws
+    let mut broker = payload.broker;
+    let seed = *broker.seed.get_or_insert_with(rand::random);
+
+    let mut names = vec![];
+    let mut prepared = vec![];
+    for (i, entrant) in payload.strategies.into_iter().enumerate() {
+        let body = Body::new(
+            payload.parameters.clone(),
+            payload.data.clone(),
+            broker.clone(),
+            entrant.strategy,
+        );
+        let name = entrant
+            .name
+            .unwrap_or_else(|| format!("strategy_{}", i + 1));
+        names.push(name);
+        prepared.push(prepare(body)?);
+    }
+
+    let contenders: Vec<Contender> = names
+        .into_iter()
+        .zip(run_all(prepared, run_contender).await?)
+        .map(|(name, (strategy_hash, result, returns))| Contender {
+            name,
+            strategy_hash,
+            result,
+            returns,
+        })
+        .collect();
+
+    let mut standings = vec![];
+    for contender in &contenders {
+        let values = serde_json::to_value(&contender.result.metrics).unwrap_or_default();
+        let mut selected = serde_json::Map::new();
+        for metric in &metrics {
+            match values.get(metric) {
+                Some(value) if value.is_number() => {
+                    selected.insert(metric.clone(), value.clone());
+                }
+                _ => return Err((StatusCode::BAD_REQUEST, "Unknown tournament metric").into()),
+            }
+        }
+        standings.push((contender, selected));
+    }
+
+    let rank_by = metrics[0].clone();
+    let descending = !LOWER_IS_BETTER.contains(&rank_by.as_str());
+    let score = |selected: &serde_json::Map<String, serde_json::Value>| {
+        selected[&rank_by].as_f64().unwrap_or(f64::NAN)
+    };
+    standings.sort_by(|(_, a), (_, b)| {
+        let ordering = score(a).total_cmp(&score(b));
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    let mut pairwise = vec![];
+    for (i, (a, _)) in standings.iter().enumerate() {
+        for (b, _) in &standings[i + 1..] {
+            if let Some(test) = significance::paired_t_test(&a.returns, &b.returns) {
+                pairwise.push(Comparison {
+                    a: a.name.clone(),
+                    b: b.name.clone(),
+                    significant: test.p_value < SIGNIFICANCE_LEVEL,
+                    test,
+                });
+            }
+        }
+    }
+
+    let leaderboard = standings
+        .into_iter()
+        .enumerate()
+        .map(|(i, (contender, metrics))| Standing {
+            rank: i + 1,
+            name: contender.name.clone(),
+            strategy_hash: contender.strategy_hash.clone(),
+            metrics,
+        })
+        .collect();
+
+    Ok(TournamentResult {
+        seed,
+        rank_by,
+        leaderboard,
+        pairwise,
+    })
+}
+
+// Strategy hash, result and returns of a contender
+fn run_contender(
+    prepared: PreparedRun,
+    result: BacktestResult,
+) -> (String, BacktestResult, Vec<f64>) {
+    let PreparedRun {
+        engine,
+        strategy_hash,
+        ..
+    } = prepared;
+
+    let equity: Vec<f64> = engine
+        .broker
+        .trade_tracker
+        .get_equity_curve()
+        .iter()
+        .map(|(_, equity)| *equity)
+        .collect();
+
+    (strategy_hash, result, significance::returns(&equity))
+}
@@ -0,0 +1,210 @@
+use crate::broker::{fee::FeeType, preset::BrokerPreset};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use super::run::Body;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// Longest simulated period accepted
+const MAX_RANGE_YEARS: i64 = 100;
+
+// What is wrong with one field of a request, `field` is its dotted path in the body
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: impl Into<String>) -> Self {
+        FieldError {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+// Every problem of a `/run` body at once rather than the first one hit while preparing it
+pub fn validate(body: &Body) -> Result<(), Vec<FieldError>> {
+    let mut errors = vec![];
+    let parse = |field: &'static str, date: &str, errors: &mut Vec<FieldError>| {
+        NaiveDateTime::parse_from_str(date, DATE_FORMAT)
+            .map_err(|_| {
+                errors.push(FieldError::new(
+                    field,
+                    format!("Expected a `YYYY-MM-DD HH:MM:SS` date, got `{}`", date),
+                ))
+            })
+            .ok()
+    };
+
+    let start = parse(
+        "parameters.start_date",
+        &body.parameters.start_date,
+        &mut errors,
+    );
+    let end = parse(
+        "parameters.end_date",
+        &body.parameters.end_date,
+        &mut errors,
+    );
+    if let (Some(start), Some(end)) = (start, end) {
+        if start >= end {
+            errors.push(FieldError::new(
+                "parameters.end_date",
+                "Must be after the start date",
+            ));
+        } else if (end - start).num_days() > MAX_RANGE_YEARS * 366 {
+            errors.push(FieldError::new(
+                "parameters.end_date",
+                format!("The date range can't exceed {} years", MAX_RANGE_YEARS),
+            ));
+        }
+
+        // The feed is only sorted once the run is prepared
+        let timestamps = body.data.source.iter().map(|bar| bar.timestamp);
+        if let (Some(first), Some(last)) = (timestamps.clone().min(), timestamps.max()) {
+            if end < first || start > last {
+                errors.push(FieldError::new(
+                    "data.source",
+                    format!(
+                        "No bar between the start and end dates, the data covers {} to {}",
+                        first, last
+                    ),
+                ));
+            }
+        }
+    }
+
+    if body.data.source.is_empty() {
+        errors.push(FieldError::new(
+            "data.source",
+            "At least one bar is required",
+        ));
+    }
+    if body
+        .data
+        .symbol
+        .as_ref()
+        .is_some_and(|symbol| symbol.trim().is_empty())
+    {
+        errors.push(FieldError::new("data.symbol", "Can't be empty"));
+    }
+
+    let broker = &body.broker;
+    if !(broker.cash.is_finite() && broker.cash >= 0.0) {
+        errors.push(FieldError::new(
+            "broker.cash",
+            "Must be a non-negative amount",
+        ));
+    }
+    let fees = match &broker.fees {
+        Some(FeeType::Flat(fee) | FeeType::Percentage(fee)) => vec![*fee],
+        Some(FeeType::PerUnit {
+            rate,
+            minimum,
+            maximum_percent,
+        }) => vec![*rate, *minimum, *maximum_percent],
+        None => vec![],
+    };
+    if !fees.iter().all(|fee| fee.is_finite() && *fee >= 0.0) {
+        errors.push(FieldError::new("broker.fees", "Fees can't be negative"));
+    }
+    if let Some(slippage) = &broker.slippage {
+        if !(slippage.min.is_finite() && slippage.max.is_finite()) {
+            errors.push(FieldError::new("broker.slippage", "Must be finite numbers"));
+        } else if slippage.min > slippage.max {
+            errors.push(FieldError::new(
+                "broker.slippage",
+                format!(
+                    "The minimum ({}) is above the maximum ({})",
+                    slippage.min, slippage.max
+                ),
+            ));
+        }
+    }
+    if let Some(name) = &broker.preset {
+        if BrokerPreset::named(name).is_none() {
+            errors.push(FieldError::new(
+                "broker.preset",
+                format!("Unknown preset `{}`", name),
+            ));
+        }
+    }
+    if let Some(lot_size) = broker.lot_size {
+        if !(lot_size.is_finite() && lot_size > 0.0) {
+            errors.push(FieldError::new(
+                "broker.lot_size",
+                "Must be a positive size",
+            ));
+        }
+    }
+
+    let wasm = body.strategy.wasm.is_some() || body.strategy.module.is_some();
+    let kinds = [
+        wasm,
+        body.strategy.rules.is_some(),
+        body.strategy.ensemble.is_some(),
+        body.strategy.sub_accounts.is_some(),
+    ];
+    if kinds.iter().filter(|kind| **kind).count() != 1 {
+        errors.push(FieldError::new(
+            "strategy",
+            "Either strategy.wasm, strategy.id, strategy.rules, strategy.ensemble or strategy.sub_accounts is required",
+        ));
+    }
+
+    for strategy in std::iter::once(&body.strategy).chain(body.strategy.members()) {
+        match strategy.manifest() {
+            Ok(manifest) => {
+                if let Err((field, message)) = body.strategy_parameters(strategy, manifest.as_ref())
+                {
+                    errors.push(FieldError::new(field, message));
+                }
+            }
+            Err(error) => errors.push(FieldError::new(
+                "strategy.wasm",
+                format!("Invalid manifest: {}", error),
+            )),
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let body: Body = serde_json::from_value(serde_json::json!({
+            "parameters": { "start_date": "2024-02-01 00:00:00", "end_date": "2024-01-01 00:00:00" },
+            "data": { "symbol": " ", "source": [] },
+            "broker": { "cash": -1.0, "slippage": { "min": 0.02, "max": 0.01 } },
+            "strategy": {}
+        }))
+        .unwrap();
+
+        let fields: Vec<&str> = validate(&body)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "parameters.end_date",
+                "data.source",
+                "data.symbol",
+                "broker.cash",
+                "broker.slippage",
+                "strategy"
+            ]
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        LocalStorage { root }
+    }
+
+    pub fn from_env() -> Self {
+        let root = std::env::var("KRONOS_STORAGE_DIR").unwrap_or_else(|_| "artifacts".to_string());
+        LocalStorage::new(PathBuf::from(root))
+    }
+
+    pub async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        tokio::fs::write(path, bytes)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        match tokio::fs::read(self.root.join(key)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
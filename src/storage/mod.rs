@@ -0,0 +1,41 @@
+pub mod local;
+pub mod object;
+
+use local::LocalStorage;
+use object::ObjectStorage;
+
+// Where run artifacts and cached data are persisted, configured with `KRONOS_STORAGE`
+pub enum Storage {
+    Local(LocalStorage),
+    Object(ObjectStorage),
+}
+
+impl Storage {
+    // Returns None when no storage is configured, artifacts are then only returned in responses
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let kind = match std::env::var("KRONOS_STORAGE") {
+            Ok(kind) => kind,
+            Err(_) => return Ok(None),
+        };
+
+        match kind.as_str() {
+            "local" => Ok(Some(Storage::Local(LocalStorage::from_env()))),
+            "s3" | "gcs" => Ok(Some(Storage::Object(ObjectStorage::from_env(&kind)?))),
+            _ => Err(format!("Unknown storage backend: {}", kind)),
+        }
+    }
+
+    pub async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        match self {
+            Storage::Local(storage) => storage.put(key, bytes).await,
+            Storage::Object(storage) => storage.put(key, bytes).await,
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Storage::Local(storage) => storage.get(key).await,
+            Storage::Object(storage) => storage.get(key).await,
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::{
+    experiment::Experiment, Annotation, RunFilter, RunRecord, StrategyFilter, StrategyRecord,
+};
+use std::sync::Mutex;
+
+const DEFAULT_MAX_RUNS: usize = 1000;
+
+// In-process index of the runs, results outlive a restart only through the artifact storage.
+// Meant for development, it keeps the last `max_runs` runs with their full results
+pub struct EmbeddedStore {
+    runs: Mutex<Vec<(RunRecord, serde_json::Value)>>,
+    max_runs: usize,
+    strategies: Mutex<Vec<(StrategyRecord, Vec<u8>)>>,
+    experiments: Mutex<Vec<Experiment>>,
+}
+
+impl EmbeddedStore {
+    pub fn new(max_runs: usize) -> Self {
+        EmbeddedStore {
+            runs: Mutex::new(vec![]),
+            max_runs,
+            strategies: Mutex::new(vec![]),
+            experiments: Mutex::new(vec![]),
+        }
+    }
+
+    // Bounded by `KRONOS_MAX_RUNS`
+    pub fn from_env() -> Result<Self, String> {
+        let max_runs = match std::env::var("KRONOS_MAX_RUNS") {
+            Ok(value) => value
+                .parse()
+                .ok()
+                .filter(|max_runs| *max_runs > 0)
+                .ok_or_else(|| format!("Invalid KRONOS_MAX_RUNS: {}", value))?,
+            Err(_) => DEFAULT_MAX_RUNS,
+        };
+        Ok(EmbeddedStore::new(max_runs))
+    }
+
+    // Past `max_runs`, evicts the oldest run that isn't starred, or the oldest one
+    pub fn insert(&self, record: RunRecord, result: serde_json::Value) -> Result<(), String> {
+        let mut runs = self.runs.lock().map_err(|e| e.to_string())?;
+        runs.push((record, result));
+        if runs.len() > self.max_runs {
+            let oldest = runs
+                .iter()
+                .position(|(record, _)| !record.starred)
+                .unwrap_or(0);
+            runs.remove(oldest);
+        }
+        Ok(())
+    }
+
+    pub fn list(&self, filter: &RunFilter) -> Result<Vec<RunRecord>, String> {
+        let runs = self.runs.lock().map_err(|e| e.to_string())?;
+        Ok(runs
+            .iter()
+            .rev()
+            .filter(|(record, _)| filter.matches(record))
+            .skip(filter.offset)
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .map(|(record, _)| record.clone())
+            .collect())
+    }
+
+    pub fn annotate(&self, id: &str, annotation: &Annotation) -> Result<Option<RunRecord>, String> {
+        let mut runs = self.runs.lock().map_err(|e| e.to_string())?;
+        Ok(runs
+            .iter_mut()
+            .find(|(record, _)| record.id == id)
+            .map(|(record, _)| {
+                annotation.apply(record);
+                record.clone()
+            }))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<serde_json::Value>, String> {
+        let runs = self.runs.lock().map_err(|e| e.to_string())?;
+        Ok(runs
+            .iter()
+            .find(|(record, _)| record.id == id)
+            .map(|(_, result)| result.clone()))
+    }
+
+    pub fn add_strategy(
+        &self,
+        mut record: StrategyRecord,
+        module: Vec<u8>,
+    ) -> Result<StrategyRecord, String> {
+        let mut strategies = self.strategies.lock().map_err(|e| e.to_string())?;
+        let latest = strategies
+            .iter()
+            .rev()
+            .map(|(latest, _)| latest)
+            .find(|latest| latest.name == record.name && latest.owner == record.owner);
+        if let Some(latest) = latest {
+            if latest.hash == record.hash {
+                return Ok(latest.clone());
+            }
+            record.version = latest.version + 1;
+        } else {
+            record.version = 1;
+        }
+        strategies.push((record.clone(), module));
+        Ok(record)
+    }
+
+    pub fn list_strategies(&self, filter: &StrategyFilter) -> Result<Vec<StrategyRecord>, String> {
+        let strategies = self.strategies.lock().map_err(|e| e.to_string())?;
+        Ok(strategies
+            .iter()
+            .rev()
+            .filter(|(record, _)| filter.matches(record))
+            .map(|(record, _)| record.clone())
+            .collect())
+    }
+
+    pub fn strategy(&self, id: &str) -> Result<Option<(StrategyRecord, Vec<u8>)>, String> {
+        let strategies = self.strategies.lock().map_err(|e| e.to_string())?;
+        Ok(strategies
+            .iter()
+            .find(|(record, _)| record.id == id)
+            .cloned())
+    }
+
+    pub fn add_experiment(&self, experiment: &Experiment) -> Result<(), String> {
+        self.experiments
+            .lock()
+            .map_err(|e| e.to_string())?
+            .push(experiment.clone());
+        Ok(())
+    }
+
+    pub fn experiment(&self, id: &str) -> Result<Option<Experiment>, String> {
+        let experiments = self.experiments.lock().map_err(|e| e.to_string())?;
+        Ok(experiments
+            .iter()
+            .find(|experiment| experiment.id == id)
+            .cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, name: &str, owner: Option<&str>, hash: &str) -> StrategyRecord {
+        StrategyRecord {
+            id: id.to_string(),
+            name: name.to_string(),
+            version: 0,
+            owner: owner.map(str::to_string),
+            description: None,
+            hash: hash.to_string(),
+            size: 0,
+            created_at: chrono::NaiveDateTime::default(),
+            manifest: None,
+        }
+    }
+
+    #[test]
+    fn versions_strategies_per_name_and_owner() {
+        let store = EmbeddedStore::new(DEFAULT_MAX_RUNS);
+        let add = |id, name, owner, hash| store.add_strategy(record(id, name, owner, hash), vec![]);
+
+        assert_eq!(add("a", "sma", Some("ana"), "h1").unwrap().version, 1);
+        assert_eq!(add("b", "sma", Some("ana"), "h2").unwrap().version, 2);
+        // The same module again is the latest version, not a new one
+        let same = add("c", "sma", Some("ana"), "h2").unwrap();
+        assert_eq!((same.id.as_str(), same.version), ("b", 2));
+        assert_eq!(add("d", "sma", Some("bo"), "h2").unwrap().version, 1);
+        assert_eq!(add("e", "sma", None, "h1").unwrap().version, 1);
+
+        let filter = StrategyFilter {
+            owner: Some("ana".to_string()),
+            ..Default::default()
+        };
+        let listed = store.list_strategies(&filter).unwrap();
+        let ids: Vec<&str> = listed.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a"]);
+        assert!(store.strategy("d").unwrap().is_some());
+        assert!(store.strategy("c").unwrap().is_none());
+    }
+
+    fn run(id: &str) -> RunRecord {
+        let time = chrono::NaiveDateTime::default();
+        RunRecord {
+            id: id.to_string(),
+            created_at: time,
+            symbol: None,
+            start_date: time,
+            end_date: time,
+            strategy_hash: String::new(),
+            metrics: serde_json::Value::Null,
+            notes: None,
+            tags: vec![],
+            starred: false,
+            experiment_id: None,
+            kronos_version: None,
+            data_hash: None,
+            asset_hashes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn annotates_and_filters_runs_by_tag() {
+        let store = EmbeddedStore::new(DEFAULT_MAX_RUNS);
+        for id in ["a", "b"] {
+            store.insert(run(id), serde_json::Value::Null).unwrap();
+        }
+
+        let mut annotation = Annotation {
+            notes: Some("Overfit".to_string()),
+            tags: Some(vec![
+                " momentum ".to_string(),
+                "momentum".to_string(),
+                "".to_string(),
+            ]),
+            starred: Some(true),
+        };
+        annotation.normalize();
+        let record = store.annotate("a", &annotation).unwrap().unwrap();
+        assert_eq!(record.tags, ["momentum"]);
+        assert!(record.starred);
+        assert!(store.annotate("c", &annotation).unwrap().is_none());
+
+        // Left out fields are kept, an empty note is removed
+        let clear = Annotation {
+            notes: Some(String::new()),
+            ..Default::default()
+        };
+        let record = store.annotate("a", &clear).unwrap().unwrap();
+        assert_eq!((record.notes, record.tags.len()), (None, 1));
+
+        let filter = RunFilter {
+            tag: Some("momentum".to_string()),
+            ..Default::default()
+        };
+        let runs = store.list(&filter).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, "a");
+    }
+
+    #[test]
+    fn evicts_the_oldest_runs_that_are_not_starred() {
+        let store = EmbeddedStore::new(2);
+        for id in ["a", "b"] {
+            store.insert(run(id), serde_json::Value::Null).unwrap();
+        }
+        let star = Annotation {
+            starred: Some(true),
+            ..Default::default()
+        };
+        store.annotate("a", &star).unwrap();
+
+        for id in ["c", "d"] {
+            store.insert(run(id), serde_json::Value::Null).unwrap();
+        }
+        let runs = store.list(&RunFilter::default()).unwrap();
+        let ids: Vec<&str> = runs.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(ids, ["d", "a"]);
+        assert!(store.get("b").unwrap().is_none());
+    }
+
+    #[test]
+    fn pages_the_runs_most_recent_first() {
+        let store = EmbeddedStore::new(DEFAULT_MAX_RUNS);
+        for id in ["a", "b", "c", "d"] {
+            store.insert(run(id), serde_json::Value::Null).unwrap();
+        }
+        let page = |limit, offset| {
+            let filter = RunFilter {
+                limit,
+                offset,
+                ..Default::default()
+            };
+            let runs = store.list(&filter).unwrap();
+            runs.into_iter().map(|record| record.id).collect::<Vec<_>>()
+        };
+        assert_eq!(page(Some(2), 0), ["d", "c"]);
+        assert_eq!(page(Some(2), 2), ["b", "a"]);
+        assert_eq!(page(None, 3), ["a"]);
+        assert!(page(Some(2), 4).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use super::RunRecord;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+// Runs grouped under a shared description, created with `POST /experiments` and referenced by
+// the runs with `experiment_id`
+#[derive(Serialize, Debug, Clone)]
+pub struct Experiment {
+    pub id: String,
+    pub created_at: NaiveDateTime,
+    pub description: Option<String>,
+    // Shared settings of the member runs as given, e.g. the grid they cover
+    pub parameters: serde_json::Value,
+}
+
+// Lowest, mean and highest value of a metric over the member runs
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MetricRange {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+// Overview of the member runs returned by `GET /experiments/{id}`
+#[derive(Serialize, Debug)]
+pub struct ExperimentSummary {
+    pub runs: usize,
+    // Distinct values over the runs, more than one hash means the runs don't share their inputs
+    pub symbols: Vec<String>,
+    pub strategy_hashes: Vec<String>,
+    pub data_hashes: Vec<String>,
+    pub kronos_versions: Vec<String>,
+    // Run with the highest Sharpe ratio
+    pub best_run: Option<String>,
+    pub net_profit: Option<MetricRange>,
+    pub sharpe_ratio: Option<MetricRange>,
+    pub max_drawdown: Option<MetricRange>,
+}
+
+impl ExperimentSummary {
+    pub fn new(runs: &[RunRecord]) -> Self {
+        let distinct = |values: Vec<&String>| {
+            let mut values: Vec<String> = values.into_iter().cloned().collect();
+            values.sort();
+            values.dedup();
+            values
+        };
+        let best_run = runs
+            .iter()
+            .filter_map(|run| Some((run, run.metrics["sharpe_ratio"].as_f64()?)))
+            .filter(|(_, sharpe)| sharpe.is_finite())
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(run, _)| run.id.clone());
+
+        ExperimentSummary {
+            runs: runs.len(),
+            symbols: distinct(runs.iter().filter_map(|run| run.symbol.as_ref()).collect()),
+            strategy_hashes: distinct(runs.iter().map(|run| &run.strategy_hash).collect()),
+            data_hashes: distinct(
+                runs.iter()
+                    .filter_map(|run| run.data_hash.as_ref())
+                    .collect(),
+            ),
+            kronos_versions: distinct(
+                runs.iter()
+                    .filter_map(|run| run.kronos_version.as_ref())
+                    .collect(),
+            ),
+            best_run,
+            net_profit: metric_range(runs, "net_profit"),
+            sharpe_ratio: metric_range(runs, "sharpe_ratio"),
+            max_drawdown: metric_range(runs, "max_drawdown"),
+        }
+    }
+}
+
+fn metric_range(runs: &[RunRecord], metric: &str) -> Option<MetricRange> {
+    let values: Vec<f64> = runs
+        .iter()
+        .filter_map(|run| run.metrics[metric].as_f64())
+        .filter(|value| value.is_finite())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(MetricRange {
+        min: values.iter().copied().fold(f64::INFINITY, f64::min),
+        mean: values.iter().sum::<f64>() / values.len() as f64,
+        max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn run(id: &str, data_hash: &str, net_profit: f64, sharpe: Option<f64>) -> RunRecord {
+        let time = NaiveDateTime::default();
+        RunRecord {
+            id: id.to_string(),
+            created_at: time,
+            symbol: Some("AAPL".to_string()),
+            start_date: time,
+            end_date: time,
+            strategy_hash: "s1".to_string(),
+            metrics: serde_json::json!({ "net_profit": net_profit, "sharpe_ratio": sharpe }),
+            notes: None,
+            tags: vec![],
+            starred: false,
+            experiment_id: Some("e1".to_string()),
+            kronos_version: Some("0.1.0".to_string()),
+            data_hash: Some(data_hash.to_string()),
+            asset_hashes: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn summarizes_the_member_runs() {
+        let runs = [
+            run("a", "d1", 100.0, Some(0.5)),
+            run("b", "d2", -50.0, Some(1.5)),
+            run("c", "d1", 250.0, None),
+        ];
+        let summary = ExperimentSummary::new(&runs);
+
+        assert_eq!(summary.runs, 3);
+        assert_eq!(summary.symbols, ["AAPL"]);
+        assert_eq!(summary.strategy_hashes, ["s1"]);
+        assert_eq!(summary.data_hashes, ["d1", "d2"]);
+        // Runs without a Sharpe ratio are left out of its range and of the best run
+        assert_eq!(summary.best_run.as_deref(), Some("b"));
+        assert_eq!(
+            summary.net_profit,
+            Some(MetricRange {
+                min: -50.0,
+                mean: 100.0,
+                max: 250.0
+            })
+        );
+        assert_eq!(summary.sharpe_ratio.map(|range| range.mean), Some(1.0));
+        assert_eq!(summary.max_drawdown, None);
+
+        let empty = ExperimentSummary::new(&[]);
+        assert_eq!((empty.runs, empty.best_run), (0, None));
+    }
+}
@@ -0,0 +1,340 @@
+pub mod embedded;
+pub mod experiment;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+use crate::analytics::trade::Trade;
+use crate::strategy::manifest::Manifest;
+use chrono::NaiveDateTime;
+use embedded::EmbeddedStore;
+use experiment::Experiment;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Queryable index of the runs, selected with `KRONOS_DATABASE_URL`
+pub enum RunStore {
+    Embedded(EmbeddedStore),
+    #[cfg(feature = "postgres")]
+    Postgres(postgres::PostgresStore),
+}
+
+// Summary of a run returned by `GET /runs`
+#[derive(Serialize, Debug, Clone)]
+pub struct RunRecord {
+    pub id: String,
+    pub created_at: NaiveDateTime,
+    pub symbol: Option<String>,
+    pub start_date: NaiveDateTime,
+    pub end_date: NaiveDateTime,
+    pub strategy_hash: String,
+    pub metrics: serde_json::Value,
+    // Set with `PATCH /runs/{id}` to organize the runs
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+    pub starred: bool,
+    // Experiment the run was made for, given with `experiment_id`
+    pub experiment_id: Option<String>,
+    // Server version and content hashes of the bars the result comes from, None for the runs
+    // indexed before they were recorded
+    pub kronos_version: Option<String>,
+    pub data_hash: Option<String>,
+    pub asset_hashes: BTreeMap<String, String>,
+}
+
+// Changes of `PATCH /runs/{id}`, the fields left out are kept
+#[derive(Deserialize, Debug, Default)]
+pub struct Annotation {
+    // An empty note removes it
+    pub notes: Option<String>,
+    // Replaces the tags
+    pub tags: Option<Vec<String>>,
+    pub starred: Option<bool>,
+}
+
+impl Annotation {
+    // Trimmed tags without blanks and duplicates
+    pub fn normalize(&mut self) {
+        if let Some(tags) = &mut self.tags {
+            let mut normalized: Vec<String> = vec![];
+            for tag in tags.iter().map(|tag| tag.trim()) {
+                if !tag.is_empty() && !normalized.iter().any(|other| other == tag) {
+                    normalized.push(tag.to_string());
+                }
+            }
+            *tags = normalized;
+        }
+    }
+
+    pub fn apply(&self, record: &mut RunRecord) {
+        if let Some(notes) = &self.notes {
+            record.notes = Some(notes.clone()).filter(|notes| !notes.is_empty());
+        }
+        if let Some(tags) = &self.tags {
+            record.tags = tags.clone();
+        }
+        if let Some(starred) = self.starred {
+            record.starred = starred;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RunFilter {
+    pub symbol: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub min_sharpe: Option<f64>,
+    pub strategy_hash: Option<String>,
+    pub tag: Option<String>,
+    pub starred: Option<bool>,
+    pub experiment: Option<String>,
+    // Page of the matching runs, all of them without a limit
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl RunFilter {
+    pub fn matches(&self, record: &RunRecord) -> bool {
+        if self.symbol.is_some() && record.symbol != self.symbol {
+            return false;
+        }
+        if self.from.is_some_and(|from| record.start_date < from) {
+            return false;
+        }
+        if self.to.is_some_and(|to| record.end_date > to) {
+            return false;
+        }
+        if let Some(min_sharpe) = self.min_sharpe {
+            let sharpe = record.metrics["sharpe_ratio"].as_f64();
+            if !sharpe.is_some_and(|sharpe| sharpe >= min_sharpe) {
+                return false;
+            }
+        }
+        if self
+            .strategy_hash
+            .as_ref()
+            .is_some_and(|hash| &record.strategy_hash != hash)
+        {
+            return false;
+        }
+        if self
+            .tag
+            .as_ref()
+            .is_some_and(|tag| !record.tags.contains(tag))
+        {
+            return false;
+        }
+        if self
+            .starred
+            .is_some_and(|starred| record.starred != starred)
+        {
+            return false;
+        }
+        if self.experiment.is_some() && record.experiment_id != self.experiment {
+            return false;
+        }
+        true
+    }
+}
+
+// WASM module of the strategy library returned by `/strategies`
+#[derive(Serialize, Debug, Clone)]
+pub struct StrategyRecord {
+    pub id: String,
+    pub name: String,
+    // Counted from 1 for each name of an owner
+    pub version: i32,
+    pub owner: Option<String>,
+    pub description: Option<String>,
+    pub hash: String,
+    pub size: i64,
+    pub created_at: NaiveDateTime,
+    pub manifest: Option<Manifest>,
+}
+
+#[derive(Default)]
+pub struct StrategyFilter {
+    pub name: Option<String>,
+    pub owner: Option<String>,
+}
+
+impl StrategyFilter {
+    pub fn matches(&self, record: &StrategyRecord) -> bool {
+        if self.name.as_ref().is_some_and(|name| &record.name != name) {
+            return false;
+        }
+        if self.owner.is_some() && record.owner != self.owner {
+            return false;
+        }
+        true
+    }
+}
+
+impl RunStore {
+    pub async fn from_env() -> Result<Self, String> {
+        match std::env::var("KRONOS_DATABASE_URL") {
+            #[cfg(feature = "postgres")]
+            Ok(url) => Ok(RunStore::Postgres(
+                postgres::PostgresStore::connect(&url).await?,
+            )),
+            #[cfg(not(feature = "postgres"))]
+            Ok(_) => Err("KRONOS_DATABASE_URL requires the `postgres` feature".to_string()),
+            Err(_) => Ok(RunStore::Embedded(EmbeddedStore::from_env()?)),
+        }
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(unused_variables))]
+    pub async fn insert(
+        &self,
+        record: RunRecord,
+        trades: &[Trade],
+        result: serde_json::Value,
+    ) -> Result<(), String> {
+        match self {
+            RunStore::Embedded(store) => store.insert(record, result),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.insert(record, trades, result).await,
+        }
+    }
+
+    pub async fn list(&self, filter: &RunFilter) -> Result<Vec<RunRecord>, String> {
+        match self {
+            RunStore::Embedded(store) => store.list(filter),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.list(filter).await,
+        }
+    }
+
+    // The annotated run, None when the store doesn't have it
+    pub async fn annotate(
+        &self,
+        id: &str,
+        annotation: &Annotation,
+    ) -> Result<Option<RunRecord>, String> {
+        match self {
+            RunStore::Embedded(store) => store.annotate(id, annotation),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.annotate(id, annotation).await,
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<serde_json::Value>, String> {
+        match self {
+            RunStore::Embedded(store) => store.get(id),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.get(id).await,
+        }
+    }
+
+    // Adds the module as the next version of its name, or returns the latest version when it
+    // holds the same module
+    pub async fn add_strategy(
+        &self,
+        record: StrategyRecord,
+        module: Vec<u8>,
+    ) -> Result<StrategyRecord, String> {
+        match self {
+            RunStore::Embedded(store) => store.add_strategy(record, module),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.add_strategy(record, module).await,
+        }
+    }
+
+    pub async fn list_strategies(
+        &self,
+        filter: &StrategyFilter,
+    ) -> Result<Vec<StrategyRecord>, String> {
+        match self {
+            RunStore::Embedded(store) => store.list_strategies(filter),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.list_strategies(filter).await,
+        }
+    }
+
+    pub async fn strategy(&self, id: &str) -> Result<Option<(StrategyRecord, Vec<u8>)>, String> {
+        match self {
+            RunStore::Embedded(store) => store.strategy(id),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.strategy(id).await,
+        }
+    }
+
+    pub async fn add_experiment(&self, experiment: &Experiment) -> Result<(), String> {
+        match self {
+            RunStore::Embedded(store) => store.add_experiment(experiment),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.add_experiment(experiment).await,
+        }
+    }
+
+    pub async fn experiment(&self, id: &str) -> Result<Option<Experiment>, String> {
+        match self {
+            RunStore::Embedded(store) => store.experiment(id),
+            #[cfg(feature = "postgres")]
+            RunStore::Postgres(store) => store.experiment(id).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_the_runs_by_symbol_dates_sharpe_and_strategy() {
+        let day = |day: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let record = RunRecord {
+            id: "a".to_string(),
+            created_at: day(20),
+            symbol: Some("AAPL".to_string()),
+            start_date: day(5),
+            end_date: day(15),
+            strategy_hash: "abc".to_string(),
+            metrics: serde_json::json!({ "sharpe_ratio": 1.5 }),
+            notes: None,
+            tags: vec![],
+            starred: false,
+            experiment_id: None,
+            kronos_version: None,
+            data_hash: None,
+            asset_hashes: BTreeMap::new(),
+        };
+        let matches = |filter: RunFilter| filter.matches(&record);
+
+        assert!(matches(RunFilter::default()));
+        assert!(matches(RunFilter {
+            symbol: Some("AAPL".to_string()),
+            from: Some(day(5)),
+            to: Some(day(15)),
+            min_sharpe: Some(1.5),
+            strategy_hash: Some("abc".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(RunFilter {
+            symbol: Some("MSFT".to_string()),
+            ..Default::default()
+        }));
+        // The simulated period has to lie within the dates
+        assert!(!matches(RunFilter {
+            from: Some(day(6)),
+            ..Default::default()
+        }));
+        assert!(!matches(RunFilter {
+            to: Some(day(14)),
+            ..Default::default()
+        }));
+        assert!(!matches(RunFilter {
+            min_sharpe: Some(2.0),
+            ..Default::default()
+        }));
+        assert!(!matches(RunFilter {
+            strategy_hash: Some("def".to_string()),
+            ..Default::default()
+        }));
+    }
+}
@@ -0,0 +1,470 @@
+use super::{
+    experiment::Experiment, Annotation, RunFilter, RunRecord, StrategyFilter, StrategyRecord,
+};
+use crate::analytics::trade::Trade;
+use sqlx::{postgres::PgPoolOptions, PgPool, Postgres, QueryBuilder, Row};
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS runs (
+        id TEXT PRIMARY KEY,
+        created_at TIMESTAMP NOT NULL,
+        symbol TEXT,
+        start_date TIMESTAMP NOT NULL,
+        end_date TIMESTAMP NOT NULL,
+        strategy_hash TEXT NOT NULL,
+        sharpe_ratio DOUBLE PRECISION,
+        roi DOUBLE PRECISION,
+        net_profit DOUBLE PRECISION,
+        metrics JSONB NOT NULL,
+        result JSONB NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS trades (
+        run_id TEXT NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+        trade_id BIGINT NOT NULL,
+        asset TEXT NOT NULL,
+        entry_time TIMESTAMP NOT NULL,
+        entry_price DOUBLE PRECISION NOT NULL,
+        quantity DOUBLE PRECISION NOT NULL,
+        exit_time TIMESTAMP,
+        exit_price DOUBLE PRECISION,
+        profit_loss DOUBLE PRECISION,
+        return_pct DOUBLE PRECISION,
+        PRIMARY KEY (run_id, trade_id)
+    )",
+    "CREATE INDEX IF NOT EXISTS runs_symbol_idx ON runs (symbol)",
+    "CREATE INDEX IF NOT EXISTS runs_strategy_hash_idx ON runs (strategy_hash)",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS notes TEXT",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}'",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS starred BOOLEAN NOT NULL DEFAULT FALSE",
+    "CREATE INDEX IF NOT EXISTS runs_tags_idx ON runs USING GIN (tags)",
+    "CREATE TABLE IF NOT EXISTS strategies (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        owner TEXT,
+        description TEXT,
+        hash TEXT NOT NULL,
+        size BIGINT NOT NULL,
+        created_at TIMESTAMP NOT NULL,
+        module BYTEA NOT NULL
+    )",
+    "CREATE UNIQUE INDEX IF NOT EXISTS strategies_version_idx ON strategies (COALESCE(owner, ''), name, version)",
+    "ALTER TABLE strategies ADD COLUMN IF NOT EXISTS manifest JSONB",
+    "CREATE TABLE IF NOT EXISTS experiments (
+        id TEXT PRIMARY KEY,
+        created_at TIMESTAMP NOT NULL,
+        description TEXT,
+        parameters JSONB NOT NULL
+    )",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS experiment_id TEXT REFERENCES experiments(id)",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS kronos_version TEXT",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS data_hash TEXT",
+    "ALTER TABLE runs ADD COLUMN IF NOT EXISTS asset_hashes JSONB NOT NULL DEFAULT '{}'",
+    "CREATE INDEX IF NOT EXISTS runs_experiment_id_idx ON runs (experiment_id)",
+];
+
+const RUN_COLUMNS: &str = "id, created_at, symbol, start_date, end_date, strategy_hash, metrics, \
+    notes, tags, starred, experiment_id, kronos_version, data_hash, asset_hashes";
+
+// Trades inserted by statement, under the 65535 bind parameters of a query
+const TRADE_BATCH: usize = 1000;
+
+const STRATEGY_COLUMNS: &str =
+    "id, name, version, owner, description, hash, size, created_at, manifest";
+
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for statement in SCHEMA {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(PostgresStore { pool })
+    }
+
+    pub async fn insert(
+        &self,
+        record: RunRecord,
+        trades: &[Trade],
+        result: serde_json::Value,
+    ) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+
+        sqlx::query(
+            "INSERT INTO runs (id, created_at, symbol, start_date, end_date, strategy_hash, sharpe_ratio, roi, net_profit, metrics, result, notes, tags, starred, experiment_id, kronos_version, data_hash, asset_hashes)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)",
+        )
+        .bind(&record.id)
+        .bind(record.created_at)
+        .bind(&record.symbol)
+        .bind(record.start_date)
+        .bind(record.end_date)
+        .bind(&record.strategy_hash)
+        .bind(record.metrics["sharpe_ratio"].as_f64())
+        .bind(record.metrics["roi"].as_f64())
+        .bind(record.metrics["net_profit"].as_f64())
+        .bind(&record.metrics)
+        .bind(&result)
+        .bind(&record.notes)
+        .bind(&record.tags)
+        .bind(record.starred)
+        .bind(&record.experiment_id)
+        .bind(&record.kronos_version)
+        .bind(&record.data_hash)
+        .bind(serde_json::to_value(&record.asset_hashes).unwrap_or_default())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for chunk in trades.chunks(TRADE_BATCH) {
+            trades_query(&record.id, chunk)
+                .build()
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        tx.commit().await.map_err(|e| e.to_string())
+    }
+
+    pub async fn list(&self, filter: &RunFilter) -> Result<Vec<RunRecord>, String> {
+        let rows = list_query(filter)
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        rows.iter().map(run_record).collect()
+    }
+
+    pub async fn annotate(
+        &self,
+        id: &str,
+        annotation: &Annotation,
+    ) -> Result<Option<RunRecord>, String> {
+        let row = sqlx::query(&format!(
+            "UPDATE runs SET
+                notes = CASE WHEN $2::TEXT IS NULL THEN notes ELSE NULLIF($2, '') END,
+                tags = COALESCE($3, tags),
+                starred = COALESCE($4, starred)
+             WHERE id = $1
+             RETURNING {RUN_COLUMNS}"
+        ))
+        .bind(id)
+        .bind(&annotation.notes)
+        .bind(&annotation.tags)
+        .bind(annotation.starred)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        row.as_ref().map(run_record).transpose()
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<serde_json::Value>, String> {
+        let row = sqlx::query("SELECT result FROM runs WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        row.map(|row| row.try_get("result").map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    pub async fn add_strategy(
+        &self,
+        mut record: StrategyRecord,
+        module: Vec<u8>,
+    ) -> Result<StrategyRecord, String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+
+        // Concurrent uploads of a name race on the version, the unique index rejects the loser
+        let latest = sqlx::query(&format!(
+            "SELECT {STRATEGY_COLUMNS} FROM strategies
+             WHERE name = $1 AND owner IS NOT DISTINCT FROM $2
+             ORDER BY version DESC LIMIT 1"
+        ))
+        .bind(&record.name)
+        .bind(&record.owner)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|row| strategy_record(&row))
+        .transpose()?;
+
+        match latest {
+            Some(latest) if latest.hash == record.hash => return Ok(latest),
+            Some(latest) => record.version = latest.version + 1,
+            None => record.version = 1,
+        }
+
+        sqlx::query(&format!(
+            "INSERT INTO strategies ({STRATEGY_COLUMNS}, module)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
+        ))
+        .bind(&record.id)
+        .bind(&record.name)
+        .bind(record.version)
+        .bind(&record.owner)
+        .bind(&record.description)
+        .bind(&record.hash)
+        .bind(record.size)
+        .bind(record.created_at)
+        .bind(
+            record
+                .manifest
+                .as_ref()
+                .map(|manifest| serde_json::to_value(manifest).unwrap_or_default()),
+        )
+        .bind(module)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        tx.commit().await.map_err(|e| e.to_string())?;
+        Ok(record)
+    }
+
+    pub async fn list_strategies(
+        &self,
+        filter: &StrategyFilter,
+    ) -> Result<Vec<StrategyRecord>, String> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {STRATEGY_COLUMNS} FROM strategies WHERE TRUE"
+        ));
+        if let Some(name) = &filter.name {
+            query.push(" AND name = ").push_bind(name);
+        }
+        if let Some(owner) = &filter.owner {
+            query.push(" AND owner = ").push_bind(owner);
+        }
+        query.push(" ORDER BY created_at DESC");
+
+        let rows = query
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        rows.iter().map(strategy_record).collect()
+    }
+
+    pub async fn strategy(&self, id: &str) -> Result<Option<(StrategyRecord, Vec<u8>)>, String> {
+        let row = sqlx::query(&format!(
+            "SELECT {STRATEGY_COLUMNS}, module FROM strategies WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        row.map(|row| {
+            let module = row.try_get("module").map_err(|e| e.to_string())?;
+            Ok((strategy_record(&row)?, module))
+        })
+        .transpose()
+    }
+
+    pub async fn add_experiment(&self, experiment: &Experiment) -> Result<(), String> {
+        sqlx::query(
+            "INSERT INTO experiments (id, created_at, description, parameters) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&experiment.id)
+        .bind(experiment.created_at)
+        .bind(&experiment.description)
+        .bind(&experiment.parameters)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub async fn experiment(&self, id: &str) -> Result<Option<Experiment>, String> {
+        let row = sqlx::query(
+            "SELECT id, created_at, description, parameters FROM experiments WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        row.map(|row| {
+            Ok(Experiment {
+                id: row.try_get("id").map_err(|e| e.to_string())?,
+                created_at: row.try_get("created_at").map_err(|e| e.to_string())?,
+                description: row.try_get("description").map_err(|e| e.to_string())?,
+                parameters: row.try_get("parameters").map_err(|e| e.to_string())?,
+            })
+        })
+        .transpose()
+    }
+}
+
+// One insert of the trades of a run
+fn trades_query<'a>(run_id: &'a str, trades: &'a [Trade]) -> QueryBuilder<'a, Postgres> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "INSERT INTO trades (run_id, trade_id, asset, entry_time, entry_price, quantity, exit_time, exit_price, profit_loss, return_pct) ",
+    );
+    query.push_values(trades, |mut row, trade| {
+        row.push_bind(run_id)
+            .push_bind(trade.id as i64)
+            .push_bind(&trade.asset)
+            .push_bind(trade.entry_time)
+            .push_bind(trade.entry_price)
+            .push_bind(trade.quantity)
+            .push_bind(trade.exit_time)
+            .push_bind(trade.exit_price)
+            .push_bind(trade.profit_loss)
+            .push_bind(trade.return_pct);
+    });
+    query
+}
+
+// Runs matching the filter, most recent first
+fn list_query(filter: &RunFilter) -> QueryBuilder<'_, Postgres> {
+    let mut query =
+        QueryBuilder::<Postgres>::new(format!("SELECT {RUN_COLUMNS} FROM runs WHERE TRUE"));
+    if let Some(symbol) = &filter.symbol {
+        query.push(" AND symbol = ").push_bind(symbol);
+    }
+    if let Some(from) = filter.from {
+        query.push(" AND start_date >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        query.push(" AND end_date <= ").push_bind(to);
+    }
+    if let Some(min_sharpe) = filter.min_sharpe {
+        query.push(" AND sharpe_ratio >= ").push_bind(min_sharpe);
+    }
+    if let Some(hash) = &filter.strategy_hash {
+        query.push(" AND strategy_hash = ").push_bind(hash);
+    }
+    if let Some(tag) = &filter.tag {
+        query.push(" AND tags @> ARRAY[").push_bind(tag).push("]");
+    }
+    if let Some(starred) = filter.starred {
+        query.push(" AND starred = ").push_bind(starred);
+    }
+    if let Some(experiment) = &filter.experiment {
+        query.push(" AND experiment_id = ").push_bind(experiment);
+    }
+    query.push(" ORDER BY created_at DESC");
+    if let Some(limit) = filter.limit {
+        query.push(" LIMIT ").push_bind(limit as i64);
+    }
+    if filter.offset > 0 {
+        query.push(" OFFSET ").push_bind(filter.offset as i64);
+    }
+    query
+}
+
+fn run_record(row: &sqlx::postgres::PgRow) -> Result<RunRecord, String> {
+    Ok(RunRecord {
+        id: row.try_get("id").map_err(|e| e.to_string())?,
+        created_at: row.try_get("created_at").map_err(|e| e.to_string())?,
+        symbol: row.try_get("symbol").map_err(|e| e.to_string())?,
+        start_date: row.try_get("start_date").map_err(|e| e.to_string())?,
+        end_date: row.try_get("end_date").map_err(|e| e.to_string())?,
+        strategy_hash: row.try_get("strategy_hash").map_err(|e| e.to_string())?,
+        metrics: row.try_get("metrics").map_err(|e| e.to_string())?,
+        notes: row.try_get("notes").map_err(|e| e.to_string())?,
+        tags: row.try_get("tags").map_err(|e| e.to_string())?,
+        starred: row.try_get("starred").map_err(|e| e.to_string())?,
+        experiment_id: row.try_get("experiment_id").map_err(|e| e.to_string())?,
+        kronos_version: row.try_get("kronos_version").map_err(|e| e.to_string())?,
+        data_hash: row.try_get("data_hash").map_err(|e| e.to_string())?,
+        asset_hashes: serde_json::from_value(
+            row.try_get("asset_hashes").map_err(|e| e.to_string())?,
+        )
+        .map_err(|e| e.to_string())?,
+    })
+}
+
+fn strategy_record(row: &sqlx::postgres::PgRow) -> Result<StrategyRecord, String> {
+    Ok(StrategyRecord {
+        id: row.try_get("id").map_err(|e| e.to_string())?,
+        name: row.try_get("name").map_err(|e| e.to_string())?,
+        version: row.try_get("version").map_err(|e| e.to_string())?,
+        owner: row.try_get("owner").map_err(|e| e.to_string())?,
+        description: row.try_get("description").map_err(|e| e.to_string())?,
+        hash: row.try_get("hash").map_err(|e| e.to_string())?,
+        size: row.try_get("size").map_err(|e| e.to_string())?,
+        created_at: row.try_get("created_at").map_err(|e| e.to_string())?,
+        manifest: row
+            .try_get::<Option<serde_json::Value>, _>("manifest")
+            .map_err(|e| e.to_string())?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| e.to_string())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::trade::TradeDirection;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn filters_and_pages_the_runs_in_sql() {
+        let time = NaiveDateTime::default();
+        let filter = RunFilter {
+            symbol: Some("AAPL".to_string()),
+            from: Some(time),
+            to: Some(time),
+            min_sharpe: Some(1.0),
+            strategy_hash: Some("abc".to_string()),
+            limit: Some(50),
+            offset: 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            list_query(&filter).sql(),
+            format!(
+                "SELECT {RUN_COLUMNS} FROM runs WHERE TRUE AND symbol = $1 AND start_date >= $2 \
+                 AND end_date <= $3 AND sharpe_ratio >= $4 AND strategy_hash = $5 \
+                 ORDER BY created_at DESC LIMIT $6 OFFSET $7"
+            )
+        );
+        assert_eq!(
+            list_query(&RunFilter::default()).sql(),
+            format!("SELECT {RUN_COLUMNS} FROM runs WHERE TRUE ORDER BY created_at DESC")
+        );
+    }
+
+    #[test]
+    fn inserts_the_trades_in_one_statement() {
+        let trades: Vec<Trade> = (0..3)
+            .map(|id| {
+                Trade::new(
+                    id,
+                    "AAPL".to_string(),
+                    NaiveDateTime::default(),
+                    10.0,
+                    1.0,
+                    0.0,
+                    0.0,
+                    None,
+                    TradeDirection::Long,
+                )
+            })
+            .collect();
+        let sql = trades_query("run", &trades).into_sql();
+        assert_eq!(sql.matches("($").count(), 3);
+        assert!(sql.ends_with("$21, $22, $23, $24, $25, $26, $27, $28, $29, $30)"));
+        assert!(TRADE_BATCH * 10 <= u16::MAX as usize);
+    }
+}
@@ -1,9 +1,70 @@
-use crate::{broker::Broker, data::OHLCVData};
-use chrono::NaiveDateTime;
+use crate::{
+    broker::Broker,
+    data::{MarketData, OHLCVData},
+};
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use std::sync::Arc;
 
+pub mod ensemble;
+pub mod manifest;
+pub mod multi;
+pub mod rules;
+pub mod simulate;
 pub mod wasm;
 
+// Error raised by the strategy code
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StrategyError {
+    pub message: String,
+    // Trap of a WASM strategy (`UnreachableCodeReached`, `MemoryOutOfBounds`, ...)
+    pub trap: Option<String>,
+    // Innermost call first, with the function names when the module kept them
+    pub backtrace: Vec<String>,
+    // Tick it was raised on, None during `init`
+    pub time: Option<NaiveDateTime>,
+}
+
+// Orders and host calls of a `tick`, `on_gap` or `init` call refused by the capabilities of the
+// strategy, the call itself went on
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StrategyWarning {
+    // Tick of the call, None during `init`
+    pub time: Option<NaiveDateTime>,
+    // Reason of the first refusal
+    pub message: String,
+    pub refused: u32,
+}
+
+// Position of the current bar in the range of the run
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RunClock {
+    // Bars of the run symbol from the first one of the range, negative during the warm-up
+    pub bar_index: i64,
+    // Bars of the range after the current one, all of them during the warm-up
+    pub bars_remaining: i64,
+}
+
 pub trait Strategy {
     fn init(&mut self);
+    // Bars of every asset of the run, for strategies reading others than the one they are ticked with
+    fn subscribe(&mut self, _market: Arc<MarketData>) {}
+    // Set before each `tick` and `on_gap` call
+    fn set_clock(&mut self, _clock: RunClock) {}
     fn tick(&mut self, current_time: &NaiveDateTime, data: Option<&OHLCVData>, broker: &mut Broker);
+    // Called instead of `tick` when no bar arrived while the market is open, `duration` is the time since the last bar
+    fn on_gap(&mut self, _current_time: &NaiveDateTime, _duration: Duration, _broker: &mut Broker) {
+    }
+    // Last error raised by `init`, `tick` or `on_gap`, cleared once taken
+    fn take_error(&mut self) -> Option<StrategyError> {
+        None
+    }
+    // Refusals of the calls since the last take
+    fn take_warnings(&mut self) -> Vec<StrategyWarning> {
+        vec![]
+    }
+    // Target exposure (0 to 1 of the equity) decided on each bar close, None when the strategy isn't vectorizable
+    fn signals(&mut self, _data: &[OHLCVData]) -> Option<Vec<f64>> {
+        None
+    }
 }
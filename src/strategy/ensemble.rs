@@ -0,0 +1,327 @@
+use crate::broker::order::{Order, OrderDirection};
+use crate::broker::Broker;
+use crate::data::{MarketData, OHLCVData};
+use crate::strategy::{RunClock, Strategy, StrategyError, StrategyWarning};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+// How the stances of the members are combined
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Vote {
+    // More than half of the members, whatever their weight
+    #[default]
+    Majority,
+    // Weighted long minus short share of the members at least the threshold
+    Weighted,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct EnsembleSettings {
+    #[serde(default)]
+    pub vote: Vote,
+    // Of the weighted vote, from 0 to 1 and 0.5 by default
+    pub threshold: Option<f64>,
+}
+
+impl EnsembleSettings {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self
+            .threshold
+            .is_some_and(|threshold| !(threshold > 0.0 && threshold <= 1.0))
+        {
+            return Err("The ensemble threshold is between 0 and 1");
+        }
+        Ok(())
+    }
+}
+
+struct Member {
+    strategy: Box<dyn Strategy + Send>,
+    weight: f64,
+    // Direction of the last order placed on each asset, held until the member turns around
+    stances: HashMap<String, OrderDirection>,
+}
+
+// Meta-strategy ticking its members against the same broker. Their orders are held back and
+// each member keeps the stance of its last order on an asset. When the vote of the stances
+// settles on a new side, the orders of the heaviest member that just took that side are
+// placed, the others are dropped
+pub struct EnsembleStrategy {
+    members: Vec<Member>,
+    settings: EnsembleSettings,
+    // Side the vote last settled on for each asset
+    decisions: HashMap<String, Option<OrderDirection>>,
+}
+
+impl EnsembleStrategy {
+    pub fn new(
+        members: Vec<(Box<dyn Strategy + Send>, f64)>,
+        settings: EnsembleSettings,
+    ) -> Result<Self, &'static str> {
+        settings.validate()?;
+        if members.is_empty() {
+            return Err("An ensemble needs at least one member");
+        }
+        if !members
+            .iter()
+            .all(|(_, weight)| weight.is_finite() && *weight > 0.0)
+        {
+            return Err("The weights of the ensemble members must be positive");
+        }
+        Ok(EnsembleStrategy {
+            members: members
+                .into_iter()
+                .map(|(strategy, weight)| Member {
+                    strategy,
+                    weight,
+                    stances: HashMap::new(),
+                })
+                .collect(),
+            settings,
+            decisions: HashMap::new(),
+        })
+    }
+
+    // Side the stances settle on for `asset`, None without enough agreement
+    fn decide(&self, asset: &str) -> Option<OrderDirection> {
+        let share = |direction: OrderDirection, weighted: bool| {
+            let weight = |member: &Member| if weighted { member.weight } else { 1.0 };
+            let total: f64 = self.members.iter().map(weight).sum();
+            let agreeing: f64 = self
+                .members
+                .iter()
+                .filter(|member| member.stances.get(asset) == Some(&direction))
+                .map(weight)
+                .sum();
+            agreeing / total
+        };
+        let passes = |share: f64| match self.settings.vote {
+            Vote::Majority => share > 0.5,
+            Vote::Weighted => share >= self.settings.threshold.unwrap_or(0.5),
+        };
+        let (long, short) = match self.settings.vote {
+            Vote::Majority => (
+                share(OrderDirection::Buy, false),
+                share(OrderDirection::Sell, false),
+            ),
+            Vote::Weighted => {
+                let net = share(OrderDirection::Buy, true) - share(OrderDirection::Sell, true);
+                (net, -net)
+            }
+        };
+        if passes(long) {
+            Some(OrderDirection::Buy)
+        } else if passes(short) {
+            Some(OrderDirection::Sell)
+        } else {
+            None
+        }
+    }
+
+    // Calls every member with the orders held back, then places the ones tipping a vote
+    fn poll(&mut self, broker: &mut Broker, mut call: impl FnMut(&mut dyn Strategy, &mut Broker)) {
+        // Orders of each member this tick, by asset
+        let mut intents: Vec<BTreeMap<String, Vec<Order>>> = vec![];
+        for member in self.members.iter_mut() {
+            broker.capture_orders();
+            call(member.strategy.as_mut(), broker);
+            let mut orders: BTreeMap<String, Vec<Order>> = BTreeMap::new();
+            for order in broker.take_captured_orders() {
+                member
+                    .stances
+                    .insert(order.asset.clone(), order.direction.clone());
+                orders.entry(order.asset.clone()).or_default().push(order);
+            }
+            intents.push(orders);
+        }
+
+        let assets: BTreeSet<String> = intents
+            .iter()
+            .flat_map(|orders| orders.keys().cloned())
+            .collect();
+        for asset in assets {
+            let decision = self.decide(&asset);
+            let previous = self.decisions.insert(asset.clone(), decision.clone());
+            let Some(direction) =
+                decision.filter(|decision| previous.flatten().as_ref() != Some(decision))
+            else {
+                continue;
+            };
+            // The orders this member placed on the asset on this tick took the side
+            let tipping = (0..self.members.len())
+                .filter(|&i| {
+                    intents[i].get(&asset).is_some_and(|orders| {
+                        orders.last().map(|order| &order.direction) == Some(&direction)
+                    })
+                })
+                .fold(None, |best: Option<usize>, i| match best {
+                    Some(best) if self.members[best].weight >= self.members[i].weight => Some(best),
+                    _ => Some(i),
+                });
+            if let Some(orders) = tipping.and_then(|i| intents[i].remove(&asset)) {
+                for order in orders {
+                    broker.place_order(order);
+                }
+            }
+        }
+    }
+}
+
+impl Strategy for EnsembleStrategy {
+    fn init(&mut self) {
+        self.decisions.clear();
+        for member in self.members.iter_mut() {
+            member.stances.clear();
+            member.strategy.init();
+        }
+    }
+
+    fn subscribe(&mut self, market: Arc<MarketData>) {
+        for member in self.members.iter_mut() {
+            member.strategy.subscribe(market.clone());
+        }
+    }
+
+    fn set_clock(&mut self, clock: RunClock) {
+        for member in self.members.iter_mut() {
+            member.strategy.set_clock(clock);
+        }
+    }
+
+    fn tick(
+        &mut self,
+        current_time: &NaiveDateTime,
+        data: Option<&OHLCVData>,
+        broker: &mut Broker,
+    ) {
+        self.poll(broker, |strategy, broker| {
+            strategy.tick(current_time, data, broker)
+        });
+    }
+
+    fn on_gap(&mut self, current_time: &NaiveDateTime, duration: Duration, broker: &mut Broker) {
+        self.poll(broker, |strategy, broker| {
+            strategy.on_gap(current_time, duration, broker)
+        });
+    }
+
+    // First error of the members
+    fn take_error(&mut self) -> Option<StrategyError> {
+        self.members
+            .iter_mut()
+            .find_map(|member| member.strategy.take_error())
+    }
+
+    fn take_warnings(&mut self) -> Vec<StrategyWarning> {
+        self.members
+            .iter_mut()
+            .flat_map(|member| member.strategy.take_warnings())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::order::{OrderType, SizeSpec, TimeInForce};
+
+    // Places an order on the ticks of its script
+    struct Scripted {
+        orders: Vec<(i64, OrderDirection, f64)>,
+    }
+
+    impl Strategy for Scripted {
+        fn init(&mut self) {}
+
+        fn tick(
+            &mut self,
+            current_time: &NaiveDateTime,
+            _: Option<&OHLCVData>,
+            broker: &mut Broker,
+        ) {
+            let tick = current_time.and_utc().timestamp();
+            for (time, direction, quantity) in &self.orders {
+                if *time == tick {
+                    broker.place_order(Order {
+                        asset: "AAPL".to_string(),
+                        direction: direction.clone(),
+                        size: SizeSpec::Quantity(*quantity),
+                        order_type: OrderType::Market,
+                        time_in_force: TimeInForce::Gtc,
+                        placed_at: None,
+                    });
+                }
+            }
+        }
+    }
+
+    fn member(
+        orders: Vec<(i64, OrderDirection, f64)>,
+        weight: f64,
+    ) -> (Box<dyn Strategy + Send>, f64) {
+        (Box::new(Scripted { orders }), weight)
+    }
+
+    // Quantities of the orders placed on each tick from 1 to 5
+    fn placed(ensemble: &mut EnsembleStrategy) -> Vec<Vec<f64>> {
+        let mut broker = Broker::new();
+        ensemble.init();
+        (1..=5)
+            .map(|tick| {
+                let time = chrono::DateTime::from_timestamp(tick, 0)
+                    .unwrap()
+                    .naive_utc();
+                ensemble.tick(&time, None, &mut broker);
+                broker
+                    .orders
+                    .drain(..)
+                    .map(|resting| resting.order)
+                    .map(|order| match order.direction {
+                        OrderDirection::Buy => order.size.fixed().unwrap(),
+                        OrderDirection::Sell => -order.size.fixed().unwrap(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn places_the_orders_tipping_the_vote() {
+        use OrderDirection::{Buy, Sell};
+        let members = || {
+            vec![
+                member(vec![(1, Buy, 1.0), (4, Sell, 1.0)], 1.0),
+                member(vec![(2, Buy, 2.0), (5, Sell, 2.0)], 1.0),
+                member(vec![(3, Buy, 3.0), (4, Sell, 3.0)], 3.0),
+            ]
+        };
+
+        // Long from the second buy, the third one keeps it long. Short once two members sold,
+        // with the orders of the heaviest of them
+        let mut majority = EnsembleStrategy::new(members(), EnsembleSettings::default()).unwrap();
+        assert_eq!(
+            placed(&mut majority),
+            [vec![], vec![2.0], vec![], vec![-3.0], vec![]]
+        );
+
+        // The third member weighs 3 of 5, alone it passes 0.6 of net weight
+        let settings = EnsembleSettings {
+            vote: Vote::Weighted,
+            threshold: Some(0.6),
+        };
+        let mut weighted = EnsembleStrategy::new(members(), settings).unwrap();
+        assert_eq!(
+            placed(&mut weighted),
+            [vec![], vec![], vec![3.0], vec![-3.0], vec![]]
+        );
+
+        let invalid = EnsembleSettings {
+            vote: Vote::Weighted,
+            threshold: Some(1.5),
+        };
+        assert!(EnsembleStrategy::new(members(), invalid).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasmparser::{Parser, Payload};
+
+// Custom section holding the manifest of a WASM strategy, as JSON
+pub const SECTION: &str = "kronos.manifest";
+
+// Points of a parameter range without a step
+const DEFAULT_GRID_POINTS: usize = 5;
+
+// What a strategy declares about itself, every field is optional
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    // Symbols the strategy reads, the run symbol or `data.assets`
+    pub assets: Vec<String>,
+    pub parameters: Vec<ParameterSpec>,
+    // Bars the indicators need before the strategy trades
+    pub warmup_bars: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterSpec {
+    pub name: String,
+    pub default: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    // Spacing of the optimizer grid between `min` and `max`
+    pub step: Option<f64>,
+}
+
+// Manifest of the module, None when it has no manifest section
+pub fn read(wasm: &[u8]) -> Result<Option<Manifest>, String> {
+    // Modules in the text format are compiled by wasmtime and carry no manifest
+    if !wasm.starts_with(b"\0asm") {
+        return Ok(None);
+    }
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::CustomSection(section) = payload.map_err(|e| e.to_string())? {
+            if section.name() == SECTION {
+                let manifest: Manifest =
+                    serde_json::from_slice(section.data()).map_err(|e| e.to_string())?;
+                manifest.check()?;
+                return Ok(Some(manifest));
+            }
+        }
+    }
+    Ok(None)
+}
+
+impl ParameterSpec {
+    fn check(&self, value: f64) -> Result<(), String> {
+        if !value.is_finite()
+            || self.min.is_some_and(|min| value < min)
+            || self.max.is_some_and(|max| value > max)
+        {
+            return Err(format!(
+                "{} is out of the bounds of `{}` ({} to {})",
+                value,
+                self.name,
+                self.min.map_or("-inf".to_string(), |min| min.to_string()),
+                self.max.map_or("inf".to_string(), |max| max.to_string()),
+            ));
+        }
+        Ok(())
+    }
+
+    // Values tried by the optimizer, only the default for a parameter without bounds
+    pub fn grid(&self) -> Vec<f64> {
+        let (Some(min), Some(max)) = (self.min, self.max) else {
+            return vec![self.default];
+        };
+        let step = self
+            .step
+            .unwrap_or((max - min) / (DEFAULT_GRID_POINTS - 1) as f64);
+        if step <= 0.0 {
+            return vec![min];
+        }
+        let points = ((max - min) / step + 1e-9).floor() as usize + 1;
+        (0..points).map(|i| min + step * i as f64).collect()
+    }
+}
+
+impl Manifest {
+    fn check(&self) -> Result<(), String> {
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if self.parameters[..i]
+                .iter()
+                .any(|other| other.name == parameter.name)
+            {
+                return Err(format!("Parameter `{}` is declared twice", parameter.name));
+            }
+            if parameter.step.is_some_and(|step| step <= 0.0) {
+                return Err(format!("The step of `{}` isn't positive", parameter.name));
+            }
+            parameter.check(parameter.default)?;
+        }
+        Ok(())
+    }
+
+    // Values of every declared parameter, the given ones checked against their bounds
+    pub fn resolve(&self, values: &HashMap<String, f64>) -> Result<HashMap<String, f64>, String> {
+        if let Some(name) = values
+            .keys()
+            .find(|name| !self.parameters.iter().any(|p| &&p.name == name))
+        {
+            return Err(format!("The strategy has no parameter `{}`", name));
+        }
+        self.parameters
+            .iter()
+            .map(|parameter| {
+                let value = values
+                    .get(&parameter.name)
+                    .copied()
+                    .unwrap_or(parameter.default);
+                parameter.check(value)?;
+                Ok((parameter.name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_manifest_section() {
+        let module = wat::parse_str(format!(
+            r#"(module (@custom "{}" "{{\"name\": \"sma\", \"assets\": [\"ETH\"], \"warmup_bars\": 50, \"parameters\": [{{\"name\": \"fast\", \"default\": 10, \"min\": 2, \"max\": 20, \"step\": 6}}, {{\"name\": \"size\", \"default\": 1}}]}}"))"#,
+            SECTION
+        ))
+        .unwrap();
+        let manifest = read(&module).unwrap().unwrap();
+        assert_eq!(manifest.name.as_deref(), Some("sma"));
+        assert_eq!(manifest.assets, ["ETH"]);
+        assert_eq!(manifest.warmup_bars, 50);
+        assert_eq!(manifest.parameters[0].grid(), [2.0, 8.0, 14.0, 20.0]);
+        assert_eq!(manifest.parameters[1].grid(), [1.0]);
+
+        let values = manifest
+            .resolve(&HashMap::from([("fast".to_string(), 12.0)]))
+            .unwrap();
+        assert_eq!(values["fast"], 12.0);
+        assert_eq!(values["size"], 1.0);
+        assert!(manifest
+            .resolve(&HashMap::from([("fast".to_string(), 30.0)]))
+            .is_err());
+        assert!(manifest
+            .resolve(&HashMap::from([("slow".to_string(), 1.0)]))
+            .is_err());
+
+        assert_eq!(read(&wat::parse_str("(module)").unwrap()), Ok(None));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::broker::Broker;
+use crate::data::{MarketData, OHLCVData};
+use crate::strategy::{RunClock, Strategy, StrategyError, StrategyWarning};
+use chrono::{Duration, NaiveDateTime};
+use std::sync::Arc;
+
+// Strategies ticked in turn against the same broker, each trading from the sub-account of the
+// same index
+pub struct MultiStrategy {
+    members: Vec<Box<dyn Strategy + Send>>,
+}
+
+impl MultiStrategy {
+    pub fn new(members: Vec<Box<dyn Strategy + Send>>) -> Self {
+        MultiStrategy { members }
+    }
+
+    fn poll(&mut self, broker: &mut Broker, mut call: impl FnMut(&mut dyn Strategy, &mut Broker)) {
+        for (i, member) in self.members.iter_mut().enumerate() {
+            broker.enter_account(i);
+            call(member.as_mut(), broker);
+            broker.leave_account();
+        }
+    }
+}
+
+impl Strategy for MultiStrategy {
+    fn init(&mut self) {
+        self.members.iter_mut().for_each(|member| member.init());
+    }
+
+    fn subscribe(&mut self, market: Arc<MarketData>) {
+        for member in self.members.iter_mut() {
+            member.subscribe(market.clone());
+        }
+    }
+
+    fn set_clock(&mut self, clock: RunClock) {
+        for member in self.members.iter_mut() {
+            member.set_clock(clock);
+        }
+    }
+
+    fn tick(
+        &mut self,
+        current_time: &NaiveDateTime,
+        data: Option<&OHLCVData>,
+        broker: &mut Broker,
+    ) {
+        self.poll(broker, |strategy, broker| {
+            strategy.tick(current_time, data, broker)
+        });
+    }
+
+    fn on_gap(&mut self, current_time: &NaiveDateTime, duration: Duration, broker: &mut Broker) {
+        self.poll(broker, |strategy, broker| {
+            strategy.on_gap(current_time, duration, broker)
+        });
+    }
+
+    // First error of the members
+    fn take_error(&mut self) -> Option<StrategyError> {
+        self.members
+            .iter_mut()
+            .find_map(|member| member.take_error())
+    }
+
+    fn take_warnings(&mut self) -> Vec<StrategyWarning> {
+        self.members
+            .iter_mut()
+            .flat_map(|member| member.take_warnings())
+            .collect()
+    }
+}
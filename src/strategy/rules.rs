@@ -0,0 +1,575 @@
+use crate::broker::algo::ExecutionAlgo;
+use crate::broker::order::{Order, OrderDirection, OrderType, SizeSpec, TimeInForce};
+use crate::broker::Broker;
+use crate::data::{volume, OHLCVData};
+use crate::strategy::Strategy;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// A rule as sent in the request, e.g. buy when "sma(20) crosses_above sma(50)" with size "10% equity"
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RuleSpec {
+    pub action: OrderAction,
+    pub when: String,
+    pub size: String,
+    // Sliced by an execution algo instead of a single market order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<ExecutionAlgo>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderAction {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Number(f64),
+    Field(Field),
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Vwap(usize),
+    Obv,
+    Imbalance(usize),
+    // Point of control, value area high and low of the volume profile
+    Poc(usize),
+    ValueAreaHigh(usize),
+    ValueAreaLow(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    CrossesAbove,
+    CrossesBelow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Size {
+    PercentEquity(f64),
+    PercentPosition(f64),
+    Quantity(f64),
+}
+
+#[derive(Debug)]
+struct Comparison {
+    left: usize,
+    operator: Operator,
+    right: usize,
+}
+
+#[derive(Debug)]
+struct Rule {
+    action: OrderAction,
+    // All the comparisons must hold
+    conditions: Vec<Comparison>,
+    size: Size,
+    execution: Option<ExecutionAlgo>,
+}
+
+// Values of an operand for every bar received so far, None during the warm up
+struct Series {
+    operand: Operand,
+    values: Vec<Option<f64>>,
+    // Running state: window sum for the SMA, average gain/loss for the RSI, total for the OBV
+    state: (f64, f64),
+}
+
+impl Series {
+    fn new(operand: Operand) -> Self {
+        Series {
+            operand,
+            values: vec![],
+            state: (0.0, 0.0),
+        }
+    }
+
+    fn push(&mut self, bars: &[OHLCVData]) {
+        let i = bars.len() - 1;
+        let bar = &bars[i];
+
+        let value = match self.operand {
+            Operand::Number(value) => Some(value),
+            Operand::Field(field) => Some(match field {
+                Field::Open => bar.open,
+                Field::High => bar.high,
+                Field::Low => bar.low,
+                Field::Close => bar.close,
+                Field::Volume => bar.volume as f64,
+            }),
+            Operand::Sma(period) => {
+                self.state.0 += bar.close;
+                if i >= period {
+                    self.state.0 -= bars[i - period].close;
+                }
+                (i + 1 >= period).then(|| self.state.0 / period as f64)
+            }
+            Operand::Ema(period) => {
+                let alpha = 2.0 / (period as f64 + 1.0);
+                let previous = self.values.last().copied().flatten();
+                match previous {
+                    Some(previous) => Some(previous + alpha * (bar.close - previous)),
+                    None if i + 1 >= period => {
+                        // Seeded with the SMA of the first period
+                        Some(
+                            bars[i + 1 - period..].iter().map(|b| b.close).sum::<f64>()
+                                / period as f64,
+                        )
+                    }
+                    None => None,
+                }
+            }
+            Operand::Rsi(period) => {
+                if i == 0 {
+                    None
+                } else {
+                    let change = bar.close - bars[i - 1].close;
+                    let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+                    let (average_gain, average_loss) = &mut self.state;
+                    if i <= period {
+                        // Simple average over the first period, Wilder smoothing afterwards
+                        *average_gain += gain / period as f64;
+                        *average_loss += loss / period as f64;
+                    } else {
+                        *average_gain =
+                            (*average_gain * (period as f64 - 1.0) + gain) / period as f64;
+                        *average_loss =
+                            (*average_loss * (period as f64 - 1.0) + loss) / period as f64;
+                    }
+
+                    if i < period {
+                        None
+                    } else if *average_loss == 0.0 {
+                        Some(100.0)
+                    } else {
+                        Some(100.0 - 100.0 / (1.0 + *average_gain / *average_loss))
+                    }
+                }
+            }
+            Operand::Obv => {
+                if i > 0 {
+                    let previous = bars[i - 1].close;
+                    if bar.close > previous {
+                        self.state.0 += bar.volume as f64;
+                    } else if bar.close < previous {
+                        self.state.0 -= bar.volume as f64;
+                    }
+                }
+                Some(self.state.0)
+            }
+            Operand::Vwap(period) => window(bars, period).and_then(volume::vwap),
+            Operand::Imbalance(period) => window(bars, period).and_then(volume::imbalance),
+            Operand::Poc(period) => window(bars, period)
+                .and_then(volume::volume_profile)
+                .map(|profile| profile.poc),
+            Operand::ValueAreaHigh(period) => window(bars, period)
+                .and_then(volume::volume_profile)
+                .map(|profile| profile.value_area_high),
+            Operand::ValueAreaLow(period) => window(bars, period)
+                .and_then(volume::volume_profile)
+                .map(|profile| profile.value_area_low),
+        };
+
+        self.values.push(value);
+    }
+
+    fn last(&self, offset: usize) -> Option<f64> {
+        let index = self.values.len().checked_sub(1 + offset)?;
+        self.values[index]
+    }
+}
+
+// Last `period` bars, None during the warm up
+fn window(bars: &[OHLCVData], period: usize) -> Option<&[OHLCVData]> {
+    bars.len().checked_sub(period).map(|start| &bars[start..])
+}
+
+fn parse_operand(token: &str) -> Result<Operand, String> {
+    if let Ok(number) = token.parse::<f64>() {
+        return Ok(Operand::Number(number));
+    }
+
+    let field = match token {
+        "open" => Some(Field::Open),
+        "high" => Some(Field::High),
+        "low" => Some(Field::Low),
+        "close" => Some(Field::Close),
+        "volume" => Some(Field::Volume),
+        _ => None,
+    };
+    if let Some(field) = field {
+        return Ok(Operand::Field(field));
+    }
+    if token == "obv" {
+        return Ok(Operand::Obv);
+    }
+
+    let (name, period) = token
+        .strip_suffix(')')
+        .and_then(|t| t.split_once('('))
+        .ok_or_else(|| format!("Unknown operand `{}`", token))?;
+    let period = period
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|p| *p > 0)
+        .ok_or_else(|| format!("Invalid period in `{}`", token))?;
+
+    match name.trim() {
+        "sma" => Ok(Operand::Sma(period)),
+        "ema" => Ok(Operand::Ema(period)),
+        "rsi" => Ok(Operand::Rsi(period)),
+        "vwap" => Ok(Operand::Vwap(period)),
+        "imbalance" => Ok(Operand::Imbalance(period)),
+        "poc" => Ok(Operand::Poc(period)),
+        "vah" => Ok(Operand::ValueAreaHigh(period)),
+        "val" => Ok(Operand::ValueAreaLow(period)),
+        _ => Err(format!("Unknown indicator `{}`", name)),
+    }
+}
+
+fn parse_operator(token: &str) -> Result<Operator, String> {
+    match token {
+        ">" => Ok(Operator::Greater),
+        ">=" => Ok(Operator::GreaterOrEqual),
+        "<" => Ok(Operator::Less),
+        "<=" => Ok(Operator::LessOrEqual),
+        "crosses_above" => Ok(Operator::CrossesAbove),
+        "crosses_below" => Ok(Operator::CrossesBelow),
+        _ => Err(format!("Unknown operator `{}`", token)),
+    }
+}
+
+fn parse_size(size: &str) -> Result<Size, String> {
+    let size = size.trim();
+    let invalid = || format!("Invalid size `{}`", size);
+
+    match size.split_once('%') {
+        Some((percent, unit)) => {
+            let percent = percent.trim().parse::<f64>().map_err(|_| invalid())? / 100.0;
+            if !(0.0..=1.0).contains(&percent) {
+                return Err(invalid());
+            }
+            match unit.trim() {
+                "equity" => Ok(Size::PercentEquity(percent)),
+                "position" => Ok(Size::PercentPosition(percent)),
+                _ => Err(invalid()),
+            }
+        }
+        None => size
+            .parse::<f64>()
+            .ok()
+            .filter(|q| *q > 0.0)
+            .map(Size::Quantity)
+            .ok_or_else(invalid),
+    }
+}
+
+pub struct RuleStrategy {
+    asset: String,
+    rules: Vec<Rule>,
+    series: Vec<Series>,
+    bars: Vec<OHLCVData>,
+}
+
+impl RuleStrategy {
+    pub fn new(asset: String, specs: &[RuleSpec]) -> Result<Self, String> {
+        let mut series: Vec<Series> = vec![];
+        let mut series_index = |operand: Operand| -> usize {
+            match series.iter().position(|s| s.operand == operand) {
+                Some(index) => index,
+                None => {
+                    series.push(Series::new(operand));
+                    series.len() - 1
+                }
+            }
+        };
+
+        let mut rules = vec![];
+        for spec in specs {
+            let mut conditions = vec![];
+            for comparison in spec.when.split(" and ") {
+                let tokens: Vec<&str> = comparison.split_whitespace().collect();
+                let [left, operator, right] = tokens[..] else {
+                    return Err(format!("Invalid condition `{}`", comparison.trim()));
+                };
+                conditions.push(Comparison {
+                    left: series_index(parse_operand(left)?),
+                    operator: parse_operator(operator)?,
+                    right: series_index(parse_operand(right)?),
+                });
+            }
+
+            rules.push(Rule {
+                action: spec.action,
+                conditions,
+                size: parse_size(&spec.size)?,
+                execution: spec.execution,
+            });
+            if let Some(algo) = spec.execution {
+                algo.validate()?;
+            }
+        }
+
+        if rules.is_empty() {
+            return Err("At least one rule is required".to_string());
+        }
+
+        Ok(RuleStrategy {
+            asset,
+            rules,
+            series,
+            bars: vec![],
+        })
+    }
+
+    fn push_bar(&mut self, bar: &OHLCVData) {
+        self.bars.push(bar.clone());
+        for series in self.series.iter_mut() {
+            series.push(&self.bars);
+        }
+    }
+
+    fn is_triggered(&self, rule: &Rule) -> bool {
+        rule.conditions.iter().all(|condition| {
+            let (left, right) = (&self.series[condition.left], &self.series[condition.right]);
+            let (Some(l), Some(r)) = (left.last(0), right.last(0)) else {
+                return false;
+            };
+
+            match condition.operator {
+                Operator::Greater => l > r,
+                Operator::GreaterOrEqual => l >= r,
+                Operator::Less => l < r,
+                Operator::LessOrEqual => l <= r,
+                Operator::CrossesAbove | Operator::CrossesBelow => {
+                    let (Some(previous_l), Some(previous_r)) = (left.last(1), right.last(1)) else {
+                        return false;
+                    };
+                    if condition.operator == Operator::CrossesAbove {
+                        previous_l <= previous_r && l > r
+                    } else {
+                        previous_l >= previous_r && l < r
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Strategy for RuleStrategy {
+    fn init(&mut self) {
+        self.bars.clear();
+        for series in self.series.iter_mut() {
+            *series = Series::new(series.operand.clone());
+        }
+    }
+
+    fn tick(
+        &mut self,
+        _current_time: &NaiveDateTime,
+        data: Option<&OHLCVData>,
+        broker: &mut Broker,
+    ) {
+        // Rules are evaluated once per bar
+        let Some(bar) = data else {
+            return;
+        };
+        if self
+            .bars
+            .last()
+            .is_some_and(|last| last.timestamp == bar.timestamp)
+        {
+            return;
+        }
+        self.push_bar(bar);
+
+        let position = broker
+            .portfolio
+            .get(&self.asset)
+            .map(|p| p.quantity)
+            .unwrap_or(0.0);
+        let equity = broker.cash + position * bar.close;
+
+        for rule in self.rules.iter().filter(|rule| self.is_triggered(rule)) {
+            let size = match rule.size {
+                Size::PercentEquity(percent) => percent * equity / bar.close,
+                Size::PercentPosition(percent) => percent * position,
+                Size::Quantity(quantity) => quantity,
+            };
+            if size <= 0.0 {
+                continue;
+            }
+
+            let order = Order {
+                asset: self.asset.clone(),
+                direction: match rule.action {
+                    OrderAction::Buy => OrderDirection::Buy,
+                    OrderAction::Sell => OrderDirection::Sell,
+                },
+                size: SizeSpec::Quantity(size),
+                order_type: OrderType::Market,
+                time_in_force: TimeInForce::Gtc,
+                placed_at: None,
+            };
+            match rule.execution {
+                Some(algo) => {
+                    if let Err(e) = broker.place_algo_order(order, algo) {
+                        eprintln!("Failed to place algo order: {}", e);
+                    }
+                }
+                None => {
+                    broker.place_order(order);
+                }
+            }
+        }
+    }
+
+    // Only percentage sizes of immediate orders can be expressed as exposures
+    fn signals(&mut self, data: &[OHLCVData]) -> Option<Vec<f64>> {
+        if self
+            .rules
+            .iter()
+            .any(|rule| matches!(rule.size, Size::Quantity(_)) || rule.execution.is_some())
+        {
+            return None;
+        }
+
+        self.init();
+        let mut exposure: f64 = 0.0;
+        let mut signals = Vec::with_capacity(data.len());
+
+        for bar in data {
+            self.push_bar(bar);
+            for rule in self.rules.iter().filter(|rule| self.is_triggered(rule)) {
+                exposure = match (rule.action, &rule.size) {
+                    (OrderAction::Buy, Size::PercentEquity(p)) => exposure + p,
+                    (OrderAction::Buy, Size::PercentPosition(p)) => exposure * (1.0 + p),
+                    (OrderAction::Sell, Size::PercentEquity(p)) => exposure - p,
+                    (OrderAction::Sell, Size::PercentPosition(p)) => exposure * (1.0 - p),
+                    (_, Size::Quantity(_)) => exposure,
+                }
+                .clamp(0.0, 1.0);
+            }
+            signals.push(exposure);
+        }
+
+        Some(signals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(closes: &[f64]) -> Vec<OHLCVData> {
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, close)| OHLCVData {
+                timestamp: start + chrono::Duration::days(i as i64),
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                volume: 1000,
+            })
+            .collect()
+    }
+
+    fn rule(action: OrderAction, when: &str, size: &str) -> RuleSpec {
+        RuleSpec {
+            action,
+            when: when.to_string(),
+            size: size.to_string(),
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let invalid = [
+            rule(OrderAction::Buy, "sma(20) crosses sma(50)", "10% equity"),
+            rule(OrderAction::Buy, "sma(0) > close", "10% equity"),
+            rule(OrderAction::Buy, "macd(12) > close", "10% equity"),
+            rule(OrderAction::Buy, "close >", "10% equity"),
+            rule(OrderAction::Buy, "close > 10", "150% equity"),
+        ];
+
+        for spec in invalid {
+            assert!(RuleStrategy::new("AAPL".to_string(), &[spec]).is_err());
+        }
+    }
+
+    #[test]
+    fn sma_crossover_signals() {
+        let mut strategy = RuleStrategy::new(
+            "AAPL".to_string(),
+            &[
+                rule(
+                    OrderAction::Buy,
+                    "sma(2) crosses_above sma(3)",
+                    "100% equity",
+                ),
+                rule(
+                    OrderAction::Sell,
+                    "sma(2) crosses_below sma(3)",
+                    "100% position",
+                ),
+            ],
+        )
+        .unwrap();
+
+        let data = bars(&[10.0, 9.0, 8.0, 9.0, 11.0, 12.0, 10.0, 7.0]);
+        let signals = strategy.signals(&data).unwrap();
+
+        assert_eq!(signals, vec![0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn volume_operands() {
+        let mut data = bars(&[10.0, 11.0, 10.0, 12.0, 13.0, 12.0]);
+        for (bar, volume) in data.iter_mut().zip([100, 300, 100, 100, 500, 100]) {
+            bar.volume = volume;
+        }
+        let exposure = |when: &str| {
+            let mut strategy = RuleStrategy::new(
+                "AAPL".to_string(),
+                &[rule(OrderAction::Buy, when, "50% equity")],
+            )
+            .unwrap();
+            strategy.signals(&data).unwrap()
+        };
+
+        // VWAP(2) is 10.75, 10.75, 11, 12.83 and 12.83 from the 2nd bar
+        assert_eq!(exposure("close > vwap(2)"), [0.0, 0.5, 0.5, 1.0, 1.0, 1.0]);
+        // 0, 300, 200, 300, 800 and 700
+        assert_eq!(exposure("obv > 500"), [0.0, 0.0, 0.0, 0.0, 0.5, 1.0]);
+        assert!(RuleStrategy::new(
+            "AAPL".to_string(),
+            &[rule(
+                OrderAction::Buy,
+                "close > poc(20) and imbalance(5) > 0",
+                "1"
+            )]
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::broker::{position::Position, Broker};
+use crate::data::{MarketData, OHLCVData};
+use crate::strategy::{
+    wasm::{Capabilities, HostCall, WasmStrategy},
+    RunClock, Strategy, StrategyError,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Strategy fed a few bars without a broker matching its orders, recording every host call. Orders
+// are only placed, so the queries answer from the initial cash and positions on every bar
+pub struct SimulatedSession {
+    strategy: WasmStrategy,
+    broker: Broker,
+    market: MarketData,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SimulationReport {
+    // Bars the strategy was ticked with
+    pub ticks: usize,
+    pub calls: Vec<HostCall>,
+    // Error that stopped the session, the calls before it are kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<StrategyError>,
+}
+
+impl SimulatedSession {
+    pub fn new(
+        wasm_bytes: &[u8],
+        capabilities: Capabilities,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut strategy = WasmStrategy::new(wasm_bytes, capabilities)?;
+        strategy.record_calls();
+        Ok(SimulatedSession {
+            strategy,
+            broker: Broker::new(),
+            market: MarketData::default(),
+        })
+    }
+
+    pub fn set_parameters(&mut self, parameters: HashMap<String, f64>) {
+        self.strategy.set_parameters(parameters);
+    }
+
+    pub fn set_cash(&mut self, cash: f64) {
+        self.broker.set_cash(cash);
+    }
+
+    pub fn set_position(&mut self, asset: String, quantity: f64) {
+        self.broker
+            .portfolio
+            .insert(asset, Position::new(quantity, 0.0));
+    }
+
+    // Bars read with `get_bar`, the bars of `run` are added under their symbol
+    pub fn add_asset(&mut self, asset: String, bars: Vec<OHLCVData>) {
+        self.market.insert(asset, bars);
+    }
+
+    // Ticks the strategy once per bar, oldest first
+    pub fn run(mut self, symbol: Option<String>, mut bars: Vec<OHLCVData>) -> SimulationReport {
+        bars.sort_by_key(|bar| bar.timestamp);
+        if let Some(symbol) = symbol {
+            self.market.insert(symbol, bars.clone());
+        }
+        self.strategy.subscribe(Arc::new(self.market));
+
+        let mut ticks = 0;
+        self.strategy.init();
+        let mut error = self.strategy.take_error();
+        for (i, bar) in bars.iter().enumerate() {
+            if error.is_some() {
+                break;
+            }
+            self.strategy.set_clock(RunClock {
+                bar_index: i as i64,
+                bars_remaining: (bars.len() - i - 1) as i64,
+            });
+            self.strategy
+                .tick(&bar.timestamp, Some(bar), &mut self.broker);
+            ticks += 1;
+            error = self.strategy.take_error();
+        }
+
+        SimulationReport {
+            ticks,
+            calls: self.strategy.take_calls(),
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDateTime};
+    use serde_json::json;
+
+    #[test]
+    fn records_the_host_calls_of_each_bar() {
+        // Logs on init, then buys one AAPL when the cash covers the close and sells what it holds
+        let wat = r#"
+            (module
+              (import "env" "memory" (memory 1))
+              (import "env" "log" (func $log (param i32 i32)))
+              (import "env" "get_cash" (func $cash (result f64)))
+              (import "env" "get_position" (func $position (param i32 i32) (result f64)))
+              (import "env" "place_market_order" (func $order (param i32 i32 i32 f64)))
+              (data (i32.const 16) "AAPL")
+              (data (i32.const 32) "ready")
+              (func (export "init") (call $log (i32.const 32) (i32.const 5)))
+              (func (export "tick") (param i64 f64 f64 f64 f64 f64)
+                (if (f64.gt (call $cash) (local.get 4))
+                  (then (call $order (i32.const 16) (i32.const 4) (i32.const 0) (f64.const 1))))
+                (call $order (i32.const 16) (i32.const 4) (i32.const 1)
+                  (call $position (i32.const 16) (i32.const 4)))))
+        "#;
+        let start = NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .expect("Invalid date");
+        let bars: Vec<_> = [120.0, 80.0]
+            .iter()
+            .enumerate()
+            .map(|(day, close)| OHLCVData {
+                timestamp: start + Duration::days(day as i64),
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                volume: 1000,
+            })
+            .collect();
+
+        let mut session = SimulatedSession::new(wat.as_bytes(), Capabilities::default())
+            .expect("Failed to load module");
+        session.set_cash(100.0);
+        session.set_position("AAPL".to_string(), 3.0);
+        let report = session.run(Some("AAPL".to_string()), bars);
+
+        assert_eq!(report.ticks, 2);
+        assert_eq!(report.error, None);
+        let functions: Vec<_> = report.calls.iter().map(|call| call.function).collect();
+        assert_eq!(
+            functions,
+            [
+                "log",
+                "get_cash",
+                "get_position",
+                "place_market_order",
+                "get_cash",
+                "place_market_order",
+                "get_position",
+                "place_market_order",
+            ]
+        );
+        assert_eq!(report.calls[0].time, None);
+        assert_eq!(report.calls[0].arguments, json!({ "message": "ready" }));
+        // Orders aren't executed, the position is the same on both bars
+        assert_eq!(report.calls[2].result, json!(3.0));
+        assert_eq!(report.calls[6].result, json!(3.0));
+        assert_eq!(
+            report.calls[5].arguments,
+            json!({
+                "asset": "AAPL",
+                "direction": "buy",
+                "type": "market",
+                "price": null,
+                "size": { "quantity": 1.0 },
+                "time_in_force": "gtc",
+            })
+        );
+        assert_eq!(report.calls[5].time, Some(start + Duration::days(1)));
+    }
+}
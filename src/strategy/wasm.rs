@@ -1,9 +1,14 @@
-use crate::broker::order::{Order, OrderDirection, OrderType};
+use crate::broker::algo::ExecutionAlgo;
+use crate::broker::order::{Order, OrderDirection, OrderType, SizeSpec, TimeInForce};
 use crate::broker::Broker;
-use crate::data::OHLCVData;
-use crate::strategy::Strategy;
-use chrono::NaiveDateTime;
+use crate::data::{volume, MarketData, OHLCVData};
+use crate::strategy::{RunClock, Strategy, StrategyError, StrategyWarning};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::ptr;
+use std::sync::Arc;
 use wasmtime::*;
 
 pub struct WasmStrategy {
@@ -11,44 +16,368 @@ pub struct WasmStrategy {
     store: Store<HostState>,
     _instance: Instance,
     init_fn: TypedFunc<(), ()>,
-    tick_fn: TypedFunc<(i64, f64, f64, f64, f64, f64), ()>,
+    tick_fn: TickFn,
+    on_gap_fn: Option<TypedFunc<(i64, i64), ()>>,
+    alloc_fn: Option<TypedFunc<i32, i32>>,
+    signals_fn: Option<TypedFunc<(i32, i32, i32), ()>>,
+    last_error: Option<StrategyError>,
+}
+
+// `tick(timestamp, open, high, low, close, volume)` gets the bar of the run symbol, `tick_v2(timestamp)`
+// lets the strategy read the bars of every asset with `get_bar`
+enum TickFn {
+    Bar(TypedFunc<(i64, f64, f64, f64, f64, f64), ()>),
+    Time(TypedFunc<i64, ()>),
+}
+
+// WASI errno returned when a pointer is out of the module memory
+const WASI_EFAULT: i32 = 21;
+// WASI errno of the calls past the host call budget
+const WASI_EAGAIN: i32 = 6;
+
+// Per call limits protecting the run from a strategy stuck placing orders or calling the host
+const DEFAULT_MAX_ORDERS_PER_TICK: u32 = 1_000;
+const DEFAULT_MAX_HOST_CALLS_PER_TICK: u32 = 100_000;
+// Bound a call stuck in a loop and a module growing its memory without end
+const DEFAULT_MAX_FUEL_PER_CALL: u64 = 1_000_000_000;
+const DEFAULT_MAX_MEMORY_BYTES: usize = 256 << 20;
+
+// What the strategy is allowed to do through the host functions, everything is allowed by default
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Capabilities {
+    // Place orders, a read-only strategy can still query the broker
+    pub orders: bool,
+    // Orders accepted per `tick`/`on_gap` call, the next ones are refused
+    pub max_orders_per_tick: Option<u32>,
+    // Host functions called per `tick`/`on_gap` call, the next ones return an error value
+    pub max_host_calls_per_tick: Option<u32>,
+    // Fuel (about one per WASM instruction) of each call into the module, which traps past it
+    pub max_fuel_per_call: Option<u64>,
+    // Size the module memory can grow to, `memory.grow` fails past it
+    pub max_memory_bytes: Option<usize>,
+    // Print through `log` and WASI `fd_write`, silently dropped otherwise
+    pub log: bool,
+    // Read the wall clock through WASI `clock_time_get`, always 0 otherwise
+    pub clock: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            orders: true,
+            max_orders_per_tick: Some(DEFAULT_MAX_ORDERS_PER_TICK),
+            max_host_calls_per_tick: Some(DEFAULT_MAX_HOST_CALLS_PER_TICK),
+            max_fuel_per_call: Some(DEFAULT_MAX_FUEL_PER_CALL),
+            max_memory_bytes: Some(DEFAULT_MAX_MEMORY_BYTES),
+            log: true,
+            clock: true,
+        }
+    }
 }
 
 struct HostState {
     broker_ptr: *mut Broker,
     memory: Option<Memory>,
+    capabilities: Capabilities,
+    orders_placed: u32,
+    host_calls: u32,
+    // Orders and host calls refused during the current call, with the reason of the first one
+    refused: u32,
+    refusal: Option<&'static str>,
+    // One per call with refusals, for `take_warnings`
+    warnings: Vec<StrategyWarning>,
+    market: Option<Arc<MarketData>>,
+    // Tick being simulated, None outside of `tick` and `on_gap`
+    time: Option<NaiveDateTime>,
+    // Bar of the tick within the range, set by the engine before each call
+    clock: RunClock,
+    // Values of the manifest parameters, read with `get_param`
+    parameters: HashMap<String, f64>,
+    // Host calls of the strategy, only kept when recording
+    calls: Option<Vec<HostCall>>,
+    // Of the orders placed from then on, set with `set_time_in_force`
+    time_in_force: TimeInForce,
+    limits: StoreLimits,
+}
+
+// Host function called by the strategy with its arguments and what it returned
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HostCall {
+    // Tick of the call, None during `init`
+    pub time: Option<NaiveDateTime>,
+    pub function: &'static str,
+    pub arguments: Value,
+    pub result: Value,
 }
 
 unsafe impl Send for HostState {}
 
-fn read_string_from_memory(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> String {
-    let memory = caller.data().memory.unwrap();
-    let data = memory.data(caller);
-    let bytes = &data[ptr as usize..(ptr + len) as usize];
-    String::from_utf8_lossy(bytes).to_string()
+// Broker of the current `tick`/`on_gap` call, None during `init` and `signals`
+fn broker<'a>(caller: &Caller<'_, HostState>) -> Option<&'a mut Broker> {
+    unsafe { caller.data().broker_ptr.as_mut() }
+}
+
+impl HostState {
+    fn refuse(&mut self, reason: &'static str) {
+        self.refused += 1;
+        self.refusal.get_or_insert(reason);
+    }
+}
+
+// Host provided memory, or the one exported by the module (AssemblyScript, TinyGo)
+fn caller_memory(caller: &mut Caller<'_, HostState>) -> Option<Memory> {
+    caller
+        .data()
+        .memory
+        .or_else(|| caller.get_export("memory").and_then(Extern::into_memory))
+}
+
+fn read_bytes_from_memory(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Vec<u8> {
+    let Some(memory) = caller_memory(caller) else {
+        return vec![];
+    };
+    memory
+        .data(&caller)
+        .get(ptr as usize..(ptr as usize).saturating_add(len as usize))
+        .unwrap_or_default()
+        .to_vec()
+}
+
+fn read_string_from_memory(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> String {
+    String::from_utf8_lossy(&read_bytes_from_memory(caller, ptr, len)).to_string()
+}
+
+// AssemblyScript strings are UTF-16 with their byte length stored right before the pointer
+fn read_assemblyscript_string(caller: &mut Caller<'_, HostState>, ptr: i32) -> String {
+    if ptr < 4 {
+        return String::new();
+    }
+    let header = read_bytes_from_memory(caller, ptr - 4, 4);
+    let Ok(len) = header.try_into().map(i32::from_le_bytes) else {
+        return String::new();
+    };
+    let units: Vec<u16> = read_bytes_from_memory(caller, ptr, len)
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn record(
+    caller: &mut Caller<'_, HostState>,
+    function: &'static str,
+    arguments: Value,
+    result: Value,
+) {
+    let state = caller.data_mut();
+    let time = state.time;
+    if let Some(calls) = state.calls.as_mut() {
+        calls.push(HostCall {
+            time,
+            function,
+            arguments,
+            result,
+        });
+    }
+}
+
+fn order_arguments(order: &Order) -> Value {
+    let (order_type, price) = match order.order_type {
+        OrderType::Market => ("market", None),
+        OrderType::Limit(price) => ("limit", Some(price)),
+        OrderType::Stop(price) => ("stop", Some(price)),
+    };
+    json!({
+        "asset": order.asset,
+        "direction": order.direction,
+        "type": order_type,
+        "price": price,
+        "size": order.size,
+        "time_in_force": order.time_in_force,
+    })
+}
+
+// Last `period` bars of the asset at the current tick, None until there are as many
+fn history<'a>(
+    caller: &'a Caller<'_, HostState>,
+    asset: &str,
+    period: i32,
+) -> Option<&'a [OHLCVData]> {
+    let state = caller.data();
+    let (market, time) = state.market.as_ref().zip(state.time)?;
+    market.history(asset, time, usize::try_from(period).ok()?)
+}
+
+fn write_bytes_to_memory(caller: &mut Caller<'_, HostState>, ptr: i32, bytes: &[u8]) -> bool {
+    caller_memory(caller)
+        .is_some_and(|memory| memory.write(&mut *caller, ptr as usize, bytes).is_ok())
+}
+
+// Whether the host call being made is past the budget of the current call, it isn't made and
+// returns an error value then. The calls are counted by the call hook
+fn over_budget(caller: &mut Caller<'_, HostState>) -> bool {
+    let state = caller.data_mut();
+    let over = state
+        .capabilities
+        .max_host_calls_per_tick
+        .is_some_and(|max| state.host_calls > max);
+    if over {
+        state.refuse("strategy exceeded its host calls per tick");
+    }
+    over
+}
+
+// Enforces the order capabilities, counting `orders` against the tick limit. Refused orders
+// aren't placed and read as rejected with `get_last_order_rejected`
+fn check_order_capabilities(caller: &mut Caller<'_, HostState>, orders: u32) -> bool {
+    let state = caller.data_mut();
+    let refusal = if !state.capabilities.orders {
+        Some("strategy is not allowed to place orders")
+    } else if state
+        .capabilities
+        .max_orders_per_tick
+        .is_some_and(|max| state.orders_placed + orders > max)
+    {
+        Some("strategy exceeded its orders per tick")
+    } else {
+        None
+    };
+    let Some(reason) = refusal else {
+        state.orders_placed += orders;
+        return true;
+    };
+    state.refuse(reason);
+    if let Some(broker) = unsafe { state.broker_ptr.as_mut() } {
+        broker.last_order_rejected = true;
+        broker.last_order_id = None;
+    }
+    false
+}
+
+fn submit_order(
+    caller: &mut Caller<'_, HostState>,
+    function: &'static str,
+    order: Order,
+) -> Result<()> {
+    let Some(broker) = broker(caller) else {
+        return Ok(());
+    };
+    if !check_order_capabilities(caller, 1) {
+        return Ok(());
+    }
+    let order = Order {
+        time_in_force: caller.data().time_in_force,
+        ..order
+    };
+    record(caller, function, order_arguments(&order), Value::Null);
+    broker.place_order(order);
+    Ok(())
+}
+
+// Market order when the limit price is NaN, invalid sizes and directions are ignored
+fn submit_sized_order(
+    caller: &mut Caller<'_, HostState>,
+    function: &'static str,
+    asset_ptr: i32,
+    asset_len: i32,
+    direction: i32,
+    size: SizeSpec,
+    limit_price: f64,
+) -> Result<()> {
+    let asset = read_string_from_memory(caller, asset_ptr, asset_len);
+
+    let order_direction = match direction {
+        0 => OrderDirection::Buy,
+        1 => OrderDirection::Sell,
+        _ => return Ok(()),
+    };
+    if !size.is_valid() {
+        return Ok(());
+    }
+
+    let order = Order {
+        asset,
+        direction: order_direction,
+        order_type: match limit_price.is_nan() {
+            true => OrderType::Market,
+            false => OrderType::Limit(limit_price),
+        },
+        size,
+        time_in_force: TimeInForce::Gtc,
+        placed_at: None,
+    };
+
+    submit_order(caller, function, order)
+}
+
+// Fuel of the next call into the module, unbounded without `max_fuel_per_call`
+fn refuel(store: &mut Store<HostState>) -> Result<()> {
+    let fuel = store
+        .data()
+        .capabilities
+        .max_fuel_per_call
+        .unwrap_or(u64::MAX);
+    store.set_fuel(fuel)
 }
 
 impl WasmStrategy {
-    pub fn new(wasm_bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(
+        wasm_bytes: &[u8],
+        capabilities: Capabilities,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut config = Config::new();
         config.cranelift_opt_level(wasmtime::OptLevel::Speed);
+        config.consume_fuel(true);
         let engine = Engine::new(&config)?;
         let module = Module::new(&engine, wasm_bytes)?;
 
+        let mut limits = StoreLimitsBuilder::new();
+        if let Some(bytes) = capabilities.max_memory_bytes {
+            limits = limits.memory_size(bytes);
+        }
         let host_state = HostState {
             broker_ptr: ptr::null_mut(),
             memory: None,
+            capabilities,
+            orders_placed: 0,
+            host_calls: 0,
+            refused: 0,
+            refusal: None,
+            warnings: vec![],
+            market: None,
+            time: None,
+            clock: RunClock::default(),
+            parameters: HashMap::new(),
+            calls: None,
+            time_in_force: TimeInForce::Gtc,
+            limits: limits.build(),
         };
 
         let mut store = Store::new(&engine, host_state);
+        store.limiter(|state| &mut state.limits);
+        refuel(&mut store)?;
+        store.call_hook(|mut store, hook| {
+            if matches!(hook, CallHook::CallingHost) {
+                store.data_mut().host_calls += 1;
+            }
+            Ok(())
+        });
 
         let mut linker = Linker::new(&engine);
 
-        let memory_ty = MemoryType::new(16, Some(256));
-        let memory = Memory::new(&mut store, memory_ty)?;
-        linker.define(&store, "env", "memory", memory)?;
-
-        store.data_mut().memory = Some(memory);
+        // Modules either import `env.memory` or export their own memory (AssemblyScript, TinyGo)
+        let memory_import = module.imports().find_map(|import| {
+            match (import.module(), import.name(), import.ty()) {
+                ("env", "memory", ExternType::Memory(ty)) => Some(ty),
+                _ => None,
+            }
+        });
+        if let Some(memory_ty) = memory_import {
+            let memory = Memory::new(&mut store, memory_ty)?;
+            linker.define(&store, "env", "memory", memory)?;
+            store.data_mut().memory = Some(memory);
+        }
 
         linker.func_wrap(
             "env",
@@ -57,27 +386,29 @@ impl WasmStrategy {
              asset_ptr: i32,
              asset_len: i32,
              direction: i32,
-             size: f64| {
-                let asset = read_string_from_memory(&caller, asset_ptr, asset_len);
+             size: f64|
+             -> Result<()> {
+                if over_budget(&mut caller) {
+                    return Ok(());
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
 
                 let order_direction = match direction {
                     0 => OrderDirection::Buy,
                     1 => OrderDirection::Sell,
-                    _ => return,
+                    _ => return Ok(()),
                 };
 
                 let order = Order {
                     asset,
                     direction: order_direction,
                     order_type: OrderType::Market,
-                    size,
-                    valid_until: None,
+                    size: SizeSpec::Quantity(size),
+                    time_in_force: TimeInForce::Gtc,
+                    placed_at: None,
                 };
 
-                unsafe {
-                    let broker = &mut *caller.data_mut().broker_ptr;
-                    broker.place_order(order);
-                }
+                submit_order(&mut caller, "place_market_order", order)
             },
         )?;
 
@@ -89,27 +420,29 @@ impl WasmStrategy {
              asset_len: i32,
              direction: i32,
              size: f64,
-             price: f64| {
-                let asset = read_string_from_memory(&caller, asset_ptr, asset_len);
+             price: f64|
+             -> Result<()> {
+                if over_budget(&mut caller) {
+                    return Ok(());
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
 
                 let order_direction = match direction {
                     0 => OrderDirection::Buy,
                     1 => OrderDirection::Sell,
-                    _ => return,
+                    _ => return Ok(()),
                 };
 
                 let order = Order {
                     asset,
                     direction: order_direction,
                     order_type: OrderType::Limit(price),
-                    size,
-                    valid_until: None,
+                    size: SizeSpec::Quantity(size),
+                    time_in_force: TimeInForce::Gtc,
+                    placed_at: None,
                 };
 
-                unsafe {
-                    let broker = &mut *caller.data_mut().broker_ptr;
-                    broker.place_order(order);
-                }
+                submit_order(&mut caller, "place_limit_order", order)
             },
         )?;
 
@@ -121,80 +454,921 @@ impl WasmStrategy {
              asset_len: i32,
              direction: i32,
              size: f64,
-             stop_price: f64| {
-                let asset = read_string_from_memory(&caller, asset_ptr, asset_len);
+             stop_price: f64|
+             -> Result<()> {
+                if over_budget(&mut caller) {
+                    return Ok(());
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
 
                 let order_direction = match direction {
                     0 => OrderDirection::Buy,
                     1 => OrderDirection::Sell,
-                    _ => return,
+                    _ => return Ok(()),
                 };
 
                 let order = Order {
                     asset,
                     direction: order_direction,
                     order_type: OrderType::Stop(stop_price),
+                    size: SizeSpec::Quantity(size),
+                    time_in_force: TimeInForce::Gtc,
+                    placed_at: None,
+                };
+
+                submit_order(&mut caller, "place_stop_order", order)
+            },
+        )?;
+
+        // Sized in account currency, the quantity is computed at the execution price
+        linker.func_wrap(
+            "env",
+            "place_notional_order",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             direction: i32,
+             value: f64,
+             limit_price: f64|
+             -> Result<()> {
+                if over_budget(&mut caller) {
+                    return Ok(());
+                }
+                let size = SizeSpec::NotionalCash(value);
+                submit_sized_order(
+                    &mut caller,
+                    "place_notional_order",
+                    asset_ptr,
+                    asset_len,
+                    direction,
                     size,
-                    valid_until: None,
+                    limit_price,
+                )
+            },
+        )?;
+
+        // Sized in percent (0 to 100) of the equity when the order executes
+        linker.func_wrap(
+            "env",
+            "place_percent_equity_order",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             direction: i32,
+             percent: f64,
+             limit_price: f64|
+             -> Result<()> {
+                if over_budget(&mut caller) {
+                    return Ok(());
+                }
+                let size = SizeSpec::PercentEquity(percent);
+                submit_sized_order(
+                    &mut caller,
+                    "place_percent_equity_order",
+                    asset_ptr,
+                    asset_len,
+                    direction,
+                    size,
+                    limit_price,
+                )
+            },
+        )?;
+
+        // Market sell of the whole position, returns 0 when the asset isn't held
+        linker.func_wrap(
+            "env",
+            "close_position",
+            |mut caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> Result<i32> {
+                if over_budget(&mut caller) {
+                    return Ok(-1);
+                }
+                let Some(broker) = broker(&caller) else {
+                    return Ok(-1);
                 };
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+                let held = broker.portfolio.contains_key(&asset);
+                if held && !check_order_capabilities(&mut caller, 1) {
+                    return Ok(-1);
+                }
+                record(
+                    &mut caller,
+                    "close_position",
+                    json!({ "asset": asset }),
+                    json!(held as i32),
+                );
+                if !held {
+                    return Ok(0);
+                }
+                Ok(broker.close_position(&asset) as i32)
+            },
+        )?;
 
-                unsafe {
-                    let broker = &mut *caller.data_mut().broker_ptr;
-                    broker.place_order(order);
+        // Returns the number of positions being closed, each one counts as an order
+        linker.func_wrap(
+            "env",
+            "close_all_positions",
+            |mut caller: Caller<'_, HostState>| -> Result<i32> {
+                if over_budget(&mut caller) {
+                    return Ok(-1);
+                }
+                let Some(broker) = broker(&caller) else {
+                    return Ok(-1);
+                };
+                let positions = broker.portfolio.len();
+                if !check_order_capabilities(&mut caller, positions as u32) {
+                    return Ok(-1);
                 }
+                let closed = broker.close_all_positions() as i32;
+                record(&mut caller, "close_all_positions", json!({}), json!(closed));
+                Ok(closed)
             },
         )?;
 
-        linker.func_wrap("env", "get_cash", |caller: Caller<'_, HostState>| -> f64 {
-            unsafe {
-                let broker = &*caller.data().broker_ptr;
-                broker.cash
-            }
-        })?;
+        // Parent order sliced by the broker, a NaN limit price is a market parent. Algos are
+        // 0 TWAP (duration in seconds, slices), 1 VWAP (participation) and 2 iceberg (visible size).
+        // Returns the parent id, or -1 when it is rejected
+        linker.func_wrap(
+            "env",
+            "place_algo_order",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             direction: i32,
+             size: f64,
+             limit_price: f64,
+             algo: i32,
+             param1: f64,
+             param2: f64|
+             -> Result<i64> {
+                if over_budget(&mut caller) {
+                    return Ok(-1);
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+
+                let order_direction = match direction {
+                    0 => OrderDirection::Buy,
+                    1 => OrderDirection::Sell,
+                    _ => return Ok(-1),
+                };
+                let algo = match algo {
+                    0 => ExecutionAlgo::Twap {
+                        duration_seconds: param1 as i64,
+                        slices: param2 as u32,
+                    },
+                    1 => ExecutionAlgo::Vwap {
+                        participation: param1,
+                    },
+                    2 => ExecutionAlgo::Iceberg { visible: param1 },
+                    _ => return Ok(-1),
+                };
+
+                let order = Order {
+                    asset,
+                    direction: order_direction,
+                    order_type: match limit_price.is_nan() {
+                        true => OrderType::Market,
+                        false => OrderType::Limit(limit_price),
+                    },
+                    size: SizeSpec::Quantity(size),
+                    time_in_force: caller.data().time_in_force,
+                    placed_at: None,
+                };
+
+                let Some(broker) = broker(&caller) else {
+                    return Ok(-1);
+                };
+                if !check_order_capabilities(&mut caller, 1) {
+                    return Ok(-1);
+                }
+                let mut arguments = order_arguments(&order);
+                arguments["algo"] = json!(algo);
+                let id = match broker.place_algo_order(order, algo) {
+                    Ok(id) => id as i64,
+                    Err(_) => -1,
+                };
+                record(&mut caller, "place_algo_order", arguments, json!(id));
+                Ok(id)
+            },
+        )?;
+
+        linker.func_wrap(
+            "env",
+            "get_cash",
+            |mut caller: Caller<'_, HostState>| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let cash = broker.cash;
+                record(&mut caller, "get_cash", json!({}), json!(cash));
+                cash
+            },
+        )?;
 
         linker.func_wrap(
             "env",
             "get_position",
-            |caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> f64 {
-                let asset = read_string_from_memory(&caller, asset_ptr, asset_len);
+            |mut caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+
+                let quantity = broker
+                    .portfolio
+                    .get(&asset)
+                    .map(|p| p.quantity)
+                    .unwrap_or(0.0);
+                record(
+                    &mut caller,
+                    "get_position",
+                    json!({ "asset": asset }),
+                    json!(quantity),
+                );
+                quantity
+            },
+        )?;
 
-                unsafe {
-                    let broker = &*caller.data().broker_ptr;
-                    broker
-                        .portfolio
-                        .get(&asset)
-                        .map(|p| p.quantity)
-                        .unwrap_or(0.0)
+        // NaN when the position can't be liquidated
+        linker.func_wrap(
+            "env",
+            "get_liquidation_price",
+            |mut caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
                 }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+
+                let price = broker.liquidation_price(&asset).unwrap_or(f64::NAN);
+                record(
+                    &mut caller,
+                    "get_liquidation_price",
+                    json!({ "asset": asset }),
+                    json!(price),
+                );
+                price
             },
         )?;
 
+        // NaN when the asset isn't held
         linker.func_wrap(
             "env",
-            "log",
-            |caller: Caller<'_, HostState>, ptr: i32, len: i32| {
-                let memory = caller.data().memory.unwrap();
-                let data = memory.data(&caller);
+            "get_position_avg_price",
+            |mut caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+
+                let price = broker
+                    .portfolio
+                    .get(&asset)
+                    .map_or(f64::NAN, |p| p.average_price);
+                record(
+                    &mut caller,
+                    "get_position_avg_price",
+                    json!({ "asset": asset }),
+                    json!(price),
+                );
+                price
+            },
+        )?;
+
+        // At the close of the current bar in the account currency, 0 when the asset isn't held
+        linker.func_wrap(
+            "env",
+            "get_unrealized_pnl",
+            |mut caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+
+                let pnl = broker.unrealized_pnl(&asset).unwrap_or(0.0);
+                record(
+                    &mut caller,
+                    "get_unrealized_pnl",
+                    json!({ "asset": asset }),
+                    json!(pnl),
+                );
+                pnl
+            },
+        )?;
+
+        // Cash and positions at the close of the current bar
+        linker.func_wrap(
+            "env",
+            "get_total_equity",
+            |mut caller: Caller<'_, HostState>| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let equity = broker.total_equity();
+                record(&mut caller, "get_total_equity", json!({}), json!(equity));
+                equity
+            },
+        )?;
+
+        // Cash positions can be bought with, the loan of a margin account included, less the cash
+        // reserved by the resting buys
+        linker.func_wrap(
+            "env",
+            "get_buying_power",
+            |mut caller: Caller<'_, HostState>| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return f64::NAN;
+                };
+                let buying_power = broker.buying_power();
+                record(
+                    &mut caller,
+                    "get_buying_power",
+                    json!({}),
+                    json!(buying_power),
+                );
+                buying_power
+            },
+        )?;
+
+        // Time in force of the next orders: 0 GTC, 1 day, 2 IOC, 3 FOK or 4 good till `until`
+        // in Unix seconds. Invalid values are ignored
+        linker.func_wrap(
+            "env",
+            "set_time_in_force",
+            |mut caller: Caller<'_, HostState>, kind: i32, until: i64| {
+                if over_budget(&mut caller) {
+                    return;
+                }
+                let time_in_force = match kind {
+                    0 => Some(TimeInForce::Gtc),
+                    1 => Some(TimeInForce::Day),
+                    2 => Some(TimeInForce::Ioc),
+                    3 => Some(TimeInForce::Fok),
+                    4 => chrono::DateTime::from_timestamp(until, 0)
+                        .map(|until| TimeInForce::Gtd(until.naive_utc())),
+                    _ => None,
+                };
+                record(
+                    &mut caller,
+                    "set_time_in_force",
+                    json!({ "kind": kind, "until": until }),
+                    json!(time_in_force),
+                );
+                if let Some(time_in_force) = time_in_force {
+                    caller.data_mut().time_in_force = time_in_force;
+                }
+            },
+        )?;
+
+        // Id of the last order placed, -1 when it was refused
+        linker.func_wrap(
+            "env",
+            "get_last_order_id",
+            |mut caller: Caller<'_, HostState>| -> i64 {
+                if over_budget(&mut caller) {
+                    return -1;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return -1;
+                };
+                let id = broker.last_order_id.map_or(-1, |id| id as i64);
+                record(&mut caller, "get_last_order_id", json!({}), json!(id));
+                id
+            },
+        )?;
+
+        // 0 once the open order or algo parent is cancelled, -1 when it isn't in the book
+        linker.func_wrap(
+            "env",
+            "cancel_order",
+            |mut caller: Caller<'_, HostState>, id: i64| -> Result<i32> {
+                if over_budget(&mut caller) {
+                    return Ok(-1);
+                }
+                let Some(broker) = broker(&caller) else {
+                    return Ok(-1);
+                };
+                if !check_order_capabilities(&mut caller, 1) {
+                    return Ok(-1);
+                }
+                let result = match broker.cancel_order(id as u64) {
+                    Ok(()) => 0,
+                    Err(_) => -1,
+                };
+                record(
+                    &mut caller,
+                    "cancel_order",
+                    json!({ "id": id }),
+                    json!(result),
+                );
+                Ok(result)
+            },
+        )?;
+
+        // New quantity and limit or stop price of an open order, NaN keeps them. 0 once amended,
+        // -1 when the order isn't in the book or the amendment is refused
+        linker.func_wrap(
+            "env",
+            "amend_order",
+            |mut caller: Caller<'_, HostState>, id: i64, size: f64, price: f64| -> Result<i32> {
+                if over_budget(&mut caller) {
+                    return Ok(-1);
+                }
+                let Some(broker) = broker(&caller) else {
+                    return Ok(-1);
+                };
+                if !check_order_capabilities(&mut caller, 1) {
+                    return Ok(-1);
+                }
+                let kept = |value: f64| (!value.is_nan()).then_some(value);
+                let result = match broker.amend_order(id as u64, kept(size), kept(price)) {
+                    Ok(()) => 0,
+                    Err(_) => -1,
+                };
+                record(
+                    &mut caller,
+                    "amend_order",
+                    json!({ "id": id, "size": kept(size), "price": kept(price) }),
+                    json!(result),
+                );
+                Ok(result)
+            },
+        )?;
 
-                let bytes = &data[ptr as usize..(ptr + len) as usize];
-                let message = String::from_utf8_lossy(bytes);
+        // 1 when the last order placed was refused right away, by the warm-up, a hook or the
+        // buying power check
+        linker.func_wrap(
+            "env",
+            "get_last_order_rejected",
+            |mut caller: Caller<'_, HostState>| -> i32 {
+                if over_budget(&mut caller) {
+                    return -1;
+                }
+                let Some(broker) = broker(&caller) else {
+                    return -1;
+                };
+                let rejected = broker.last_order_rejected;
+                record(
+                    &mut caller,
+                    "get_last_order_rejected",
+                    json!({}),
+                    json!(rejected),
+                );
+                rejected as i32
+            },
+        )?;
+
+        // Unix timestamp in seconds of the tick being simulated, 0 during `init`
+        linker.func_wrap(
+            "env",
+            "get_time",
+            |mut caller: Caller<'_, HostState>| -> i64 {
+                if over_budget(&mut caller) {
+                    return i64::MIN;
+                }
+                let time = caller
+                    .data()
+                    .time
+                    .map_or(0, |time| time.and_utc().timestamp());
+                record(&mut caller, "get_time", json!({}), json!(time));
+                time
+            },
+        )?;
+
+        // Index of the current bar from the first one of the range, negative during the warm-up
+        linker.func_wrap(
+            "env",
+            "get_bar_index",
+            |mut caller: Caller<'_, HostState>| -> i64 {
+                if over_budget(&mut caller) {
+                    return i64::MIN;
+                }
+                let index = caller.data().clock.bar_index;
+                record(&mut caller, "get_bar_index", json!({}), json!(index));
+                index
+            },
+        )?;
+
+        // Bars of the range after the current one, 0 on the last bar
+        linker.func_wrap(
+            "env",
+            "get_bars_remaining",
+            |mut caller: Caller<'_, HostState>| -> i64 {
+                if over_budget(&mut caller) {
+                    return i64::MIN;
+                }
+                let remaining = caller.data().clock.bars_remaining;
+                record(
+                    &mut caller,
+                    "get_bars_remaining",
+                    json!({}),
+                    json!(remaining),
+                );
+                remaining
+            },
+        )?;
+
+        // Value of a parameter declared in the manifest, NaN when it isn't declared
+        linker.func_wrap(
+            "env",
+            "get_param",
+            |mut caller: Caller<'_, HostState>, name_ptr: i32, name_len: i32| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let name = read_string_from_memory(&mut caller, name_ptr, name_len);
+                let value = caller
+                    .data()
+                    .parameters
+                    .get(&name)
+                    .copied()
+                    .unwrap_or(f64::NAN);
+                record(
+                    &mut caller,
+                    "get_param",
+                    json!({ "name": name }),
+                    json!(value),
+                );
+                value
+            },
+        )?;
+
+        // Writes the last bar of the asset at the current tick to `out_ptr` as 6 f64: timestamp,
+        // open, high, low, close and volume. Returns 0 when the asset has no bar yet
+        linker.func_wrap(
+            "env",
+            "get_bar",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             out_ptr: i32|
+             -> i32 {
+                if over_budget(&mut caller) {
+                    return -1;
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+                let state = caller.data();
+                let bar = state
+                    .market
+                    .as_ref()
+                    .zip(state.time)
+                    .and_then(|(market, time)| market.bar(&asset, time))
+                    .cloned();
+                record(
+                    &mut caller,
+                    "get_bar",
+                    json!({ "asset": asset }),
+                    json!(bar),
+                );
+                let Some(bar) = bar else {
+                    return 0;
+                };
+                let bytes: Vec<u8> = [
+                    bar.timestamp.and_utc().timestamp() as f64,
+                    bar.open,
+                    bar.high,
+                    bar.low,
+                    bar.close,
+                    bar.volume as f64,
+                ]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+                write_bytes_to_memory(&mut caller, out_ptr, &bytes) as i32
+            },
+        )?;
+
+        // Volume weighted average of the typical price over the last `period` bars of the asset,
+        // NaN until there are as many bars or without volume
+        linker.func_wrap(
+            "env",
+            "get_vwap",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             period: i32|
+             -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+                let vwap = history(&caller, &asset, period)
+                    .and_then(volume::vwap)
+                    .unwrap_or(f64::NAN);
+                record(
+                    &mut caller,
+                    "get_vwap",
+                    json!({ "asset": asset, "period": period }),
+                    json!(vwap),
+                );
+                vwap
+            },
+        )?;
+
+        // On-balance volume of the asset from its first bar, NaN when it has no bar yet
+        linker.func_wrap(
+            "env",
+            "get_obv",
+            |mut caller: Caller<'_, HostState>, asset_ptr: i32, asset_len: i32| -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+                let state = caller.data();
+                let obv = state
+                    .market
+                    .as_ref()
+                    .zip(state.time)
+                    .and_then(|(market, time)| market.obv(&asset, time))
+                    .unwrap_or(f64::NAN);
+                record(
+                    &mut caller,
+                    "get_obv",
+                    json!({ "asset": asset }),
+                    json!(obv),
+                );
+                obv
+            },
+        )?;
+
+        // Buying against selling pressure over the last `period` bars, from -1 (every bar closing
+        // on its low) to 1 (on its high). NaN until there are as many bars or without volume
+        linker.func_wrap(
+            "env",
+            "get_volume_imbalance",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             period: i32|
+             -> f64 {
+                if over_budget(&mut caller) {
+                    return f64::NAN;
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+                let imbalance = history(&caller, &asset, period)
+                    .and_then(volume::imbalance)
+                    .unwrap_or(f64::NAN);
+                record(
+                    &mut caller,
+                    "get_volume_imbalance",
+                    json!({ "asset": asset, "period": period }),
+                    json!(imbalance),
+                );
+                imbalance
+            },
+        )?;
+
+        // Writes the volume profile of the last `period` bars to `out_ptr` as 3 f64: point of
+        // control, value area low and high. Returns 0 until there are as many bars
+        linker.func_wrap(
+            "env",
+            "get_volume_profile",
+            |mut caller: Caller<'_, HostState>,
+             asset_ptr: i32,
+             asset_len: i32,
+             period: i32,
+             out_ptr: i32|
+             -> i32 {
+                if over_budget(&mut caller) {
+                    return -1;
+                }
+                let asset = read_string_from_memory(&mut caller, asset_ptr, asset_len);
+                let profile = history(&caller, &asset, period).and_then(volume::volume_profile);
+                record(
+                    &mut caller,
+                    "get_volume_profile",
+                    json!({ "asset": asset, "period": period }),
+                    json!(profile.map(|profile| [
+                        profile.poc,
+                        profile.value_area_low,
+                        profile.value_area_high
+                    ])),
+                );
+                let Some(profile) = profile else {
+                    return 0;
+                };
+                let bytes: Vec<u8> = [profile.poc, profile.value_area_low, profile.value_area_high]
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect();
+                write_bytes_to_memory(&mut caller, out_ptr, &bytes) as i32
+            },
+        )?;
+
+        linker.func_wrap(
+            "env",
+            "log",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+                if over_budget(&mut caller) {
+                    return;
+                }
+                let message = read_string_from_memory(&mut caller, ptr, len);
+                record(
+                    &mut caller,
+                    "log",
+                    json!({ "message": message }),
+                    Value::Null,
+                );
+                if !caller.data().capabilities.log {
+                    return;
+                }
                 println!("[WASM]: {}", message);
             },
         )?;
 
+        // AssemblyScript runtime: `abort(message, file, line, column)` traps the current call
         linker.func_wrap(
             "env",
             "abort",
-            |_: Caller<'_, HostState>, _: i32, _: i32, _: i32, _: i32| {
-                eprintln!("[WASM]: abort called");
+            |mut caller: Caller<'_, HostState>,
+             message_ptr: i32,
+             file_ptr: i32,
+             line: i32,
+             column: i32|
+             -> Result<()> {
+                let message = read_assemblyscript_string(&mut caller, message_ptr);
+                let file = read_assemblyscript_string(&mut caller, file_ptr);
+                record(
+                    &mut caller,
+                    "abort",
+                    json!({ "message": message, "file": file, "line": line, "column": column }),
+                    Value::Null,
+                );
+                eprintln!("[WASM]: abort: {} at {}:{}:{}", message, file, line, column);
+                Err(Error::msg(format!("abort: {}", message)))
+            },
+        )?;
+
+        // AssemblyScript seeds `Math.random` with it
+        linker.func_wrap("env", "seed", |_: Caller<'_, HostState>| -> f64 {
+            rand::random::<f64>() * u32::MAX as f64
+        })?;
+
+        // WASI subset TinyGo needs: output goes to the logs, no args or environment
+        linker.func_wrap(
+            "wasi_snapshot_preview1",
+            "fd_write",
+            |mut caller: Caller<'_, HostState>,
+             fd: i32,
+             iovs_ptr: i32,
+             iovs_len: i32,
+             written_ptr: i32|
+             -> i32 {
+                if over_budget(&mut caller) {
+                    return WASI_EAGAIN;
+                }
+                let mut bytes = vec![];
+                for i in 0..iovs_len {
+                    let iov = read_bytes_from_memory(&mut caller, iovs_ptr + i * 8, 8);
+                    if iov.len() != 8 {
+                        return WASI_EFAULT;
+                    }
+                    let ptr = i32::from_le_bytes([iov[0], iov[1], iov[2], iov[3]]);
+                    let len = i32::from_le_bytes([iov[4], iov[5], iov[6], iov[7]]);
+                    bytes.extend(read_bytes_from_memory(&mut caller, ptr, len));
+                }
+
+                let message = String::from_utf8_lossy(&bytes);
+                let message = message.trim_end();
+                if !message.is_empty() {
+                    let arguments = json!({ "fd": fd, "message": message });
+                    record(&mut caller, "fd_write", arguments, Value::Null);
+                }
+                if caller.data().capabilities.log && !message.is_empty() {
+                    match fd {
+                        2 => eprintln!("[WASM]: {}", message),
+                        _ => println!("[WASM]: {}", message),
+                    }
+                }
+
+                let written = (bytes.len() as u32).to_le_bytes();
+                if write_bytes_to_memory(&mut caller, written_ptr, &written) {
+                    0
+                } else {
+                    WASI_EFAULT
+                }
+            },
+        )?;
+
+        linker.func_wrap(
+            "wasi_snapshot_preview1",
+            "random_get",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i32 {
+                if over_budget(&mut caller) {
+                    return WASI_EAGAIN;
+                }
+                let bytes: Vec<u8> = (0..len).map(|_| rand::random::<u8>()).collect();
+                if write_bytes_to_memory(&mut caller, ptr, &bytes) {
+                    0
+                } else {
+                    WASI_EFAULT
+                }
+            },
+        )?;
+
+        for name in ["args_sizes_get", "environ_sizes_get"] {
+            linker.func_wrap(
+                "wasi_snapshot_preview1",
+                name,
+                |mut caller: Caller<'_, HostState>, count_ptr: i32, size_ptr: i32| -> i32 {
+                    let ok = write_bytes_to_memory(&mut caller, count_ptr, &0u32.to_le_bytes())
+                        && write_bytes_to_memory(&mut caller, size_ptr, &0u32.to_le_bytes());
+                    if ok {
+                        0
+                    } else {
+                        WASI_EFAULT
+                    }
+                },
+            )?;
+        }
+
+        for name in ["args_get", "environ_get"] {
+            linker.func_wrap(
+                "wasi_snapshot_preview1",
+                name,
+                |_: Caller<'_, HostState>, _: i32, _: i32| -> i32 { 0 },
+            )?;
+        }
+
+        linker.func_wrap(
+            "wasi_snapshot_preview1",
+            "clock_time_get",
+            |mut caller: Caller<'_, HostState>, _: i32, _: i64, time_ptr: i32| -> i32 {
+                if over_budget(&mut caller) {
+                    return WASI_EAGAIN;
+                }
+                let now = match caller.data().capabilities.clock {
+                    true => chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+                    false => 0,
+                };
+                if write_bytes_to_memory(&mut caller, time_ptr, &(now as u64).to_le_bytes()) {
+                    0
+                } else {
+                    WASI_EFAULT
+                }
+            },
+        )?;
+
+        linker.func_wrap(
+            "wasi_snapshot_preview1",
+            "proc_exit",
+            |_: Caller<'_, HostState>, code: i32| -> Result<()> {
+                Err(Error::msg(format!("exited with code {}", code)))
             },
         )?;
 
+        // Any other import only traps if the strategy actually calls it
+        linker.define_unknown_imports_as_traps(&module)?;
+
         let instance = linker.instantiate(&mut store, &module)?;
 
+        if store.data().memory.is_none() {
+            store.data_mut().memory = instance.get_memory(&mut store, "memory");
+        }
+
+        // TinyGo reactors (and AssemblyScript with --exportStart) initialize their runtime here
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            initialize.call(&mut store, ())?;
+        }
+
         let init_fn = instance.get_typed_func::<(), ()>(&mut store, "init")?;
-        let tick_fn =
-            instance.get_typed_func::<(i64, f64, f64, f64, f64, f64), ()>(&mut store, "tick")?;
+        let tick_fn = match instance.get_typed_func::<i64, ()>(&mut store, "tick_v2") {
+            Ok(tick_v2) => TickFn::Time(tick_v2),
+            Err(_) => TickFn::Bar(
+                instance
+                    .get_typed_func::<(i64, f64, f64, f64, f64, f64), ()>(&mut store, "tick")?,
+            ),
+        };
+        // Optional export, strategies without it are never notified of gaps
+        let on_gap_fn = instance
+            .get_typed_func::<(i64, i64), ()>(&mut store, "on_gap")
+            .ok();
+        // Vectorizable strategies export `alloc(size) -> ptr` and `signals(bars_ptr, len, out_ptr)`
+        let alloc_fn = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .ok();
+        let signals_fn = instance
+            .get_typed_func::<(i32, i32, i32), ()>(&mut store, "signals")
+            .ok();
 
         Ok(WasmStrategy {
             _engine: engine,
@@ -202,13 +1376,101 @@ impl WasmStrategy {
             _instance: instance,
             init_fn,
             tick_fn,
+            on_gap_fn,
+            alloc_fn,
+            signals_fn,
+            last_error: None,
         })
     }
 }
 
+impl WasmStrategy {
+    // Parameter values read by the strategy from `init` on
+    pub fn set_parameters(&mut self, parameters: HashMap<String, f64>) {
+        self.store.data_mut().parameters = parameters;
+    }
+
+    // Keeps every host call from now on, for `take_calls`
+    pub fn record_calls(&mut self) {
+        self.store.data_mut().calls = Some(vec![]);
+    }
+
+    // Host calls recorded since the last take
+    pub fn take_calls(&mut self) -> Vec<HostCall> {
+        let calls = &mut self.store.data_mut().calls;
+        calls.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // Gives the strategy the broker and resets the per call limits
+    fn enter(&mut self, broker: *mut Broker, time: Option<NaiveDateTime>) {
+        // Only fails when fuel isn't enabled in the config, which it always is
+        let _ = refuel(&mut self.store);
+        let state = self.store.data_mut();
+        state.broker_ptr = broker;
+        state.time = time;
+        state.orders_placed = 0;
+        state.host_calls = 0;
+    }
+
+    // Takes the broker back, warning of the calls refused by the limits
+    fn leave(&mut self) {
+        let state = self.store.data_mut();
+        if let Some(reason) = state.refusal.take() {
+            state.warnings.push(StrategyWarning {
+                time: state.time,
+                message: reason.to_string(),
+                refused: std::mem::take(&mut state.refused),
+            });
+        }
+        state.broker_ptr = ptr::null_mut();
+        state.time = None;
+    }
+
+    // Keeps the trap and backtrace of a failed call for `take_error`
+    fn record_error(&mut self, error: Error, time: Option<NaiveDateTime>) {
+        let backtrace = error
+            .downcast_ref::<WasmBacktrace>()
+            .map(|backtrace| {
+                backtrace
+                    .frames()
+                    .iter()
+                    .map(|frame| {
+                        let function = match frame.func_name() {
+                            Some(name) => name.to_string(),
+                            None => format!("<wasm function {}>", frame.func_index()),
+                        };
+                        match frame.module_offset() {
+                            Some(offset) => format!("{} @ {:#x}", function, offset),
+                            None => function,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.last_error = Some(StrategyError {
+            message: error.root_cause().to_string(),
+            trap: error
+                .downcast_ref::<Trap>()
+                .map(|trap| format!("{:?}", trap)),
+            backtrace,
+            time,
+        });
+    }
+}
+
 impl Strategy for WasmStrategy {
     fn init(&mut self) {
-        self.init_fn.call(&mut self.store, ()).ok();
+        self.enter(ptr::null_mut(), None);
+        self.store.data_mut().time_in_force = TimeInForce::Gtc;
+        if let Err(e) = self.init_fn.call(&mut self.store, ()) {
+            self.record_error(e, None);
+        }
+        self.leave();
+    }
+
+    fn set_clock(&mut self, clock: RunClock) {
+        self.store.data_mut().clock = clock;
     }
 
     fn tick(
@@ -217,25 +1479,419 @@ impl Strategy for WasmStrategy {
         data: Option<&OHLCVData>,
         broker: &mut Broker,
     ) {
-        self.store.data_mut().broker_ptr = broker as *mut Broker;
-
-        if let Some(current) = data {
-            let timestamp = current_time.and_utc().timestamp();
-            self.tick_fn
-                .call(
-                    &mut self.store,
-                    (
-                        timestamp,
-                        current.open,
-                        current.high,
-                        current.low,
-                        current.close,
-                        current.volume as f64,
-                    ),
-                )
-                .ok();
+        self.enter(broker as *mut Broker, Some(*current_time));
+
+        let timestamp = current_time.and_utc().timestamp();
+        let result = match (&self.tick_fn, data) {
+            (TickFn::Time(tick_v2), _) => tick_v2.call(&mut self.store, timestamp),
+            (TickFn::Bar(tick), Some(current)) => tick.call(
+                &mut self.store,
+                (
+                    timestamp,
+                    current.open,
+                    current.high,
+                    current.low,
+                    current.close,
+                    current.volume as f64,
+                ),
+            ),
+            (TickFn::Bar(_), None) => Ok(()),
+        };
+        if let Err(e) = result {
+            self.record_error(e, Some(*current_time));
+        }
+        self.leave();
+    }
+
+    fn on_gap(&mut self, current_time: &NaiveDateTime, duration: Duration, broker: &mut Broker) {
+        let Some(on_gap_fn) = self.on_gap_fn.clone() else {
+            return;
+        };
+
+        self.enter(broker as *mut Broker, Some(*current_time));
+        let result = on_gap_fn.call(
+            &mut self.store,
+            (current_time.and_utc().timestamp(), duration.num_seconds()),
+        );
+        if let Err(e) = result {
+            self.record_error(e, Some(*current_time));
+        }
+        self.leave();
+    }
+
+    fn subscribe(&mut self, market: Arc<MarketData>) {
+        self.store.data_mut().market = Some(market);
+    }
+
+    fn take_error(&mut self) -> Option<StrategyError> {
+        self.last_error.take()
+    }
+
+    fn take_warnings(&mut self) -> Vec<StrategyWarning> {
+        std::mem::take(&mut self.store.data_mut().warnings)
+    }
+
+    // Bars are written as [open, high, low, close, volume] f64 rows, one f64 exposure is read back per bar
+    fn signals(&mut self, data: &[OHLCVData]) -> Option<Vec<f64>> {
+        let (alloc_fn, signals_fn) = (self.alloc_fn.clone()?, self.signals_fn.clone()?);
+        let memory = self.store.data().memory?;
+
+        // No broker to call, but the budgets of a call still apply
+        self.enter(ptr::null_mut(), None);
+        let result = (|| -> Result<Vec<f64>> {
+            let bars: Vec<u8> = data
+                .iter()
+                .flat_map(|bar| [bar.open, bar.high, bar.low, bar.close, bar.volume as f64])
+                .flat_map(f64::to_le_bytes)
+                .collect();
+            let bars_ptr = alloc_fn.call(&mut self.store, bars.len() as i32)?;
+            memory.write(&mut self.store, bars_ptr as usize, &bars)?;
+
+            let out_ptr = alloc_fn.call(&mut self.store, (data.len() * 8) as i32)?;
+            signals_fn.call(&mut self.store, (bars_ptr, data.len() as i32, out_ptr))?;
+
+            let mut out = vec![0u8; data.len() * 8];
+            memory.read(&self.store, out_ptr as usize, &mut out)?;
+            Ok(out
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect())
+        })();
+        self.leave();
+
+        result.map_err(|error| self.record_error(error, None)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle() -> OHLCVData {
+        OHLCVData {
+            timestamp: NaiveDateTime::parse_from_str("2024-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+                .expect("Invalid date"),
+            open: 100.0,
+            high: 100.0,
+            low: 100.0,
+            close: 100.0,
+            volume: 1000,
         }
+    }
+
+    #[test]
+    fn module_exported_memory_with_runtime_imports() {
+        // Shaped like AssemblyScript/TinyGo output: own memory, abort, seed and WASI imports
+        let wat = r#"
+            (module
+              (import "env" "place_market_order" (func $order (param i32 i32 i32 f64)))
+              (import "env" "abort" (func $abort (param i32 i32 i32 i32)))
+              (import "env" "seed" (func $seed (result f64)))
+              (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "sched_yield" (func $yield (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 16) "AAPL")
+              (data (i32.const 32) "\14\00\00\00\04\00\00\00")
+              (data (i32.const 20) "init\n")
+              (global $ticks (mut i32) (i32.const 0))
+              (func (export "_initialize")
+                (drop (call $fd_write (i32.const 1) (i32.const 32) (i32.const 1) (i32.const 48))))
+              (func (export "init"))
+              (func (export "tick") (param i64 f64 f64 f64 f64 f64)
+                (global.set $ticks (i32.add (global.get $ticks) (i32.const 1)))
+                (if (i32.eq (global.get $ticks) (i32.const 2))
+                  (then (call $abort (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 1))))
+                (call $order (i32.const 16) (i32.const 4) (i32.const 0) (f64.const 1))))
+        "#;
+
+        let mut strategy = WasmStrategy::new(wat.as_bytes(), Capabilities::default())
+            .expect("Failed to load module");
+        let mut broker = Broker::new();
+        let bar = candle();
+
+        strategy.init();
+        strategy.tick(&bar.timestamp, Some(&bar), &mut broker);
+        assert_eq!(broker.orders.len(), 1);
+        assert_eq!(broker.orders[0].order.asset, "AAPL");
+
+        // `abort` traps the call before the order is placed
+        strategy.tick(&bar.timestamp, Some(&bar), &mut broker);
+        assert_eq!(broker.orders.len(), 1);
+    }
+
+    #[test]
+    fn traps_keep_their_backtrace() {
+        let wat = r#"
+            (module
+              (import "env" "memory" (memory 1))
+              (func (export "init"))
+              (func $divide (param i32) (result i32)
+                (i32.div_u (i32.const 1) (local.get 0)))
+              (func (export "tick") (param i64 f64 f64 f64 f64 f64)
+                (drop (call $divide (i32.const 0)))))
+        "#;
+        let mut strategy = WasmStrategy::new(wat.as_bytes(), Capabilities::default())
+            .expect("Failed to load module");
+        let bar = candle();
+
+        strategy.init();
+        assert_eq!(strategy.take_error(), None);
+        strategy.tick(&bar.timestamp, Some(&bar), &mut Broker::new());
+
+        let error = strategy.take_error().unwrap();
+        assert_eq!(error.trap.as_deref(), Some("IntegerDivisionByZero"));
+        assert_eq!(error.time, Some(bar.timestamp));
+        assert!(error.backtrace[0].starts_with("divide @ "));
+        assert_eq!(strategy.take_error(), None);
+    }
+
+    #[test]
+    fn tick_v2_reads_the_bars_of_other_assets() {
+        // Buys one ETH when its close is above 50, the bar is written at 64
+        let wat = r#"
+            (module
+              (import "env" "memory" (memory 1))
+              (import "env" "get_bar" (func $bar (param i32 i32 i32) (result i32)))
+              (import "env" "place_market_order" (func $order (param i32 i32 i32 f64)))
+              (data (i32.const 16) "ETH")
+              (func (export "init"))
+              (func (export "tick_v2") (param i64)
+                (if (i32.and
+                      (call $bar (i32.const 16) (i32.const 3) (i32.const 64))
+                      (f64.gt (f64.load (i32.const 96)) (f64.const 50)))
+                  (then (call $order (i32.const 16) (i32.const 3) (i32.const 0) (f64.const 1))))))
+        "#;
+        let mut strategy = WasmStrategy::new(wat.as_bytes(), Capabilities::default())
+            .expect("Failed to load module");
+        let bar = candle();
+        let eth = |close: f64| OHLCVData {
+            close,
+            ..bar.clone()
+        };
+        let mut market = MarketData::default();
+        market.insert("ETH".to_string(), vec![eth(40.0)]);
+        strategy.subscribe(Arc::new(market));
+
+        let mut broker = Broker::new();
+        strategy.tick(&bar.timestamp, None, &mut broker);
+        assert!(broker.orders.is_empty());
+
+        let mut market = MarketData::default();
+        market.insert("ETH".to_string(), vec![eth(60.0)]);
+        strategy.subscribe(Arc::new(market));
+        strategy.tick(&bar.timestamp, None, &mut broker);
+        assert_eq!(broker.orders[0].order.asset, "ETH");
+    }
+
+    #[test]
+    fn reads_volume_indicators_up_to_the_tick() {
+        // Orders sized with the VWAP of the last 2 bars and the OBV of ETH
+        let wat = r#"
+            (module
+              (import "env" "memory" (memory 1))
+              (import "env" "get_vwap" (func $vwap (param i32 i32 i32) (result f64)))
+              (import "env" "get_obv" (func $obv (param i32 i32) (result f64)))
+              (import "env" "place_market_order" (func $order (param i32 i32 i32 f64)))
+              (data (i32.const 16) "ETH")
+              (func (export "init"))
+              (func (export "tick_v2") (param i64)
+                (call $order (i32.const 16) (i32.const 3) (i32.const 0)
+                  (call $vwap (i32.const 16) (i32.const 3) (i32.const 2)))
+                (call $order (i32.const 16) (i32.const 3) (i32.const 0)
+                  (call $obv (i32.const 16) (i32.const 3)))))
+        "#;
+        let mut strategy = WasmStrategy::new(wat.as_bytes(), Capabilities::default())
+            .expect("Failed to load module");
+        let bar = candle();
+        let eth = |days: i64, close: f64, volume: u64| OHLCVData {
+            timestamp: bar.timestamp + Duration::days(days),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume,
+        };
+        let mut market = MarketData::default();
+        market.insert(
+            "ETH".to_string(),
+            vec![eth(0, 10.0, 100), eth(1, 11.0, 300), eth(2, 20.0, 100)],
+        );
+        strategy.subscribe(Arc::new(market));
+
+        let mut broker = Broker::new();
+        strategy.tick(&bar.timestamp, None, &mut broker);
+        // A single bar is not enough for the VWAP
+        assert!(matches!(broker.orders[0].order.size, SizeSpec::Quantity(size) if size.is_nan()));
+        assert!(matches!(broker.orders[1].order.size, SizeSpec::Quantity(size) if size == 0.0));
+
+        let mut broker = Broker::new();
+        strategy.tick(&(bar.timestamp + Duration::days(1)), None, &mut broker);
+        assert!(matches!(broker.orders[0].order.size, SizeSpec::Quantity(size) if size == 10.75));
+        assert!(matches!(broker.orders[1].order.size, SizeSpec::Quantity(size) if size == 300.0));
+    }
+
+    #[test]
+    fn capabilities_limit_orders() {
+        let wat = r#"
+            (module
+              (import "env" "memory" (memory 1))
+              (import "env" "place_market_order" (func $order (param i32 i32 i32 f64)))
+              (import "env" "get_last_order_rejected" (func $rejected (result i32)))
+              (import "env" "get_cash" (func $cash (result f64)))
+              (data (i32.const 16) "AAPL")
+              (func (export "init"))
+              (func (export "tick") (param i64 f64 f64 f64 f64 f64)
+                (call $order (i32.const 16) (i32.const 4) (i32.const 0) (f64.const 1))
+                (call $order (i32.const 16) (i32.const 4) (i32.const 0) (f64.const 1))
+                (call $order (i32.const 16) (i32.const 4) (i32.const 0) (f64.const 1))
+                (drop (call $rejected))
+                (drop (call $cash))))
+        "#;
+        let bar = candle();
+        let warning = |message: &str, refused| StrategyWarning {
+            time: Some(bar.timestamp),
+            message: message.to_string(),
+            refused,
+        };
+
+        // The third order is refused and the tick goes on
+        let capped = Capabilities {
+            max_orders_per_tick: Some(2),
+            ..Capabilities::default()
+        };
+        let mut strategy =
+            WasmStrategy::new(wat.as_bytes(), capped).expect("Failed to load module");
+        strategy.record_calls();
+        let mut broker = Broker::new();
+        strategy.tick(&bar.timestamp, Some(&bar), &mut broker);
+        assert_eq!(broker.orders.len(), 2);
+        let calls = strategy.take_calls();
+        assert_eq!(calls[2].function, "get_last_order_rejected");
+        assert_eq!(calls[2].result, json!(true));
+        assert_eq!(calls[3].function, "get_cash");
+        // The counter is per call
+        strategy.tick(&bar.timestamp, Some(&bar), &mut broker);
+        assert_eq!(broker.orders.len(), 4);
+        let exceeded = warning("strategy exceeded its orders per tick", 1);
+        assert_eq!(strategy.take_warnings(), [exceeded.clone(), exceeded]);
+
+        // Each order is a host call, the calls past the second one return an error value
+        let budget = Capabilities {
+            max_host_calls_per_tick: Some(2),
+            ..Capabilities::default()
+        };
+        let mut strategy =
+            WasmStrategy::new(wat.as_bytes(), budget).expect("Failed to load module");
+        let mut broker = Broker::new();
+        strategy.tick(&bar.timestamp, Some(&bar), &mut broker);
+        assert_eq!(broker.orders.len(), 2);
+        assert_eq!(strategy.take_error(), None);
+        assert_eq!(
+            strategy.take_warnings(),
+            [warning("strategy exceeded its host calls per tick", 3)]
+        );
+
+        let read_only = Capabilities {
+            orders: false,
+            ..Capabilities::default()
+        };
+        let mut strategy =
+            WasmStrategy::new(wat.as_bytes(), read_only).expect("Failed to load module");
+        let mut broker = Broker::new();
+        strategy.tick(&bar.timestamp, Some(&bar), &mut broker);
+        assert!(broker.orders.is_empty());
+        assert_eq!(
+            strategy.take_warnings(),
+            [warning("strategy is not allowed to place orders", 3)]
+        );
+    }
+
+    #[test]
+    fn sandbox_survives_broker_calls_loops_and_memory_growth() {
+        // Queries and orders during `init` have no broker to reach
+        let wat = r#"
+            (module
+              (import "env" "get_cash" (func $cash (result f64)))
+              (import "env" "close_all_positions" (func $close (result i32)))
+              (import "env" "cancel_order" (func $cancel (param i64) (result i32)))
+              (memory (export "memory") 1)
+              (global (export "cash") (mut f64) (f64.const 0))
+              (global (export "grown") (mut i32) (i32.const 0))
+              (func (export "init")
+                (global.set 0 (call $cash))
+                (drop (call $close))
+                (drop (call $cancel (i64.const 1))))
+              (func (export "tick") (param i64 f64 f64 f64 f64 f64)
+                (global.set 1 (memory.grow (i32.const 16)))
+                (if (f64.gt (local.get 1) (f64.const 100))
+                  (then (loop $forever (br $forever))))))
+        "#;
+        let capped = Capabilities {
+            max_fuel_per_call: Some(100_000),
+            max_memory_bytes: Some(4 << 16),
+            ..Capabilities::default()
+        };
+        let mut strategy =
+            WasmStrategy::new(wat.as_bytes(), capped).expect("Failed to load module");
+        strategy.init();
+        assert_eq!(strategy.take_error(), None);
+        let global = |strategy: &mut WasmStrategy, name| {
+            strategy
+                ._instance
+                .get_global(&mut strategy.store, name)
+                .map(|global| global.get(&mut strategy.store))
+        };
+        assert!(global(&mut strategy, "cash")
+            .and_then(|cash| cash.f64())
+            .is_some_and(f64::is_nan));
+
+        // Growing past the cap fails instead of taking the memory
+        let mut bar = candle();
+        strategy.tick(&bar.timestamp, Some(&bar), &mut Broker::new());
+        assert_eq!(strategy.take_error(), None);
+        assert_eq!(
+            global(&mut strategy, "grown").and_then(|grown| grown.i32()),
+            Some(-1)
+        );
+
+        // A call stuck in a loop runs out of fuel, the next one gets a full tank
+        bar.open = 101.0;
+        strategy.tick(&bar.timestamp, Some(&bar), &mut Broker::new());
+        let error = strategy.take_error().unwrap();
+        assert_eq!(error.trap.as_deref(), Some("OutOfFuel"));
+        bar.open = 100.0;
+        strategy.tick(&bar.timestamp, Some(&bar), &mut Broker::new());
+        assert_eq!(strategy.take_error(), None);
+    }
+
+    #[test]
+    fn signals_record_their_traps_and_get_a_full_tank() {
+        // Loops forever on more than one bar
+        let wat = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "init"))
+              (func (export "tick") (param i64 f64 f64 f64 f64 f64))
+              (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "signals") (param i32 i32 i32)
+                (if (i32.gt_s (local.get 1) (i32.const 1))
+                  (then (loop $forever (br $forever))))
+                (f64.store (local.get 2) (f64.const 1))))
+        "#;
+        let capped = Capabilities {
+            max_fuel_per_call: Some(100_000),
+            ..Capabilities::default()
+        };
+        let mut strategy =
+            WasmStrategy::new(wat.as_bytes(), capped).expect("Failed to load module");
+        strategy.init();
+
+        assert_eq!(strategy.signals(&[candle(), candle()]), None);
+        let error = strategy.take_error().unwrap();
+        assert_eq!(error.trap.as_deref(), Some("OutOfFuel"));
+        assert!(!error.backtrace.is_empty());
 
-        self.store.data_mut().broker_ptr = ptr::null_mut();
+        assert_eq!(strategy.signals(&[candle()]), Some(vec![1.0]));
+        assert_eq!(strategy.take_error(), None);
     }
 }
@@ -0,0 +1,105 @@
+use crate::analytics::metrics::GlobalMetrics;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+
+// A callback that doesn't answer in time is given up on
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Completed,
+    Failed,
+}
+
+// Payload POSTed to the callback URL once a run is over
+#[derive(Serialize, Debug, Clone)]
+pub struct RunSummary {
+    pub run_id: Option<String>,
+    pub status: RunStatus,
+    pub error: Option<String>,
+    pub metrics: Option<GlobalMetrics>,
+}
+
+// Only HTTP callbacks are called, like the alert webhooks
+pub fn validate(callback_url: &str) -> Result<(), &'static str> {
+    match callback_url.starts_with("http://") || callback_url.starts_with("https://") {
+        true => Ok(()),
+        false => Err("Invalid callback URL"),
+    }
+}
+
+// Client of the callbacks and alert webhooks, with the timeout
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+// Hex encoded HMAC-SHA256 of the payload, sent in the `X-Kronos-Signature` header
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(payload);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Fire and forget, a failing callback should never impact the run response
+pub fn notify(callback_url: String, summary: RunSummary) {
+    tokio::spawn(async move {
+        if let Err(e) = send(&callback_url, &summary).await {
+            eprintln!("Failed to call webhook {}: {}", callback_url, e);
+        }
+    });
+}
+
+async fn send(callback_url: &str, summary: &RunSummary) -> Result<(), String> {
+    let body = serde_json::to_vec(summary).map_err(|e| e.to_string())?;
+
+    let mut request = client()
+        .post(callback_url)
+        .header("Content-Type", "application/json");
+
+    match std::env::var("KRONOS_WEBHOOK_SECRET") {
+        Ok(secret) => {
+            request = request.header(
+                "X-Kronos-Signature",
+                format!("sha256={}", sign(&secret, &body)),
+            );
+        }
+        Err(_) => eprintln!("KRONOS_WEBHOOK_SECRET is not set, sending an unsigned webhook"),
+    }
+
+    let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Callback responded with {}", response.status()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_like_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn only_calls_back_http_urls() {
+        assert!(validate("https://example.com/hooks/kronos").is_ok());
+        assert!(validate("http://localhost:8080").is_ok());
+        for url in ["file:///etc/passwd", "ftp://example.com", "example.com", ""] {
+            assert!(validate(url).is_err(), "{}", url);
+        }
+    }
+}